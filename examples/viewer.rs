@@ -0,0 +1,164 @@
+//! Displays the frames of a packed atlas, one at a time.
+//!
+//! ```sh
+//! cargo run --example viewer --features viewer -- out/sprites.ron [name filter]
+//! ```
+//!
+//! Left/Right cycle through the frames, Escape quits. Besides being a debugging aid this
+//! shows how the atlas file is meant to be read: `layer` selects the array layer (or
+//! page image), `x/y/width/height` the pixels on it, the pivot is relative to the
+//! region, and variant frames reuse another frame's pixels with a `draw_transform`.
+
+use texture_packer::atlas::{NamedTextureRegion, SpriteTransform, TextureAtlas};
+
+const WINDOW_SIZE: usize = 512;
+
+/// Atlas layers as luma/alpha pairs, the layout the packer writes.
+struct Layers {
+    width: u32,
+    pixels: Vec<Vec<[u8; 2]>>,
+}
+
+fn load_layers(atlas: &TextureAtlas, atlas_dir: &std::path::Path) -> Result<Layers, String> {
+    //
+    // the page images if there are any, the decoded texture otherwise
+    let pixels = atlas
+        .load_pages(atlas_dir)?
+        .iter()
+        .map(|page| page.to_luma_alpha8().pixels().map(|p| p.0).collect())
+        .collect();
+
+    Ok(Layers {
+        width: atlas.size.0,
+        pixels,
+    })
+}
+
+/// Maps a pixel of the transformed frame back into the untransformed region.
+fn source_texel(t: SpriteTransform, (w, h): (u32, u32), (x, y): (u32, u32)) -> (u32, u32) {
+    let (x, y) = if t.rotate90 { (y, h - 1 - x) } else { (x, y) };
+    let x = if t.flip_x { w - 1 - x } else { x };
+    let y = if t.flip_y { h - 1 - y } else { y };
+    (x, y)
+}
+
+fn draw_frame(layers: &Layers, frame: &NamedTextureRegion, buffer: &mut [u32]) {
+    let transform = frame.draw_transform.unwrap_or_default();
+    let (fw, fh) = if transform.rotate90 {
+        (frame.height, frame.width)
+    } else {
+        (frame.width, frame.height)
+    };
+
+    let scale = (WINDOW_SIZE as u32 / fw.max(fh)).max(1) as usize;
+    let (ox, oy) = (
+        (WINDOW_SIZE.saturating_sub(fw as usize * scale)) / 2,
+        (WINDOW_SIZE.saturating_sub(fh as usize * scale)) / 2,
+    );
+
+    let layer = &layers.pixels[frame.layer as usize];
+
+    for (i, px) in buffer.iter_mut().enumerate() {
+        let (wx, wy) = (i % WINDOW_SIZE, i / WINDOW_SIZE);
+        let checker = if (wx / 16 + wy / 16) % 2 == 0 {
+            0x60
+        } else {
+            0x90
+        };
+
+        let inside =
+            wx >= ox && wy >= oy && wx < ox + fw as usize * scale && wy < oy + fh as usize * scale;
+        let value = if inside {
+            let (sx, sy) = source_texel(
+                transform,
+                (frame.width, frame.height),
+                (((wx - ox) / scale) as u32, ((wy - oy) / scale) as u32),
+            );
+            let [luma, alpha] = layer[((frame.y + sy) * layers.width + frame.x + sx) as usize];
+            (luma as u32 * alpha as u32 + checker * (255 - alpha as u32)) / 255
+        } else {
+            checker
+        };
+
+        *px = value << 16 | value << 8 | value;
+    }
+
+    //
+    // pivot marker, red cross
+    let (px, py) = (
+        ox as i64 + (frame.pivot_x * (fw as usize * scale) as f32) as i64,
+        oy as i64 + (frame.pivot_y * (fh as usize * scale) as f32) as i64,
+    );
+    for d in -4..=4 {
+        for (x, y) in [(px + d, py), (px, py + d)] {
+            if (0..WINDOW_SIZE as i64).contains(&x) && (0..WINDOW_SIZE as i64).contains(&y) {
+                buffer[y as usize * WINDOW_SIZE + x as usize] = 0xFF0000;
+            }
+        }
+    }
+}
+
+fn main() -> Result<(), String> {
+    let mut args = std::env::args().skip(1);
+    let atlas_path = std::path::PathBuf::from(
+        args.next()
+            .ok_or_else(|| "usage: viewer <atlas.ron> [name filter]".to_string())?,
+    );
+    let filter = args.next().unwrap_or_default();
+
+    let atlas = TextureAtlas::load(&atlas_path)?;
+    let atlas_dir = atlas_path.parent().unwrap_or(std::path::Path::new("."));
+    let layers = load_layers(&atlas, atlas_dir)?;
+
+    let frames = atlas
+        .frames
+        .iter()
+        .filter(|f| f.key.contains(&filter))
+        .collect::<Vec<_>>();
+    if frames.is_empty() {
+        return Err(format!("No frames matching '{}'", filter));
+    }
+
+    let mut window = minifb::Window::new(
+        "viewer",
+        WINDOW_SIZE,
+        WINDOW_SIZE,
+        minifb::WindowOptions::default(),
+    )
+    .map_err(|e| e.to_string())?;
+    window.set_target_fps(30);
+
+    let mut buffer = vec![0u32; WINDOW_SIZE * WINDOW_SIZE];
+    let mut current = 0usize;
+
+    while window.is_open() && !window.is_key_down(minifb::Key::Escape) {
+        if window.is_key_pressed(minifb::Key::Right, minifb::KeyRepeat::Yes) {
+            current = (current + 1) % frames.len();
+        }
+        if window.is_key_pressed(minifb::Key::Left, minifb::KeyRepeat::Yes) {
+            current = (current + frames.len() - 1) % frames.len();
+        }
+
+        let frame = frames[current];
+        window.set_title(&format!(
+            "{} - layer {} rect ({}, {}, {}, {}) uv ({:.4}, {:.4}, {:.4}, {:.4})",
+            frame.key,
+            frame.layer,
+            frame.x,
+            frame.y,
+            frame.width,
+            frame.height,
+            frame.u0,
+            frame.v0,
+            frame.u1,
+            frame.v1
+        ));
+
+        draw_frame(&layers, frame, &mut buffer);
+        window
+            .update_with_buffer(&buffer, WINDOW_SIZE, WINDOW_SIZE)
+            .map_err(|e| e.to_string())?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,121 @@
+// Loads the `<atlas>.bevy.ron` written by `--export bevy` (Bevy 0.16).
+//
+// Copy this file into the game crate, which needs `serde`, `ron` and Bevy's `serialize`
+// feature, and add the plugin:
+//
+//     app.add_plugins(TexturePackerPlugin);
+//     let atlas: Handle<PackedAtlas> = asset_server.load("sprites.bevy.ron");
+//
+// Once the atlas is loaded, `atlases.get(&atlas).unwrap().sprite("hero_idle")` makes a
+// `Sprite` drawing that frame. The page images are loaded along with it, every page's
+// layout is a labeled asset `page<N>`.
+
+use bevy::asset::io::Reader;
+use bevy::asset::{AssetLoader, LoadContext};
+use bevy::prelude::*;
+use serde::Deserialize;
+use std::collections::HashMap;
+
+#[derive(Deserialize)]
+struct PageFile {
+    texture: String,
+    layout: TextureAtlasLayout,
+}
+
+#[derive(Deserialize)]
+struct AtlasFile {
+    pages: Vec<PageFile>,
+    names: HashMap<String, FrameIndex>,
+}
+
+/// Where a frame is: the page, and the index of its rect in the page's layout.
+#[derive(Copy, Clone, Debug, Deserialize)]
+pub struct FrameIndex {
+    pub page: usize,
+    pub index: usize,
+}
+
+pub struct AtlasPage {
+    pub image: Handle<Image>,
+    pub layout: Handle<TextureAtlasLayout>,
+}
+
+#[derive(Asset, TypePath)]
+pub struct PackedAtlas {
+    pub pages: Vec<AtlasPage>,
+    pub names: HashMap<String, FrameIndex>,
+}
+
+impl PackedAtlas {
+    /// The page image and atlas index of the frame packed under `name`.
+    pub fn frame(&self, name: &str) -> Option<(Handle<Image>, TextureAtlas)> {
+        let frame = self.names.get(name)?;
+        let page = &self.pages[frame.page];
+        Some((
+            page.image.clone(),
+            TextureAtlas {
+                layout: page.layout.clone(),
+                index: frame.index,
+            },
+        ))
+    }
+
+    /// A sprite drawing the frame packed under `name`.
+    pub fn sprite(&self, name: &str) -> Option<Sprite> {
+        let (image, atlas) = self.frame(name)?;
+        Some(Sprite::from_atlas_image(image, atlas))
+    }
+}
+
+#[derive(Default)]
+struct PackedAtlasLoader;
+
+impl AssetLoader for PackedAtlasLoader {
+    type Asset = PackedAtlas;
+    type Settings = ();
+    type Error = Box<dyn std::error::Error + Send + Sync>;
+
+    async fn load(
+        &self,
+        reader: &mut dyn Reader,
+        _settings: &(),
+        load_context: &mut LoadContext<'_>,
+    ) -> Result<PackedAtlas, Self::Error> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+        let file: AtlasFile = ron::de::from_bytes(&bytes)?;
+
+        let dir = load_context
+            .path()
+            .parent()
+            .map(|d| d.to_path_buf())
+            .unwrap_or_default();
+        let pages = file
+            .pages
+            .into_iter()
+            .enumerate()
+            .map(|(idx, page)| AtlasPage {
+                image: load_context.load(dir.join(&page.texture)),
+                layout: load_context.add_labeled_asset(format!("page{}", idx), page.layout),
+            })
+            .collect();
+
+        Ok(PackedAtlas {
+            pages,
+            names: file.names,
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["bevy.ron"]
+    }
+}
+
+pub struct TexturePackerPlugin;
+
+impl Plugin for TexturePackerPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_asset::<PackedAtlas>()
+            .init_asset_loader::<PackedAtlasLoader>();
+    }
+}
@@ -0,0 +1,115 @@
+// Slices the page textures of a texture-packer atlas into sprites, from the
+// `<atlas>.unity.json` written by `--export unity`.
+//
+// Copy this file into an `Editor` folder of the project. Layouts are applied whenever a
+// `*.unity.json` is imported or reimported, or from Assets > Texture Packer > Apply
+// Atlas Layout with the JSON selected.
+
+using System.IO;
+using UnityEditor;
+using UnityEngine;
+
+namespace TexturePacker
+{
+    public class TexturePackerAtlasImporter : AssetPostprocessor
+    {
+        [System.Serializable]
+        class Sprite
+        {
+            public string name;
+            public Rect rect;
+            public Vector2 pivot;
+            public Vector4 border;
+        }
+
+        [System.Serializable]
+        class Page
+        {
+            public string texture;
+            public int width;
+            public int height;
+            public Sprite[] sprites;
+        }
+
+        [System.Serializable]
+        class Layout
+        {
+            public string atlas;
+            public Page[] pages;
+        }
+
+        static void OnPostprocessAllAssets(
+            string[] imported,
+            string[] deleted,
+            string[] moved,
+            string[] movedFrom)
+        {
+            foreach (var path in imported)
+            {
+                if (path.EndsWith(".unity.json"))
+                {
+                    Apply(path);
+                }
+            }
+        }
+
+        [MenuItem("Assets/Texture Packer/Apply Atlas Layout")]
+        static void ApplySelected()
+        {
+            foreach (var asset in Selection.objects)
+            {
+                var path = AssetDatabase.GetAssetPath(asset);
+                if (path.EndsWith(".unity.json"))
+                {
+                    Apply(path);
+                }
+            }
+        }
+
+        static void Apply(string layoutPath)
+        {
+            var layout = JsonUtility.FromJson<Layout>(File.ReadAllText(layoutPath));
+            var dir = Path.GetDirectoryName(layoutPath);
+
+            foreach (var page in layout.pages)
+            {
+                var texturePath = Path.GetFullPath(Path.Combine(dir, page.texture))
+                    .Replace('\\', '/');
+                var projectRoot = Path.GetFullPath(".").Replace('\\', '/') + "/";
+                if (texturePath.StartsWith(projectRoot))
+                {
+                    texturePath = texturePath.Substring(projectRoot.Length);
+                }
+
+                var importer = AssetImporter.GetAtPath(texturePath) as TextureImporter;
+                if (importer == null)
+                {
+                    Debug.LogWarning($"{layout.atlas}: page {texturePath} is not a texture in the project");
+                    continue;
+                }
+
+                var sprites = new SpriteMetaData[page.sprites.Length];
+                for (var i = 0; i < sprites.Length; i++)
+                {
+                    var sprite = page.sprites[i];
+                    sprites[i] = new SpriteMetaData
+                    {
+                        name = sprite.name,
+                        rect = sprite.rect,
+                        alignment = (int)SpriteAlignment.Custom,
+                        pivot = sprite.pivot,
+                        border = sprite.border,
+                    };
+                }
+
+                importer.textureType = TextureImporterType.Sprite;
+                importer.spriteImportMode = SpriteImportMode.Multiple;
+                importer.mipmapEnabled = false;
+                importer.npotScale = TextureImporterNPOTScale.None;
+                importer.maxTextureSize = Mathf.Max(importer.maxTextureSize, page.width, page.height);
+                importer.spritesheet = sprites;
+                importer.SaveAndReimport();
+            }
+        }
+    }
+}
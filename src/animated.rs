@@ -0,0 +1,200 @@
+//! Frame extraction from animated GIF and APNG inputs.
+//!
+//! Optimized animations only store the pixels that changed between frames, so frames
+//! are composited onto the canvas following the disposal/blend ops by default. The raw
+//! mode keeps the stored sub-rectangles as they are and records how to composite them.
+
+use image::AnimationDecoder;
+use std::io::Read;
+
+use crate::atlas::{FrameBlend, FrameDelta, FrameDispose};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum FrameMode {
+    /// Full frames, composited according to the disposal/blend ops.
+    Composite,
+    /// The stored frame deltas, with their placement on the canvas.
+    Raw,
+}
+
+pub struct AnimatedFrame {
+    pub image: image::RgbaImage,
+    pub delta: Option<FrameDelta>,
+    /// How long the frame is shown.
+    pub delay_ms: u32,
+}
+
+/// Rounds a delay of `numerator / denominator` seconds to milliseconds.
+fn delay_ms(numerator: u32, denominator: u32) -> u32 {
+    ((numerator as u64 * 1000 + denominator as u64 / 2) / denominator.max(1) as u64) as u32
+}
+
+fn open(path: &std::path::Path) -> Result<std::io::BufReader<std::fs::File>, String> {
+    std::fs::File::open(path)
+        .map(std::io::BufReader::new)
+        .map_err(|e| e.to_string())
+}
+
+/// The format the first bytes of the file say it has.
+fn format(path: &std::path::Path) -> Option<image::ImageFormat> {
+    let mut header = Vec::new();
+    open(path).ok()?.take(16).read_to_end(&mut header).ok()?;
+    image::guess_format(&header).ok()
+}
+
+fn is_gif(path: &std::path::Path) -> bool {
+    format(path) == Some(image::ImageFormat::Gif)
+}
+
+/// GIFs and PNGs carrying an animation control chunk.
+pub fn is_animated(path: &std::path::Path) -> bool {
+    match format(path) {
+        Some(image::ImageFormat::Gif) => true,
+        Some(image::ImageFormat::Png) => open(path)
+            .ok()
+            .and_then(|reader| image::codecs::png::PngDecoder::new(reader).ok())
+            .is_some_and(|decoder| decoder.is_apng()),
+        _ => false,
+    }
+}
+
+pub fn load(path: &std::path::Path, mode: FrameMode) -> Result<Vec<AnimatedFrame>, String> {
+    match (is_gif(path), mode) {
+        (true, FrameMode::Composite) => {
+            composite(image::codecs::gif::GifDecoder::new(open(path)?).map_err(|e| e.to_string())?)
+        }
+        (false, FrameMode::Composite) => composite(
+            image::codecs::png::PngDecoder::new(open(path)?)
+                .map_err(|e| e.to_string())?
+                .apng(),
+        ),
+        (true, FrameMode::Raw) => raw_gif(path),
+        (false, FrameMode::Raw) => raw_apng(path),
+    }
+}
+
+fn composite<'a>(decoder: impl AnimationDecoder<'a>) -> Result<Vec<AnimatedFrame>, String> {
+    decoder
+        .into_frames()
+        .map(|frame| {
+            frame
+                .map(|f| {
+                    let (numerator, denominator) = f.delay().numer_denom_ms();
+                    AnimatedFrame {
+                        delay_ms: delay_ms(numerator, denominator * 1000),
+                        image: f.into_buffer(),
+                        delta: None,
+                    }
+                })
+                .map_err(|e| e.to_string())
+        })
+        .collect()
+}
+
+fn raw_gif(path: &std::path::Path) -> Result<Vec<AnimatedFrame>, String> {
+    let mut options = gif::DecodeOptions::new();
+    options.set_color_output(gif::ColorOutput::RGBA);
+    let mut decoder = options.read_info(open(path)?).map_err(|e| e.to_string())?;
+    let (canvas_width, canvas_height) = (decoder.width() as u32, decoder.height() as u32);
+
+    let mut frames = Vec::new();
+    while let Some(frame) = decoder.read_next_frame().map_err(|e| e.to_string())? {
+        let image = image::RgbaImage::from_raw(
+            frame.width as u32,
+            frame.height as u32,
+            frame.buffer.to_vec(),
+        )
+        .ok_or_else(|| "invalid GIF frame".to_string())?;
+
+        frames.push(AnimatedFrame {
+            image,
+            delta: Some(FrameDelta::new(
+                frame.left as u32,
+                frame.top as u32,
+                (canvas_width, canvas_height),
+                match frame.dispose {
+                    gif::DisposalMethod::Background => FrameDispose::Background,
+                    gif::DisposalMethod::Previous => FrameDispose::Previous,
+                    _ => FrameDispose::None,
+                },
+                FrameBlend::Over,
+            )),
+            //
+            // in hundredths of a second
+            delay_ms: frame.delay as u32 * 10,
+        });
+    }
+
+    Ok(frames)
+}
+
+fn raw_apng(path: &std::path::Path) -> Result<Vec<AnimatedFrame>, String> {
+    let mut decoder = png::Decoder::new(open(path)?);
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut reader = decoder.read_info().map_err(|e| e.to_string())?;
+
+    let info = reader.info();
+    let (canvas_width, canvas_height) = (info.width, info.height);
+    let frame_count = info
+        .animation_control()
+        .map(|ac| ac.num_frames)
+        .unwrap_or(1);
+    //
+    // without a fcTL before the IDAT the default image is not part of the animation
+    let skip_default = info.frame_control().is_none();
+
+    let mut buf = vec![0; reader.output_buffer_size()];
+    let mut frames = Vec::new();
+
+    for idx in 0..frame_count + skip_default as u32 {
+        let out = reader.next_frame(&mut buf).map_err(|e| e.to_string())?;
+        if idx == 0 && skip_default {
+            continue;
+        }
+
+        let data = &buf[..out.buffer_size()];
+        let rgba = match out.color_type {
+            png::ColorType::Rgba => data.to_vec(),
+            png::ColorType::Rgb => data
+                .chunks_exact(3)
+                .flat_map(|p| [p[0], p[1], p[2], 255])
+                .collect(),
+            png::ColorType::GrayscaleAlpha => data
+                .chunks_exact(2)
+                .flat_map(|p| [p[0], p[0], p[0], p[1]])
+                .collect(),
+            png::ColorType::Grayscale => data.iter().flat_map(|&g| [g, g, g, 255]).collect(),
+            png::ColorType::Indexed => return Err("unexpanded indexed APNG frame".to_string()),
+        };
+
+        let image = image::RgbaImage::from_raw(out.width, out.height, rgba)
+            .ok_or_else(|| "invalid APNG frame".to_string())?;
+        let fc = reader.info().frame_control().copied().unwrap_or_default();
+
+        frames.push(AnimatedFrame {
+            image,
+            delta: Some(FrameDelta::new(
+                fc.x_offset,
+                fc.y_offset,
+                (canvas_width, canvas_height),
+                match fc.dispose_op {
+                    png::DisposeOp::Background => FrameDispose::Background,
+                    png::DisposeOp::Previous => FrameDispose::Previous,
+                    png::DisposeOp::None => FrameDispose::None,
+                },
+                match fc.blend_op {
+                    png::BlendOp::Over => FrameBlend::Over,
+                    png::BlendOp::Source => FrameBlend::Source,
+                },
+            )),
+            //
+            // a denominator of 0 means hundredths of a second
+            delay_ms: delay_ms(
+                fc.delay_num as u32,
+                Some(fc.delay_den).filter(|&d| d != 0).unwrap_or(100) as u32,
+            ),
+        });
+    }
+
+    Ok(frames)
+}
@@ -0,0 +1,177 @@
+//! Adds sprites to an atlas that already shipped with `--append <atlas.ron>`, without
+//! moving what it holds: its frames keep their place, sprites with the key of one of
+//! them are left out and the others are packed into the space left on its pages, or on
+//! new pages once those are full. The pages are read back from the outputs of the build
+//! that wrote the atlas, PNG pages or uncompressed KTX2.
+
+use crate::atlas::{
+    Animation, AttributionGroup, FontFace, NamedTextureRegion, PixelFormat, TextureAtlas,
+};
+use crate::encode::PageFormat;
+use crate::planner::{self, Placed};
+use std::path::{Path, PathBuf};
+
+pub struct AppendedAtlas {
+    path: PathBuf,
+    atlas: TextureAtlas,
+    pages: Vec<image::GrayAlphaImage>,
+    keys: std::collections::HashSet<String>,
+}
+
+impl AppendedAtlas {
+    /// Reads the atlas at `path` and its pages, checking they hold what a build with
+    /// `format` and `pixel_format` would compose.
+    pub fn load(
+        path: &Path,
+        format: PageFormat,
+        pixel_format: PixelFormat,
+    ) -> Result<AppendedAtlas, String> {
+        let atlas = TextureAtlas::load(path)?;
+        let cannot =
+            |why: &dyn std::fmt::Display| format!("Cannot append to {}: {}", path.display(), why);
+
+        if !pixel_format.is_la8() || !atlas.pixel_format.is_la8() {
+            return Err(cannot(&"only la8 atlases can be appended to"));
+        }
+        if atlas.motion_vectors.is_some() {
+            return Err(cannot(&"its motion vector pages can not be added to"));
+        }
+        if (atlas.content, atlas.color_space, atlas.premultiplied)
+            != (format.content, format.color_space, format.premultiplied)
+        {
+            return Err(cannot(&format_args!(
+                "it holds {:?} {:?}{} pixels, pack with the --content, --color-space \
+                 and --premultiply-alpha it was packed with",
+                atlas.content,
+                atlas.color_space,
+                if atlas.premultiplied {
+                    " premultiplied"
+                } else {
+                    ""
+                }
+            )));
+        }
+
+        let pages = atlas
+            .load_pages(path.parent().unwrap_or(Path::new("")))
+            .map_err(|e| cannot(&e))?
+            .into_iter()
+            .map(|page| page.into_luma_alpha8())
+            .collect::<Vec<_>>();
+        if let Some(page) = pages.iter().find(|p| p.dimensions() != atlas.size) {
+            return Err(cannot(&format_args!(
+                "a page is {}x{}, the atlas {}x{}",
+                page.width(),
+                page.height(),
+                atlas.size.0,
+                atlas.size.1
+            )));
+        }
+
+        Ok(AppendedAtlas {
+            path: path.to_path_buf(),
+            keys: atlas.frames.iter().map(|f| f.key.clone()).collect(),
+            atlas,
+            pages,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn page_size(&self) -> (u32, u32) {
+        self.atlas.size
+    }
+
+    pub fn page_count(&self) -> u32 {
+        self.pages.len() as u32
+    }
+
+    /// Whether the atlas has a frame named `key`.
+    pub fn contains(&self, key: &str) -> bool {
+        self.keys.contains(key)
+    }
+
+    /// The space left on the pages, keeping `margin` pixels free around every frame.
+    pub fn free_space(&self, margin: u32) -> Vec<Placed> {
+        let taken = self
+            .atlas
+            .frames
+            .iter()
+            .map(|f| {
+                let (x, y) = (f.x.saturating_sub(margin), f.y.saturating_sub(margin));
+                Placed {
+                    page: f.layer,
+                    x,
+                    y,
+                    width: f.x + f.width + margin - x,
+                    height: f.y + f.height + margin - y,
+                }
+            })
+            .collect::<Vec<_>>();
+        planner::free_space(&taken, self.atlas.size, self.page_count())
+    }
+
+    /// Takes the pages out, for the build to compose onto.
+    pub fn take_pages(&mut self) -> Vec<image::GrayAlphaImage> {
+        std::mem::take(&mut self.pages)
+    }
+
+    /// The frames of the atlas followed by `frames`, with their sources absolute like
+    /// the frames of a build. Aliases are left out, they are added like the others.
+    pub fn frames(&self, frames: Vec<NamedTextureRegion>) -> Vec<NamedTextureRegion> {
+        let dir = self.path.parent().unwrap_or(Path::new(""));
+        self.atlas
+            .frames
+            .iter()
+            .filter(|f| f.alias_of.is_none())
+            .cloned()
+            .map(|mut frame| {
+                frame.source = frame
+                    .source
+                    .map(|s| std::path::absolute(dir.join(&s)).unwrap_or(s));
+                frame
+            })
+            .chain(frames)
+            .collect()
+    }
+
+    /// The animations of the atlas, and of the build where it has none by that name.
+    pub fn animations(&self, animations: Vec<Animation>) -> Vec<Animation> {
+        let mut merged = self.atlas.animations.clone();
+        for animation in animations {
+            if !merged.iter().any(|a| a.name == animation.name) {
+                merged.push(animation);
+            }
+        }
+        merged
+    }
+
+    /// The credits of the atlas and of the build, frames with the same attribution in
+    /// one group.
+    pub fn attributions(&self, attributions: Vec<AttributionGroup>) -> Vec<AttributionGroup> {
+        let mut merged = self.atlas.attributions.clone();
+        for group in attributions {
+            match merged
+                .iter_mut()
+                .find(|g| g.attribution == group.attribution)
+            {
+                Some(old) => old.frames.extend(group.frames),
+                None => merged.push(group),
+            }
+        }
+        merged
+    }
+
+    /// The font faces of the atlas, and of the build where it has none by that name.
+    pub fn fonts(&self, fonts: Vec<FontFace>) -> Vec<FontFace> {
+        let mut merged = self.atlas.fonts.clone();
+        for face in fonts {
+            if !merged.iter().any(|f| f.name == face.name) {
+                merged.push(face);
+            }
+        }
+        merged
+    }
+}
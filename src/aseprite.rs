@@ -0,0 +1,495 @@
+//! Reader for Aseprite (`.aseprite`/`.ase`) files.
+//!
+//! Only what the packer needs is decoded: frames composited from the visible layers,
+//! tags and the user data strings attached to cels and tags. See
+//! <https://github.com/aseprite/aseprite/blob/main/docs/ase-file-specs.md>.
+
+use std::io::Read;
+
+use crate::atlas::PlaybackDirection;
+
+/// Whether the first bytes of a file are an Aseprite header, by its magic number.
+pub fn is_aseprite(header: &[u8]) -> bool {
+    header.get(4..6) == Some(&[0xE0, 0xA5])
+}
+
+/// Whether the visible layers are flattened into one image per frame or kept apart.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum LayerMode {
+    Merge,
+    Split,
+}
+
+pub struct Frame {
+    pub image: image::RgbaImage,
+    /// User data text of the cels making up this frame.
+    pub user_data: Vec<String>,
+    pub duration_ms: u32,
+    /// Per layer images (index into `layers`), only filled when splitting layers.
+    pub layers: Vec<LayerImage>,
+}
+
+pub struct LayerImage {
+    pub layer: usize,
+    pub image: image::RgbaImage,
+    pub user_data: Option<String>,
+}
+
+pub struct Tag {
+    pub name: String,
+    pub from: u32,
+    pub to: u32,
+    pub direction: PlaybackDirection,
+    /// Number of times to play, 0 for forever.
+    pub repeat: u16,
+    pub user_data: Option<String>,
+}
+
+pub struct LayerInfo {
+    pub name: String,
+    /// Visible itself and all its parent groups.
+    pub visible: bool,
+}
+
+pub struct AsepriteFile {
+    pub frames: Vec<Frame>,
+    pub tags: Vec<Tag>,
+    pub layers: Vec<LayerInfo>,
+}
+
+const CHUNK_LAYER: u16 = 0x2004;
+const CHUNK_CEL: u16 = 0x2005;
+const CHUNK_TAGS: u16 = 0x2018;
+const CHUNK_PALETTE: u16 = 0x2019;
+const CHUNK_USER_DATA: u16 = 0x2020;
+
+const LAYER_FLAG_VISIBLE: u16 = 1;
+const LAYER_FLAG_BACKGROUND: u16 = 8;
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Reader { data, pos: 0 }
+    }
+
+    fn bytes(&mut self, n: usize) -> Result<&'a [u8], String> {
+        let end = self
+            .pos
+            .checked_add(n)
+            .filter(|&end| end <= self.data.len())
+            .ok_or_else(|| "unexpected end of data".to_string())?;
+        let b = &self.data[self.pos..end];
+        self.pos = end;
+        Ok(b)
+    }
+
+    fn skip(&mut self, n: usize) -> Result<(), String> {
+        self.bytes(n).map(|_| ())
+    }
+
+    fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, String> {
+        let b = self.bytes(2)?;
+        Ok(u16::from_le_bytes([b[0], b[1]]))
+    }
+
+    fn i16(&mut self) -> Result<i16, String> {
+        self.u16().map(|v| v as i16)
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        let b = self.bytes(4)?;
+        Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        let len = self.u16()? as usize;
+        Ok(String::from_utf8_lossy(self.bytes(len)?).into_owned())
+    }
+}
+
+struct Layer {
+    name: String,
+    visible: bool,
+    background: bool,
+    opacity: u8,
+    child_level: u16,
+}
+
+#[derive(Clone)]
+struct Cel {
+    layer: usize,
+    x: i32,
+    y: i32,
+    opacity: u8,
+    image: image::RgbaImage,
+    user_data: Option<String>,
+}
+
+/// The chunk a following user data chunk belongs to.
+enum UserDataTarget {
+    None,
+    Cel(usize),
+    Tag(usize),
+}
+
+struct Header {
+    frames: u16,
+    width: u16,
+    height: u16,
+    color_depth: u16,
+    transparent_index: u8,
+}
+
+fn read_header(r: &mut Reader) -> Result<Header, String> {
+    let _file_size = r.u32()?;
+    if r.u16()? != 0xA5E0 {
+        return Err("not an aseprite file".to_string());
+    }
+
+    let frames = r.u16()?;
+    let width = r.u16()?;
+    let height = r.u16()?;
+    let color_depth = r.u16()?;
+    // flags, speed, two reserved dwords
+    r.skip(4 + 2 + 4 + 4)?;
+    let transparent_index = r.u8()?;
+    // rest of the 128 byte header
+    r.skip(128 - 29)?;
+
+    Ok(Header {
+        frames,
+        width,
+        height,
+        color_depth,
+        transparent_index,
+    })
+}
+
+fn decode_pixels(
+    raw: &[u8],
+    width: u32,
+    height: u32,
+    header: &Header,
+    palette: &[[u8; 4]],
+    background: bool,
+) -> Result<image::RgbaImage, String> {
+    let bpp = match header.color_depth {
+        32 => 4,
+        16 => 2,
+        8 => 1,
+        d => return Err(format!("unsupported color depth {}", d)),
+    };
+
+    let expected = width as usize * height as usize * bpp;
+    if raw.len() < expected {
+        return Err("truncated cel pixel data".to_string());
+    }
+
+    let pixels = raw[..expected]
+        .chunks_exact(bpp)
+        .flat_map(|px| match bpp {
+            4 => [px[0], px[1], px[2], px[3]],
+            2 => [px[0], px[0], px[0], px[1]],
+            _ => {
+                if px[0] == header.transparent_index && !background {
+                    [0, 0, 0, 0]
+                } else {
+                    palette
+                        .get(px[0] as usize)
+                        .copied()
+                        .unwrap_or([0, 0, 0, 255])
+                }
+            }
+        })
+        .collect::<Vec<_>>();
+
+    image::RgbaImage::from_raw(width, height, pixels)
+        .ok_or_else(|| "invalid cel dimensions".to_string())
+}
+
+fn blend(dst: &mut image::RgbaImage, cel: &Cel, layer_opacity: u8) {
+    let opacity = cel.opacity as u32 * layer_opacity as u32 / 255;
+
+    for (x, y, src) in cel.image.enumerate_pixels() {
+        let (dx, dy) = (cel.x + x as i32, cel.y + y as i32);
+        if dx < 0 || dy < 0 || dx >= dst.width() as i32 || dy >= dst.height() as i32 {
+            continue;
+        }
+
+        let sa = src.0[3] as u32 * opacity / 255;
+        if sa == 0 {
+            continue;
+        }
+
+        let d = dst.get_pixel_mut(dx as u32, dy as u32);
+        let da = d.0[3] as u32;
+        let out_a = sa + da * (255 - sa) / 255;
+
+        for c in 0..3 {
+            let sc = src.0[c] as u32;
+            let dc = d.0[c] as u32;
+            d.0[c] = ((sc * sa + dc * da * (255 - sa) / 255) / out_a) as u8;
+        }
+        d.0[3] = out_a as u8;
+    }
+}
+
+pub fn load(path: &std::path::Path, layer_mode: LayerMode) -> Result<AsepriteFile, String> {
+    let data = std::fs::read(path).map_err(|e| e.to_string())?;
+    let mut r = Reader::new(&data);
+    let header = read_header(&mut r)?;
+
+    let mut layers = Vec::<Layer>::new();
+    let mut palette = Vec::<[u8; 4]>::new();
+    let mut tags = Vec::<Tag>::new();
+    let mut cels_per_frame = Vec::<Vec<Cel>>::new();
+    let mut durations = Vec::<u32>::new();
+
+    for frame_idx in 0..header.frames as usize {
+        let frame_start = r.pos;
+        let frame_size = r.u32()? as usize;
+        if r.u16()? != 0xF1FA {
+            return Err(format!("bad magic for frame {}", frame_idx));
+        }
+        let old_chunks = r.u16()?;
+        durations.push(r.u16()? as u32);
+        r.skip(2)?;
+        let new_chunks = r.u32()?;
+        let chunk_count = if new_chunks == 0 {
+            old_chunks as u32
+        } else {
+            new_chunks
+        };
+
+        let mut cels = Vec::<Cel>::new();
+        let mut user_data_target = UserDataTarget::None;
+
+        for _ in 0..chunk_count {
+            let chunk_start = r.pos;
+            let chunk_size = r.u32()? as usize;
+            let chunk_type = r.u16()?;
+            let mut c = Reader::new(r.bytes(chunk_size.saturating_sub(6))?);
+
+            match chunk_type {
+                CHUNK_LAYER => {
+                    let flags = c.u16()?;
+                    let _layer_type = c.u16()?;
+                    let child_level = c.u16()?;
+                    c.skip(2 + 2 + 2)?;
+                    let opacity = c.u8()?;
+                    c.skip(3)?;
+                    let name = c.string()?;
+                    layers.push(Layer {
+                        name,
+                        visible: flags & LAYER_FLAG_VISIBLE != 0,
+                        background: flags & LAYER_FLAG_BACKGROUND != 0,
+                        opacity,
+                        child_level,
+                    });
+                    user_data_target = UserDataTarget::None;
+                }
+                CHUNK_CEL => {
+                    let layer = c.u16()? as usize;
+                    let x = c.i16()? as i32;
+                    let y = c.i16()? as i32;
+                    let opacity = c.u8()?;
+                    let cel_type = c.u16()?;
+                    c.skip(2 + 5)?;
+
+                    let background = layers.get(layer).map(|l| l.background).unwrap_or(false);
+
+                    let image = match cel_type {
+                        0 => {
+                            let (w, h) = (c.u16()? as u32, c.u16()? as u32);
+                            let raw = &c.data[c.pos..];
+                            Some(decode_pixels(raw, w, h, &header, &palette, background)?)
+                        }
+                        1 => {
+                            let linked = c.u16()? as usize;
+                            cels_per_frame
+                                .get(linked)
+                                .and_then(|cels| cels.iter().find(|cel| cel.layer == layer))
+                                .map(|cel| cel.image.clone())
+                        }
+                        2 => {
+                            let (w, h) = (c.u16()? as u32, c.u16()? as u32);
+                            let mut raw = Vec::new();
+                            flate2::read::ZlibDecoder::new(&c.data[c.pos..])
+                                .read_to_end(&mut raw)
+                                .map_err(|e| format!("failed to inflate cel: {}", e))?;
+                            Some(decode_pixels(&raw, w, h, &header, &palette, background)?)
+                        }
+                        // tilemap cels are not supported
+                        _ => None,
+                    };
+
+                    user_data_target = match image {
+                        Some(image) => {
+                            cels.push(Cel {
+                                layer,
+                                x,
+                                y,
+                                opacity,
+                                image,
+                                user_data: None,
+                            });
+                            UserDataTarget::Cel(cels.len() - 1)
+                        }
+                        None => UserDataTarget::None,
+                    };
+                }
+                CHUNK_TAGS => {
+                    let count = c.u16()?;
+                    c.skip(8)?;
+                    let first_tag = tags.len();
+                    for _ in 0..count {
+                        let from = c.u16()? as u32;
+                        let to = c.u16()? as u32;
+                        let direction = match c.u8()? {
+                            1 => PlaybackDirection::Reverse,
+                            2 => PlaybackDirection::PingPong,
+                            3 => PlaybackDirection::PingPongReverse,
+                            _ => PlaybackDirection::Forward,
+                        };
+                        let repeat = c.u16()?;
+                        c.skip(6 + 3 + 1)?;
+                        let name = c.string()?;
+                        tags.push(Tag {
+                            name,
+                            from,
+                            to,
+                            direction,
+                            repeat,
+                            user_data: None,
+                        });
+                    }
+                    user_data_target = UserDataTarget::Tag(first_tag);
+                }
+                CHUNK_PALETTE => {
+                    let size = c.u32()? as usize;
+                    let first = c.u32()? as usize;
+                    let last = c.u32()? as usize;
+                    c.skip(8)?;
+                    palette.resize(size.max(palette.len()), [0, 0, 0, 255]);
+                    for idx in first..=last {
+                        let flags = c.u16()?;
+                        let rgba = [c.u8()?, c.u8()?, c.u8()?, c.u8()?];
+                        if flags & 1 != 0 {
+                            c.string()?;
+                        }
+                        if let Some(entry) = palette.get_mut(idx) {
+                            *entry = rgba;
+                        }
+                    }
+                }
+                CHUNK_USER_DATA => {
+                    let flags = c.u32()?;
+                    let text = if flags & 1 != 0 {
+                        Some(c.string()?)
+                    } else {
+                        None
+                    };
+
+                    match user_data_target {
+                        UserDataTarget::Cel(idx) => {
+                            cels[idx].user_data = text;
+                            user_data_target = UserDataTarget::None;
+                        }
+                        //
+                        // the tags chunk is followed by one user data chunk per tag
+                        UserDataTarget::Tag(idx) => {
+                            if let Some(tag) = tags.get_mut(idx) {
+                                tag.user_data = text;
+                            }
+                            user_data_target = if idx + 1 < tags.len() {
+                                UserDataTarget::Tag(idx + 1)
+                            } else {
+                                UserDataTarget::None
+                            };
+                        }
+                        UserDataTarget::None => {}
+                    }
+                }
+                _ => {}
+            }
+
+            r.pos = chunk_start + chunk_size;
+        }
+
+        cels_per_frame.push(cels);
+        r.pos = frame_start + frame_size;
+    }
+
+    //
+    // layers inside a hidden group are hidden too
+    let mut parents_visible = Vec::<bool>::new();
+    let layer_infos = layers
+        .iter()
+        .map(|layer| {
+            parents_visible.truncate(layer.child_level as usize);
+            let visible = layer.visible && parents_visible.iter().all(|&v| v);
+            parents_visible.push(layer.visible);
+            LayerInfo {
+                name: layer.name.clone(),
+                visible,
+            }
+        })
+        .collect::<Vec<_>>();
+
+    let (width, height) = (header.width as u32, header.height as u32);
+
+    let frames = cels_per_frame
+        .into_iter()
+        .zip(durations)
+        .map(|(mut cels, duration_ms)| {
+            cels.sort_by_key(|cel| cel.layer);
+
+            let mut image = image::RgbaImage::new(width, height);
+            let mut user_data = Vec::new();
+            let mut layer_images = Vec::new();
+
+            for cel in cels.iter() {
+                let Some(layer) = layers
+                    .get(cel.layer)
+                    .filter(|_| layer_infos[cel.layer].visible)
+                else {
+                    continue;
+                };
+
+                blend(&mut image, cel, layer.opacity);
+                user_data.extend(cel.user_data.clone().filter(|s| !s.is_empty()));
+
+                if layer_mode == LayerMode::Split {
+                    let mut layer_image = image::RgbaImage::new(width, height);
+                    blend(&mut layer_image, cel, layer.opacity);
+                    layer_images.push(LayerImage {
+                        layer: cel.layer,
+                        image: layer_image,
+                        user_data: cel.user_data.clone().filter(|s| !s.is_empty()),
+                    });
+                }
+            }
+
+            Frame {
+                image,
+                user_data,
+                duration_ms,
+                layers: layer_images,
+            }
+        })
+        .collect();
+
+    Ok(AsepriteFile {
+        frames,
+        tags,
+        layers: layer_infos,
+    })
+}
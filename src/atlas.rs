@@ -0,0 +1,814 @@
+pub use crate::model::{NamedTextureRegion, TextureAtlas};
+
+/// Flips are applied first, then the 90 degree clockwise rotation.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+#[non_exhaustive]
+pub struct SpriteTransform {
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub flip_x: bool,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub flip_y: bool,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub rotate90: bool,
+}
+
+impl SpriteTransform {
+    pub fn new(flip_x: bool, flip_y: bool, rotate90: bool) -> Self {
+        SpriteTransform {
+            flip_x,
+            flip_y,
+            rotate90,
+        }
+    }
+
+    pub fn is_identity(&self) -> bool {
+        *self == SpriteTransform::default()
+    }
+}
+
+/// Where a tile sits in the source image it was cut from.
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+#[non_exhaustive]
+pub struct SplitPart {
+    /// Key of the source image.
+    pub parent: String,
+    pub x: u32,
+    pub y: u32,
+    pub parent_width: u32,
+    pub parent_height: u32,
+}
+
+impl SplitPart {
+    /// The tile at `(x, y)` of the `parent_size` image keyed `parent`.
+    pub fn new(parent: impl Into<String>, x: u32, y: u32, parent_size: (u32, u32)) -> Self {
+        SplitPart {
+            parent: parent.into(),
+            x,
+            y,
+            parent_width: parent_size.0,
+            parent_height: parent_size.1,
+        }
+    }
+}
+
+/// License and credits of packed artwork, from the sidecars.
+#[derive(Clone, Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+#[non_exhaustive]
+pub struct Attribution {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub author: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub license: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    /// Text that has to be reproduced as is.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub notice: Option<String>,
+}
+
+/// Frames sharing an attribution.
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+#[non_exhaustive]
+pub struct AttributionGroup {
+    pub attribution: Attribution,
+    pub frames: Vec<String>,
+}
+
+impl AttributionGroup {
+    pub fn new(attribution: Attribution, frames: Vec<String>) -> Self {
+        AttributionGroup {
+            attribution,
+            frames,
+        }
+    }
+}
+
+/// Motion vectors of a flipbook atlas, laid out exactly like the color texture.
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+#[non_exhaustive]
+pub struct MotionVectorTexture {
+    pub file: std::path::PathBuf,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub pages: Vec<std::path::PathBuf>,
+}
+
+impl MotionVectorTexture {
+    /// Motion vectors in `file`, with one file per page in `pages` when there are more.
+    pub fn new(file: impl Into<std::path::PathBuf>, pages: Vec<std::path::PathBuf>) -> Self {
+        MotionVectorTexture {
+            file: file.into(),
+            pages,
+        }
+    }
+}
+
+/// Exposure and gamma correction applied to a sprite's color when it was loaded, in
+/// linear light.
+#[derive(Clone, Copy, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+#[non_exhaustive]
+pub struct ToneAdjustment {
+    /// In stops, the color is scaled by `2^exposure`.
+    #[serde(default)]
+    pub exposure: f32,
+    /// The exposed color is raised to `1 / gamma`.
+    #[serde(default = "default_gamma")]
+    pub gamma: f32,
+}
+
+impl ToneAdjustment {
+    pub fn new(exposure: f32, gamma: f32) -> Self {
+        ToneAdjustment { exposure, gamma }
+    }
+}
+
+fn default_gamma() -> f32 {
+    1.0
+}
+
+/// Where a frame came from, with `--with-provenance`. The file itself and its blake3 are
+/// the `source` and `source_hash` of the frame.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[non_exhaustive]
+pub struct Provenance {
+    /// Size of the image or animation frame in the file, before it was trimmed, scaled
+    /// or cut into tiles.
+    pub source_width: u32,
+    pub source_height: u32,
+    /// Modification time of the file, in seconds since the Unix epoch.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub modified: Option<u64>,
+}
+
+impl Provenance {
+    /// A frame `source_size` big in a file last modified at `modified`.
+    pub fn new(source_size: (u32, u32), modified: Option<u64>) -> Self {
+        Provenance {
+            source_width: source_size.0,
+            source_height: source_size.1,
+            modified,
+        }
+    }
+}
+
+/// Where the packed pixels sit in the image before its transparent borders were cropped.
+#[derive(Clone, Copy, Debug, serde::Serialize, serde::Deserialize)]
+#[non_exhaustive]
+pub struct Trim {
+    pub x: u32,
+    pub y: u32,
+    pub source_width: u32,
+    pub source_height: u32,
+}
+
+impl Trim {
+    /// The pixels at `(x, y)` of a `source_size` image.
+    pub fn new(x: u32, y: u32, source_size: (u32, u32)) -> Self {
+        Trim {
+            x,
+            y,
+            source_width: source_size.0,
+            source_height: source_size.1,
+        }
+    }
+}
+
+/// Outline of the visible pixels of a frame, to draw it as a mesh leaving out most of
+/// its transparent area. Vertices are in pixels from the top left of the region.
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+#[non_exhaustive]
+pub struct Polygon {
+    pub vertices: Vec<(f32, f32)>,
+    /// Three indices into `vertices` per triangle.
+    pub triangles: Vec<u32>,
+}
+
+impl Polygon {
+    pub fn new(vertices: Vec<(f32, f32)>, triangles: Vec<u32>) -> Self {
+        Polygon {
+            vertices,
+            triangles,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum FilterHint {
+    /// Hard edged pixel art.
+    Nearest,
+    Linear,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum AlphaUsage {
+    /// Every pixel is opaque, can be drawn without blending.
+    Opaque,
+    /// Only fully transparent or opaque pixels, alpha testing is enough.
+    Binary,
+    Blended,
+}
+
+/// Placement and compositing ops of an uncomposited animation frame.
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+#[non_exhaustive]
+pub struct FrameDelta {
+    pub x: u32,
+    pub y: u32,
+    pub canvas_width: u32,
+    pub canvas_height: u32,
+    /// What happens to the frame's area before the next frame is drawn.
+    pub dispose: FrameDispose,
+    pub blend: FrameBlend,
+}
+
+impl FrameDelta {
+    /// A frame drawn at `(x, y)` of a `canvas_size` canvas.
+    pub fn new(
+        x: u32,
+        y: u32,
+        canvas_size: (u32, u32),
+        dispose: FrameDispose,
+        blend: FrameBlend,
+    ) -> Self {
+        FrameDelta {
+            x,
+            y,
+            canvas_width: canvas_size.0,
+            canvas_height: canvas_size.1,
+            dispose,
+            blend,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum FrameDispose {
+    /// Leave the frame on the canvas.
+    None,
+    /// Clear the frame's area to transparent.
+    Background,
+    /// Restore the canvas to what it was before the frame.
+    Previous,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum FrameBlend {
+    /// Alpha blend onto the canvas.
+    Over,
+    /// Replace the canvas pixels.
+    Source,
+}
+
+/// Nine-slice border insets in pixels, measured from the region edges.
+#[derive(Copy, Clone, Debug, serde::Serialize, serde::Deserialize)]
+#[non_exhaustive]
+pub struct NineSlice {
+    pub left: u32,
+    pub right: u32,
+    pub top: u32,
+    pub bottom: u32,
+}
+
+impl NineSlice {
+    pub fn new(left: u32, right: u32, top: u32, bottom: u32) -> Self {
+        NineSlice {
+            left,
+            right,
+            top,
+            bottom,
+        }
+    }
+}
+
+/// Texel format of the atlas texture.
+#[derive(
+    Copy,
+    Clone,
+    Debug,
+    Default,
+    PartialEq,
+    Eq,
+    serde::Serialize,
+    serde::Deserialize,
+    clap::ValueEnum,
+)]
+pub enum PixelFormat {
+    /// 8-bit luminance and alpha, the only format the compressed encodes take.
+    #[default]
+    La8,
+    /// 16-bit UNORM RGBA, e.g. from 16-bit PNGs.
+    Rgba16,
+    /// Half float RGBA, for lightmaps and HDR sprites.
+    Rgba16f,
+    Rgba32f,
+}
+
+impl PixelFormat {
+    pub fn is_la8(&self) -> bool {
+        *self == PixelFormat::La8
+    }
+}
+
+/// What the texels of an `la8` page hold. With `--mixed-formats` the pages of opaque
+/// sprites and of masks leave out the channel they do not need.
+#[derive(
+    Copy,
+    Clone,
+    Debug,
+    Default,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    serde::Serialize,
+    serde::Deserialize,
+)]
+pub enum PageChannels {
+    #[default]
+    LuminanceAlpha,
+    /// Luminance only, every texel is opaque.
+    Luminance,
+    /// Alpha only, in the first channel. The luminance is white, or the alpha itself
+    /// when premultiplied.
+    Alpha,
+}
+
+impl PageChannels {
+    /// The cheapest channels holding `pixels`: luminance when they are all opaque, alpha
+    /// when all the visible ones are white.
+    pub fn of(pixels: &image::ImageBuffer<image::LumaA<u8>, Vec<u8>>) -> PageChannels {
+        if pixels.pixels().all(|p| p.0[1] == 255) {
+            PageChannels::Luminance
+        } else if pixels.pixels().all(|p| p.0[1] == 0 || p.0[0] == 255) {
+            PageChannels::Alpha
+        } else {
+            PageChannels::LuminanceAlpha
+        }
+    }
+
+    /// The page with only the channels it holds, a grayscale image for the single
+    /// channel pages.
+    pub fn reduce(
+        self,
+        page: &image::ImageBuffer<image::LumaA<u8>, Vec<u8>>,
+    ) -> image::DynamicImage {
+        let channel = |c: usize| {
+            image::DynamicImage::ImageLuma8(image::ImageBuffer::from_fn(
+                page.width(),
+                page.height(),
+                |x, y| image::Luma([page.get_pixel(x, y).0[c]]),
+            ))
+        };
+        match self {
+            PageChannels::LuminanceAlpha => image::DynamicImage::ImageLumaA8(page.clone()),
+            PageChannels::Luminance => channel(0),
+            PageChannels::Alpha => channel(1),
+        }
+    }
+
+    /// A luminance/alpha page again from a page holding these channels, as decoded or
+    /// read from its image.
+    pub fn expand(
+        self,
+        page: &image::DynamicImage,
+        premultiplied: bool,
+    ) -> image::ImageBuffer<image::LumaA<u8>, Vec<u8>> {
+        if self == PageChannels::LuminanceAlpha {
+            return page.to_luma_alpha8();
+        }
+
+        let values = page.to_luma8();
+        image::ImageBuffer::from_fn(page.width(), page.height(), |x, y| {
+            let v = values.get_pixel(x, y).0[0];
+            match self {
+                PageChannels::Alpha if premultiplied => image::LumaA([v, v]),
+                PageChannels::Alpha => image::LumaA([255, v]),
+                _ => image::LumaA([v, 255]),
+            }
+        })
+    }
+}
+
+/// How far the floats of a descriptor are rounded before it is written, so small
+/// differences in the computation don't show up as changes. Parsed from `N` for `N`
+/// decimal places, or `fixed:N` for multiples of `1 / 2^N`, which are exact in binary.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum FloatPrecision {
+    Decimals(u32),
+    Fixed(u32),
+}
+
+impl FloatPrecision {
+    pub fn round(self, value: f32) -> f32 {
+        let scale = match self {
+            FloatPrecision::Decimals(digits) => 10f64.powi(digits as i32),
+            FloatPrecision::Fixed(bits) => (1u64 << bits) as f64,
+        };
+        ((value as f64 * scale).round() / scale) as f32
+    }
+}
+
+impl std::str::FromStr for FloatPrecision {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parse = |digits: &str, max: u32| {
+            digits
+                .parse::<u32>()
+                .ok()
+                .filter(|&n| n <= max)
+                .ok_or_else(|| format!("expected a count from 0 to {}, got '{}'", max, digits))
+        };
+        match s.strip_prefix("fixed:") {
+            Some(bits) => parse(bits, 24).map(FloatPrecision::Fixed),
+            None => parse(s, 9).map(FloatPrecision::Decimals),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum ColorSpace {
+    Srgb,
+    #[default]
+    Linear,
+}
+
+/// Interpretation of the two texture channels.
+#[derive(
+    Copy,
+    Clone,
+    Debug,
+    Default,
+    PartialEq,
+    Eq,
+    serde::Serialize,
+    serde::Deserialize,
+    clap::ValueEnum,
+)]
+pub enum Content {
+    /// Luminance and alpha.
+    #[default]
+    Color,
+    /// Tangent space normal XY in `[-1, 1]` mapped to `[0, 1]`, Z is reconstructed from
+    /// them. Compressed textures use the Basis normal map layout (RGB = X, A = Y),
+    /// which transcodes to BC5.
+    NormalMap,
+    /// Flipbook motion vectors, XY in `[-1, 1]` mapped to `[0, 1]` like normal maps.
+    MotionVectors,
+}
+
+impl Content {
+    pub fn is_color(&self) -> bool {
+        *self == Content::Color
+    }
+}
+
+/// A named sequence of frames, e.g. built from an Aseprite tag.
+#[derive(Clone, Default, serde::Serialize, serde::Deserialize)]
+#[non_exhaustive]
+pub struct Animation {
+    pub name: String,
+    /// Keys of the frames, in playback order.
+    pub frames: Vec<String>,
+    /// How long each frame is shown, parallel to `frames`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub durations_ms: Vec<u32>,
+    #[serde(default, skip_serializing_if = "PlaybackDirection::is_forward")]
+    pub direction: PlaybackDirection,
+    /// Number of times to play, 0 for looping forever.
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub repeat: u32,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub events: Vec<String>,
+}
+
+impl Animation {
+    /// Plays `frames` forward and loops, without durations or events.
+    pub fn new(name: impl Into<String>, frames: Vec<String>) -> Self {
+        Animation {
+            name: name.into(),
+            frames,
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum PlaybackDirection {
+    #[default]
+    Forward,
+    Reverse,
+    /// Forward, then back.
+    PingPong,
+    /// Backwards, then forward again.
+    PingPongReverse,
+}
+
+impl PlaybackDirection {
+    pub fn is_forward(&self) -> bool {
+        *self == PlaybackDirection::Forward
+    }
+}
+
+fn is_zero(v: &u32) -> bool {
+    *v == 0
+}
+
+/// How the glyphs of a font are stored.
+#[derive(
+    Copy,
+    Clone,
+    Debug,
+    Default,
+    PartialEq,
+    Eq,
+    serde::Serialize,
+    serde::Deserialize,
+    clap::ValueEnum,
+)]
+pub enum GlyphMode {
+    /// Coverage in alpha.
+    #[default]
+    Plain,
+    /// Signed distance field in alpha, 0.5 on the outline.
+    Sdf,
+}
+
+impl GlyphMode {
+    pub fn is_plain(&self) -> bool {
+        *self == GlyphMode::Plain
+    }
+}
+
+/// A font rasterized into the atlas. Metrics are in pixels at `size`, y pointing up
+/// from the baseline.
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+#[non_exhaustive]
+pub struct FontFace {
+    pub name: String,
+    pub size: f32,
+    pub ascent: f32,
+    /// Negative, below the baseline.
+    pub descent: f32,
+    pub line_height: f32,
+    #[serde(default, skip_serializing_if = "GlyphMode::is_plain")]
+    pub mode: GlyphMode,
+    /// Distance in pixels from the outline to either end of the field's range.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub spread: Option<u32>,
+    pub glyphs: Vec<Glyph>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub kerning: Vec<Kerning>,
+}
+
+impl FontFace {
+    /// A plain coverage font without glyphs or kerning pairs.
+    pub fn new(
+        name: impl Into<String>,
+        size: f32,
+        ascent: f32,
+        descent: f32,
+        line_height: f32,
+    ) -> Self {
+        FontFace {
+            name: name.into(),
+            size,
+            ascent,
+            descent,
+            line_height,
+            mode: GlyphMode::default(),
+            spread: None,
+            glyphs: Vec::new(),
+            kerning: Vec::new(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+#[non_exhaustive]
+pub struct Glyph {
+    pub codepoint: char,
+    /// Frame holding the glyph, absent for blank glyphs like the space.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key: Option<String>,
+    /// How far to move the pen after the glyph.
+    pub advance: f32,
+    /// Offset from the pen position to the left edge of the frame.
+    pub bearing_x: f32,
+    /// Height of the frame's top edge above the baseline.
+    pub bearing_y: f32,
+}
+
+impl Glyph {
+    /// A glyph drawn from frame `key` with its top left corner at `bearing` from the pen.
+    pub fn new(codepoint: char, key: Option<String>, advance: f32, bearing: (f32, f32)) -> Self {
+        Glyph {
+            codepoint,
+            key,
+            advance,
+            bearing_x: bearing.0,
+            bearing_y: bearing.1,
+        }
+    }
+}
+
+/// Extra advance between two glyphs, usually negative.
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+#[non_exhaustive]
+pub struct Kerning {
+    pub left: char,
+    pub right: char,
+    pub amount: f32,
+}
+
+impl Kerning {
+    pub fn new(left: char, right: char, amount: f32) -> Self {
+        Kerning {
+            left,
+            right,
+            amount,
+        }
+    }
+}
+
+/// Version of the descriptor format written by the tool, described by
+/// [`descriptor_schema`](crate::schema::descriptor_schema):
+///
+/// - 0: no version, frames may lack UVs
+/// - 1: `version` and frame UVs
+/// - 2: the version is `schema_version`
+pub const SCHEMA_VERSION: u32 = 2;
+
+/// KTX2 key/value entry holding the embedded descriptor, as RON.
+pub const KTX2_KEY: &str = "texture-packer.atlas";
+
+impl TextureAtlas {
+    /// Reads a descriptor file, or the descriptor embedded in a `.ktx2` texture.
+    pub fn load(path: &std::path::Path) -> Result<TextureAtlas, String> {
+        if path
+            .extension()
+            .is_some_and(|e| e.eq_ignore_ascii_case("ktx2"))
+        {
+            return Self::from_ktx2(&crate::ktx2::Ktx2::open(path)?)
+                .map_err(|e| format!("{}: {}", path.display(), e));
+        }
+
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        Self::parse(&contents).map_err(|e| format!("Failed to parse {}: {}", path.display(), e))
+    }
+
+    /// Reads a descriptor of any version up to [`SCHEMA_VERSION`], bringing older ones up
+    /// to date: version 0 frames without UVs get them from their pixel rect, and those
+    /// without a key the hex of their name hash. [`Self::frame`] still finds those by
+    /// the name they were packed under.
+    pub fn parse(text: &str) -> Result<TextureAtlas, String> {
+        let mut atlas: TextureAtlas = ron::from_str(text).map_err(|e| e.to_string())?;
+        if atlas.version > SCHEMA_VERSION {
+            return Err(format!(
+                "schema version {} is newer than this version of the tool reads ({})",
+                atlas.version, SCHEMA_VERSION
+            ));
+        }
+
+        if atlas.version == 0 {
+            for frame in atlas.frames.iter_mut() {
+                if frame.key.is_empty() {
+                    frame.key = hashed_key(frame.name);
+                }
+                if frame.uv_rect() == (0.0, 0.0, 0.0, 0.0) {
+                    (frame.u0, frame.v0, frame.u1, frame.v1) =
+                        frame.uv_rect_for(atlas.size, atlas.uv_inset);
+                }
+            }
+        }
+        Ok(atlas)
+    }
+
+    /// The descriptor embedded in a texture. Its paths are relative to the texture.
+    pub fn from_ktx2(ktx: &crate::ktx2::Ktx2) -> Result<TextureAtlas, String> {
+        let value = ktx
+            .key_value(KTX2_KEY)
+            .ok_or_else(|| "no embedded atlas descriptor".to_string())?;
+        let text = std::str::from_utf8(value.strip_suffix(&[0]).unwrap_or(value))
+            .map_err(|e| format!("invalid embedded descriptor: {}", e))?;
+        Self::parse(text).map_err(|e| format!("Failed to parse embedded descriptor: {}", e))
+    }
+
+    /// The base level of every page, from the page images or textures when the
+    /// descriptor lists them and decoded from the texture otherwise. `dir` is the
+    /// descriptor directory. `la8` atlases come back as luminance/alpha, the others as
+    /// RGBA.
+    pub fn load_pages(&self, dir: &std::path::Path) -> Result<Vec<image::DynamicImage>, String> {
+        if self.pages.is_empty() {
+            return self.decode_layers(&dir.join(&self.file));
+        }
+
+        let mut pages = Vec::new();
+        for page in self.pages.iter() {
+            match page.extension().is_some_and(|e| e == "ktx2") {
+                true => pages.extend(self.decode_layers(&dir.join(page))?),
+                false => pages.push(
+                    image::open(dir.join(page))
+                        .map_err(|e| format!("Failed to open page {}: {}", page.display(), e))?,
+                ),
+            }
+        }
+
+        //
+        // single channel pages get the channel they left out back
+        for (page, channels) in pages.iter_mut().zip(self.page_channels.iter()) {
+            if *channels != PageChannels::LuminanceAlpha {
+                *page = image::DynamicImage::ImageLumaA8(channels.expand(page, self.premultiplied));
+            }
+        }
+        Ok(pages)
+    }
+
+    /// The base level of every layer (or slice) of a KTX2 at `path`.
+    fn decode_layers(&self, path: &std::path::Path) -> Result<Vec<image::DynamicImage>, String> {
+        let ktx = crate::ktx2::Ktx2::open(path)?;
+        //
+        // the slices of a 3D texture follow each other like layers
+        (0..ktx.layers.max(ktx.depth))
+            .map(|layer| {
+                let rgba = ktx
+                    .decode(0, layer)
+                    .map_err(|e| format!("Failed to decode {}: {}", path.display(), e))?;
+                Ok(match self.pixel_format {
+                    PixelFormat::La8 => image::DynamicImage::ImageLumaA8(
+                        image::ImageBuffer::from_fn(rgba.width(), rgba.height(), |x, y| {
+                            let p = rgba.get_pixel(x, y).0;
+                            image::LumaA([p[0], p[3]])
+                        }),
+                    ),
+                    _ => image::DynamicImage::ImageRgba8(rgba),
+                })
+            })
+            .collect()
+    }
+}
+
+/// Key given to version 0 frames that have none, from their name hash.
+pub(crate) fn hashed_key(name: u64) -> String {
+    format!("{:016x}", name)
+}
+
+pub fn region_name_hash(name: &str) -> u64 {
+    use std::hash::Hasher;
+    let mut h = fnv::FnvHasher::default();
+    h.write(name.as_bytes());
+    h.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn version_0_frames_get_keys_and_uvs() {
+        let text = format!(
+            "(frames: [(name: {}, layer: 0, x: 16, y: 0, width: 16, height: 32)], \
+             size: (64, 32), file: \"atlas.png\")",
+            region_name_hash("hero")
+        );
+        let atlas = TextureAtlas::parse(&text).unwrap();
+
+        assert_eq!(atlas.version, 0);
+        let frame = atlas.frame("hero").unwrap();
+        assert_eq!(frame.key, hashed_key(region_name_hash("hero")));
+        assert_eq!(frame.uv_rect(), (0.25, 0.0, 0.5, 1.0));
+        assert!(atlas.frame("villain").is_none());
+    }
+
+    #[test]
+    fn version_1_and_2_are_read_as_written() {
+        let frame = "(name: 1, key: \"hero\", layer: 0, x: 0, y: 0, width: 8, height: 8)";
+        for (field, version) in [("version", 1), ("schema_version", 2)] {
+            let text = format!(
+                "({}: {}, frames: [{}], size: (64, 64), file: \"atlas.png\")",
+                field, version, frame
+            );
+            let atlas = TextureAtlas::parse(&text).unwrap();
+
+            assert_eq!(atlas.version, version);
+            let frame = atlas.frame("hero").unwrap();
+            assert_eq!(frame.name, 1);
+            //
+            // frames of versioned descriptors keep the UVs they were written with
+            assert_eq!(frame.uv_rect(), (0.0, 0.0, 0.0, 0.0));
+        }
+    }
+
+    #[test]
+    fn newer_versions_are_refused() {
+        let text = format!(
+            "(schema_version: {}, frames: [], size: (64, 64), file: \"atlas.png\")",
+            SCHEMA_VERSION + 1
+        );
+        assert!(TextureAtlas::parse(&text).is_err());
+    }
+}
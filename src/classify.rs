@@ -0,0 +1,237 @@
+//! Content classification, picking per sprite settings from what the pixels look like.
+//!
+//! Every classifier looks at one property of a sprite and fills in a part of its
+//! [`Decision`]. Settings given in the sidecar's `classify` always win over what the
+//! classifiers decided.
+
+use crate::atlas::{AlphaUsage, FilterHint, Trim};
+use crate::source::SourceSprite;
+
+/// Sidecar overrides, e.g. `(classify: (trim: false, filter: Linear))`.
+#[derive(Clone, Debug, Default, serde::Deserialize)]
+#[serde(default)]
+pub struct ClassifyOverrides {
+    pub trim: Option<bool>,
+    pub filter: Option<FilterHint>,
+    pub alpha: Option<AlphaUsage>,
+}
+
+/// Settings chosen for a sprite and why, written to the classification report.
+#[derive(Clone, Debug, Default, serde::Serialize)]
+pub struct Decision {
+    pub source: String,
+    pub key: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub alpha: Option<AlphaUsage>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub filter: Option<FilterHint>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub trim: Option<Trim>,
+    /// Size of the trimmed pixels.
+    #[serde(skip)]
+    pub trimmed_size: (u32, u32),
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub grayscale: bool,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub notes: Vec<String>,
+}
+
+pub trait Classifier {
+    fn classify(&self, sprite: &SourceSprite, decision: &mut Decision);
+}
+
+/// Alpha usage, and the opaque bounds to trim to.
+pub struct AlphaClassifier {
+    pub trim: bool,
+}
+
+impl Classifier for AlphaClassifier {
+    fn classify(&self, sprite: &SourceSprite, decision: &mut Decision) {
+        let (width, height) = sprite.pixels.dimensions();
+        let alphas = sprite.pixels.pixels().map(|p| p.0[1]);
+
+        decision.alpha = Some(if alphas.clone().all(|a| a == 255) {
+            AlphaUsage::Opaque
+        } else if alphas.clone().all(|a| a == 0 || a == 255) {
+            AlphaUsage::Binary
+        } else {
+            AlphaUsage::Blended
+        });
+
+        //
+        // insets and frame deltas are measured in the untrimmed pixels
+        if !self.trim || sprite.nine_slice.is_some() || sprite.delta.is_some() {
+            return;
+        }
+
+        let (mut x0, mut y0, mut x1, mut y1) = (width, height, 0, 0);
+        for (x, y, p) in sprite.pixels.enumerate_pixels() {
+            if p.0[1] != 0 {
+                (x0, y0, x1, y1) = (x0.min(x), y0.min(y), x1.max(x + 1), y1.max(y + 1));
+            }
+        }
+
+        //
+        // keep a single texel of fully transparent sprites
+        if x0 >= x1 {
+            (x0, y0, x1, y1) = (0, 0, 1.min(width), 1.min(height));
+        }
+
+        if (x0, y0, x1, y1) != (0, 0, width, height) {
+            decision.trim = Some(Trim::new(x0, y0, (width, height)));
+            decision.trimmed_size = (x1 - x0, y1 - y0);
+            decision.notes.push(format!(
+                "transparent border, trimmed to {}x{} at ({}, {})",
+                x1 - x0,
+                y1 - y0,
+                x0,
+                y0
+            ));
+        }
+    }
+}
+
+/// Sources with color, which is lost in the luminance pages.
+pub struct GrayscaleClassifier;
+
+impl Classifier for GrayscaleClassifier {
+    fn classify(&self, sprite: &SourceSprite, decision: &mut Decision) {
+        decision.grayscale = !sprite.colored;
+        if sprite.colored {
+            decision
+                .notes
+                .push("colored source, stored as luminance".to_string());
+        }
+    }
+}
+
+/// Pixel art has few distinct values and hard edges, where smooth artwork has
+/// anti-aliased edges and gradients made of small steps.
+pub struct PixelArtClassifier;
+
+impl PixelArtClassifier {
+    const MAX_VALUES: usize = 64;
+    const SOFT_STEP: u8 = 32;
+    const MAX_SOFT_RATIO: f32 = 0.15;
+}
+
+impl Classifier for PixelArtClassifier {
+    fn classify(&self, sprite: &SourceSprite, decision: &mut Decision) {
+        let pixels = &sprite.pixels;
+        let (width, height) = pixels.dimensions();
+        //
+        // luma of fully transparent pixels does not matter
+        let value = |x: u32, y: u32| match pixels.get_pixel(x, y).0 {
+            [_, 0] => [0, 0],
+            p => p,
+        };
+
+        let mut seen = vec![false; 1 << 16];
+        let mut distinct = 0usize;
+        let (mut edges, mut soft) = (0u32, 0u32);
+
+        for y in 0..height {
+            for x in 0..width {
+                let v = value(x, y);
+                let slot = &mut seen[(v[0] as usize) << 8 | v[1] as usize];
+                distinct += !*slot as usize;
+                *slot = true;
+
+                for (nx, ny) in [(x + 1, y), (x, y + 1)] {
+                    if nx >= width || ny >= height {
+                        continue;
+                    }
+                    let n = value(nx, ny);
+                    let step = v[0].abs_diff(n[0]).max(v[1].abs_diff(n[1]));
+                    if step > 0 {
+                        edges += 1;
+                        soft += (step < Self::SOFT_STEP) as u32;
+                    }
+                }
+            }
+        }
+
+        if edges == 0 {
+            return;
+        }
+
+        let soft_ratio = soft as f32 / edges as f32;
+        let pixel_art = distinct <= Self::MAX_VALUES && soft_ratio <= Self::MAX_SOFT_RATIO;
+        decision.filter = Some(if pixel_art {
+            FilterHint::Nearest
+        } else {
+            FilterHint::Linear
+        });
+        decision.notes.push(format!(
+            "{} values, {:.0}% soft edges{}",
+            distinct,
+            soft_ratio * 100.0,
+            if pixel_art { ", pixel art" } else { "" }
+        ));
+    }
+}
+
+/// The classifiers run by `--classify`, in order.
+pub fn classifiers(trim: bool) -> Vec<Box<dyn Classifier>> {
+    vec![
+        Box::new(AlphaClassifier { trim }),
+        Box::new(GrayscaleClassifier),
+        Box::new(PixelArtClassifier),
+    ]
+}
+
+pub fn decide(
+    sprite: &SourceSprite,
+    classifiers: &[Box<dyn Classifier>],
+    overrides: Option<&ClassifyOverrides>,
+) -> Decision {
+    let mut decision = Decision {
+        source: sprite.label(),
+        key: sprite.key.clone(),
+        ..Default::default()
+    };
+
+    for classifier in classifiers {
+        classifier.classify(sprite, &mut decision);
+    }
+
+    let Some(overrides) = overrides else {
+        return decision;
+    };
+
+    if overrides.trim == Some(false) && decision.trim.take().is_some() {
+        decision.notes.push("trim disabled by sidecar".to_string());
+    }
+    if let Some(filter) = overrides.filter {
+        decision.filter = Some(filter);
+        decision
+            .notes
+            .push(format!("filter {:?} from sidecar", filter));
+    }
+    if let Some(alpha) = overrides.alpha {
+        decision.alpha = Some(alpha);
+        decision
+            .notes
+            .push(format!("alpha {:?} from sidecar", alpha));
+    }
+
+    decision
+}
+
+/// Applies a decision: crops the sprite to its trim rect, keeping the pivot on the same
+/// source pixel, and records the hints.
+pub fn apply(sprite: &mut SourceSprite, decision: &Decision) {
+    sprite.filter = decision.filter;
+    sprite.alpha = decision.alpha;
+
+    let Some(trim) = decision.trim else {
+        return;
+    };
+
+    let (w, h) = decision.trimmed_size;
+
+    *sprite = sprite.crop(trim.x, trim.y, w, h);
+    sprite.pivot.0 = (sprite.pivot.0 * trim.source_width as f32 - trim.x as f32) / w as f32;
+    sprite.pivot.1 = (sprite.pivot.1 * trim.source_height as f32 - trim.y as f32) / h as f32;
+    sprite.trim = Some(trim);
+}
@@ -0,0 +1,96 @@
+//! Pixel level color operations.
+
+use crate::atlas::ColorSpace;
+
+pub fn srgb_to_linear(c: f32) -> f32 {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+pub fn linear_to_srgb(c: f32) -> f32 {
+    if c <= 0.0031308 {
+        c * 12.92
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// Decodes an 8 bit channel to linear light.
+pub fn decode(c: u8, color_space: ColorSpace) -> f32 {
+    match color_space {
+        ColorSpace::Srgb => srgb_to_linear(c as f32 / 255.0),
+        ColorSpace::Linear => c as f32 / 255.0,
+    }
+}
+
+pub fn encode(c: f32, color_space: ColorSpace) -> u8 {
+    let c = match color_space {
+        ColorSpace::Srgb => linear_to_srgb(c),
+        ColorSpace::Linear => c,
+    };
+    (c * 255.0).round().clamp(0.0, 255.0) as u8
+}
+
+/// Multiplies the luminance by alpha, in linear light.
+pub fn premultiply(px: image::LumaA<u8>, color_space: ColorSpace) -> image::LumaA<u8> {
+    let [l, a] = px.0;
+    image::LumaA([
+        encode(decode(l, color_space) * a as f32 / 255.0, color_space),
+        a,
+    ])
+}
+
+/// Divides the luminance by alpha, in linear light, undoing [`premultiply`].
+pub fn unpremultiply(px: image::LumaA<u8>, color_space: ColorSpace) -> image::LumaA<u8> {
+    let [l, a] = px.0;
+    match a {
+        0 => image::LumaA([0, 0]),
+        _ => image::LumaA([
+            encode(decode(l, color_space) * 255.0 / a as f32, color_space),
+            a,
+        ]),
+    }
+}
+
+/// A background color to be made transparent, `RRGGBB` on the command line.
+#[derive(Copy, Clone, Debug)]
+pub struct ColorKey {
+    pub rgb: [u8; 3],
+    /// Maximum difference per channel still treated as the key color.
+    pub tolerance: u8,
+}
+
+impl std::str::FromStr for ColorKey {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let hex = s.trim_start_matches('#');
+        let channel = |i: usize| {
+            hex.get(i * 2..i * 2 + 2)
+                .and_then(|c| u8::from_str_radix(c, 16).ok())
+        };
+
+        match (hex.len(), channel(0), channel(1), channel(2)) {
+            (6, Some(r), Some(g), Some(b)) => Ok(ColorKey {
+                rgb: [r, g, b],
+                tolerance: 0,
+            }),
+            _ => Err(format!("invalid color key '{}', expected RRGGBB", s)),
+        }
+    }
+}
+
+impl ColorKey {
+    /// Clears the alpha of every pixel matching the key.
+    pub fn apply(&self, img: &mut image::RgbaImage) {
+        for px in img.pixels_mut() {
+            let matches = (0..3).all(|c| px.0[c].abs_diff(self.rgb[c]) <= self.tolerance);
+            if matches {
+                px.0 = [0, 0, 0, 0];
+            }
+        }
+    }
+}
@@ -0,0 +1,170 @@
+//! Cubemaps assembled from six face images, through the same encode as the atlas pages.
+
+use crate::atlas::{Content, PageChannels, PixelFormat};
+use crate::encode::{self, ColorSpaceOption, Encode, EncoderOptions, PageFormat, TextureShape};
+use crate::mips;
+use crate::pack;
+use crate::publish::Publish;
+use crate::scratch::ScratchDir;
+
+#[derive(clap::Args, Debug)]
+pub struct CubemapOptions {
+    /// Face looking towards +X.
+    #[arg(long)]
+    px: std::path::PathBuf,
+    /// Face looking towards -X.
+    #[arg(long)]
+    nx: std::path::PathBuf,
+    /// Face looking towards +Y.
+    #[arg(long)]
+    py: std::path::PathBuf,
+    /// Face looking towards -Y.
+    #[arg(long)]
+    ny: std::path::PathBuf,
+    /// Face looking towards +Z.
+    #[arg(long)]
+    pz: std::path::PathBuf,
+    /// Face looking towards -Z.
+    #[arg(long)]
+    nz: std::path::PathBuf,
+    /// The KTX2 file to write.
+    #[arg(short, long)]
+    output: std::path::PathBuf,
+    #[arg(long, value_enum, default_value_t = Encode::Raw)]
+    encode: Encode,
+    /// Texel format of the cubemap, as for `pack`. Only `la8` takes the compressed
+    /// encodes, the others keep the full RGBA.
+    #[arg(long, value_enum, default_value_t = PixelFormat::La8)]
+    pixel_format: PixelFormat,
+    #[arg(long, value_enum, default_value_t = ColorSpaceOption::Srgb)]
+    color_space: ColorSpaceOption,
+    /// Generate a mip chain for every face.
+    #[arg(long)]
+    mipmaps: bool,
+    /// Where to put temporary files, defaults to the system temp directory.
+    #[arg(long)]
+    scratch_dir: Option<std::path::PathBuf>,
+}
+
+pub fn run(options: CubemapOptions) -> Result<(), String> {
+    if !options.encode.is_ktx2() {
+        return Err(format!(
+            "The {:?} encode writes no KTX2 to make a cubemap of",
+            options.encode
+        ));
+    }
+    if !options.pixel_format.is_la8() && options.encode != Encode::Raw {
+        return Err(format!(
+            "The {:?} encode only takes the la8 pixel format, use raw",
+            options.encode
+        ));
+    }
+
+    let paths = [
+        &options.px,
+        &options.nx,
+        &options.py,
+        &options.ny,
+        &options.pz,
+        &options.nz,
+    ];
+    let faces = paths
+        .iter()
+        .map(|path| {
+            image::open(path).map_err(|e| format!("Failed to open {}: {}", path.display(), e))
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+
+    let size = faces[0].width();
+    if let Some((path, face)) = paths
+        .iter()
+        .zip(faces.iter())
+        .find(|(_, face)| face.width() != size || face.height() != size)
+    {
+        return Err(format!(
+            "The faces of a cubemap are squares of the same size, {} is {}x{} and {} is {}x{}",
+            path.display(),
+            face.width(),
+            face.height(),
+            options.px.display(),
+            size,
+            faces[0].height()
+        ));
+    }
+
+    let scratch_dir = ScratchDir::create(options.scratch_dir.as_deref(), false)?;
+    let name = options
+        .output
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or("cubemap");
+
+    let texture = match options.pixel_format {
+        PixelFormat::La8 => {
+            let format = PageFormat {
+                content: Content::Color,
+                color_space: options.color_space.resolve(Content::Color),
+                premultiplied: false,
+                channels: PageChannels::LuminanceAlpha,
+            };
+            let levels = pack::save_levels(
+                &faces.iter().map(|f| f.to_luma_alpha8()).collect::<Vec<_>>(),
+                format,
+                &[],
+                options.mipmaps,
+                scratch_dir.path(),
+                "face",
+                0,
+            )?;
+            encode::encode_pages(
+                &levels,
+                options.encode,
+                format,
+                TextureShape::Cube,
+                &EncoderOptions::default(),
+                scratch_dir.path(),
+                name,
+            )?
+        }
+        _ => {
+            let face_levels = faces
+                .iter()
+                .map(|face| {
+                    let face = face.to_rgba32f();
+                    let mips = match options.mipmaps {
+                        true => mips::mip_chain_hdr(&face, false),
+                        false => Vec::new(),
+                    };
+                    std::iter::once(face).chain(mips).collect::<Vec<_>>()
+                })
+                .collect::<Vec<_>>();
+            let levels = (0..face_levels[0].len())
+                .map(|level| face_levels.iter().map(|l| l[level].clone()).collect())
+                .collect::<Vec<Vec<_>>>();
+            encode::encode_hdr_pages(
+                &levels,
+                options.encode,
+                TextureShape::Cube,
+                options.pixel_format,
+                false,
+                scratch_dir.path(),
+                name,
+            )?
+        }
+    };
+
+    let path = scratch_dir.path().join(&texture.file);
+    let data =
+        std::fs::read(&path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    let mut publish = Publish::default();
+    publish.write(&options.output, &data)?;
+    publish.commit()?;
+
+    log::info!(
+        "Wrote a {}x{} cubemap to {}",
+        size,
+        size,
+        options.output.display()
+    );
+    Ok(())
+}
@@ -0,0 +1,84 @@
+//! Sprites with the same pixels, packed once and shared by their frames.
+//!
+//! Exact duplicates are always safe to merge. For lossy encodes sprites that only
+//! differ by less than the encoder's own error can be merged too, compared by their
+//! PSNR over luminance and alpha.
+
+use crate::source::SourceSprite;
+
+/// A sprite that reuses the pixels of another one.
+#[derive(Clone, Debug, serde::Serialize)]
+pub struct Merge {
+    pub key: String,
+    pub kept: String,
+    /// PSNR in dB between the two, absent for exact duplicates.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub psnr: Option<f64>,
+}
+
+/// Whether `a` and `b` are within `min_psnr` dB of each other, with the PSNR if they
+/// are. Stops comparing as soon as the error gets too large.
+fn similar(a: &[u8], b: &[u8], min_psnr: f64) -> Option<f64> {
+    let max_error = a.len() as f64 * 255.0 * 255.0 / 10f64.powf(min_psnr / 10.0);
+
+    let mut error = 0.0;
+    for chunk in a.chunks(4096).zip(b.chunks(4096)) {
+        error += chunk
+            .0
+            .iter()
+            .zip(chunk.1)
+            .map(|(&x, &y)| (x as f64 - y as f64).powi(2))
+            .sum::<f64>();
+        if error > max_error {
+            return None;
+        }
+    }
+
+    let mse = error / a.len() as f64;
+    Some(10.0 * (255.0 * 255.0 / mse).log10())
+}
+
+/// Sprites whose motion vectors or full precision planes differ have to stay apart.
+fn same_planes(a: &SourceSprite, b: &SourceSprite) -> bool {
+    a.motion == b.motion && a.hdr == b.hdr
+}
+
+/// Finds the sprites that can share another sprite's pixels. Returns for every sprite
+/// the index of the sprite it reuses, if any, and the merges made. With `min_psnr`
+/// sprites at least that close are merged as well.
+pub fn find(sprites: &[SourceSprite], min_psnr: Option<f64>) -> (Vec<Option<usize>>, Vec<Merge>) {
+    let mut kept_by_size = fnv::FnvHashMap::<(u32, u32), Vec<usize>>::default();
+    let mut shared = vec![None; sprites.len()];
+    let mut merges = Vec::new();
+
+    for (idx, sprite) in sprites.iter().enumerate() {
+        let kept = kept_by_size.entry(sprite.pixels.dimensions()).or_default();
+
+        let found = kept.iter().find_map(|&k| {
+            let other = &sprites[k];
+            if !same_planes(sprite, other) {
+                return None;
+            }
+            if sprite.pixels == other.pixels {
+                return Some((k, None));
+            }
+            min_psnr
+                .and_then(|min| similar(&sprite.pixels, &other.pixels, min))
+                .map(|psnr| (k, Some(psnr)))
+        });
+
+        match found {
+            Some((k, psnr)) => {
+                shared[idx] = Some(k);
+                merges.push(Merge {
+                    key: sprite.key.clone(),
+                    kept: sprites[k].key.clone(),
+                    psnr,
+                });
+            }
+            None => kept.push(idx),
+        }
+    }
+
+    (shared, merges)
+}
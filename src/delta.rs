@@ -0,0 +1,826 @@
+//! Patches between two builds of the same atlas outputs, for shipping small sprite
+//! fixes without the whole textures.
+//!
+//! A delta holds, per file of the output directory:
+//!
+//! - the changed rects of uncompressed KTX2 textures, per array layer and mip level,
+//!   narrowed down to the frames that changed
+//! - the frames added, changed or removed in descriptors
+//! - everything else that changed (compressed textures, PNG pages, reports) as a whole
+//!
+//! Texture and descriptor patches record a hash of the file they were made against,
+//! and [`apply`] refuses to patch anything else.
+//!
+//! On disk a delta is the magic `TPDELTA1` followed by a gzip stream holding the
+//! length of the RON manifest, the manifest and the patch data it points into.
+
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+use crate::atlas::{NamedTextureRegion, TextureAtlas};
+use crate::ktx2::Ktx2;
+use crate::staging::staged_path;
+
+const MAGIC: &[u8; 8] = b"TPDELTA1";
+
+/// A range of the delta's patch data.
+#[derive(Copy, Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct Blob {
+    pub offset: u64,
+    pub length: u64,
+}
+
+/// Texels of a rect on one layer and mip level, rows stored tightly.
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct TexelPatch {
+    pub level: u32,
+    pub layer: u32,
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+    pub data: Blob,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+pub enum Change {
+    /// A new or changed file, stored whole.
+    File {
+        path: PathBuf,
+        data: Blob,
+    },
+    Removed {
+        path: PathBuf,
+    },
+    /// Texels replaced in an uncompressed KTX2.
+    Texture {
+        path: PathBuf,
+        base: u64,
+        patches: Vec<TexelPatch>,
+    },
+    /// Frames replaced, added or removed in a descriptor. `atlas` holds the rest of
+    /// the descriptor, without frames, when that changed too, and `order` the keys of
+    /// the patched descriptor's frames in the order they are written in.
+    Descriptor {
+        path: PathBuf,
+        base: u64,
+        atlas: Option<Box<TextureAtlas>>,
+        frames: Vec<NamedTextureRegion>,
+        removed: Vec<String>,
+        #[serde(default)]
+        order: Vec<String>,
+    },
+}
+
+#[derive(Default)]
+pub struct AtlasDelta {
+    pub changes: Vec<Change>,
+    payload: Vec<u8>,
+}
+
+impl AtlasDelta {
+    pub fn blob(&self, blob: Blob) -> Result<&[u8], String> {
+        blob.offset
+            .checked_add(blob.length)
+            .and_then(|end| self.payload.get(blob.offset as usize..end as usize))
+            .ok_or_else(|| "patch data out of range".to_string())
+    }
+
+    fn store(&mut self, data: &[u8]) -> Blob {
+        let blob = Blob {
+            offset: self.payload.len() as u64,
+            length: data.len() as u64,
+        };
+        self.payload.extend_from_slice(data);
+        blob
+    }
+
+    /// Size of the patch data, before compression.
+    pub fn payload_size(&self) -> usize {
+        self.payload.len()
+    }
+
+    /// Writes the delta to a file beside `path` and renames it into place, a failed
+    /// write never leaves a truncated delta to apply.
+    pub fn write(&self, path: &Path) -> Result<(), String> {
+        let manifest = ron::to_string(&self.changes)
+            .map_err(|e| format!("Failed to serialize delta: {}", e))?;
+
+        let temp = staged_path(path);
+
+        let mut out = std::fs::File::create(&temp)
+            .map_err(|e| format!("Failed to create {}: {}", temp.display(), e))?;
+        let written = out.write_all(MAGIC).and_then(|_| {
+            let mut gz = flate2::write::GzEncoder::new(out, flate2::Compression::best());
+            gz.write_all(&(manifest.len() as u32).to_le_bytes())?;
+            gz.write_all(manifest.as_bytes())?;
+            gz.write_all(&self.payload)?;
+            gz.finish()?.sync_all()
+        });
+        if let Err(e) = written.and_then(|_| std::fs::rename(&temp, path)) {
+            let _ = std::fs::remove_file(&temp);
+            return Err(format!("Failed to write {}: {}", path.display(), e));
+        }
+        Ok(())
+    }
+
+    pub fn read(path: &Path) -> Result<AtlasDelta, String> {
+        let data =
+            std::fs::read(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        if !data.starts_with(MAGIC) {
+            return Err(format!("{} is not an atlas delta", path.display()));
+        }
+
+        let mut contents = Vec::new();
+        flate2::read::GzDecoder::new(&data[MAGIC.len()..])
+            .read_to_end(&mut contents)
+            .map_err(|e| format!("Failed to decompress {}: {}", path.display(), e))?;
+
+        let manifest_len = contents
+            .get(..4)
+            .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as usize)
+            .filter(|len| 4 + len <= contents.len())
+            .ok_or_else(|| format!("{} is truncated", path.display()))?;
+        let changes: Vec<Change> = ron::from_str(
+            std::str::from_utf8(&contents[4..4 + manifest_len]).map_err(|e| e.to_string())?,
+        )
+        .map_err(|e| format!("Failed to parse {}: {}", path.display(), e))?;
+
+        Ok(AtlasDelta {
+            changes,
+            payload: contents.split_off(4 + manifest_len),
+        })
+    }
+}
+
+fn hash(data: &[u8]) -> u64 {
+    use std::hash::Hasher;
+    let mut h = fnv::FnvHasher::default();
+    h.write(data);
+    h.finish()
+}
+
+/// Every file below `root`, relative to it.
+fn list_files(root: &Path) -> Result<Vec<PathBuf>, String> {
+    let mut files = Vec::new();
+    let mut pending = vec![PathBuf::new()];
+
+    while let Some(dir) = pending.pop() {
+        let entries = std::fs::read_dir(root.join(&dir))
+            .map_err(|e| format!("Failed to read {}: {}", root.join(&dir).display(), e))?;
+        for entry in entries.filter_map(|e| e.ok()) {
+            let path = dir.join(entry.file_name());
+            match entry.file_type().map(|t| t.is_dir()) {
+                Ok(true) => pending.push(path),
+                _ => files.push(path),
+            }
+        }
+    }
+
+    files.sort();
+    Ok(files)
+}
+
+/// Resolves `..` and `.` without touching the file system.
+fn normalize(path: &Path) -> PathBuf {
+    let mut out = PathBuf::new();
+    for component in path.components() {
+        match component {
+            std::path::Component::ParentDir => {
+                out.pop();
+            }
+            std::path::Component::CurDir => {}
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+fn frames_text(frame: &NamedTextureRegion) -> String {
+    ron::to_string(frame).unwrap_or_default()
+}
+
+/// Descriptor without its frames, for comparing the rest.
+fn header_text(atlas: &mut TextureAtlas) -> String {
+    let frames = std::mem::take(&mut atlas.frames);
+    let text = ron::to_string(atlas).unwrap_or_default();
+    atlas.frames = frames;
+    text
+}
+
+/// Byte ranges of every layer of every level, for the uncompressed layouts patches work
+/// on. `None` for anything else.
+fn texel_layout(ktx: &Ktx2) -> Option<Vec<Vec<std::ops::Range<usize>>>> {
+    let bpp = ktx.texel_size()?;
+    if ktx.supercompression != 0 || ktx.depth > 1 || ktx.faces != 1 {
+        return None;
+    }
+
+    ktx.levels
+        .iter()
+        .enumerate()
+        .map(|(idx, level)| {
+            let (w, h) = ((ktx.width >> idx).max(1), (ktx.height >> idx).max(1));
+            let layer_size = w as usize * h as usize * bpp;
+            if level.length as usize != layer_size * ktx.layers as usize {
+                return None;
+            }
+            Some(
+                (0..ktx.layers as usize)
+                    .map(|layer| {
+                        let start = level.offset as usize + layer * layer_size;
+                        start..start + layer_size
+                    })
+                    .collect(),
+            )
+        })
+        .collect()
+}
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+struct Rect {
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+}
+
+fn copy_rect(src: &[u8], stride: usize, bpp: usize, rect: Rect, out: &mut Vec<u8>) {
+    for row in rect.y..rect.y + rect.height {
+        let start = row as usize * stride + rect.x as usize * bpp;
+        out.extend_from_slice(&src[start..start + rect.width as usize * bpp]);
+    }
+}
+
+fn paste_rect(dst: &mut [u8], stride: usize, bpp: usize, rect: Rect, data: &[u8]) {
+    let row_len = rect.width as usize * bpp;
+    for (idx, row) in (rect.y..rect.y + rect.height).enumerate() {
+        let start = row as usize * stride + rect.x as usize * bpp;
+        dst[start..start + row_len].copy_from_slice(&data[idx * row_len..(idx + 1) * row_len]);
+    }
+}
+
+fn rect_differs(a: &[u8], b: &[u8], stride: usize, bpp: usize, rect: Rect) -> bool {
+    (rect.y..rect.y + rect.height).any(|row| {
+        let start = row as usize * stride + rect.x as usize * bpp;
+        let end = start + rect.width as usize * bpp;
+        a[start..end] != b[start..end]
+    })
+}
+
+/// Bounds of the texels that differ.
+fn diff_bounds(a: &[u8], b: &[u8], (w, h): (u32, u32), bpp: usize) -> Option<Rect> {
+    let stride = w as usize * bpp;
+    let (mut x0, mut y0, mut x1, mut y1) = (w, h, 0, 0);
+    for y in 0..h {
+        let row = y as usize * stride..(y as usize + 1) * stride;
+        for (x, (ta, tb)) in a[row.clone()]
+            .chunks_exact(bpp)
+            .zip(b[row].chunks_exact(bpp))
+            .enumerate()
+        {
+            if ta != tb {
+                let x = x as u32;
+                (x0, y0, x1, y1) = (x0.min(x), y0.min(y), x1.max(x + 1), y1.max(y + 1));
+            }
+        }
+    }
+
+    (x0 < x1).then(|| Rect {
+        x: x0,
+        y: y0,
+        width: x1 - x0,
+        height: y1 - y0,
+    })
+}
+
+/// Texel patches turning `old` into `new`, or `None` when the textures differ in more
+/// than their texels (size, format, metadata) and have to be replaced as a whole.
+fn texture_patches(
+    delta: &mut AtlasDelta,
+    old: &[u8],
+    new: &[u8],
+    frames: &[(u32, Rect)],
+) -> Option<Vec<TexelPatch>> {
+    let (old_ktx, new_ktx) = (
+        Ktx2::parse(old.to_vec()).ok()?,
+        Ktx2::parse(new.to_vec()).ok()?,
+    );
+    let layout = texel_layout(&new_ktx)?;
+    if old.len() != new.len() || texel_layout(&old_ktx)? != layout {
+        return None;
+    }
+
+    //
+    // header, format description and key/value data have to match
+    let data_start = layout.iter().flatten().map(|r| r.start).min()?;
+    if old[..data_start] != new[..data_start] {
+        return None;
+    }
+
+    let bpp = new_ktx.texel_size()?;
+    let mut patches = Vec::new();
+
+    for (level, layers) in layout.iter().enumerate() {
+        let size = (
+            (new_ktx.width >> level).max(1),
+            (new_ktx.height >> level).max(1),
+        );
+        let stride = size.0 as usize * bpp;
+
+        for (layer, range) in layers.iter().enumerate() {
+            let (old_texels, new_texels) = (&old[range.clone()], &new[range.clone()]);
+            if old_texels == new_texels {
+                continue;
+            }
+
+            //
+            // the frames on the layer, in the level's texels including the texels their
+            // mips were filtered into
+            let shift = level as u32;
+            let mut rects = frames
+                .iter()
+                .filter(|(l, _)| *l == layer as u32)
+                .map(|(_, r)| {
+                    let (x0, y0) = (r.x >> shift, r.y >> shift);
+                    let x1 = ((r.x + r.width + (1 << shift) - 1) >> shift).min(size.0);
+                    let y1 = ((r.y + r.height + (1 << shift) - 1) >> shift).min(size.1);
+                    Rect {
+                        x: x0,
+                        y: y0,
+                        width: x1.saturating_sub(x0),
+                        height: y1.saturating_sub(y0),
+                    }
+                })
+                .filter(|r| r.width > 0 && r.height > 0)
+                .filter(|&r| rect_differs(old_texels, new_texels, stride, bpp, r))
+                .collect::<Vec<_>>();
+            rects.dedup();
+
+            //
+            // anything not covered by a frame (removed sprites) goes in one more rect
+            let mut patched = old_texels.to_vec();
+            for &rect in rects.iter() {
+                let mut data = Vec::new();
+                copy_rect(new_texels, stride, bpp, rect, &mut data);
+                paste_rect(&mut patched, stride, bpp, rect, &data);
+            }
+            if let Some(rest) = diff_bounds(&patched, new_texels, size, bpp) {
+                rects.push(rest);
+            }
+
+            //
+            // past half the layer it is cheaper to send the layer
+            let area = rects
+                .iter()
+                .map(|r| r.width as u64 * r.height as u64)
+                .sum::<u64>();
+            if area * 2 > size.0 as u64 * size.1 as u64 {
+                rects = vec![Rect {
+                    x: 0,
+                    y: 0,
+                    width: size.0,
+                    height: size.1,
+                }];
+            }
+
+            for rect in rects {
+                let mut data = Vec::new();
+                copy_rect(new_texels, stride, bpp, rect, &mut data);
+                patches.push(TexelPatch {
+                    level: level as u32,
+                    layer: layer as u32,
+                    x: rect.x,
+                    y: rect.y,
+                    width: rect.width,
+                    height: rect.height,
+                    data: delta.store(&data),
+                });
+            }
+        }
+    }
+
+    Some(patches)
+}
+
+/// Computes the delta turning the output directory `old` into `new`.
+pub fn diff(old: &Path, new: &Path) -> Result<AtlasDelta, String> {
+    let (old_files, new_files) = (list_files(old)?, list_files(new)?);
+    let mut delta = AtlasDelta::default();
+
+    //
+    // frames of the new descriptors, by the texture they are on, to narrow down the
+    // texel patches
+    let mut descriptors = std::collections::HashMap::<PathBuf, TextureAtlas>::new();
+    let mut texture_frames = std::collections::HashMap::<PathBuf, Vec<(u32, Rect)>>::new();
+    for path in new_files
+        .iter()
+        .filter(|p| p.extension() == Some("ron".as_ref()))
+    {
+        let Ok(atlas) = std::fs::read_to_string(new.join(path))
+            .map_err(|e| e.to_string())
+            .and_then(|text| TextureAtlas::parse(&text))
+        else {
+            continue;
+        };
+
+        let dir = path.parent().unwrap_or(Path::new(""));
+        let rects = atlas
+            .frames
+            .iter()
+            .map(|f| {
+                let rect = Rect {
+                    x: f.x,
+                    y: f.y,
+                    width: f.width,
+                    height: f.height,
+                };
+                (f.layer, rect)
+            })
+            .collect::<Vec<_>>();
+        for texture in
+            std::iter::once(&atlas.file).chain(atlas.motion_vectors.as_ref().map(|m| &m.file))
+        {
+            texture_frames
+                .entry(normalize(&dir.join(texture)))
+                .or_default()
+                .extend(rects.iter().copied());
+        }
+        descriptors.insert(path.clone(), atlas);
+    }
+
+    for path in old_files.iter().filter(|p| !new_files.contains(p)) {
+        delta.changes.push(Change::Removed { path: path.clone() });
+    }
+
+    for path in new_files.iter() {
+        let new_data = std::fs::read(new.join(path))
+            .map_err(|e| format!("Failed to read {}: {}", new.join(path).display(), e))?;
+        let old_data = match old_files.contains(path) {
+            true => Some(
+                std::fs::read(old.join(path))
+                    .map_err(|e| format!("Failed to read {}: {}", old.join(path).display(), e))?,
+            ),
+            false => None,
+        };
+
+        let Some(old_data) = old_data else {
+            let data = delta.store(&new_data);
+            delta.changes.push(Change::File {
+                path: path.clone(),
+                data,
+            });
+            continue;
+        };
+
+        if old_data == new_data {
+            continue;
+        }
+
+        let old_atlas = std::str::from_utf8(&old_data)
+            .map_err(|e| e.to_string())
+            .and_then(TextureAtlas::parse);
+        if let (Some(new_atlas), Ok(mut old_atlas)) = (descriptors.get_mut(path), old_atlas) {
+            let old_frames = old_atlas
+                .frames
+                .iter()
+                .map(|f| (f.key.as_str(), frames_text(f)))
+                .collect::<std::collections::HashMap<_, _>>();
+            let frames = new_atlas
+                .frames
+                .iter()
+                .filter(|f| old_frames.get(f.key.as_str()) != Some(&frames_text(f)))
+                .cloned()
+                .collect();
+            let removed = old_atlas
+                .frames
+                .iter()
+                .filter(|f| !new_atlas.frames.iter().any(|n| n.key == f.key))
+                .map(|f| f.key.clone())
+                .collect();
+            let order = new_atlas.frames.iter().map(|f| f.key.clone()).collect();
+
+            let new_header = header_text(new_atlas);
+            let atlas = match header_text(&mut old_atlas) == new_header {
+                true => None,
+                false => {
+                    let mut header: TextureAtlas =
+                        ron::from_str(&new_header).map_err(|e| e.to_string())?;
+                    header.frames.clear();
+                    Some(Box::new(header))
+                }
+            };
+
+            delta.changes.push(Change::Descriptor {
+                path: path.clone(),
+                base: hash(&old_data),
+                atlas,
+                frames,
+                removed,
+                order,
+            });
+            continue;
+        }
+
+        let frames = texture_frames.get(path).map(Vec::as_slice).unwrap_or(&[]);
+        match texture_patches(&mut delta, &old_data, &new_data, frames) {
+            Some(patches) => delta.changes.push(Change::Texture {
+                path: path.clone(),
+                base: hash(&old_data),
+                patches,
+            }),
+            None => {
+                let data = delta.store(&new_data);
+                delta.changes.push(Change::File {
+                    path: path.clone(),
+                    data,
+                });
+            }
+        }
+    }
+
+    Ok(delta)
+}
+
+fn check_base(path: &Path, data: &[u8], base: u64) -> Result<(), String> {
+    match hash(data) == base {
+        true => Ok(()),
+        false => Err(format!(
+            "{} differs from the version the delta was made for",
+            path.display()
+        )),
+    }
+}
+
+/// Paths in a delta have to stay inside the directory it is applied to.
+fn check_path(path: &Path) -> Result<(), String> {
+    use std::path::Component;
+    let mut components = path.components().peekable();
+    match components.peek().is_some()
+        && components.all(|c| matches!(c, Component::Normal(_) | Component::CurDir))
+    {
+        true => Ok(()),
+        false => Err(format!(
+            "{} is outside of the directory the delta applies to",
+            path.display()
+        )),
+    }
+}
+
+/// Applies a delta to the output directory `dir`. Every patched file is checked
+/// against the version the delta was made for before anything is written.
+pub fn apply(delta: &AtlasDelta, dir: &Path) -> Result<(), String> {
+    for change in delta.changes.iter() {
+        check_path(match change {
+            Change::File { path, .. }
+            | Change::Removed { path }
+            | Change::Texture { path, .. }
+            | Change::Descriptor { path, .. } => path,
+        })?;
+    }
+
+    let read = |path: &Path| {
+        std::fs::read(dir.join(path))
+            .map_err(|e| format!("Failed to read {}: {}", dir.join(path).display(), e))
+    };
+
+    let mut writes = Vec::<(PathBuf, Vec<u8>)>::new();
+    let mut removals = Vec::new();
+
+    for change in delta.changes.iter() {
+        match change {
+            Change::File { path, data } => writes.push((path.clone(), delta.blob(*data)?.to_vec())),
+            Change::Removed { path } => removals.push(path.clone()),
+            Change::Texture {
+                path,
+                base,
+                patches,
+            } => {
+                let mut data = read(path)?;
+                check_base(path, &data, *base)?;
+
+                let ktx = Ktx2::parse(data.clone())?;
+                let (layout, bpp) = texel_layout(&ktx)
+                    .zip(ktx.texel_size())
+                    .ok_or_else(|| format!("{} can't be patched", path.display()))?;
+
+                for patch in patches {
+                    let range = layout
+                        .get(patch.level as usize)
+                        .and_then(|layers| layers.get(patch.layer as usize))
+                        .ok_or_else(|| format!("patch outside of {}", path.display()))?;
+                    let level_width = (ktx.width >> patch.level).max(1);
+                    let level_height = (ktx.height >> patch.level).max(1);
+                    let texels = delta.blob(patch.data)?;
+                    let inside = |start: u32, len: u32, end: u32| {
+                        start.checked_add(len).is_some_and(|e| e <= end)
+                    };
+                    if !inside(patch.x, patch.width, level_width)
+                        || !inside(patch.y, patch.height, level_height)
+                        || texels.len() != patch.width as usize * patch.height as usize * bpp
+                    {
+                        return Err(format!("patch outside of {}", path.display()));
+                    }
+
+                    paste_rect(
+                        &mut data[range.clone()],
+                        level_width as usize * bpp,
+                        bpp,
+                        Rect {
+                            x: patch.x,
+                            y: patch.y,
+                            width: patch.width,
+                            height: patch.height,
+                        },
+                        texels,
+                    );
+                }
+                writes.push((path.clone(), data));
+            }
+            Change::Descriptor {
+                path,
+                base,
+                atlas,
+                frames,
+                removed,
+                order,
+            } => {
+                let data = read(path)?;
+                check_base(path, &data, *base)?;
+
+                let mut current = std::str::from_utf8(&data)
+                    .map_err(|e| e.to_string())
+                    .and_then(TextureAtlas::parse)
+                    .map_err(|e| format!("Failed to parse {}: {}", path.display(), e))?;
+                if let Some(atlas) = atlas {
+                    let frames = std::mem::take(&mut current.frames);
+                    current = ron::from_str(&ron::to_string(atlas).map_err(|e| e.to_string())?)
+                        .map_err(|e| e.to_string())?;
+                    current.frames = frames;
+                }
+
+                current.frames.retain(|f| !removed.contains(&f.key));
+                for frame in frames {
+                    match current.frames.iter_mut().find(|f| f.key == frame.key) {
+                        Some(existing) => *existing = frame.clone(),
+                        None => current.frames.push(frame.clone()),
+                    }
+                }
+                if !order.is_empty() {
+                    let position = order
+                        .iter()
+                        .enumerate()
+                        .map(|(idx, key)| (key.as_str(), idx))
+                        .collect::<std::collections::HashMap<_, _>>();
+                    current.frames.sort_by_key(|f| {
+                        position.get(f.key.as_str()).copied().unwrap_or(usize::MAX)
+                    });
+                }
+
+                let text = ron::ser::to_string_pretty(&current, ron::ser::PrettyConfig::new())
+                    .map_err(|e| format!("Failed to serialize {}: {}", path.display(), e))?;
+                writes.push((path.clone(), text.into_bytes()));
+            }
+        }
+    }
+
+    //
+    // every file is written next to its destination first and only moved into place
+    // once all of them were, a failure part way leaves the atlas as it was
+    let mut staged = Vec::with_capacity(writes.len());
+    let discard = |staged: &[(PathBuf, PathBuf)]| {
+        for (temp, _) in staged {
+            let _ = std::fs::remove_file(temp);
+        }
+    };
+    for (path, data) in writes {
+        let path = dir.join(path);
+        let temp = staged_path(&path);
+
+        let written = path
+            .parent()
+            .map_or(Ok(()), std::fs::create_dir_all)
+            .and_then(|_| std::fs::File::create(&temp))
+            .and_then(|mut file| {
+                file.write_all(&data)?;
+                file.sync_all()
+            });
+        if let Err(e) = written {
+            let _ = std::fs::remove_file(&temp);
+            discard(&staged);
+            return Err(format!("Failed to write {}: {}", temp.display(), e));
+        }
+        staged.push((temp, path));
+    }
+    for (idx, (temp, path)) in staged.iter().enumerate() {
+        if let Err(e) = std::fs::rename(temp, path) {
+            discard(&staged[idx..]);
+            return Err(format!(
+                "Failed to move {} into place: {}",
+                path.display(),
+                e
+            ));
+        }
+    }
+    for path in removals {
+        let path = dir.join(path);
+        std::fs::remove_file(&path)
+            .map_err(|e| format!("Failed to remove {}: {}", path.display(), e))?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Key and pixel rect of a frame on the first page.
+    type Frame<'a> = (&'a str, (u32, u32, u32, u32));
+
+    fn write_atlas(dir: &Path, frames: &[Frame]) {
+        let mut atlas = TextureAtlas::new("atlas.png", (64, 64));
+        atlas.frames = frames
+            .iter()
+            .map(|&(key, rect)| NamedTextureRegion::new(key, 0, rect).with_uvs((64, 64), 0.0))
+            .collect();
+        std::fs::create_dir_all(dir).unwrap();
+        std::fs::write(
+            dir.join("atlas.ron"),
+            ron::ser::to_string_pretty(&atlas, ron::ser::PrettyConfig::new()).unwrap(),
+        )
+        .unwrap();
+    }
+
+    fn contents(dir: &Path) -> Vec<(PathBuf, Vec<u8>)> {
+        list_files(dir)
+            .unwrap()
+            .into_iter()
+            .map(|path| {
+                let data = std::fs::read(dir.join(&path)).unwrap();
+                (path, data)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn applied_delta_reproduces_the_new_build() {
+        let root = std::env::temp_dir().join(format!("tp-delta-{}", std::process::id()));
+        let (old, new, patched) = (root.join("old"), root.join("new"), root.join("patched"));
+        let _ = std::fs::remove_dir_all(&root);
+
+        write_atlas(
+            &old,
+            &[
+                ("kept", (0, 0, 8, 8)),
+                ("changed", (8, 0, 8, 8)),
+                ("removed", (16, 0, 8, 8)),
+            ],
+        );
+        std::fs::write(old.join("atlas.png"), b"old pixels").unwrap();
+        write_atlas(
+            &new,
+            &[
+                ("added", (32, 0, 4, 4)),
+                ("kept", (0, 0, 8, 8)),
+                ("changed", (8, 8, 16, 8)),
+            ],
+        );
+        std::fs::write(new.join("atlas.png"), b"new pixels").unwrap();
+
+        let delta = diff(&old, &new).unwrap();
+        let file = root.join("patch.tpdelta");
+        delta.write(&file).unwrap();
+
+        std::fs::create_dir_all(&patched).unwrap();
+        for (path, data) in contents(&old) {
+            std::fs::write(patched.join(path), data).unwrap();
+        }
+        apply(&AtlasDelta::read(&file).unwrap(), &patched).unwrap();
+
+        assert!(contents(&patched) == contents(&new));
+        let _ = std::fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn paths_outside_the_directory_are_refused() {
+        let dir = std::env::temp_dir().join(format!("tp-delta-paths-{}", std::process::id()));
+        for path in ["../escaped", "/tmp/escaped", "a/../../escaped", ""] {
+            let delta = AtlasDelta {
+                changes: vec![Change::Removed { path: path.into() }],
+                ..Default::default()
+            };
+            assert!(apply(&delta, &dir).is_err(), "{}", path);
+        }
+    }
+
+    #[test]
+    fn patch_data_ranges_are_checked() {
+        let mut delta = AtlasDelta::default();
+        let blob = delta.store(b"texels");
+        assert_eq!(delta.blob(blob).unwrap(), b"texels");
+        for (offset, length) in [(0, 7), (u64::MAX, 2), (1, u64::MAX)] {
+            assert!(delta.blob(Blob { offset, length }).is_err());
+        }
+    }
+}
@@ -0,0 +1,190 @@
+//! Stable codes for the warnings and errors of a build, so pipeline tooling can filter,
+//! suppress or escalate classes of problems without matching on the messages.
+//!
+//! Codes are grouped by hundreds: `TP00xx` inputs, `TP01xx` names, `TP02xx` layout and
+//! `TP03xx` outputs. A published code keeps its meaning and is never reused.
+
+use std::sync::OnceLock;
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum MessageFormat {
+    /// `warning[TP0001]: ...` lines, warnings on stdout and errors on stderr.
+    #[default]
+    Human,
+    /// One JSON object per warning or error on stderr.
+    Json,
+}
+
+static FORMAT: OnceLock<MessageFormat> = OnceLock::new();
+
+/// Sets how warnings and errors are printed, for the rest of the process.
+pub fn set_format(format: MessageFormat) {
+    let _ = FORMAT.set(format);
+}
+
+fn format() -> MessageFormat {
+    FORMAT.get().copied().unwrap_or_default()
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Code {
+    /// An input file that could not be opened or decoded.
+    UnreadableInput,
+    /// A `.meta.ron` sidecar that could not be parsed.
+    InvalidMetadata,
+    /// Motion vectors that do not match their color frame.
+    MotionVectorsIgnored,
+    /// A sheet that could not be sliced as its metadata describes.
+    SheetSliceFailed,
+    /// Charset characters the font has no glyphs for.
+    MissingGlyphs,
+    /// A nine-patch image without room for its marker border.
+    NinePatchTooSmall,
+    /// A colored sprite turned into a distance field.
+    ColorsLost,
+    /// Sources that changed since the atlas was packed.
+    SourceDrift,
+    /// An input name that breaks on other platforms.
+    NotPortable,
+    /// Sprites sharing a key, only one of them can be looked up by name.
+    NameClash,
+    /// Input names that only differ by case.
+    CaseClash,
+    /// Sprites that do not fit in the allowed number of pages.
+    OutOfPages,
+    /// A group too large for a single page.
+    GroupTooLarge,
+    /// A sprite larger than a page.
+    OversizedSprite,
+    /// Sprites that do not fit the grid.
+    GridLayoutFailed,
+    /// Regions overlapping, or reaching past their page.
+    InvalidLayout,
+    /// Encode targets that cannot come with the requested options.
+    UnsupportedTarget,
+    /// A scratch directory that could not be removed.
+    ScratchNotRemoved,
+    /// A frame an export format has no way to describe.
+    ExportUnsupported,
+    /// The external encoder failed, timed out or hit its memory cap.
+    EncoderFailed,
+    /// Output that came out different when packed again.
+    NotDeterministic,
+    /// An output already on disk that `--no-overwrite` keeps.
+    OutputExists,
+}
+
+impl Code {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Code::UnreadableInput => "TP0001",
+            Code::InvalidMetadata => "TP0002",
+            Code::MotionVectorsIgnored => "TP0003",
+            Code::SheetSliceFailed => "TP0004",
+            Code::MissingGlyphs => "TP0005",
+            Code::NinePatchTooSmall => "TP0006",
+            Code::ColorsLost => "TP0007",
+            Code::SourceDrift => "TP0008",
+            Code::NotPortable => "TP0101",
+            Code::NameClash => "TP0102",
+            Code::CaseClash => "TP0103",
+            Code::OutOfPages => "TP0201",
+            Code::GroupTooLarge => "TP0202",
+            Code::OversizedSprite => "TP0203",
+            Code::GridLayoutFailed => "TP0204",
+            Code::InvalidLayout => "TP0205",
+            Code::UnsupportedTarget => "TP0301",
+            Code::ScratchNotRemoved => "TP0302",
+            Code::ExportUnsupported => "TP0303",
+            Code::EncoderFailed => "TP0304",
+            Code::NotDeterministic => "TP0305",
+            Code::OutputExists => "TP0306",
+        }
+    }
+
+    /// An error message carrying this code, for the `Result<_, String>` the commands
+    /// return. [`report_error`] picks the code up again.
+    pub fn error(self, message: impl std::fmt::Display) -> String {
+        format!("error[{}]: {}", self, message)
+    }
+}
+
+impl std::fmt::Display for Code {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[derive(serde::Serialize)]
+struct Record<'a> {
+    level: &'a str,
+    code: Option<&'a str>,
+    message: &'a str,
+}
+
+fn print_json(level: &str, code: Option<&str>, message: &str) {
+    let record = Record {
+        level,
+        code,
+        message,
+    };
+    eprintln!("{}", serde_json::to_string(&record).unwrap());
+}
+
+/// Sends the log to stdout: errors only when `quiet`, progress by default, and with
+/// `verbose` 1 and 2 also per file steps and timings, then everything. `RUST_LOG`
+/// overrides the level per module.
+pub fn init_logging(quiet: bool, verbose: u8) {
+    use std::io::Write;
+
+    let level = match (quiet, verbose) {
+        (true, _) => log::LevelFilter::Error,
+        (false, 0) => log::LevelFilter::Info,
+        (false, 1) => log::LevelFilter::Debug,
+        (false, _) => log::LevelFilter::Trace,
+    };
+    env_logger::Builder::new()
+        .filter_level(level)
+        .parse_default_env()
+        .target(env_logger::Target::Stdout)
+        .format(|out, record| {
+            crate::progress::suspend();
+            match record.level() {
+                log::Level::Info => writeln!(out, "{}", record.args()),
+                level => writeln!(out, "{}: {}", level.as_str().to_lowercase(), record.args()),
+            }
+        })
+        .init();
+}
+
+/// Prints a warning, unless the log is quiet.
+pub fn warn(code: Code, message: impl std::fmt::Display) {
+    if !log::log_enabled!(log::Level::Warn) {
+        return;
+    }
+    match format() {
+        MessageFormat::Human => {
+            crate::progress::suspend();
+            println!("warning[{}]: {}", code, message)
+        }
+        MessageFormat::Json => print_json("warning", Some(code.as_str()), &message.to_string()),
+    }
+}
+
+/// Prints an error returned by a command. Errors without a code are reported with a
+/// `null` code in JSON.
+pub fn report_error(error: &str) {
+    match format() {
+        MessageFormat::Human => eprintln!("{}", error),
+        MessageFormat::Json => {
+            let coded = error
+                .strip_prefix("error[")
+                .and_then(|rest| rest.split_once("]: "))
+                .filter(|(code, _)| code.starts_with("TP"));
+            match coded {
+                Some((code, message)) => print_json("error", Some(code), message),
+                None => print_json("error", None, error),
+            }
+        }
+    }
+}
@@ -0,0 +1,125 @@
+//! Compares the sources an atlas was packed from with the files on disk, by the
+//! `source_hash` of its frames, to catch sources changed without a repack before the
+//! atlas ships.
+
+use crate::atlas::TextureAtlas;
+use crate::diagnostic::Code;
+use crate::flipbook;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+
+#[derive(clap::Args, Debug)]
+pub struct VerifySourcesOptions {
+    /// Atlas description file (.ron, or a KTX2 with the descriptor embedded).
+    atlas: PathBuf,
+    /// Folders to look for untracked sources in, by default the folders of the recorded
+    /// sources.
+    #[arg(short, long = "input")]
+    input_folders: Vec<PathBuf>,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum Drift {
+    /// The file is not what was packed.
+    Changed,
+    /// The file is gone.
+    Missing,
+    /// A file in the source folders that no frame was packed from.
+    Untracked,
+}
+
+/// The path without `..` and links, to compare paths reached from different folders.
+fn canonical(path: PathBuf) -> PathBuf {
+    std::fs::canonicalize(&path).unwrap_or(path)
+}
+
+pub fn run(options: VerifySourcesOptions) -> Result<(), String> {
+    let atlas = TextureAtlas::load(&options.atlas)?;
+    let atlas_dir = options.atlas.parent().unwrap_or(Path::new(""));
+
+    //
+    // every frame cut from a file carries its hash, the keys are for the report
+    let mut sources = BTreeMap::<PathBuf, (String, Vec<&str>)>::new();
+    for frame in atlas.frames.iter() {
+        if let (Some(source), Some(hash)) = (frame.source.as_ref(), frame.source_hash.as_ref()) {
+            sources
+                .entry(canonical(atlas_dir.join(source)))
+                .or_insert_with(|| (hash.clone(), Vec::new()))
+                .1
+                .push(&frame.key);
+        }
+    }
+
+    if sources.is_empty() {
+        return Err(format!(
+            "{} records no sources, it was packed before they were",
+            options.atlas.display()
+        ));
+    }
+
+    let mut drift = Vec::new();
+    for (path, (hash, keys)) in sources.iter() {
+        match std::fs::read(path) {
+            Ok(data) if blake3::hash(&data).to_hex().as_str() == hash => (),
+            Ok(_) => drift.push((Drift::Changed, path.clone(), keys.join(", "))),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                drift.push((Drift::Missing, path.clone(), keys.join(", ")))
+            }
+            Err(e) => return Err(format!("Failed to read {}: {}", path.display(), e)),
+        }
+    }
+
+    let folders = match options.input_folders.is_empty() {
+        true => sources
+            .keys()
+            .filter_map(|p| p.parent().map(|p| canonical(p.to_path_buf())))
+            .collect::<BTreeSet<_>>(),
+        false => options
+            .input_folders
+            .iter()
+            .cloned()
+            .map(canonical)
+            .collect(),
+    };
+    for folder in folders.iter() {
+        let entries = std::fs::read_dir(folder)
+            .map_err(|e| format!("Failed to read {}: {}", folder.display(), e))?;
+        for path in entries.filter_map(|de| de.ok().map(|d| canonical(d.path()))) {
+            //
+            // sidecars and motion vectors are packed along with their source
+            if !path.is_file()
+                || path.to_string_lossy().ends_with(".meta.ron")
+                || sources.contains_key(&path)
+                || flipbook::color_partner(&path).is_some_and(|c| sources.contains_key(&c))
+            {
+                continue;
+            }
+            drift.push((Drift::Untracked, path, String::new()));
+        }
+    }
+
+    drift.sort();
+    for (kind, path, keys) in drift.iter() {
+        match kind {
+            Drift::Changed => println!("changed   {} ({})", path.display(), keys),
+            Drift::Missing => println!("missing   {} ({})", path.display(), keys),
+            Drift::Untracked => println!("untracked {}", path.display()),
+        }
+    }
+
+    match drift.is_empty() {
+        true => {
+            println!(
+                "{} source(s) of {} are as packed",
+                sources.len(),
+                options.atlas.display()
+            );
+            Ok(())
+        }
+        false => Err(Code::SourceDrift.error(format_args!(
+            "{} source file(s) drifted since {} was packed",
+            drift.len(),
+            options.atlas.display()
+        ))),
+    }
+}
@@ -0,0 +1,641 @@
+//! Turns the packed page images into the final texture file(s).
+
+use crate::atlas::{ColorSpace, Content, PageChannels, PixelFormat};
+use crate::diagnostic::{self, Code};
+use crate::ktx2;
+use crate::parallel;
+use crate::progress;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum Encode {
+    /// Uncompressed KTX2 texture array.
+    Raw,
+    /// UASTC compressed KTX2.
+    Uastc,
+    /// UASTC compressed KTX2 with zstd supercompression.
+    UastcZstd,
+    /// ETC1S (BasisLZ) compressed KTX2.
+    Etc1s,
+    /// Plain PNG per page, no KTX2.
+    #[value(alias = "png-pages")]
+    Png,
+    /// Lossless WebP per page, no KTX2.
+    Webp,
+    /// QOI per page (expanded to RGBA), no KTX2.
+    Qoi,
+}
+
+impl Encode {
+    /// The extension of the page images written instead of a KTX2, for the encodes that
+    /// don't need toktx.
+    pub fn page_extension(self) -> Option<&'static str> {
+        match self {
+            Encode::Png => Some("png"),
+            Encode::Webp => Some("webp"),
+            Encode::Qoi => Some("qoi"),
+            _ => None,
+        }
+    }
+
+    /// Whether the encode writes a KTX2 (through toktx, unless raw).
+    pub fn is_ktx2(self) -> bool {
+        self.page_extension().is_none()
+    }
+
+    /// Whether the encode loses detail, so near identical pixels come out the same.
+    pub fn is_lossy(self) -> bool {
+        matches!(self, Encode::Uastc | Encode::UastcZstd | Encode::Etc1s)
+    }
+}
+
+impl std::str::FromStr for Encode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        <Encode as clap::ValueEnum>::from_str(s, true)
+            .map_err(|_| format!("unknown encode target '{}'", s))
+    }
+}
+
+/// How the pages are arranged in the texture files.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum TextureLayout {
+    /// One texture array, a layer per page.
+    Array,
+    /// A 2D texture per page, each with a descriptor of its own.
+    Pages,
+    /// All pages side by side on one large 2D texture.
+    Mega,
+    /// A 3D texture, a slice per page.
+    Volume,
+}
+
+impl TextureLayout {
+    /// The shape of the textures the pages go to.
+    pub fn shape(self) -> TextureShape {
+        match self {
+            TextureLayout::Array | TextureLayout::Mega => TextureShape::Array,
+            TextureLayout::Pages => TextureShape::Single,
+            TextureLayout::Volume => TextureShape::Volume,
+        }
+    }
+}
+
+/// What kind of texture the pages given to an encode make.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TextureShape {
+    /// An array with a layer per page, even when there is only one.
+    Array,
+    /// A 2D texture of the only page.
+    Single,
+    /// A 3D texture with a slice per page.
+    Volume,
+    /// A cubemap of six pages, in the order +X, -X, +Y, -Y, +Z, -Z.
+    Cube,
+}
+
+impl TextureShape {
+    fn ktx2(self, pages: usize) -> ktx2::Shape {
+        match self {
+            TextureShape::Array | TextureShape::Single => ktx2::Shape::Layers(pages as u32),
+            TextureShape::Volume => ktx2::Shape::Volume(pages as u32),
+            TextureShape::Cube => ktx2::Shape::Cube,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum ColorSpaceOption {
+    Srgb,
+    Linear,
+    /// Linear for normal maps, sRGB otherwise.
+    Auto,
+}
+
+impl ColorSpaceOption {
+    pub fn resolve(self, content: Content) -> ColorSpace {
+        match (self, content) {
+            (ColorSpaceOption::Srgb, _) => ColorSpace::Srgb,
+            (ColorSpaceOption::Linear, _)
+            | (ColorSpaceOption::Auto, Content::NormalMap | Content::MotionVectors) => {
+                ColorSpace::Linear
+            }
+            (ColorSpaceOption::Auto, Content::Color) => ColorSpace::Srgb,
+        }
+    }
+}
+
+/// How the page pixels are to be interpreted.
+#[derive(Copy, Clone, Debug)]
+pub struct PageFormat {
+    pub content: Content,
+    pub color_space: ColorSpace,
+    pub premultiplied: bool,
+    pub channels: PageChannels,
+}
+
+/// Texture files written by an encode, relative to the output directory.
+pub struct EncodedTexture {
+    pub file: std::path::PathBuf,
+    pub pages: Vec<std::path::PathBuf>,
+}
+
+/// How toktx is run.
+#[derive(Clone, Debug, Default)]
+pub struct EncoderOptions {
+    /// The toktx binary, `toktx` from the `PATH` when not given.
+    pub toktx: Option<std::path::PathBuf>,
+    /// Passed to toktx after the arguments of the encode, taking precedence over them.
+    pub toktx_args: Vec<String>,
+    /// Runs taking longer are killed.
+    pub timeout: Option<std::time::Duration>,
+    /// Cap of the encoder's address space in MiB, Unix only.
+    pub memory_mib: Option<u64>,
+    /// Probe every page after a failure, instead of stopping at the first that fails.
+    pub keep_going: bool,
+    /// Threads encoding pages one by one, one per core for 0.
+    pub jobs: usize,
+}
+
+impl EncoderOptions {
+    pub fn toktx(&self) -> &std::path::Path {
+        self.toktx
+            .as_deref()
+            .unwrap_or(std::path::Path::new("toktx"))
+    }
+}
+
+/// Runs an encoder with its output passed through, under the limits.
+fn run_encoder(cmd: std::process::Command, limits: &EncoderOptions) -> Result<(), String> {
+    let program = cmd.get_program().to_string_lossy().into_owned();
+    let mut cmd = match limits.memory_mib {
+        Some(mib) if cfg!(unix) => {
+            let mut capped = std::process::Command::new("sh");
+            capped
+                .arg("-c")
+                .arg(format!("ulimit -v {} && exec \"$0\" \"$@\"", mib * 1024))
+                .arg(cmd.get_program())
+                .args(cmd.get_args());
+            capped
+        }
+        Some(_) => {
+            diagnostic::warn(
+                Code::UnsupportedTarget,
+                "--encoder-memory is only supported on Unix, running the encoder without a cap",
+            );
+            cmd
+        }
+        None => cmd,
+    };
+
+    log::debug!("Running {:?}", cmd);
+    progress::suspend();
+    let mut child = cmd
+        .spawn()
+        .map_err(|e| format!("Failed to run {}: {}", program, e))?;
+
+    let started = std::time::Instant::now();
+    let status = loop {
+        if let Some(status) = child
+            .try_wait()
+            .map_err(|e| format!("Failed to wait for {}: {}", program, e))?
+        {
+            break status;
+        }
+        if let Some(timeout) = limits.timeout.filter(|&t| started.elapsed() > t) {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(format!("{} timed out after {:?}", program, timeout));
+        }
+        std::thread::sleep(std::time::Duration::from_millis(20));
+    };
+
+    match status.success() {
+        true => Ok(()),
+        //
+        // hitting the memory cap shows as a failed allocation, usually an abort
+        false if limits.memory_mib.is_some() => Err(format!(
+            "{} failed ({}), possibly at the memory cap",
+            program, status
+        )),
+        false => Err(format!("{} failed ({})", program, status)),
+    }
+}
+
+fn toktx_args(encode: Encode) -> &'static [&'static str] {
+    match encode {
+        Encode::Raw | Encode::Png | Encode::Webp | Encode::Qoi => &[],
+        Encode::Uastc => &["--encode", "uastc"],
+        Encode::UastcZstd => &["--encode", "uastc", "--zcmp", "18"],
+        Encode::Etc1s => &["--encode", "etc1s"],
+    }
+}
+
+/// Writes a PNG with fixed encoder settings, so the same pixels always give the same
+/// bytes.
+pub fn write_png(image: &image::DynamicImage, path: &std::path::Path) -> Result<(), String> {
+    let failed = |e: &dyn std::fmt::Display| format!("Failed to write {}: {}", path.display(), e);
+    let file = std::fs::File::create(path).map_err(|e| failed(&e))?;
+    let encoder = image::codecs::png::PngEncoder::new_with_quality(
+        std::io::BufWriter::new(file),
+        image::codecs::png::CompressionType::Default,
+        image::codecs::png::FilterType::Adaptive,
+    );
+    image::ImageEncoder::write_image(
+        encoder,
+        image.as_bytes(),
+        image.width(),
+        image.height(),
+        image.color(),
+    )
+    .map_err(|e| failed(&e))
+}
+
+/// Writes a page PNG as the page image of `encode`.
+fn write_page_image(
+    page: &std::path::Path,
+    encode: Encode,
+    output: &std::path::Path,
+) -> Result<(), String> {
+    if encode == Encode::Png {
+        return std::fs::copy(page, output)
+            .map(|_| ())
+            .map_err(|e| format!("Failed to copy page {}: {}", page.display(), e));
+    }
+
+    let image =
+        image::open(page).map_err(|e| format!("Failed to read page {}: {}", page.display(), e))?;
+    let failed = |e: &dyn std::fmt::Display| format!("Failed to write {}: {}", output.display(), e);
+    let (width, height) = (image.width(), image.height());
+
+    match encode {
+        Encode::Webp => {
+            let (data, color) = match image {
+                image::DynamicImage::ImageLuma8(l) => (l.into_raw(), image_webp::ColorType::L8),
+                image::DynamicImage::ImageLumaA8(la) => (la.into_raw(), image_webp::ColorType::La8),
+                image => (image.into_rgba8().into_raw(), image_webp::ColorType::Rgba8),
+            };
+            let file = std::fs::File::create(output).map_err(|e| failed(&e))?;
+            image_webp::WebPEncoder::new(std::io::BufWriter::new(file))
+                .encode(&data, width, height, color)
+                .map_err(|e| failed(&e))
+        }
+        _ => image
+            .into_rgba8()
+            .save_with_format(output, image::ImageFormat::Qoi)
+            .map_err(|e| failed(&e)),
+    }
+}
+
+/// Encodes the pages, `levels[0]` holding the base level image of every page and any
+/// further entries the smaller mip levels. The pages are arranged in a texture of
+/// `shape`.
+pub fn encode_pages(
+    levels: &[Vec<std::path::PathBuf>],
+    encode: Encode,
+    format: PageFormat,
+    shape: TextureShape,
+    encoder: &EncoderOptions,
+    output_dir: &std::path::Path,
+    atlas_name: &str,
+) -> Result<EncodedTexture, String> {
+    let pages = &levels[0];
+
+    if let Some(extension) = encode.page_extension() {
+        let files = parallel::map(pages.iter().enumerate(), encoder.jobs, |(idx, page)| {
+            let file = std::path::PathBuf::from(format!("{}_{}.{}", atlas_name, idx, extension));
+            write_page_image(page, encode, &output_dir.join(&file))?;
+            Ok(file)
+        })
+        .into_iter()
+        .collect::<Result<Vec<_>, String>>()?;
+
+        return Ok(EncodedTexture {
+            file: files[0].clone(),
+            pages: files,
+        });
+    }
+
+    let texture_file_path = output_dir.join(format!("{}.ktx2", atlas_name));
+
+    //
+    // there is no sRGB format that leaves the second channel linear, so sRGB pages have
+    // their luminance expanded to RGB with alpha in A. Single channel sRGB formats are
+    // not widely sampled by GPUs either, opaque sRGB pages are RGB.
+    let (target_type, oetf) = match (format.channels, format.color_space) {
+        (PageChannels::LuminanceAlpha, ColorSpace::Srgb) => ("RGBA", "srgb"),
+        (PageChannels::LuminanceAlpha, ColorSpace::Linear) => ("RG", "linear"),
+        (PageChannels::Luminance, ColorSpace::Srgb) => ("RGB", "srgb"),
+        //
+        // coverage is linear whatever the color is in
+        (PageChannels::Luminance, ColorSpace::Linear) | (PageChannels::Alpha, _) => ("R", "linear"),
+    };
+
+    let toktx = |layers: usize, shape: TextureShape, output: &std::path::Path| {
+        let mut cmd = std::process::Command::new(encoder.toktx());
+        match shape {
+            TextureShape::Array => {
+                cmd.arg("--layers").arg(layers.to_string());
+            }
+            TextureShape::Single => {}
+            TextureShape::Volume => {
+                cmd.arg("--depth").arg(layers.to_string());
+            }
+            TextureShape::Cube => {
+                cmd.arg("--cubemap");
+            }
+        }
+        cmd.arg("--target_type")
+            .arg(target_type)
+            .arg("--assign_oetf")
+            .arg(oetf)
+            .args(toktx_args(encode))
+            //
+            // lets the transcoder pick BC5 for the two vector components
+            .args(
+                Some("--normal_mode")
+                    .filter(|_| !format.content.is_color() && encode != Encode::Raw),
+            );
+
+        if levels.len() > 1 {
+            cmd.arg("--mipmap")
+                .arg("--levels")
+                .arg(levels.len().to_string());
+        }
+
+        cmd.args(&encoder.toktx_args);
+        cmd.arg("--t2").arg(output);
+        cmd
+    };
+
+    //
+    // inputs are given level by level, every level listing all layers
+    let mut cmd = toktx(pages.len(), shape, &texture_file_path);
+    cmd.args(levels.iter().flatten());
+
+    if let Err(e) = run_encoder(cmd, encoder) {
+        //
+        // encoding the pages one by one finds the ones the encoder chokes on
+        let mut failed = Vec::new();
+        if pages.len() > 1 {
+            for idx in 0..pages.len() {
+                let probe = output_dir.join(format!("{}_probe{}.ktx2", atlas_name, idx));
+                let mut cmd = toktx(1, TextureShape::Single, &probe);
+                cmd.args(levels.iter().map(|level| &level[idx]));
+                let result = run_encoder(cmd, encoder);
+                let _ = std::fs::remove_file(&probe);
+                if let Err(e) = result {
+                    failed.push(format!("page {}: {}", idx, e));
+                    if !encoder.keep_going {
+                        break;
+                    }
+                }
+            }
+        }
+
+        return Err(Code::EncoderFailed.error(format_args!(
+            "Failed to encode {}: {}",
+            atlas_name,
+            match failed.is_empty() {
+                true => e,
+                false => failed.join(", "),
+            }
+        )));
+    }
+
+    Ok(EncodedTexture {
+        file: texture_file_path.file_name().unwrap().into(),
+        pages: Vec::new(),
+    })
+}
+
+/// Encodes the pages, `levels` laid out as for [`encode_pages`], with `encode_texture`:
+/// all in one texture, or with `separate` every page in a texture of its own named
+/// `<atlas>_<page>`, on up to `jobs` threads. The separate textures are listed as the
+/// pages. `encode_texture` is also told which pages it gets.
+pub fn encode_layers<T: Clone + Sync>(
+    levels: &[Vec<T>],
+    separate: bool,
+    jobs: usize,
+    atlas_name: &str,
+    encode_texture: impl Fn(&[Vec<T>], &str, std::ops::Range<usize>) -> Result<EncodedTexture, String>
+        + Sync,
+) -> Result<EncodedTexture, String> {
+    if !separate {
+        return encode_texture(levels, atlas_name, 0..levels[0].len());
+    }
+
+    let files = parallel::map(0..levels[0].len(), jobs, |idx| {
+        let page_levels = levels
+            .iter()
+            .map(|level| vec![level[idx].clone()])
+            .collect::<Vec<_>>();
+        Ok(encode_texture(
+            &page_levels,
+            &format!("{}_{}", atlas_name, idx),
+            idx..idx + 1,
+        )?
+        .file)
+    })
+    .into_iter()
+    .collect::<Result<Vec<_>, String>>()?;
+
+    Ok(EncodedTexture {
+        file: files[0].clone(),
+        pages: files,
+    })
+}
+
+/// Rounds to the nearest half float, ties to even.
+fn f16_bits(v: f32) -> u16 {
+    let bits = v.to_bits();
+    let sign = (bits >> 16 & 0x8000) as u16;
+    let exp = (bits >> 23 & 0xff) as i32;
+    let mant = bits & 0x7f_ffff;
+
+    if exp == 0xff {
+        return sign | 0x7c00 | if mant != 0 { 0x200 } else { 0 };
+    }
+
+    //
+    // the `shift` low bits of `m` are dropped, rounding to the nearest and ties to even
+    let round = |m: u32, shift: u32| {
+        let (kept, dropped, half) = (m >> shift, m & ((1 << shift) - 1), 1 << (shift - 1));
+        kept + (dropped > half || dropped == half && kept & 1 == 1) as u32
+    };
+
+    let e = exp - 127 + 15;
+    match e {
+        _ if e >= 0x1f => sign | 0x7c00,
+        _ if e < -10 => sign,
+        //
+        // subnormal, rounding up to the smallest normal carries into the exponent
+        _ if e <= 0 => sign | round(mant | 0x80_0000, (14 - e) as u32) as u16,
+        //
+        // a carry out of the mantissa rounds into the exponent, up to infinity
+        _ => sign | round((e as u32) << 23 | mant, 13) as u16,
+    }
+}
+
+fn texel_bytes(page: &image::Rgba32FImage, format: PixelFormat) -> Vec<u8> {
+    let channels = page.as_raw().iter();
+    match format {
+        PixelFormat::Rgba16 => channels
+            .flat_map(|&c| ((c.clamp(0.0, 1.0) * 65535.0).round() as u16).to_le_bytes())
+            .collect(),
+        PixelFormat::Rgba16f => channels.flat_map(|&c| f16_bits(c).to_le_bytes()).collect(),
+        PixelFormat::Rgba32f | PixelFormat::La8 => {
+            channels.flat_map(|&c| c.to_le_bytes()).collect()
+        }
+    }
+}
+
+/// Encodes full precision pages, `levels` laid out as for [`encode_pages`]. Only the
+/// uncompressed encodes take them: `raw` writes the KTX2 directly, `png` writes 16-bit
+/// PNGs for `Rgba16` and OpenEXR pages for the float formats.
+pub fn encode_hdr_pages(
+    levels: &[Vec<image::Rgba32FImage>],
+    encode: Encode,
+    shape: TextureShape,
+    pixel_format: PixelFormat,
+    premultiplied: bool,
+    output_dir: &std::path::Path,
+    atlas_name: &str,
+) -> Result<EncodedTexture, String> {
+    let pages = &levels[0];
+
+    match encode {
+        Encode::Png => {
+            let files = pages
+                .iter()
+                .enumerate()
+                .map(|(idx, page)| {
+                    let file = std::path::PathBuf::from(match pixel_format {
+                        PixelFormat::Rgba16 => format!("{}_{}.png", atlas_name, idx),
+                        _ => format!("{}_{}.exr", atlas_name, idx),
+                    });
+                    let image = image::DynamicImage::ImageRgba32F(page.clone());
+                    match pixel_format {
+                        PixelFormat::Rgba16 => write_png(
+                            &image::DynamicImage::ImageRgba16(image.to_rgba16()),
+                            &output_dir.join(&file),
+                        )?,
+                        _ => image.save(output_dir.join(&file)).map_err(|e| {
+                            format!("Failed to write page {}: {}", file.display(), e)
+                        })?,
+                    }
+                    Ok(file)
+                })
+                .collect::<Result<Vec<_>, String>>()?;
+
+            Ok(EncodedTexture {
+                file: files[0].clone(),
+                pages: files,
+            })
+        }
+        Encode::Raw => {
+            let (vk_format, channel_size, float) = match pixel_format {
+                PixelFormat::Rgba16 => (ktx2::VK_FORMAT_R16G16B16A16_UNORM, 2, false),
+                PixelFormat::Rgba16f => (ktx2::VK_FORMAT_R16G16B16A16_SFLOAT, 2, true),
+                PixelFormat::Rgba32f | PixelFormat::La8 => {
+                    (ktx2::VK_FORMAT_R32G32B32A32_SFLOAT, 4, true)
+                }
+            };
+
+            let file = std::path::PathBuf::from(format!("{}.ktx2", atlas_name));
+            ktx2::write(
+                &output_dir.join(&file),
+                ktx2::RgbaFormat {
+                    vk_format,
+                    channel_size,
+                    float,
+                    premultiplied,
+                },
+                pages[0].dimensions(),
+                shape.ktx2(pages.len()),
+                &levels
+                    .iter()
+                    .map(|level| {
+                        level
+                            .iter()
+                            .flat_map(|page| texel_bytes(page, pixel_format))
+                            .collect::<Vec<_>>()
+                    })
+                    .collect::<Vec<_>>(),
+            )?;
+
+            Ok(EncodedTexture {
+                file,
+                pages: Vec::new(),
+            })
+        }
+        _ => Err(format!(
+            "The {:?} encode only takes the la8 pixel format, use raw or png",
+            encode
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::f16_bits;
+
+    #[test]
+    fn f16_exact_values() {
+        assert_eq!(f16_bits(0.0), 0x0000);
+        assert_eq!(f16_bits(-0.0), 0x8000);
+        assert_eq!(f16_bits(1.0), 0x3c00);
+        assert_eq!(f16_bits(-2.0), 0xc000);
+        assert_eq!(f16_bits(0.5), 0x3800);
+        assert_eq!(f16_bits(65504.0), 0x7bff);
+    }
+
+    #[test]
+    fn f16_rounds_to_nearest_even() {
+        let ulp = 2f32.powi(-10);
+        assert_eq!(f16_bits(0.1), 0x2e66);
+        assert_eq!(f16_bits(1.0 + ulp * 0.4), 0x3c00);
+        assert_eq!(f16_bits(1.0 + ulp * 0.6), 0x3c01);
+        //
+        // halfway, to the even mantissa
+        assert_eq!(f16_bits(1.0 + ulp * 0.5), 0x3c00);
+        assert_eq!(f16_bits(1.0 + ulp * 1.5), 0x3c02);
+        //
+        // rounding up a full mantissa carries into the exponent
+        assert_eq!(f16_bits(2.0 - ulp * 0.25), 0x4000);
+    }
+
+    #[test]
+    fn f16_subnormals() {
+        let smallest = 2f32.powi(-24);
+        assert_eq!(f16_bits(smallest), 0x0001);
+        assert_eq!(f16_bits(-smallest), 0x8001);
+        assert_eq!(f16_bits(smallest * 1023.0), 0x03ff);
+        assert_eq!(f16_bits(2f32.powi(-14)), 0x0400);
+        assert_eq!(f16_bits(smallest * 1023.5), 0x0400);
+        assert_eq!(f16_bits(smallest * 2.5), 0x0002);
+        assert_eq!(f16_bits(smallest * 0.5), 0x0000);
+        assert_eq!(f16_bits(smallest * 0.75), 0x0001);
+        assert_eq!(f16_bits(smallest * 0.25), 0x0000);
+        assert_eq!(f16_bits(f32::MIN_POSITIVE), 0x0000);
+    }
+
+    #[test]
+    fn f16_overflows_to_infinity() {
+        assert_eq!(f16_bits(65519.0), 0x7bff);
+        assert_eq!(f16_bits(65520.0), 0x7c00);
+        assert_eq!(f16_bits(1e10), 0x7c00);
+        assert_eq!(f16_bits(-1e10), 0xfc00);
+        assert_eq!(f16_bits(f32::INFINITY), 0x7c00);
+        assert_eq!(f16_bits(f32::NEG_INFINITY), 0xfc00);
+    }
+
+    #[test]
+    fn f16_keeps_nan() {
+        for nan in [f32::NAN, -f32::NAN, f32::from_bits(0x7f80_0001)] {
+            let bits = f16_bits(nan);
+            assert_eq!(bits & 0x7c00, 0x7c00);
+            assert_ne!(bits & 0x03ff, 0);
+        }
+    }
+}
@@ -0,0 +1,205 @@
+//! `estimate`: predicts how much memory and disk space an atlas would take under
+//! different encodes and page sizes, without writing anything.
+//!
+//! GPU sizes assume the formats the textures are transcoded to (4x4 blocks of 128 bits
+//! for UASTC and ETC1S with alpha). Disk sizes of the compressed encodes are rough
+//! rates per used texel, empty page space compresses to next to nothing; raw sizes
+//! are exact.
+
+use std::io::Write;
+
+use clap::Parser;
+
+use crate::atlas::ColorSpace;
+use crate::encode::{ColorSpaceOption, Encode};
+use crate::pack::{self, PackCache, PackOptions};
+use crate::planner;
+
+#[derive(clap::Args, Debug)]
+pub struct EstimateOptions {
+    /// Folders with the images to pack, as `path` or `tag:path`.
+    #[arg(short, long = "input", alias = "input-folders", required = true)]
+    input_folders: Vec<String>,
+    /// Encodes to compare, all of them by default.
+    #[arg(long, value_enum, value_delimiter = ',')]
+    encode: Vec<Encode>,
+    /// Page sizes to compare.
+    #[arg(long, value_delimiter = ',', default_values_t = [1024, 2048, 4096])]
+    sheet_sizes: Vec<u32>,
+    #[arg(long, default_value_t = planner::MAX_PAGES)]
+    max_layers: u32,
+    #[arg(long, value_enum, default_value_t = ColorSpaceOption::Linear)]
+    color_space: ColorSpaceOption,
+    #[arg(long)]
+    mipmaps: bool,
+    #[arg(long)]
+    group_tags: bool,
+}
+
+#[derive(clap::Parser)]
+#[command(no_binary_name = true)]
+struct EstimateArgs {
+    #[command(flatten)]
+    pack: PackOptions,
+}
+
+fn human(bytes: f64) -> String {
+    const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+    let mut value = bytes;
+    let mut unit = 0;
+    while value >= 1024.0 && unit + 1 < UNITS.len() {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", value, UNITS[unit])
+}
+
+/// `(gpu bytes per texel, disk bytes per texel, whether empty texels count on disk)`
+fn rates(encode: Encode, color_space: ColorSpace) -> (f64, f64, bool) {
+    //
+    // sRGB pages are expanded to RGBA
+    let raw = match color_space {
+        ColorSpace::Srgb => 4.0,
+        ColorSpace::Linear => 2.0,
+    };
+
+    match encode {
+        Encode::Raw => (raw, raw, true),
+        // measured separately
+        Encode::Png | Encode::Webp | Encode::Qoi => (raw, 0.0, false),
+        Encode::Uastc => (1.0, 1.0, true),
+        Encode::UastcZstd => (1.0, 0.6, false),
+        Encode::Etc1s => (1.0, 0.25, false),
+    }
+}
+
+/// Deflated size of the sprite pixels, close to what the PNG pages come out as and a
+/// rough guess for WebP and QOI.
+fn png_estimate(sprites: &[crate::source::SourceSprite]) -> Result<f64, String> {
+    let mut encoder =
+        flate2::write::ZlibEncoder::new(std::io::sink(), flate2::Compression::default());
+    for sprite in sprites {
+        encoder
+            .write_all(sprite.pixels.as_raw())
+            .map_err(|e| e.to_string())?;
+    }
+    encoder.try_finish().map_err(|e| e.to_string())?;
+    Ok(encoder.total_out() as f64)
+}
+
+pub fn run(options: EstimateOptions) -> Result<(), String> {
+    let encodes = match options.encode.is_empty() {
+        true => vec![
+            Encode::Raw,
+            Encode::Png,
+            Encode::Uastc,
+            Encode::UastcZstd,
+            Encode::Etc1s,
+        ],
+        false => options.encode.clone(),
+    };
+
+    let mut cache = PackCache::default();
+    //
+    // the KTX2 sizes only take the layout, the headers tell it without decoding
+    let needs_pixels = encodes.iter().any(|encode| !encode.is_ktx2());
+
+    for &sheet_size in options.sheet_sizes.iter() {
+        let mut args = vec![
+            "-a".to_string(),
+            "estimate".to_string(),
+            "-o".to_string(),
+            ".".to_string(),
+            "-s".to_string(),
+            sheet_size.to_string(),
+            "--max-layers".to_string(),
+            options.max_layers.to_string(),
+        ];
+        for folder in options.input_folders.iter() {
+            args.extend(["-i".to_string(), folder.clone()]);
+        }
+        if options.group_tags {
+            args.push("--group-tags".to_string());
+        }
+
+        let pack_options = EstimateArgs::try_parse_from(&args)
+            .map_err(|e| e.to_string())?
+            .pack;
+
+        let (rects, labels, sprites) =
+            match pack::probe_inputs(&pack_options).filter(|_| !needs_pixels) {
+                Some(probed) => {
+                    log::info!("Sized {} sprite(s) from their headers", probed.rects.len());
+                    (probed.rects, probed.labels, Vec::new())
+                }
+                None => {
+                    //
+                    // sources are decoded once, only the placement depends on the page size
+                    let inputs = pack::load_inputs(&pack_options, &[], Some(&mut cache))?;
+                    pack::report_unreadable(&pack_options, &inputs.rejected)?;
+                    let labels = inputs.sprites.iter().map(|s| s.label()).collect();
+                    (inputs.rects, labels, inputs.sprites)
+                }
+            };
+
+        let placement = match planner::plan(
+            &rects,
+            (sheet_size, sheet_size),
+            1,
+            options.max_layers,
+            pack_options.packing(),
+        ) {
+            Ok(placement) => placement,
+            Err(e) => {
+                println!(
+                    "{}x{}: does not fit ({})",
+                    sheet_size,
+                    sheet_size,
+                    e.map_ids(|id| labels[id].clone())
+                );
+                continue;
+            }
+        };
+
+        let pages = placement.values().map(|p| p.page + 1).max().unwrap_or(1);
+        let page_texels = sheet_size as f64 * sheet_size as f64;
+        let total_texels = page_texels * pages as f64;
+        let used_texels = rects
+            .iter()
+            .map(|r| r.width as f64 * r.height as f64)
+            .sum::<f64>();
+        let mip_factor = if options.mipmaps { 4.0 / 3.0 } else { 1.0 };
+        let color_space = options.color_space.resolve(Default::default());
+
+        println!(
+            "{}x{}: {} page(s), {:.1}% used",
+            sheet_size,
+            sheet_size,
+            pages,
+            used_texels / total_texels * 100.0
+        );
+
+        for &encode in encodes.iter() {
+            let (gpu_rate, disk_rate, counts_empty) = rates(encode, color_space);
+            let gpu = total_texels * gpu_rate * mip_factor;
+            let disk = match encode {
+                _ if !encode.is_ktx2() => png_estimate(&sprites)?,
+                _ if counts_empty => total_texels * disk_rate * mip_factor,
+                _ => used_texels * disk_rate * mip_factor,
+            };
+
+            let exact = matches!(encode, Encode::Raw);
+            println!(
+                "  {:<12} gpu {:>10}   disk {}{}",
+                <Encode as clap::ValueEnum>::to_possible_value(&encode)
+                    .map(|v| v.get_name().to_string())
+                    .unwrap_or_default(),
+                human(gpu),
+                if exact { "" } else { "~" },
+                human(disk)
+            );
+        }
+    }
+
+    Ok(())
+}
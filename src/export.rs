@@ -0,0 +1,32 @@
+//! The `--export` formats, from the library's registry of them, written next to
+//! `<atlas>.ron`.
+
+use crate::diagnostic::{self, Code};
+use texture_packer::exporter::ExporterRegistry;
+pub use texture_packer::exporter::{ExportFile, ExportInput};
+
+/// The formats of the tool. Exporters are stateless, one registry serves every pack.
+pub fn registry() -> &'static ExporterRegistry {
+    static REGISTRY: std::sync::OnceLock<ExporterRegistry> = std::sync::OnceLock::new();
+    REGISTRY.get_or_init(ExporterRegistry::builtin)
+}
+
+/// The files of every format in `names`, warning about what they cannot express.
+pub fn files(names: &[impl AsRef<str>], input: &ExportInput) -> Result<Vec<ExportFile>, String> {
+    let mut warnings = Vec::new();
+    let files = registry().files(names, input, &mut warnings)?;
+    for warning in warnings {
+        diagnostic::warn(Code::ExportUnsupported, warning);
+    }
+    Ok(files)
+}
+
+/// Parses `--export` values, the names of the registered formats, listing them in the
+/// help.
+pub fn format_parser() -> clap::builder::PossibleValuesParser {
+    clap::builder::PossibleValuesParser::new(
+        registry()
+            .iter()
+            .map(|e| clap::builder::PossibleValue::new(e.name()).help(e.description())),
+    )
+}
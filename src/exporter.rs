@@ -0,0 +1,361 @@
+//! Writers of atlas descriptions, looked up by name. [`ExporterRegistry::builtin`] has
+//! the tool's RON descriptor and engine formats (`--export`); crates using the library
+//! can run those, or implement [`AtlasExporter`] for formats of their own, on a loaded
+//! [`TextureAtlas`] the same way:
+//!
+//! ```ignore
+//! let mut registry = ExporterRegistry::builtin();
+//! registry.register(MyFormat);
+//! let atlas = TextureAtlas::load("out/sprites.ron")?;
+//! let input = ExportInput { name: "sprites", atlas: &atlas, page_atlases: &[], page_images: &pages };
+//! let mut warnings = Vec::new();
+//! for file in registry.files(&["godot", "my-format"], &input, &mut warnings)? {
+//!     /* write file.data */
+//! }
+//! ```
+
+use crate::atlas::TextureAtlas;
+use std::collections::BTreeMap;
+
+mod bevy;
+mod c_header;
+mod cocos2d;
+mod css;
+mod descriptor;
+mod gdx;
+mod godot;
+mod pixi;
+mod rust;
+mod shader;
+mod unity;
+
+/// The formats of the tool, its own RON descriptor and the engine formats.
+const BUILTIN: &[Builtin] = &[
+    Builtin {
+        name: "ron",
+        description: "The RON descriptor `<atlas>.ron`, and `<atlas>_<page>.ron` per page with a \
+             texture per page",
+        files: descriptor::files,
+    },
+    Builtin {
+        name: "godot",
+        description:
+            "Godot `AtlasTexture` resources, one `<atlas>_godot/<frame>.tres` per frame, and a \
+             `SpriteFrames` resource `<atlas>.tres` for the animations",
+        files: godot::files,
+    },
+    Builtin {
+        name: "unity",
+        description:
+            "`<atlas>.unity.json` with the sprite rects, pivots and borders per page, for the \
+             editor script in `integrations/unity`",
+        files: unity::files,
+    },
+    Builtin {
+        name: "gdx",
+        description: "The libGDX/Spine text atlas `<atlas>.atlas`",
+        files: gdx::files,
+    },
+    Builtin {
+        name: "pixi",
+        description:
+            "TexturePacker \"JSON (hash)\" sheets for PixiJS and Phaser, `<atlas>.pixi.json` or \
+             `<atlas>_<page>.pixi.json` per page",
+        files: pixi::files,
+    },
+    Builtin {
+        name: "cocos2d",
+        description:
+            "Cocos2d-x sprite frame property lists, `<atlas>.plist` or `<atlas>_<page>.plist` \
+             per page",
+        files: cocos2d::files,
+    },
+    Builtin {
+        name: "css",
+        description: "`<atlas>.css` with a class per frame over the page PNGs, and an HTML page \
+             `<atlas>.html` showing them",
+        files: css::files,
+    },
+    Builtin {
+        name: "rust",
+        description:
+            "`<atlas>.rs` with a `Sprite` enum of the frames and their regions as constants",
+        files: rust::files,
+    },
+    Builtin {
+        name: "c-header",
+        description: "`<atlas>.h` with the regions as a C array of structs and an enum indexing it",
+        files: c_header::files,
+    },
+    Builtin {
+        name: "wgsl",
+        description: "`<atlas>.wgsl` with the regions as a constant array and a constant per frame",
+        files: shader::wgsl,
+    },
+    Builtin {
+        name: "glsl",
+        description: "`<atlas>.glsl` with the regions as constant arrays and a constant per frame",
+        files: shader::glsl,
+    },
+    Builtin {
+        name: "bevy",
+        description: "`<atlas>.bevy.ron` with a `TextureAtlasLayout` per page and the frame names \
+             mapped to their index, for the loader in `integrations/bevy`",
+        files: bevy::files,
+    },
+];
+
+#[derive(Copy, Clone)]
+struct Builtin {
+    name: &'static str,
+    description: &'static str,
+    files: fn(&ExportInput, &mut Vec<String>) -> Vec<ExportFile>,
+}
+
+impl AtlasExporter for Builtin {
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    fn description(&self) -> &str {
+        self.description
+    }
+
+    fn files(&self, input: &ExportInput, warnings: &mut Vec<String>) -> Vec<ExportFile> {
+        (self.files)(input, warnings)
+    }
+}
+
+/// What the exporters work from.
+pub struct ExportInput<'a> {
+    pub name: &'a str,
+    /// The descriptor, its paths relative to the descriptor directory.
+    pub atlas: &'a TextureAtlas,
+    /// With a texture per page the descriptor of every page, empty otherwise.
+    pub page_atlases: &'a [TextureAtlas],
+    /// A PNG per page, relative to the descriptor directory.
+    pub page_images: &'a [std::path::PathBuf],
+}
+
+/// A file to write, relative to the descriptor directory. The registry refuses paths
+/// leaving it.
+pub struct ExportFile {
+    pub path: std::path::PathBuf,
+    pub data: Vec<u8>,
+}
+
+pub trait AtlasExporter: Send + Sync {
+    /// The name the format is selected by, e.g. `godot`.
+    fn name(&self) -> &str;
+
+    /// One line on what gets written, for listings.
+    fn description(&self) -> &str {
+        ""
+    }
+
+    /// The files of the format. What the format cannot express is left out, with a
+    /// line about it in `warnings`.
+    fn files(&self, input: &ExportInput, warnings: &mut Vec<String>) -> Vec<ExportFile>;
+}
+
+/// Exporters keyed by their name.
+#[derive(Default)]
+pub struct ExporterRegistry {
+    exporters: BTreeMap<String, Box<dyn AtlasExporter>>,
+}
+
+impl ExporterRegistry {
+    /// A registry with the formats of the tool.
+    pub fn builtin() -> ExporterRegistry {
+        let mut registry = ExporterRegistry::default();
+        for &exporter in BUILTIN {
+            registry.register(exporter);
+        }
+        registry
+    }
+
+    /// Adds an exporter, replacing (and returning) one registered under the same name.
+    pub fn register(
+        &mut self,
+        exporter: impl AtlasExporter + 'static,
+    ) -> Option<Box<dyn AtlasExporter>> {
+        self.exporters
+            .insert(exporter.name().to_string(), Box::new(exporter))
+    }
+
+    pub fn get(&self, name: &str) -> Option<&dyn AtlasExporter> {
+        self.exporters.get(name).map(|e| e.as_ref())
+    }
+
+    /// The registered exporters, by name.
+    pub fn iter(&self) -> impl Iterator<Item = &dyn AtlasExporter> {
+        self.exporters.values().map(|e| e.as_ref())
+    }
+
+    /// The files of every format in `names`, each format once.
+    pub fn files(
+        &self,
+        names: &[impl AsRef<str>],
+        input: &ExportInput,
+        warnings: &mut Vec<String>,
+    ) -> Result<Vec<ExportFile>, String> {
+        let mut done = std::collections::BTreeSet::new();
+        let mut files = Vec::new();
+
+        for name in names.iter().map(|n| n.as_ref()) {
+            let exporter = self.get(name).ok_or_else(|| {
+                format!(
+                    "Unknown export format '{}', expected one of {}",
+                    name,
+                    self.exporters
+                        .keys()
+                        .map(|k| k.as_str())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                )
+            })?;
+            if !done.insert(name) {
+                continue;
+            }
+            for file in exporter.files(input, warnings) {
+                let inside = file.path.components().next().is_some()
+                    && file
+                        .path
+                        .components()
+                        .all(|c| matches!(c, std::path::Component::Normal(_)));
+                if !inside {
+                    return Err(format!(
+                        "The {} export names {}, outside of the descriptor directory",
+                        name,
+                        file.path.display()
+                    ));
+                }
+                files.push(file);
+            }
+        }
+
+        Ok(files)
+    }
+}
+
+/// The words of `text` for identifiers, split at anything but ASCII letters and digits.
+fn words(text: &str) -> impl Iterator<Item = &str> {
+    text.split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|word| !word.is_empty())
+}
+
+/// `text` as an UpperCamelCase identifier part.
+fn camel_name(text: &str) -> String {
+    words(text)
+        .map(|word| {
+            let mut chars = word.chars();
+            chars
+                .next()
+                .map(|first| first.to_ascii_uppercase().to_string() + chars.as_str())
+                .unwrap_or_default()
+        })
+        .collect()
+}
+
+/// `text` as an UPPER_SNAKE_CASE identifier part, `_` when it has no words.
+fn constant_name(text: &str) -> String {
+    let name = words(text)
+        .map(|word| word.to_ascii_uppercase())
+        .collect::<Vec<_>>()
+        .join("_");
+    match name.is_empty() {
+        true => "_".to_string(),
+        false => name,
+    }
+}
+
+/// The prefix of all identifiers generated for atlas `name`, not starting with a digit.
+fn atlas_prefix(name: &str) -> String {
+    let prefix = constant_name(name);
+    match prefix.starts_with(|c: char| c.is_ascii_alphabetic()) {
+        true => prefix,
+        false => format!("ATLAS{}", prefix),
+    }
+}
+
+/// `names`, with the ones that come out the same numbered.
+fn unique_names(names: impl Iterator<Item = String>) -> Vec<String> {
+    let mut taken = std::collections::BTreeSet::new();
+    names
+        .map(|base| {
+            let mut name = base.clone();
+            let mut n = 2;
+            while !taken.insert(name.clone()) {
+                name = format!("{}{}", base, n);
+                n += 1;
+            }
+            name
+        })
+        .collect()
+}
+
+/// `path` with forward slashes, the way engines spell resource paths on every
+/// platform.
+fn resource_path(path: &std::path::Path) -> String {
+    path.components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Writes one file at the path it is made with.
+    struct Fixed(&'static str);
+
+    impl AtlasExporter for Fixed {
+        fn name(&self) -> &str {
+            "fixed"
+        }
+
+        fn files(&self, _input: &ExportInput, _warnings: &mut Vec<String>) -> Vec<ExportFile> {
+            vec![ExportFile {
+                path: self.0.into(),
+                data: Vec::new(),
+            }]
+        }
+    }
+
+    fn export(registry: &ExporterRegistry, names: &[&str]) -> Result<Vec<ExportFile>, String> {
+        let atlas = TextureAtlas::new("atlas.png", (64, 64));
+        let input = ExportInput {
+            name: "atlas",
+            atlas: &atlas,
+            page_atlases: &[],
+            page_images: &["atlas.png".into()],
+        };
+        registry.files(names, &input, &mut Vec::new())
+    }
+
+    #[test]
+    fn builtin_formats_include_the_descriptor() {
+        let registry = ExporterRegistry::builtin();
+        for name in ["ron", "godot", "unity", "gdx", "pixi", "bevy"] {
+            assert!(registry.get(name).is_some(), "{}", name);
+        }
+
+        let files = export(&registry, &["ron", "ron"]).unwrap();
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].path, std::path::Path::new("atlas.ron"));
+        let atlas = TextureAtlas::parse(std::str::from_utf8(&files[0].data).unwrap()).unwrap();
+        assert_eq!(atlas.size, (64, 64));
+    }
+
+    #[test]
+    fn paths_leaving_the_descriptor_directory_are_refused() {
+        for path in ["nested/ok.txt", "../escaped.txt", "/tmp/escaped.txt", ""] {
+            let mut registry = ExporterRegistry::default();
+            registry.register(Fixed(path));
+            let result = export(&registry, &["fixed"]);
+            assert_eq!(result.is_ok(), path == "nested/ok.txt", "{}", path);
+        }
+        assert!(export(&ExporterRegistry::default(), &["fixed"]).is_err());
+    }
+}
@@ -0,0 +1,108 @@
+//! RON for the Bevy loader in `integrations/bevy`. Every page holds its rects as a
+//! `TextureAtlasLayout`, so Bevy deserializes them straight into one, and the frames
+//! map to their page and index in its rects.
+
+use super::{resource_path, ExportFile, ExportInput};
+use std::collections::BTreeMap;
+
+/// Bevy's `URect`.
+#[derive(serde::Serialize)]
+struct Rect {
+    min: (u32, u32),
+    max: (u32, u32),
+}
+
+/// Bevy's `TextureAtlasLayout`.
+#[derive(serde::Serialize)]
+struct Layout {
+    size: (u32, u32),
+    textures: Vec<Rect>,
+}
+
+#[derive(serde::Serialize)]
+struct Page {
+    /// Relative to the RON file.
+    texture: String,
+    layout: Layout,
+}
+
+#[derive(Copy, Clone, serde::Serialize)]
+struct FrameIndex {
+    page: u32,
+    index: usize,
+}
+
+#[derive(serde::Serialize)]
+struct BevyAtlas<'a> {
+    pages: Vec<Page>,
+    names: BTreeMap<&'a str, FrameIndex>,
+}
+
+pub fn files(input: &ExportInput, warnings: &mut Vec<String>) -> Vec<ExportFile> {
+    let atlas = input.atlas;
+
+    //
+    // the rects of a page are ordered by key, so indices only move when frames are
+    // added or removed, not when the packing changes
+    let mut frames = atlas
+        .frames
+        .iter()
+        .filter(|f| f.alias_of.is_none())
+        .filter(|frame| match frame.draw_transform {
+            Some(_) => {
+                warnings.push(format!(
+                    "{} is drawn transformed, which a Bevy texture atlas cannot express",
+                    frame.key
+                ));
+                false
+            }
+            None => true,
+        })
+        .collect::<Vec<_>>();
+    frames.sort_by(|a, b| a.key.cmp(&b.key));
+
+    let mut pages = input
+        .page_images
+        .iter()
+        .map(|page| Page {
+            texture: resource_path(page),
+            layout: Layout {
+                size: atlas.size,
+                textures: Vec::new(),
+            },
+        })
+        .collect::<Vec<_>>();
+
+    let mut names = BTreeMap::new();
+    for frame in frames {
+        let textures = &mut pages[frame.layer as usize].layout.textures;
+        names.entry(frame.key.as_str()).or_insert(FrameIndex {
+            page: frame.layer,
+            index: textures.len(),
+        });
+        textures.push(Rect {
+            min: (frame.x, frame.y),
+            max: (frame.x + frame.width, frame.y + frame.height),
+        });
+    }
+
+    //
+    // aliases are only another name for the index of their frame
+    for frame in atlas.frames.iter() {
+        if let Some(index) = frame
+            .alias_of
+            .as_deref()
+            .and_then(|target| names.get(target).copied())
+        {
+            names.entry(frame.key.as_str()).or_insert(index);
+        }
+    }
+
+    let text =
+        ron::ser::to_string_pretty(&BevyAtlas { pages, names }, ron::ser::PrettyConfig::new())
+            .unwrap();
+    vec![ExportFile {
+        path: format!("{}.bevy.ron", input.name).into(),
+        data: text.into_bytes(),
+    }]
+}
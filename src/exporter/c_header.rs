@@ -0,0 +1,107 @@
+//! A C header with the frame regions as a constant array and an enum indexing it.
+
+use super::{atlas_prefix, constant_name, unique_names, ExportFile, ExportInput};
+use std::fmt::Write;
+
+/// `text` as a C string literal, bytes outside printable ASCII as octal escapes.
+fn c_string(text: &str) -> String {
+    let mut literal = String::from("\"");
+    for b in text.bytes() {
+        match b {
+            b'"' | b'\\' => {
+                literal.push('\\');
+                literal.push(b as char);
+            }
+            0x20..=0x7e => literal.push(b as char),
+            _ => {
+                let _ = write!(literal, "\\{:03o}", b);
+            }
+        }
+    }
+    literal + "\""
+}
+
+pub fn files(input: &ExportInput, _warnings: &mut Vec<String>) -> Vec<ExportFile> {
+    let frames = &input.atlas.frames;
+    let prefix = atlas_prefix(input.name);
+    let lower = prefix.to_ascii_lowercase();
+    let names = unique_names(
+        frames
+            .iter()
+            .map(|frame| format!("{}_{}", prefix, constant_name(&frame.key))),
+    );
+
+    let mut code = format!(
+        "/* Generated by texture-packer from the atlas `{name}`, do not edit. */\n\
+         #ifndef {prefix}_ATLAS_H\n\
+         #define {prefix}_ATLAS_H\n\
+         \n\
+         #include <stdint.h>\n\
+         \n\
+         #define {prefix}_PAGE_WIDTH {width}u\n\
+         #define {prefix}_PAGE_HEIGHT {height}u\n\
+         #define {prefix}_PAGE_COUNT {pages}u\n\
+         #define {prefix}_FRAME_COUNT {count}u\n\
+         \n\
+         /* Pixel rects are measured from the top left of the page, UVs have the atlas UV\n\
+         \x20  inset applied, (0, 0) is the top left corner of a pivot. */\n\
+         typedef struct {lower}_region {{\n\
+         \x20   uint32_t layer;\n\
+         \x20   uint32_t x, y, width, height;\n\
+         \x20   float u0, v0, u1, v1;\n\
+         \x20   float pivot_x, pivot_y;\n\
+         \x20   const char *key;\n\
+         }} {lower}_region;\n",
+        name = input.name.replace("*/", "* /"),
+        prefix = prefix,
+        lower = lower,
+        width = input.atlas.size.0,
+        height = input.atlas.size.1,
+        pages = input.page_images.len(),
+        count = frames.len(),
+    );
+
+    //
+    // C has no empty arrays or enums
+    if !frames.is_empty() {
+        let _ = write!(code, "\nenum {}_sprite {{\n", lower);
+        for (idx, name) in names.iter().enumerate() {
+            let _ = writeln!(code, "    {} = {},", name, idx);
+        }
+        code += "};\n";
+
+        let _ = write!(
+            code,
+            "\nstatic const {lower}_region {lower}_regions[{prefix}_FRAME_COUNT] = {{\n",
+            lower = lower,
+            prefix = prefix
+        );
+        for frame in frames.iter() {
+            let (u0, v0, u1, v1) = frame.uv_rect();
+            let _ = writeln!(
+                code,
+                "    {{{}u, {}u, {}u, {}u, {}u, {:?}f, {:?}f, {:?}f, {:?}f, {:?}f, {:?}f, {}}},",
+                frame.layer,
+                frame.x,
+                frame.y,
+                frame.width,
+                frame.height,
+                u0,
+                v0,
+                u1,
+                v1,
+                frame.pivot_x,
+                frame.pivot_y,
+                c_string(&frame.key)
+            );
+        }
+        code += "};\n";
+    }
+
+    let _ = write!(code, "\n#endif /* {}_ATLAS_H */\n", prefix);
+
+    vec![ExportFile {
+        path: format!("{}.h", input.name).into(),
+        data: code.into_bytes(),
+    }]
+}
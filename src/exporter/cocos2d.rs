@@ -0,0 +1,151 @@
+//! Property lists in the format 3 layout that Cocos2d-x's `SpriteFrameCache` loads, one
+//! per page.
+
+use super::{resource_path, ExportFile, ExportInput};
+use crate::atlas::PixelFormat;
+use std::fmt::Write;
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+fn plist_name(name: &str, page: usize, pages: usize) -> String {
+    match pages {
+        1 => format!("{}.plist", name),
+        _ => format!("{}_{}.plist", name, page),
+    }
+}
+
+pub fn files(input: &ExportInput, warnings: &mut Vec<String>) -> Vec<ExportFile> {
+    let atlas = input.atlas;
+    let (width, height) = atlas.size;
+    let pixel_format = match atlas.pixel_format {
+        PixelFormat::La8 => "AI88",
+        _ => "RGBA8888",
+    };
+
+    //
+    // aliases go into the `aliases` of their frame instead of getting one of their own
+    let mut aliases = fnv::FnvHashMap::<&str, Vec<&str>>::default();
+    for frame in atlas.frames.iter() {
+        if let Some(target) = frame.alias_of.as_deref() {
+            aliases.entry(target).or_default().push(&frame.key);
+        }
+    }
+
+    let mut frames = vec![String::new(); input.page_images.len()];
+    for frame in atlas.frames.iter().filter(|f| f.alias_of.is_none()) {
+        if frame.draw_transform.is_some() {
+            warnings.push(format!(
+                "{} is drawn transformed, which a Cocos2d sprite frame cannot express",
+                frame.key
+            ));
+            continue;
+        }
+
+        //
+        // the offset is from the center of the untrimmed sprite to the center of the
+        // trimmed one, y up
+        let (offset, source_size) = match frame.trim {
+            Some(trim) => (
+                (
+                    trim.x as f32 + frame.width as f32 * 0.5 - trim.source_width as f32 * 0.5,
+                    trim.source_height as f32 * 0.5 - trim.y as f32 - frame.height as f32 * 0.5,
+                ),
+                (trim.source_width, trim.source_height),
+            ),
+            None => ((0.0, 0.0), (frame.width, frame.height)),
+        };
+
+        let _ = write!(
+            frames[frame.layer as usize],
+            concat!(
+                "            <key>{}</key>\n",
+                "            <dict>\n",
+                "                <key>aliases</key>\n",
+                "{}",
+                "                <key>spriteOffset</key>\n",
+                "                <string>{{{},{}}}</string>\n",
+                "                <key>spriteSize</key>\n",
+                "                <string>{{{},{}}}</string>\n",
+                "                <key>spriteSourceSize</key>\n",
+                "                <string>{{{},{}}}</string>\n",
+                "                <key>textureRect</key>\n",
+                "                <string>{{{{{},{}}},{{{},{}}}}}</string>\n",
+                "                <key>textureRotated</key>\n",
+                "                <false/>\n",
+                "            </dict>\n",
+            ),
+            escape(&frame.key),
+            match aliases.get(frame.key.as_str()) {
+                Some(keys) => format!(
+                    "                <array>\n{}                </array>\n",
+                    keys.iter()
+                        .map(|key| format!(
+                            "                    <string>{}</string>\n",
+                            escape(key)
+                        ))
+                        .collect::<String>()
+                ),
+                None => "                <array/>\n".to_string(),
+            },
+            offset.0,
+            offset.1,
+            frame.width,
+            frame.height,
+            source_size.0,
+            source_size.1,
+            frame.x,
+            frame.y,
+            frame.width,
+            frame.height,
+        );
+    }
+
+    let page_count = frames.len();
+    frames
+        .into_iter()
+        .zip(input.page_images.iter())
+        .enumerate()
+        .map(|(idx, (frames, page))| {
+            let texture = escape(&resource_path(page));
+            let text = format!(
+                concat!(
+                    "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+                    "<!DOCTYPE plist PUBLIC \"-//Apple//DTD PLIST 1.0//EN\" ",
+                    "\"http://www.apple.com/DTDs/PropertyList-1.0.dtd\">\n",
+                    "<plist version=\"1.0\">\n",
+                    "    <dict>\n",
+                    "        <key>frames</key>\n",
+                    "        <dict>\n",
+                    "{}",
+                    "        </dict>\n",
+                    "        <key>metadata</key>\n",
+                    "        <dict>\n",
+                    "            <key>format</key>\n",
+                    "            <integer>3</integer>\n",
+                    "            <key>pixelFormat</key>\n",
+                    "            <string>{}</string>\n",
+                    "            <key>premultiplyAlpha</key>\n",
+                    "            <{}/>\n",
+                    "            <key>realTextureFileName</key>\n",
+                    "            <string>{}</string>\n",
+                    "            <key>size</key>\n",
+                    "            <string>{{{},{}}}</string>\n",
+                    "            <key>textureFileName</key>\n",
+                    "            <string>{}</string>\n",
+                    "        </dict>\n",
+                    "    </dict>\n",
+                    "</plist>\n",
+                ),
+                frames, pixel_format, atlas.premultiplied, texture, width, height, texture,
+            );
+            ExportFile {
+                path: plist_name(input.name, idx, page_count).into(),
+                data: text.into_bytes(),
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,111 @@
+//! CSS sprites over the page PNGs: a class per frame setting its page, size and
+//! `background-position`, and an HTML page showing every frame with its class.
+
+use super::{resource_path, ExportFile, ExportInput};
+use crate::atlas::SpriteTransform;
+use std::fmt::Write;
+
+/// `text` as a CSS identifier part, anything but letters, digits, `-` and `_` replaced.
+fn class_name(text: &str) -> String {
+    text.chars()
+        .map(
+            |c| match c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                true => c,
+                false => '-',
+            },
+        )
+        .collect()
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// CSS applies the rightmost function first: the flips, then the rotation.
+fn css_transform(transform: &SpriteTransform) -> String {
+    let mut functions = Vec::new();
+    if transform.rotate90 {
+        functions.push("rotate(90deg)".to_string());
+    }
+    if transform.flip_x || transform.flip_y {
+        functions.push(format!(
+            "scale({}, {})",
+            if transform.flip_x { -1 } else { 1 },
+            if transform.flip_y { -1 } else { 1 }
+        ));
+    }
+    functions.join(" ")
+}
+
+pub fn files(input: &ExportInput, _warnings: &mut Vec<String>) -> Vec<ExportFile> {
+    let base = class_name(input.name);
+    let mut css = format!(
+        ".{} {{\n  display: inline-block;\n  background-repeat: no-repeat;\n}}\n",
+        base
+    );
+    let mut sprites = String::new();
+
+    for frame in input.atlas.frames.iter() {
+        let class = format!("{}-{}", base, class_name(&frame.key));
+        let _ = write!(
+            css,
+            "\n.{} {{\n  background-image: url(\"{}\");\n  background-position: {}px {}px;\n  width: {}px;\n  height: {}px;\n",
+            class,
+            resource_path(&input.page_images[frame.layer as usize]),
+            -(frame.x as i64),
+            -(frame.y as i64),
+            frame.width,
+            frame.height
+        );
+        if let Some(transform) = frame.draw_transform.filter(|t| !t.is_identity()) {
+            let _ = writeln!(css, "  transform: {};", css_transform(&transform));
+        }
+        css += "}\n";
+
+        let _ = writeln!(
+            sprites,
+            "    <figure><div class=\"{} {}\"></div><figcaption>{}</figcaption></figure>",
+            base,
+            class,
+            escape_html(&frame.key)
+        );
+    }
+
+    let html = format!(
+        concat!(
+            "<!DOCTYPE html>\n",
+            "<html>\n",
+            "  <head>\n",
+            "    <meta charset=\"utf-8\">\n",
+            "    <title>{}</title>\n",
+            "    <link rel=\"stylesheet\" href=\"{}.css\">\n",
+            "    <style>\n",
+            "      body {{ font-family: sans-serif; }}\n",
+            "      figure {{ display: inline-block; margin: 8px; text-align: center; }}\n",
+            "      figcaption {{ font-size: 12px; }}\n",
+            "    </style>\n",
+            "  </head>\n",
+            "  <body>\n",
+            "{}",
+            "  </body>\n",
+            "</html>\n",
+        ),
+        escape_html(input.name),
+        escape_html(input.name),
+        sprites
+    );
+
+    vec![
+        ExportFile {
+            path: format!("{}.css", input.name).into(),
+            data: css.into_bytes(),
+        },
+        ExportFile {
+            path: format!("{}.html", input.name).into(),
+            data: html.into_bytes(),
+        },
+    ]
+}
@@ -0,0 +1,25 @@
+//! The tool's own descriptor, RON in the serde data model of [`TextureAtlas`].
+
+use super::{ExportFile, ExportInput};
+use crate::atlas::TextureAtlas;
+
+fn ron_file(path: String, atlas: &TextureAtlas) -> ExportFile {
+    ExportFile {
+        path: path.into(),
+        data: ron::ser::to_string_pretty(atlas, ron::ser::PrettyConfig::new())
+            .unwrap()
+            .into_bytes(),
+    }
+}
+
+pub fn files(input: &ExportInput, _warnings: &mut Vec<String>) -> Vec<ExportFile> {
+    std::iter::once(ron_file(format!("{}.ron", input.name), input.atlas))
+        .chain(
+            input
+                .page_atlases
+                .iter()
+                .enumerate()
+                .map(|(idx, page)| ron_file(format!("{}_{}.ron", input.name, idx), page)),
+        )
+        .collect()
+}
@@ -0,0 +1,93 @@
+//! The libGDX text atlas, also read by Spine runtimes and Heaps. Frames of an animation
+//! are named after it and numbered by their `index`, the way `TextureAtlas.findRegions`
+//! looks them up.
+
+use super::{resource_path, ExportFile, ExportInput};
+use crate::atlas::{NamedTextureRegion, PixelFormat};
+use std::fmt::Write;
+
+/// Region name and index of a frame: its animation and position in it, or the key
+/// and -1.
+fn region_name<'a>(input: &'a ExportInput, frame: &'a NamedTextureRegion) -> (&'a str, i64) {
+    input
+        .atlas
+        .animations
+        .iter()
+        .find_map(|animation| {
+            animation
+                .frames
+                .iter()
+                .position(|key| *key == frame.key)
+                .map(|idx| (animation.name.as_str(), idx as i64))
+        })
+        .unwrap_or((frame.key.as_str(), -1))
+}
+
+pub fn files(input: &ExportInput, warnings: &mut Vec<String>) -> Vec<ExportFile> {
+    let atlas = input.atlas;
+    let format = match atlas.pixel_format {
+        PixelFormat::La8 => "LuminanceAlpha",
+        _ => "RGBA8888",
+    };
+    let min_filter = match atlas.mip_levels > 1 {
+        true => "MipMapLinearLinear",
+        false => "Linear",
+    };
+
+    let mut text = String::new();
+    for (layer, page) in input.page_images.iter().enumerate() {
+        let _ = write!(
+            text,
+            "\n{}\nsize: {}, {}\nformat: {}\nfilter: {}, Linear\nrepeat: none\n",
+            resource_path(page),
+            atlas.size.0,
+            atlas.size.1,
+            format,
+            min_filter
+        );
+
+        for frame in atlas.frames.iter().filter(|f| f.layer as usize == layer) {
+            if frame.draw_transform.is_some() {
+                warnings.push(format!(
+                    "{} is drawn transformed, which a libGDX region cannot express",
+                    frame.key
+                ));
+                continue;
+            }
+
+            //
+            // offsets are measured from the bottom left of the untrimmed image
+            let (orig, offset) = match frame.trim {
+                Some(trim) => (
+                    (trim.source_width, trim.source_height),
+                    (trim.x, trim.source_height - trim.y - frame.height),
+                ),
+                None => ((frame.width, frame.height), (0, 0)),
+            };
+
+            let (name, index) = region_name(input, frame);
+            let _ = write!(
+                text,
+                "{}\n  rotate: false\n  xy: {}, {}\n  size: {}, {}\n",
+                name, frame.x, frame.y, frame.width, frame.height
+            );
+            if let Some(ns) = frame.nine_slice {
+                let _ = writeln!(
+                    text,
+                    "  split: {}, {}, {}, {}",
+                    ns.left, ns.right, ns.top, ns.bottom
+                );
+            }
+            let _ = write!(
+                text,
+                "  orig: {}, {}\n  offset: {}, {}\n  index: {}\n",
+                orig.0, orig.1, offset.0, offset.1, index
+            );
+        }
+    }
+
+    vec![ExportFile {
+        path: format!("{}.atlas", input.name).into(),
+        data: text.into_bytes(),
+    }]
+}
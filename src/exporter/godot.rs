@@ -0,0 +1,153 @@
+//! Godot 4 text resources. Every frame becomes an `AtlasTexture` over its page image,
+//! the animations a `SpriteFrames` resource using them.
+
+use super::{resource_path, ExportFile, ExportInput};
+use crate::atlas::{Animation, NamedTextureRegion, PlaybackDirection};
+use std::fmt::Write;
+
+/// Shown when an animation has no durations.
+const DEFAULT_FRAME_MS: u32 = 100;
+
+fn atlas_texture(frame: &NamedTextureRegion, page: &str) -> String {
+    //
+    // the margin restores the trimmed borders, the texture keeps the source size
+    let (left, top, width, height) = match frame.trim {
+        Some(trim) => (
+            trim.x,
+            trim.y,
+            trim.source_width - frame.width,
+            trim.source_height - frame.height,
+        ),
+        None => (0, 0, 0, 0),
+    };
+
+    format!(
+        "[gd_resource type=\"AtlasTexture\" load_steps=2 format=3]\n\
+         \n\
+         [ext_resource type=\"Texture2D\" path=\"{}\" id=\"1_page\"]\n\
+         \n\
+         [resource]\n\
+         atlas = ExtResource(\"1_page\")\n\
+         region = Rect2({}, {}, {}, {})\n\
+         margin = Rect2({}, {}, {}, {})\n\
+         filter_clip = true\n",
+        page, frame.x, frame.y, frame.width, frame.height, left, top, width, height
+    )
+}
+
+/// Frame keys and durations in playback order. `SpriteFrames` only plays forward, the
+/// direction goes into the frame list.
+fn playback(animation: &Animation) -> Vec<(&str, u32)> {
+    let frames = animation
+        .frames
+        .iter()
+        .enumerate()
+        .map(|(idx, key)| {
+            let ms = animation.durations_ms.get(idx).copied();
+            (key.as_str(), ms.unwrap_or(DEFAULT_FRAME_MS))
+        })
+        .collect::<Vec<_>>();
+
+    let reversed = frames.iter().rev().copied().collect::<Vec<_>>();
+
+    match animation.direction {
+        PlaybackDirection::Forward => frames,
+        PlaybackDirection::Reverse => reversed,
+        PlaybackDirection::PingPong => bounce(&frames, &reversed),
+        PlaybackDirection::PingPongReverse => bounce(&reversed, &frames),
+    }
+}
+
+/// `there`, then `back` without the frames at either end.
+fn bounce<'a>(there: &[(&'a str, u32)], back: &[(&'a str, u32)]) -> Vec<(&'a str, u32)> {
+    there
+        .iter()
+        .chain(back.iter().skip(1).take(back.len().saturating_sub(2)))
+        .copied()
+        .collect()
+}
+
+fn sprite_frames(animations: &[Animation], exported: &[&str], dir: &str) -> String {
+    let mut ids = Vec::<&str>::new();
+    let mut body = String::new();
+
+    for (idx, animation) in animations.iter().enumerate() {
+        let frames = playback(animation)
+            .into_iter()
+            .filter(|(key, _)| exported.contains(key))
+            .map(|(key, ms)| {
+                let id = match ids.iter().position(|k| *k == key) {
+                    Some(id) => id,
+                    None => {
+                        ids.push(key);
+                        ids.len() - 1
+                    }
+                };
+                format!(
+                    "{{\n\"duration\": {:?},\n\"texture\": ExtResource(\"{}\")\n}}",
+                    ms as f32 / 1000.0,
+                    id + 1
+                )
+            })
+            .collect::<Vec<_>>();
+
+        let _ = write!(
+            body,
+            "{}{{\n\"frames\": [{}],\n\"loop\": {},\n\"name\": &\"{}\",\n\"speed\": 1.0\n}}",
+            if idx == 0 { "" } else { ", " },
+            frames.join(", "),
+            animation.repeat == 0,
+            animation.name
+        );
+    }
+
+    let mut resource = format!(
+        "[gd_resource type=\"SpriteFrames\" load_steps={} format=3]\n\n",
+        ids.len() + 1
+    );
+    for (id, key) in ids.iter().enumerate() {
+        let _ = writeln!(
+            resource,
+            "[ext_resource type=\"Texture2D\" path=\"{}/{}.tres\" id=\"{}\"]",
+            dir,
+            key,
+            id + 1
+        );
+    }
+    let _ = write!(resource, "\n[resource]\nanimations = [{}]\n", body);
+    resource
+}
+
+pub fn files(input: &ExportInput, warnings: &mut Vec<String>) -> Vec<ExportFile> {
+    let dir = format!("{}_godot", input.name);
+    let mut files = Vec::new();
+    let mut exported = Vec::new();
+
+    for frame in input.atlas.frames.iter() {
+        if frame.draw_transform.is_some() {
+            warnings.push(format!(
+                "{} is drawn transformed, which an AtlasTexture cannot express",
+                frame.key
+            ));
+            continue;
+        }
+
+        //
+        // resource paths are relative to the resource
+        let page = std::path::Path::new("..").join(&input.page_images[frame.layer as usize]);
+        files.push(ExportFile {
+            path: std::path::Path::new(&dir).join(format!("{}.tres", frame.key)),
+            data: atlas_texture(frame, &resource_path(&page)).into_bytes(),
+        });
+        exported.push(frame.key.as_str());
+    }
+
+    if !input.atlas.animations.is_empty() {
+        files.push(ExportFile {
+            path: format!("{}.tres", input.name).into(),
+            data: sprite_frames(&input.atlas.animations, &exported, &dir).into_bytes(),
+        });
+    }
+
+    files
+}
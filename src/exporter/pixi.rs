@@ -0,0 +1,192 @@
+//! The TexturePacker "JSON (hash)" format read by the PixiJS and Phaser loaders, one
+//! sheet per page. Sheets of a multi page atlas list each other in
+//! `related_multi_packs`.
+
+use super::{resource_path, ExportFile, ExportInput};
+use crate::atlas::PixelFormat;
+use std::collections::BTreeMap;
+
+#[derive(serde::Serialize)]
+struct Rect {
+    x: u32,
+    y: u32,
+    w: u32,
+    h: u32,
+}
+
+#[derive(serde::Serialize)]
+struct Size {
+    w: u32,
+    h: u32,
+}
+
+#[derive(serde::Serialize)]
+struct Point {
+    x: f32,
+    y: f32,
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Frame {
+    frame: Rect,
+    rotated: bool,
+    trimmed: bool,
+    sprite_source_size: Rect,
+    source_size: Size,
+    /// Relative to the untrimmed sprite.
+    pivot: Point,
+    /// The outline of a polygon frame in pixels of the untrimmed sprite, and of the
+    /// sheet in `verticesUV`.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    vertices: Vec<[f32; 2]>,
+    #[serde(rename = "verticesUV", skip_serializing_if = "Vec::is_empty")]
+    vertices_uv: Vec<[f32; 2]>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    triangles: Vec<[u32; 3]>,
+}
+
+#[derive(serde::Serialize)]
+struct Meta {
+    app: &'static str,
+    version: &'static str,
+    image: String,
+    format: &'static str,
+    size: Size,
+    scale: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    related_multi_packs: Vec<String>,
+}
+
+#[derive(serde::Serialize)]
+struct Sheet<'a> {
+    frames: BTreeMap<&'a str, Frame>,
+    animations: BTreeMap<&'a str, &'a [String]>,
+    meta: Meta,
+}
+
+fn sheet_name(name: &str, page: usize, pages: usize) -> String {
+    match pages {
+        1 => format!("{}.pixi.json", name),
+        _ => format!("{}_{}.pixi.json", name, page),
+    }
+}
+
+pub fn files(input: &ExportInput, warnings: &mut Vec<String>) -> Vec<ExportFile> {
+    let atlas = input.atlas;
+    let page_count = input.page_images.len();
+    let format = match atlas.pixel_format {
+        PixelFormat::La8 => "LUMINANCE_ALPHA",
+        _ => "RGBA8888",
+    };
+
+    let mut sheets = input
+        .page_images
+        .iter()
+        .enumerate()
+        .map(|(idx, page)| Sheet {
+            frames: BTreeMap::new(),
+            animations: BTreeMap::new(),
+            meta: Meta {
+                app: env!("CARGO_PKG_NAME"),
+                version: env!("CARGO_PKG_VERSION"),
+                image: resource_path(page),
+                format,
+                size: Size {
+                    w: atlas.size.0,
+                    h: atlas.size.1,
+                },
+                scale: atlas.scale.to_string(),
+                related_multi_packs: (0..page_count)
+                    .filter(|&other| page_count > 1 && other != idx)
+                    .map(|other| sheet_name(input.name, other, page_count))
+                    .collect(),
+            },
+        })
+        .collect::<Vec<_>>();
+
+    let mut pages = fnv::FnvHashMap::default();
+    for frame in atlas.frames.iter() {
+        if frame.draw_transform.is_some() {
+            warnings.push(format!(
+                "{} is drawn transformed, which a Pixi frame cannot express",
+                frame.key
+            ));
+            continue;
+        }
+
+        let (trim_x, trim_y, source_width, source_height) = match frame.trim {
+            Some(trim) => (trim.x, trim.y, trim.source_width, trim.source_height),
+            None => (0, 0, frame.width, frame.height),
+        };
+        let outline = frame.polygon.as_ref();
+        let vertices = |(dx, dy): (u32, u32)| {
+            outline
+                .iter()
+                .flat_map(|p| p.vertices.iter())
+                .map(|&(x, y)| [x + dx as f32, y + dy as f32])
+                .collect()
+        };
+        pages.insert(frame.key.as_str(), frame.layer as usize);
+        sheets[frame.layer as usize].frames.insert(
+            &frame.key,
+            Frame {
+                frame: Rect {
+                    x: frame.x,
+                    y: frame.y,
+                    w: frame.width,
+                    h: frame.height,
+                },
+                rotated: false,
+                trimmed: frame.trim.is_some(),
+                sprite_source_size: Rect {
+                    x: trim_x,
+                    y: trim_y,
+                    w: frame.width,
+                    h: frame.height,
+                },
+                source_size: Size {
+                    w: source_width,
+                    h: source_height,
+                },
+                pivot: Point {
+                    x: (trim_x as f32 + frame.pivot_x * frame.width as f32) / source_width as f32,
+                    y: (trim_y as f32 + frame.pivot_y * frame.height as f32) / source_height as f32,
+                },
+                vertices: vertices((trim_x, trim_y)),
+                vertices_uv: vertices((frame.x, frame.y)),
+                triangles: outline
+                    .iter()
+                    .flat_map(|p| p.triangles.chunks_exact(3))
+                    .map(|t| [t[0], t[1], t[2]])
+                    .collect(),
+            },
+        );
+    }
+
+    //
+    // the loaders resolve animation frames within their sheet
+    for animation in atlas.animations.iter() {
+        let mut frame_pages = animation.frames.iter().map(|key| pages.get(key.as_str()));
+        match frame_pages.next().flatten().copied() {
+            Some(page) if frame_pages.all(|p| p == Some(&page)) => {
+                sheets[page]
+                    .animations
+                    .insert(&animation.name, &animation.frames);
+            }
+            _ => warnings.push(format!(
+                "animation {} is not on a single page, which a Pixi sheet cannot express",
+                animation.name
+            )),
+        }
+    }
+
+    sheets
+        .iter()
+        .enumerate()
+        .map(|(idx, sheet)| ExportFile {
+            path: sheet_name(input.name, idx, page_count).into(),
+            data: serde_json::to_vec_pretty(sheet).unwrap(),
+        })
+        .collect()
+}
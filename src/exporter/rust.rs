@@ -0,0 +1,118 @@
+//! A Rust module with an enum of the frames, for looking regions up with compile time
+//! checked names. The module depends on nothing, the regions are plain constants.
+
+use super::{camel_name, unique_names, ExportFile, ExportInput};
+use std::fmt::Write;
+
+/// `key` as an UpperCamelCase identifier.
+fn variant_name(key: &str) -> String {
+    let mut name = camel_name(key);
+
+    if !name.starts_with(|c: char| c.is_ascii_alphabetic()) {
+        name.insert_str(0, "Sprite");
+    }
+    if name == "Self" {
+        name += "_";
+    }
+    name
+}
+
+pub fn files(input: &ExportInput, _warnings: &mut Vec<String>) -> Vec<ExportFile> {
+    let frames = &input.atlas.frames;
+
+    let variants = unique_names(frames.iter().map(|frame| variant_name(&frame.key)));
+
+    let mut code = format!(
+        "// Generated by texture-packer from the atlas `{}`, do not edit.\n\
+         \n\
+         /// Where a frame sits in the atlas.\n\
+         #[derive(Copy, Clone, Debug, PartialEq)]\n\
+         pub struct Region {{\n\
+         \x20   /// Array layer (page).\n\
+         \x20   pub layer: u32,\n\
+         \x20   /// Pixel rect from the top left of the page.\n\
+         \x20   pub x: u32,\n\
+         \x20   pub y: u32,\n\
+         \x20   pub width: u32,\n\
+         \x20   pub height: u32,\n\
+         \x20   /// `(u0, v0, u1, v1)`, with the atlas UV inset applied.\n\
+         \x20   pub uv: [f32; 4],\n\
+         \x20   /// Normalized pivot, `(0, 0)` is the top left corner.\n\
+         \x20   pub pivot: [f32; 2],\n\
+         }}\n\
+         \n\
+         pub const PAGE_SIZE: (u32, u32) = ({}, {});\n\
+         \n\
+         #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]\n\
+         pub enum Sprite {{\n",
+        input.name, input.atlas.size.0, input.atlas.size.1
+    );
+    for (frame, variant) in frames.iter().zip(variants.iter()) {
+        let _ = writeln!(code, "    /// `{}`", frame.key);
+        let _ = writeln!(code, "    {},", variant);
+    }
+    code += "}\n\n";
+
+    let _ = writeln!(code, "pub const REGIONS: [Region; {}] = [", frames.len());
+    for frame in frames.iter() {
+        let (u0, v0, u1, v1) = frame.uv_rect();
+        let _ = writeln!(
+            code,
+            "    Region {{ layer: {}, x: {}, y: {}, width: {}, height: {}, uv: [{:?}, {:?}, {:?}, {:?}], pivot: [{:?}, {:?}] }},",
+            frame.layer,
+            frame.x,
+            frame.y,
+            frame.width,
+            frame.height,
+            u0,
+            v0,
+            u1,
+            v1,
+            frame.pivot_x,
+            frame.pivot_y
+        );
+    }
+    code += "];\n\n";
+
+    let _ = writeln!(code, "impl Sprite {{");
+    let _ = writeln!(code, "    pub const ALL: [Sprite; {}] = [", frames.len());
+    for variant in variants.iter() {
+        let _ = writeln!(code, "        Sprite::{},", variant);
+    }
+    code += "    ];\n\n";
+
+    code += "    /// The key the frame was packed under.\n";
+    code += "    pub const fn key(self) -> &'static str {\n        match self {\n";
+    for (frame, variant) in frames.iter().zip(variants.iter()) {
+        let _ = writeln!(code, "            Sprite::{} => {:?},", variant, frame.key);
+    }
+    code += "        }\n    }\n\n";
+
+    code += match frames.is_empty() {
+        true => "    pub const fn region(self) -> Region {\n        match self {}\n    }\n\n",
+        false => {
+            "    pub const fn region(self) -> Region {\n        REGIONS[self as usize]\n    }\n\n"
+        }
+    };
+
+    code += "    /// The frame packed under `key`.\n";
+    code += "    pub fn from_key(key: &str) -> Option<Sprite> {\n        match key {\n";
+    //
+    // clashing keys find the first frame, like the descriptor lookups
+    let mut keys = std::collections::BTreeSet::new();
+    for (frame, variant) in frames.iter().zip(variants.iter()) {
+        if keys.insert(frame.key.as_str()) {
+            let _ = writeln!(
+                code,
+                "            {:?} => Some(Sprite::{}),",
+                frame.key, variant
+            );
+        }
+    }
+    code += "            _ => None,\n        }\n    }\n}\n";
+
+    vec![ExportFile {
+        path: format!("{}.rs", input.name).into(),
+        data: code.into_bytes(),
+    }]
+}
@@ -0,0 +1,176 @@
+//! Shader includes with the frame regions as constant arrays, indexed by a constant per
+//! frame: WGSL with an array of region structs, GLSL with an array per field.
+
+use super::{atlas_prefix, camel_name, constant_name, unique_names, ExportFile, ExportInput};
+use std::fmt::Write;
+
+/// The identifier prefix and the constant naming every frame.
+fn frame_constants(input: &ExportInput) -> (String, Vec<String>) {
+    let prefix = atlas_prefix(input.name);
+    let names = unique_names(
+        input
+            .atlas
+            .frames
+            .iter()
+            .map(|frame| format!("{}_{}", prefix, constant_name(&frame.key))),
+    );
+    (prefix, names)
+}
+
+fn float(v: f32) -> String {
+    format!("{:?}", v)
+}
+
+pub fn wgsl(input: &ExportInput, _warnings: &mut Vec<String>) -> Vec<ExportFile> {
+    let frames = &input.atlas.frames;
+    let (prefix, names) = frame_constants(input);
+    let region = match camel_name(input.name) {
+        name if name.starts_with(|c: char| c.is_ascii_alphabetic()) => name + "Region",
+        name => format!("Atlas{}Region", name),
+    };
+
+    let mut code = format!(
+        "// Generated by texture-packer from the atlas `{}`, do not edit.\n\
+         \n\
+         // `rect` is x, y, width, height in pixels from the top left of the page, `uv` has\n\
+         // the atlas UV inset applied, (0, 0) is the top left corner of a pivot.\n\
+         struct {} {{\n\
+         \x20   layer: u32,\n\
+         \x20   rect: vec4<u32>,\n\
+         \x20   uv: vec4<f32>,\n\
+         \x20   pivot: vec2<f32>,\n\
+         }}\n\
+         \n\
+         const {p}_PAGE_SIZE: vec2<u32> = vec2<u32>({}u, {}u);\n\
+         const {p}_FRAME_COUNT: u32 = {}u;\n",
+        input.name,
+        region,
+        input.atlas.size.0,
+        input.atlas.size.1,
+        frames.len(),
+        p = prefix,
+    );
+
+    if !frames.is_empty() {
+        code += "\n";
+        for (idx, name) in names.iter().enumerate() {
+            let _ = writeln!(code, "const {}: u32 = {}u;", name, idx);
+        }
+
+        let _ = write!(
+            code,
+            "\nconst {}_REGIONS = array<{}, {}>(\n",
+            prefix,
+            region,
+            frames.len()
+        );
+        for frame in frames.iter() {
+            let (u0, v0, u1, v1) = frame.uv_rect();
+            let _ = writeln!(
+                code,
+                "    {}({}u, vec4<u32>({}u, {}u, {}u, {}u), vec4<f32>({}, {}, {}, {}), vec2<f32>({}, {})),",
+                region,
+                frame.layer,
+                frame.x,
+                frame.y,
+                frame.width,
+                frame.height,
+                float(u0),
+                float(v0),
+                float(u1),
+                float(v1),
+                float(frame.pivot_x),
+                float(frame.pivot_y)
+            );
+        }
+        code += ");\n";
+    }
+
+    vec![ExportFile {
+        path: format!("{}.wgsl", input.name).into(),
+        data: code.into_bytes(),
+    }]
+}
+
+pub fn glsl(input: &ExportInput, _warnings: &mut Vec<String>) -> Vec<ExportFile> {
+    let frames = &input.atlas.frames;
+    let (prefix, names) = frame_constants(input);
+    let count = frames.len();
+
+    let mut code = format!(
+        "// Generated by texture-packer from the atlas `{}`, do not edit.\n\
+         //\n\
+         // Rects are x, y, width, height in pixels from the top left of the page, UVs have\n\
+         // the atlas UV inset applied, (0, 0) is the top left corner of a pivot.\n\
+         \n\
+         const uvec2 {p}_PAGE_SIZE = uvec2({}u, {}u);\n\
+         const uint {p}_FRAME_COUNT = {}u;\n",
+        input.name,
+        input.atlas.size.0,
+        input.atlas.size.1,
+        count,
+        p = prefix,
+    );
+
+    if !frames.is_empty() {
+        code += "\n";
+        for (idx, name) in names.iter().enumerate() {
+            let _ = writeln!(code, "const uint {} = {}u;", name, idx);
+        }
+
+        let mut array = |ty: &str, field: &str, values: Vec<String>| {
+            let _ = write!(
+                code,
+                "\nconst {ty} {p}_{field}[{n}] = {ty}[{n}](\n    {}\n);\n",
+                values.join(",\n    "),
+                ty = ty,
+                p = prefix,
+                field = field,
+                n = count
+            );
+        };
+        array(
+            "uint",
+            "LAYERS",
+            frames.iter().map(|f| format!("{}u", f.layer)).collect(),
+        );
+        array(
+            "uvec4",
+            "RECTS",
+            frames
+                .iter()
+                .map(|f| format!("uvec4({}u, {}u, {}u, {}u)", f.x, f.y, f.width, f.height))
+                .collect(),
+        );
+        array(
+            "vec4",
+            "UVS",
+            frames
+                .iter()
+                .map(|f| {
+                    let (u0, v0, u1, v1) = f.uv_rect();
+                    format!(
+                        "vec4({}, {}, {}, {})",
+                        float(u0),
+                        float(v0),
+                        float(u1),
+                        float(v1)
+                    )
+                })
+                .collect(),
+        );
+        array(
+            "vec2",
+            "PIVOTS",
+            frames
+                .iter()
+                .map(|f| format!("vec2({}, {})", float(f.pivot_x), float(f.pivot_y)))
+                .collect(),
+        );
+    }
+
+    vec![ExportFile {
+        path: format!("{}.glsl", input.name).into(),
+        data: code.into_bytes(),
+    }]
+}
@@ -0,0 +1,115 @@
+//! JSON for the Unity editor script in `integrations/unity`, which slices the page
+//! textures into sprites. Field names follow Unity's `Rect`, `Vector2` and `Vector4`
+//! so `JsonUtility` reads them straight into those types.
+
+use super::{resource_path, ExportFile, ExportInput};
+
+#[derive(serde::Serialize)]
+struct Rect {
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+}
+
+#[derive(serde::Serialize)]
+struct Vector2 {
+    x: f32,
+    y: f32,
+}
+
+/// Sprite borders as Unity orders them: left, bottom, right, top.
+#[derive(serde::Serialize)]
+struct Vector4 {
+    x: u32,
+    y: u32,
+    z: u32,
+    w: u32,
+}
+
+#[derive(serde::Serialize)]
+struct Sprite<'a> {
+    name: &'a str,
+    rect: Rect,
+    pivot: Vector2,
+    border: Vector4,
+}
+
+#[derive(serde::Serialize)]
+struct Page<'a> {
+    /// Relative to the JSON file.
+    texture: String,
+    width: u32,
+    height: u32,
+    sprites: Vec<Sprite<'a>>,
+}
+
+#[derive(serde::Serialize)]
+struct Layout<'a> {
+    atlas: &'a str,
+    pages: Vec<Page<'a>>,
+}
+
+pub fn files(input: &ExportInput, warnings: &mut Vec<String>) -> Vec<ExportFile> {
+    let (width, height) = input.atlas.size;
+    let mut pages = input
+        .page_images
+        .iter()
+        .map(|page| Page {
+            texture: resource_path(page),
+            width,
+            height,
+            sprites: Vec::new(),
+        })
+        .collect::<Vec<_>>();
+
+    for frame in input.atlas.frames.iter() {
+        if frame.draw_transform.is_some() {
+            warnings.push(format!(
+                "{} is drawn transformed, which a Unity sprite cannot express",
+                frame.key
+            ));
+            continue;
+        }
+
+        //
+        // Unity measures from the bottom left
+        let border = frame.nine_slice.map_or(
+            Vector4 {
+                x: 0,
+                y: 0,
+                z: 0,
+                w: 0,
+            },
+            |ns| Vector4 {
+                x: ns.left,
+                y: ns.bottom,
+                z: ns.right,
+                w: ns.top,
+            },
+        );
+        pages[frame.layer as usize].sprites.push(Sprite {
+            name: &frame.key,
+            rect: Rect {
+                x: frame.x,
+                y: height - frame.y - frame.height,
+                width: frame.width,
+                height: frame.height,
+            },
+            pivot: Vector2 {
+                x: frame.pivot_x,
+                y: 1.0 - frame.pivot_y,
+            },
+            border,
+        });
+    }
+
+    let layout = Layout {
+        atlas: input.name,
+        pages,
+    };
+    vec![ExportFile {
+        path: format!("{}.unity.json", input.name).into(),
+        data: serde_json::to_vec_pretty(&layout).unwrap(),
+    }]
+}
@@ -0,0 +1,202 @@
+use crate::atlas::{region_name_hash, NamedTextureRegion, TextureAtlas};
+
+#[derive(clap::Args, Debug)]
+pub struct FindOptions {
+    /// Atlas description file (.ron) to search.
+    atlas: std::path::PathBuf,
+    /// Sprite name pattern, `*` and `?` wildcards are supported.
+    pattern: String,
+    /// Crop every matching frame out of its page into this folder. Without page images
+    /// the texture is decoded, block compressed ones need the `transcode` feature.
+    #[arg(long)]
+    crop_dir: Option<std::path::PathBuf>,
+}
+
+/// Glob style match supporting `*` (any run of characters) and `?` (any single character).
+fn wildcard_match(pattern: &str, text: &str) -> bool {
+    let pattern = pattern.chars().collect::<Vec<_>>();
+    let text = text.chars().collect::<Vec<_>>();
+
+    let (mut p, mut t) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            backtrack = Some((p, t));
+            p += 1;
+        } else if let Some((star_p, star_t)) = backtrack {
+            p = star_p + 1;
+            t = star_t + 1;
+            backtrack = Some((star_p, star_t + 1));
+        } else {
+            return false;
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+fn frame_matches(frame: &NamedTextureRegion, pattern: &str) -> bool {
+    //
+    // descriptors written before keys were recorded only have the name hash, their
+    // frames are keyed by it
+    wildcard_match(pattern, &frame.key)
+        || (!pattern.contains(['*', '?']) && region_name_hash(pattern) == frame.name)
+}
+
+pub fn run(options: FindOptions) -> Result<(), String> {
+    let atlas = TextureAtlas::load(&options.atlas)?;
+    let atlas_dir = options
+        .atlas
+        .parent()
+        .unwrap_or_else(|| std::path::Path::new("."));
+
+    let matches = atlas
+        .frames
+        .iter()
+        .filter(|frame| frame_matches(frame, &options.pattern))
+        .collect::<Vec<_>>();
+
+    if matches.is_empty() {
+        println!("No frames matching '{}'", options.pattern);
+        return Ok(());
+    }
+
+    if let Some(crop_dir) = options.crop_dir.as_ref() {
+        std::fs::create_dir_all(crop_dir)
+            .map_err(|e| format!("Failed to create {}: {}", crop_dir.display(), e))?;
+    }
+
+    let mut pages = Vec::new();
+
+    for frame in matches {
+        let (u0, v0, u1, v1) = frame.uv_rect_for(atlas.size, atlas.uv_inset);
+        println!(
+            "{} (0x{:016x}) layer {} page {} rect ({}, {}, {}, {}) uv ({:.6}, {:.6}, {:.6}, {:.6})",
+            if wildcard_match(&options.pattern, &frame.key) {
+                frame.key.as_str()
+            } else {
+                options.pattern.as_str()
+            },
+            frame.name,
+            frame.layer,
+            atlas
+                .pages
+                .get(frame.layer as usize)
+                .map(|p| p.display().to_string())
+                .unwrap_or_else(|| "?".to_string()),
+            frame.x,
+            frame.y,
+            frame.width,
+            frame.height,
+            u0,
+            v0,
+            u1,
+            v1
+        );
+
+        let Some(crop_dir) = options.crop_dir.as_ref() else {
+            continue;
+        };
+
+        //
+        // pages are loaded on the first crop, decoding the texture is slow
+        if pages.is_empty() {
+            pages = atlas.load_pages(atlas_dir)?;
+        }
+        let page = pages
+            .get(frame.layer as usize)
+            .ok_or_else(|| format!("Layer {} is out of range", frame.layer))?;
+
+        let cropped = page.crop_imm(frame.x, frame.y, frame.width, frame.height);
+        let crop_name = if frame.key.is_empty() {
+            format!("{:016x}.png", frame.name)
+        } else {
+            format!("{}.png", frame.key)
+        };
+        //
+        // keys with folders (prefixed, from manifests) crop into subfolders, but never
+        // out of the crop dir
+        let crop_name = std::path::Path::new(&crop_name);
+        if !crop_name
+            .components()
+            .all(|c| matches!(c, std::path::Component::Normal(_)))
+        {
+            return Err(format!(
+                "Can't crop '{}', the key is not a relative path",
+                frame.key
+            ));
+        }
+        let out_path = crop_dir.join(crop_name);
+        if let Some(dir) = out_path.parent() {
+            std::fs::create_dir_all(dir)
+                .map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+        }
+        cropped
+            .save_with_format(&out_path, image::ImageFormat::Png)
+            .map_err(|e| format!("Failed to save {}: {}", out_path.display(), e))?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn crop(dir: &std::path::Path, key: &str) -> Result<(), String> {
+        image::RgbaImage::from_pixel(16, 16, image::Rgba([255, 0, 0, 255]))
+            .save(dir.join("atlas_0.png"))
+            .unwrap();
+        let mut atlas = TextureAtlas::new("atlas.png", (16, 16));
+        atlas.pages = vec!["atlas_0.png".into()];
+        atlas.frames = vec![NamedTextureRegion::new(key, 0, (4, 4, 8, 8))];
+        let path = dir.join("atlas.ron");
+        std::fs::write(&path, ron::to_string(&atlas).unwrap()).unwrap();
+
+        run(FindOptions {
+            atlas: path,
+            pattern: "*".into(),
+            crop_dir: Some(dir.join("crops")),
+        })
+    }
+
+    #[test]
+    fn version_0_frames_are_found_by_name() {
+        let text = format!(
+            "(frames: [(name: {}, layer: 0, x: 0, y: 0, width: 4, height: 4)], \
+             size: (16, 16), file: \"atlas.png\")",
+            region_name_hash("hero")
+        );
+        let atlas = TextureAtlas::parse(&text).unwrap();
+        assert!(frame_matches(&atlas.frames[0], "hero"));
+        assert!(!frame_matches(&atlas.frames[0], "villain"));
+        assert!(!frame_matches(&atlas.frames[0], "her*"));
+    }
+
+    #[test]
+    fn nested_keys_crop_into_subfolders() {
+        let dir = std::env::temp_dir().join(format!("tp-find-nested-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        crop(&dir, "ui/icons/close").unwrap();
+        let cropped = image::open(dir.join("crops/ui/icons/close.png")).unwrap();
+        assert_eq!((cropped.width(), cropped.height()), (8, 8));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn keys_leaving_the_crop_dir_are_refused() {
+        let dir = std::env::temp_dir().join(format!("tp-find-escape-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        for key in ["../escaped", "/tmp/escaped", "ui/../../escaped"] {
+            assert!(crop(&dir, key).is_err(), "{}", key);
+        }
+        assert!(!dir.join("escaped.png").exists());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,75 @@
+//! Flipbooks with motion vectors: every frame image (`smoke_03.png`) can be paired
+//! with a motion vector image (`smoke_mv_03.png`, or `smoke_mv.png` for `smoke.png`).
+//! The vectors travel with the frame through all preprocessing and end up on pages of
+//! their own, at the same place as the frame.
+
+use crate::source::LoadedSource;
+
+const MARKER: &str = "_mv";
+
+/// Splits a trailing `_<digits>` frame number off a file stem.
+fn split_frame_number(stem: &str) -> (&str, &str) {
+    match stem.rfind('_') {
+        Some(at) if at + 1 < stem.len() && stem[at + 1..].bytes().all(|b| b.is_ascii_digit()) => {
+            stem.split_at(at)
+        }
+        _ => (stem, ""),
+    }
+}
+
+fn with_stem(path: &std::path::Path, stem: String) -> std::path::PathBuf {
+    let file_name = match path.extension() {
+        Some(ext) => format!("{}.{}", stem, ext.to_string_lossy()),
+        None => stem,
+    };
+    path.with_file_name(file_name)
+}
+
+/// The motion vector image belonging to a frame image.
+pub fn motion_partner(path: &std::path::Path) -> std::path::PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let (name, number) = split_frame_number(&stem);
+    with_stem(path, format!("{}{}{}", name, MARKER, number))
+}
+
+/// The frame image a motion vector image belongs to, if the name marks it as one.
+pub fn color_partner(path: &std::path::Path) -> Option<std::path::PathBuf> {
+    let stem = path.file_stem()?.to_string_lossy();
+    let (name, number) = split_frame_number(&stem);
+    name.strip_suffix(MARKER)
+        .filter(|name| !name.is_empty())
+        .map(|name| with_stem(path, format!("{}{}", name, number)))
+}
+
+/// Attaches the frames of a motion vector source to the frames of its color source.
+pub fn attach(color: &mut LoadedSource, motion: LoadedSource) -> Result<(), String> {
+    if color.sprites.len() != motion.sprites.len() {
+        return Err(format!(
+            "{} frames of color but {} of motion vectors",
+            color.sprites.len(),
+            motion.sprites.len()
+        ));
+    }
+
+    if let Some((c, m)) = color
+        .sprites
+        .iter()
+        .zip(motion.sprites.iter())
+        .find(|(c, m)| c.pixels.dimensions() != m.pixels.dimensions())
+    {
+        return Err(format!(
+            "{} is {}x{} but its motion vectors are {}x{}",
+            c.label(),
+            c.pixels.width(),
+            c.pixels.height(),
+            m.pixels.width(),
+            m.pixels.height()
+        ));
+    }
+
+    for (c, m) in color.sprites.iter_mut().zip(motion.sprites) {
+        c.motion = Some(m.pixels);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,180 @@
+//! Glyph sprites rasterized from TTF/OTF fonts.
+//!
+//! Every glyph of the charset becomes a sprite keyed `<font>_u<codepoint>` and is
+//! packed like any other sprite. The font's metrics go to the descriptor, linking
+//! each glyph to its frame.
+
+use crate::atlas::{FontFace, Glyph, GlyphMode, Kerning};
+use crate::diagnostic::{self, Code};
+use crate::sdf;
+use crate::source::{Pivot, SourceSprite};
+
+/// Characters to rasterize, as comma separated `U+XXXX` codepoints, `U+XXXX-U+YYYY`
+/// ranges or literal characters (`U+0020-U+007E,äöü`).
+#[derive(Clone, Debug)]
+pub struct Charset(pub Vec<char>);
+
+impl std::str::FromStr for Charset {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let codepoint = |s: &str| {
+            s.strip_prefix("U+")
+                .or_else(|| s.strip_prefix("u+"))
+                .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+                .and_then(char::from_u32)
+                .ok_or_else(|| format!("invalid codepoint '{}'", s))
+        };
+
+        let mut chars = Vec::new();
+        for item in s.split(',').filter(|item| !item.is_empty()) {
+            if !item.starts_with("U+") && !item.starts_with("u+") {
+                chars.extend(item.chars());
+                continue;
+            }
+
+            match item.split_once('-') {
+                Some((first, last)) => chars.extend(codepoint(first)?..=codepoint(last)?),
+                None => chars.push(codepoint(item)?),
+            }
+        }
+
+        chars.sort_unstable();
+        chars.dedup();
+        Ok(Charset(chars))
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct FontOptions {
+    pub charset: Charset,
+    /// Pixel size to rasterize at.
+    pub size: f32,
+    pub mode: GlyphMode,
+    /// Field range in pixels for `GlyphMode::Sdf`.
+    pub spread: u32,
+}
+
+/// Rasterizes the charset, at `options.size` times `scale`.
+pub fn rasterize(
+    path: &std::path::Path,
+    options: &FontOptions,
+    scale: f32,
+) -> Result<(Vec<SourceSprite>, FontFace), String> {
+    let data = std::fs::read(path).map_err(|e| e.to_string())?;
+    let size = options.size * scale;
+    let font = fontdue::Font::from_bytes(
+        data,
+        fontdue::FontSettings {
+            scale: size,
+            ..Default::default()
+        },
+    )
+    .map_err(|e| e.to_string())?;
+
+    let name = path.file_stem().unwrap().to_string_lossy().to_string();
+    let line = font
+        .horizontal_line_metrics(size)
+        .ok_or_else(|| "no horizontal metrics".to_string())?;
+    let padding = match options.mode {
+        GlyphMode::Plain => 0,
+        GlyphMode::Sdf => options.spread,
+    };
+
+    let (chars, missing): (Vec<char>, Vec<char>) = options
+        .charset
+        .0
+        .iter()
+        .partition(|&&c| font.lookup_glyph_index(c) != 0);
+    if !missing.is_empty() {
+        diagnostic::warn(
+            Code::MissingGlyphs,
+            format_args!(
+                "{} has no glyphs for {}",
+                path.display(),
+                missing.iter().collect::<String>()
+            ),
+        );
+    }
+
+    let mut sprites = Vec::new();
+    let mut glyphs = Vec::new();
+
+    for &c in chars.iter() {
+        let (metrics, coverage) = font.rasterize(c, size);
+        let (width, height) = (metrics.width as u32, metrics.height as u32);
+        let blank = width == 0 || height == 0 || coverage.iter().all(|&a| a == 0);
+
+        let key = Some(format!("{}_u{:04x}", name, c as u32)).filter(|_| !blank);
+        glyphs.push(Glyph::new(
+            c,
+            key.clone(),
+            metrics.advance_width,
+            (
+                metrics.xmin as f32 - padding as f32,
+                (metrics.ymin + metrics.height as i32 + padding as i32) as f32,
+            ),
+        ));
+
+        let Some(key) = key else {
+            continue;
+        };
+
+        let coverage = image::GrayImage::from_raw(width, height, coverage)
+            .ok_or_else(|| format!("invalid bitmap for '{}'", c))?;
+        let alpha = match options.mode {
+            GlyphMode::Plain => coverage,
+            GlyphMode::Sdf => sdf::distance_field(&coverage, options.spread),
+        };
+        let (width, height) = alpha.dimensions();
+
+        sprites.push(SourceSprite {
+            source: path.to_path_buf(),
+            frame: Some(c as u32),
+            key,
+            pixels: image::ImageBuffer::from_fn(width, height, |x, y| {
+                image::LumaA([255, alpha.get_pixel(x, y).0[0]])
+            }),
+            tag: None,
+            nine_slice: None,
+            events: Vec::new(),
+            //
+            // on the pen position, so glyphs can be drawn like any other sprite
+            pivot: Pivot(
+                (padding as i32 - metrics.xmin) as f32 / width as f32,
+                (metrics.height as i32 + metrics.ymin + padding as i32) as f32 / height as f32,
+            ),
+            split: None,
+            baked_transform: None,
+            variants: Vec::new(),
+            delta: None,
+            max_mip: None,
+            colored: false,
+            trim: None,
+            filter: None,
+            alpha: None,
+            motion: None,
+            hdr: None,
+            tone: None,
+            source_size: (width, height),
+        });
+    }
+
+    let kerning = chars
+        .iter()
+        .flat_map(|&left| chars.iter().map(move |&right| (left, right)))
+        .filter_map(|(left, right)| {
+            font.horizontal_kern(left, right, size)
+                .filter(|&amount| amount != 0.0)
+                .map(|amount| Kerning::new(left, right, amount))
+        })
+        .collect();
+
+    let mut face = FontFace::new(name, size, line.ascent, line.descent, line.new_line_size);
+    face.mode = options.mode;
+    face.spread = Some(options.spread).filter(|_| options.mode == GlyphMode::Sdf);
+    face.glyphs = glyphs;
+    face.kerning = kerning;
+
+    Ok((sprites, face))
+}
@@ -0,0 +1,309 @@
+//! Uploads atlas textures to wgpu, with the `gpu` feature. The texture comes with a
+//! view and a sampler for binding it:
+//!
+//! ```ignore
+//! let (atlas, texture) = AtlasTexture::load(&device, &queue, "out/sprites.ron".as_ref())?;
+//! let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+//!     label: Some("atlas"),
+//!     entries: &texture.layout_entries(0, 1, wgpu::ShaderStages::FRAGMENT),
+//! });
+//! let bind_group = texture.bind_group(&device, &layout, 0, 1);
+//! let (u0, v0, u1, v1) = atlas.frame("hero_idle").unwrap().uv_rect();
+//! ```
+//!
+//! Atlases are bound as `texture_2d_array<f32>`, cubemaps as `texture_cube<f32>` and
+//! volumes as `texture_3d<f32>`. Uncompressed RGBA textures are uploaded as they are,
+//! in their sRGB format when the texture is sRGB. The others (luminance/alpha, Basis and
+//! BCn with the `transcode` feature, PNG pages) are decoded to RGBA8 first, luminance
+//! in RGB and alpha in A, sRGB when the descriptor says so.
+
+use crate::atlas::{ColorSpace, TextureAtlas};
+use crate::ktx2::{self, Ktx2};
+use wgpu::util::DeviceExt;
+
+pub struct AtlasTexture {
+    pub texture: wgpu::Texture,
+    pub view: wgpu::TextureView,
+    pub view_dimension: wgpu::TextureViewDimension,
+    /// Clamped to the edges, filtering linearly between texels and mip levels unless the
+    /// format cannot be filtered (32-bit float).
+    pub sampler: wgpu::Sampler,
+}
+
+/// An RGBA8 format in the descriptor's color space.
+fn rgba8(atlas: &TextureAtlas) -> wgpu::TextureFormat {
+    match atlas.color_space {
+        ColorSpace::Srgb => wgpu::TextureFormat::Rgba8UnormSrgb,
+        ColorSpace::Linear => wgpu::TextureFormat::Rgba8Unorm,
+    }
+}
+
+impl AtlasTexture {
+    /// Uploads the KTX2 texture described by `atlas`, given as its file contents.
+    pub fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        atlas: &TextureAtlas,
+        ktx2: &[u8],
+    ) -> Result<AtlasTexture, String> {
+        let ktx = Ktx2::parse(ktx2.to_vec())?;
+
+        let (dimension, view_dimension, images) = match (ktx.depth, ktx.faces) {
+            (0, 6) if ktx.layers > 1 => (
+                wgpu::TextureDimension::D2,
+                wgpu::TextureViewDimension::CubeArray,
+                ktx.layers * 6,
+            ),
+            (0, 6) => (
+                wgpu::TextureDimension::D2,
+                wgpu::TextureViewDimension::Cube,
+                6,
+            ),
+            (0, _) => (
+                wgpu::TextureDimension::D2,
+                wgpu::TextureViewDimension::D2Array,
+                ktx.layers,
+            ),
+            (depth, _) => (
+                wgpu::TextureDimension::D3,
+                wgpu::TextureViewDimension::D3,
+                depth,
+            ),
+        };
+
+        let direct = match ktx.vk_format {
+            ktx2::VK_FORMAT_R8G8B8A8_SRGB => Some(wgpu::TextureFormat::Rgba8UnormSrgb),
+            ktx2::VK_FORMAT_R8G8B8A8_UNORM => Some(wgpu::TextureFormat::Rgba8Unorm),
+            ktx2::VK_FORMAT_R16G16B16A16_UNORM
+                if device
+                    .features()
+                    .contains(wgpu::Features::TEXTURE_FORMAT_16BIT_NORM) =>
+            {
+                Some(wgpu::TextureFormat::Rgba16Unorm)
+            }
+            ktx2::VK_FORMAT_R16G16B16A16_SFLOAT => Some(wgpu::TextureFormat::Rgba16Float),
+            ktx2::VK_FORMAT_R32G32B32A32_SFLOAT => Some(wgpu::TextureFormat::Rgba32Float),
+            _ => None,
+        };
+
+        //
+        // the levels one after the other, each holding every image, as wgpu takes them
+        let mut data = Vec::new();
+        for level in 0..ktx.levels.len() {
+            match direct {
+                Some(_) => data.extend_from_slice(&ktx.level_data(level)?),
+                None => {
+                    let images = match dimension {
+                        wgpu::TextureDimension::D3 => (images >> level).max(1),
+                        _ => images,
+                    };
+                    for image in 0..images {
+                        data.extend(ktx.decode_image(level, image)?.into_raw());
+                    }
+                }
+            }
+        }
+
+        Ok(Self::create(
+            device,
+            queue,
+            wgpu::TextureDescriptor {
+                label: atlas.file.to_str(),
+                size: wgpu::Extent3d {
+                    width: ktx.width,
+                    height: ktx.height,
+                    depth_or_array_layers: images,
+                },
+                mip_level_count: ktx.levels.len() as u32,
+                sample_count: 1,
+                dimension,
+                format: direct.unwrap_or_else(|| rgba8(atlas)),
+                usage: wgpu::TextureUsages::TEXTURE_BINDING
+                    | wgpu::TextureUsages::COPY_DST
+                    | wgpu::TextureUsages::COPY_SRC,
+                view_formats: &[],
+            },
+            view_dimension,
+            &data,
+        ))
+    }
+
+    /// Uploads page images, e.g. from [`TextureAtlas::load_pages`], as an RGBA8 array
+    /// without mip levels.
+    pub fn from_pages(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        atlas: &TextureAtlas,
+        pages: &[image::DynamicImage],
+    ) -> Result<AtlasTexture, String> {
+        let (width, height) = atlas.size;
+        let mut data = Vec::new();
+        for (idx, page) in pages.iter().enumerate() {
+            if (page.width(), page.height()) != (width, height) {
+                return Err(format!(
+                    "Page {} is {}x{}, the atlas pages are {}x{}",
+                    idx,
+                    page.width(),
+                    page.height(),
+                    width,
+                    height
+                ));
+            }
+            //
+            // luminance goes to RGB, as for the decoded textures
+            data.extend(page.to_rgba8().into_raw());
+        }
+
+        Ok(Self::create(
+            device,
+            queue,
+            wgpu::TextureDescriptor {
+                label: atlas.file.to_str(),
+                size: wgpu::Extent3d {
+                    width,
+                    height,
+                    depth_or_array_layers: pages.len() as u32,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: rgba8(atlas),
+                usage: wgpu::TextureUsages::TEXTURE_BINDING
+                    | wgpu::TextureUsages::COPY_DST
+                    | wgpu::TextureUsages::COPY_SRC,
+                view_formats: &[],
+            },
+            wgpu::TextureViewDimension::D2Array,
+            &data,
+        ))
+    }
+
+    /// Loads a descriptor (`.ron`, or a KTX2 with the descriptor embedded) and uploads
+    /// its texture, or its pages when they are images.
+    pub fn load(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        path: &std::path::Path,
+    ) -> Result<(TextureAtlas, AtlasTexture), String> {
+        let atlas = TextureAtlas::load(path)?;
+        let dir = path.parent().unwrap_or(std::path::Path::new(""));
+
+        let texture = match atlas
+            .file
+            .extension()
+            .is_some_and(|e| e.eq_ignore_ascii_case("ktx2"))
+            && atlas.pages.is_empty()
+        {
+            true => {
+                let texture_path = dir.join(&atlas.file);
+                let data = std::fs::read(&texture_path)
+                    .map_err(|e| format!("Failed to read {}: {}", texture_path.display(), e))?;
+                Self::new(device, queue, &atlas, &data)
+            }
+            false => Self::from_pages(device, queue, &atlas, &atlas.load_pages(dir)?),
+        }?;
+        Ok((atlas, texture))
+    }
+
+    fn create(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        descriptor: wgpu::TextureDescriptor,
+        view_dimension: wgpu::TextureViewDimension,
+        data: &[u8],
+    ) -> AtlasTexture {
+        let texture = device.create_texture_with_data(
+            queue,
+            &descriptor,
+            wgpu::util::TextureDataOrder::MipMajor,
+            data,
+        );
+        let view = texture.create_view(&wgpu::TextureViewDescriptor {
+            dimension: Some(view_dimension),
+            ..Default::default()
+        });
+
+        let filter = match descriptor.format == wgpu::TextureFormat::Rgba32Float {
+            true => wgpu::FilterMode::Nearest,
+            false => wgpu::FilterMode::Linear,
+        };
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: descriptor.label,
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: filter,
+            min_filter: filter,
+            mipmap_filter: filter,
+            ..Default::default()
+        });
+
+        AtlasTexture {
+            texture,
+            view,
+            view_dimension,
+            sampler,
+        }
+    }
+
+    fn filterable(&self) -> bool {
+        self.texture.format() != wgpu::TextureFormat::Rgba32Float
+    }
+
+    /// Bind group layout entries for the texture and its sampler.
+    pub fn layout_entries(
+        &self,
+        texture_binding: u32,
+        sampler_binding: u32,
+        visibility: wgpu::ShaderStages,
+    ) -> [wgpu::BindGroupLayoutEntry; 2] {
+        [
+            wgpu::BindGroupLayoutEntry {
+                binding: texture_binding,
+                visibility,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Float {
+                        filterable: self.filterable(),
+                    },
+                    view_dimension: self.view_dimension,
+                    multisampled: false,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: sampler_binding,
+                visibility,
+                ty: wgpu::BindingType::Sampler(match self.filterable() {
+                    true => wgpu::SamplerBindingType::Filtering,
+                    false => wgpu::SamplerBindingType::NonFiltering,
+                }),
+                count: None,
+            },
+        ]
+    }
+
+    /// A bind group of the texture and its sampler, for a layout made from
+    /// [`Self::layout_entries`].
+    pub fn bind_group(
+        &self,
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        texture_binding: u32,
+        sampler_binding: u32,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: texture_binding,
+                    resource: wgpu::BindingResource::TextureView(&self.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: sampler_binding,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+            ],
+        })
+    }
+}
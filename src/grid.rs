@@ -0,0 +1,133 @@
+//! Grid layout for tilesets: uniform cells filled in row-major order, no rect packing.
+
+use crate::atlas::SplitPart;
+use crate::planner::{Placed, Placement};
+use crate::source::SourceSprite;
+
+/// Cell size, `WxH` on the command line.
+#[derive(Copy, Clone, Debug)]
+pub struct GridSize {
+    pub width: u32,
+    pub height: u32,
+}
+
+impl std::str::FromStr for GridSize {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.split_once('x')
+            .and_then(|(w, h)| Some((w.trim().parse().ok()?, h.trim().parse().ok()?)))
+            .filter(|&(w, h)| w > 0 && h > 0)
+            .map(|(width, height)| GridSize { width, height })
+            .ok_or_else(|| format!("invalid grid size '{}', expected WxH", s))
+    }
+}
+
+impl GridSize {
+    fn columns(&self, page_size: (u32, u32)) -> u32 {
+        page_size.0 / self.width
+    }
+
+    fn cells_per_page(&self, page_size: (u32, u32)) -> u32 {
+        self.columns(page_size) * (page_size.1 / self.height)
+    }
+
+    /// Index of the cell a placed sprite occupies, counting across pages.
+    pub fn tile_index(&self, placed: &Placed, page_size: (u32, u32)) -> u32 {
+        placed.page * self.cells_per_page(page_size)
+            + placed.y / self.height * self.columns(page_size)
+            + placed.x / self.width
+    }
+}
+
+/// Cuts a tilesheet into cells, row by row. Sprites no larger than a cell are returned
+/// unchanged.
+pub fn slice(sprite: SourceSprite, grid: GridSize) -> Vec<SourceSprite> {
+    let (width, height) = sprite.pixels.dimensions();
+    if width <= grid.width && height <= grid.height {
+        return vec![sprite];
+    }
+
+    let columns = width.div_ceil(grid.width);
+    let mut tiles = Vec::new();
+
+    for y in (0..height).step_by(grid.height as usize) {
+        for x in (0..width).step_by(grid.width as usize) {
+            let (w, h) = ((width - x).min(grid.width), (height - y).min(grid.height));
+
+            tiles.push(SourceSprite {
+                key: format!(
+                    "{}_{}",
+                    sprite.key,
+                    y / grid.height * columns + x / grid.width
+                ),
+                split: Some(SplitPart::new(sprite.key.clone(), x, y, (width, height))),
+                ..sprite.crop(x, y, w, h)
+            });
+        }
+    }
+
+    tiles
+}
+
+/// Puts the sprites into consecutive cells, in the order given.
+pub fn place(
+    sprites: &[SourceSprite],
+    order: &[usize],
+    grid: GridSize,
+    page_size: (u32, u32),
+    max_pages: u32,
+) -> Result<Placement<usize>, String> {
+    let per_page = grid.cells_per_page(page_size);
+    if per_page == 0 {
+        return Err(format!(
+            "grid cells of {}x{} don't fit on a {}x{} page",
+            grid.width, grid.height, page_size.0, page_size.1
+        ));
+    }
+
+    let oversized = order
+        .iter()
+        .map(|&id| &sprites[id])
+        .filter(|s| s.pixels.width() > grid.width || s.pixels.height() > grid.height)
+        .map(|s| s.label())
+        .collect::<Vec<_>>();
+    if !oversized.is_empty() {
+        return Err(format!(
+            "larger than a {}x{} grid cell: {}",
+            grid.width,
+            grid.height,
+            oversized.join(", ")
+        ));
+    }
+
+    if order.len() as u64 > per_page as u64 * max_pages as u64 {
+        return Err(format!(
+            "{} tiles don't fit on {} pages of {} cells",
+            order.len(),
+            max_pages,
+            per_page
+        ));
+    }
+
+    let columns = grid.columns(page_size);
+
+    Ok(order
+        .iter()
+        .enumerate()
+        .map(|(idx, &id)| {
+            let (page, cell) = (idx as u32 / per_page, idx as u32 % per_page);
+            let (width, height) = sprites[id].pixels.dimensions();
+            (
+                id,
+                Placed {
+                    page,
+                    x: cell % columns * grid.width,
+                    y: cell / columns * grid.height,
+                    width,
+                    height,
+                },
+            )
+        })
+        .collect())
+}
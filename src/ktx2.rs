@@ -0,0 +1,824 @@
+//! Minimal KTX2 container reader, enough to get at the atlas layers and key/value data,
+//! and a writer for the uncompressed formats `toktx` does not take. Compressed layers
+//! can be read back with the `transcode` feature (UASTC, BCn).
+//! See <https://registry.khronos.org/KTX/specs/2.0/ktxspec.v2.html>.
+
+const IDENTIFIER: [u8; 12] = [
+    0xAB, 0x4B, 0x54, 0x58, 0x20, 0x32, 0x30, 0xBB, 0x0D, 0x0A, 0x1A, 0x0A,
+];
+
+pub const VK_FORMAT_R8_UNORM: u32 = 9;
+pub const VK_FORMAT_R8_SRGB: u32 = 15;
+pub const VK_FORMAT_R8G8_UNORM: u32 = 16;
+pub const VK_FORMAT_R8G8B8_UNORM: u32 = 23;
+pub const VK_FORMAT_R8G8B8_SRGB: u32 = 29;
+pub const VK_FORMAT_R8G8B8A8_UNORM: u32 = 37;
+pub const VK_FORMAT_R8G8B8A8_SRGB: u32 = 43;
+pub const VK_FORMAT_R16G16B16A16_UNORM: u32 = 91;
+pub const VK_FORMAT_R16G16B16A16_SFLOAT: u32 = 97;
+pub const VK_FORMAT_R32G32B32A32_SFLOAT: u32 = 109;
+pub const VK_FORMAT_BC1_RGB_UNORM_BLOCK: u32 = 131;
+pub const VK_FORMAT_BC7_SRGB_BLOCK: u32 = 146;
+
+/// Data format descriptor color models of the Basis encodes, whose vkFormat is 0.
+const KHR_DF_MODEL_ETC1S: u8 = 163;
+const KHR_DF_MODEL_UASTC: u8 = 166;
+
+const SUPERCOMPRESSION_BASIS_LZ: u32 = 1;
+const SUPERCOMPRESSION_ZSTD: u32 = 2;
+const SUPERCOMPRESSION_ZLIB: u32 = 3;
+
+pub struct Level {
+    pub offset: u64,
+    pub length: u64,
+    pub uncompressed_length: u64,
+}
+
+pub struct Ktx2 {
+    pub vk_format: u32,
+    pub type_size: u32,
+    pub width: u32,
+    pub height: u32,
+    pub depth: u32,
+    pub layers: u32,
+    pub faces: u32,
+    pub supercompression: u32,
+    /// Color model of the data format descriptor, tells the Basis encodes apart.
+    pub color_model: u8,
+    pub levels: Vec<Level>,
+    pub key_values: Vec<(String, Vec<u8>)>,
+    data: Vec<u8>,
+}
+
+fn read_u32(data: &[u8], at: usize) -> Result<u32, String> {
+    data.get(at..at + 4)
+        .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .ok_or_else(|| "truncated KTX2 file".to_string())
+}
+
+fn read_u64(data: &[u8], at: usize) -> Result<u64, String> {
+    Ok(read_u32(data, at)? as u64 | (read_u32(data, at + 4)? as u64) << 32)
+}
+
+fn parse_key_values(data: &[u8]) -> Result<Vec<(String, Vec<u8>)>, String> {
+    let mut entries = Vec::new();
+    let mut pos = 0;
+
+    while pos + 4 <= data.len() {
+        let len = read_u32(data, pos)? as usize;
+        let entry = data
+            .get(pos + 4..pos + 4 + len)
+            .ok_or_else(|| "truncated KTX2 key/value data".to_string())?;
+
+        //
+        // key and value are separated by the key's NUL terminator
+        let split = entry
+            .iter()
+            .position(|&b| b == 0)
+            .ok_or_else(|| "unterminated KTX2 key".to_string())?;
+        let key = String::from_utf8_lossy(&entry[..split]).into_owned();
+        entries.push((key, entry[split + 1..].to_vec()));
+
+        pos += 4 + len;
+        pos = (pos + 3) & !3;
+    }
+
+    Ok(entries)
+}
+
+/// Whether the first bytes of a file are the KTX2 identifier.
+pub fn is_ktx2(header: &[u8]) -> bool {
+    header.starts_with(&IDENTIFIER)
+}
+
+impl Ktx2 {
+    pub fn parse(data: Vec<u8>) -> Result<Ktx2, String> {
+        if data.len() < 80 || data[..12] != IDENTIFIER {
+            return Err("not a KTX2 file".to_string());
+        }
+
+        let level_count = read_u32(&data, 40)?.max(1);
+        let dfd_offset = read_u32(&data, 48)? as usize;
+        let kvd_offset = read_u32(&data, 56)? as usize;
+        let kvd_length = read_u32(&data, 60)? as usize;
+
+        let levels = (0..level_count as usize)
+            .map(|i| {
+                let at = 80 + i * 24;
+                Ok(Level {
+                    offset: read_u64(&data, at)?,
+                    length: read_u64(&data, at + 8)?,
+                    uncompressed_length: read_u64(&data, at + 16)?,
+                })
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+
+        let key_values = match kvd_length {
+            0 => Vec::new(),
+            _ => parse_key_values(
+                data.get(kvd_offset..kvd_offset + kvd_length)
+                    .ok_or_else(|| "truncated KTX2 key/value data".to_string())?,
+            )?,
+        };
+
+        Ok(Ktx2 {
+            vk_format: read_u32(&data, 12)?,
+            type_size: read_u32(&data, 16)?,
+            width: read_u32(&data, 20)?,
+            height: read_u32(&data, 24)?,
+            depth: read_u32(&data, 28)?,
+            layers: read_u32(&data, 32)?.max(1),
+            faces: read_u32(&data, 36)?.max(1),
+            supercompression: read_u32(&data, 44)?,
+            //
+            // the first byte of the basic block after its header words
+            color_model: match dfd_offset {
+                0 => 0,
+                _ => data.get(dfd_offset + 12).copied().unwrap_or_default(),
+            },
+            levels,
+            key_values,
+            data,
+        })
+    }
+
+    pub fn open(path: &std::path::Path) -> Result<Ktx2, String> {
+        let data =
+            std::fs::read(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        Ktx2::parse(data)
+    }
+
+    pub fn key_value(&self, key: &str) -> Option<&[u8]> {
+        self.key_values
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_slice())
+    }
+
+    /// Bytes per pixel for the uncompressed formats the packer writes.
+    pub fn bytes_per_pixel(&self) -> Option<usize> {
+        match self.vk_format {
+            VK_FORMAT_R8_UNORM | VK_FORMAT_R8_SRGB => Some(1),
+            VK_FORMAT_R8G8_UNORM => Some(2),
+            VK_FORMAT_R8G8B8_UNORM | VK_FORMAT_R8G8B8_SRGB => Some(3),
+            VK_FORMAT_R8G8B8A8_UNORM | VK_FORMAT_R8G8B8A8_SRGB => Some(4),
+            _ => None,
+        }
+    }
+
+    /// Bytes per texel of every uncompressed format the packer writes, including the
+    /// 16-bit and float ones.
+    pub fn texel_size(&self) -> Option<usize> {
+        match self.vk_format {
+            VK_FORMAT_R16G16B16A16_UNORM | VK_FORMAT_R16G16B16A16_SFLOAT => Some(8),
+            VK_FORMAT_R32G32B32A32_SFLOAT => Some(16),
+            _ => self.bytes_per_pixel(),
+        }
+    }
+
+    /// Pixels of one array layer of the base mip level. Only works for uncompressed
+    /// formats without supercompression.
+    pub fn layer_pixels(&self, layer: u32) -> Result<&[u8], String> {
+        if self.supercompression != 0 {
+            return Err(format!(
+                "supercompression scheme {} is not supported",
+                self.supercompression
+            ));
+        }
+
+        let bpp = self
+            .bytes_per_pixel()
+            .ok_or_else(|| format!("unsupported vkFormat {}", self.vk_format))?;
+        let layer_size = (self.width as usize)
+            .checked_mul(self.height as usize)
+            .and_then(|texels| texels.checked_mul(bpp * self.faces as usize));
+        let start = layer_size
+            .and_then(|size| size.checked_mul(layer as usize))
+            .and_then(|start| start.checked_add(self.levels[0].offset as usize));
+
+        start
+            .zip(layer_size)
+            .and_then(|(start, size)| self.data.get(start..start.checked_add(size)?))
+            .ok_or_else(|| format!("layer {} is out of range", layer))
+    }
+}
+
+impl Ktx2 {
+    /// Data of a mip level with the supercompression undone.
+    pub(crate) fn level_data(&self, level: usize) -> Result<std::borrow::Cow<'_, [u8]>, String> {
+        let info = self
+            .levels
+            .get(level)
+            .ok_or_else(|| format!("level {} is out of range", level))?;
+        let data = info
+            .offset
+            .checked_add(info.length)
+            .and_then(|end| self.data.get(info.offset as usize..end as usize))
+            .ok_or_else(|| "truncated KTX2 level data".to_string())?;
+
+        let inflated = match self.supercompression {
+            0 => return Ok(std::borrow::Cow::Borrowed(data)),
+            SUPERCOMPRESSION_ZLIB => {
+                let mut out = Vec::with_capacity(info.uncompressed_length as usize);
+                std::io::Read::read_to_end(&mut flate2::read::ZlibDecoder::new(data), &mut out)
+                    .map(|_| out)
+                    .map_err(|e| e.to_string())
+            }
+            #[cfg(feature = "transcode")]
+            SUPERCOMPRESSION_ZSTD => {
+                let mut out = Vec::with_capacity(info.uncompressed_length as usize);
+                ruzstd::decoding::StreamingDecoder::new(data)
+                    .map_err(|e| e.to_string())
+                    .and_then(|mut decoder| {
+                        std::io::Read::read_to_end(&mut decoder, &mut out)
+                            .map(|_| out)
+                            .map_err(|e| e.to_string())
+                    })
+            }
+            #[cfg(not(feature = "transcode"))]
+            SUPERCOMPRESSION_ZSTD => Err("zstd needs the transcode feature".to_string()),
+            //
+            // the basis transcoder bindings only take whole .basis files for ETC1S
+            SUPERCOMPRESSION_BASIS_LZ => {
+                Err("ETC1S (BasisLZ) textures cannot be read back, only UASTC".to_string())
+            }
+            scheme => Err(format!(
+                "supercompression scheme {} is not supported",
+                scheme
+            )),
+        };
+
+        inflated
+            .map(std::borrow::Cow::Owned)
+            .map_err(|e| format!("Failed to decompress level {}: {}", level, e))
+    }
+
+    /// Decodes one array layer of a mip level to 8-bit RGBA. Two channel textures come
+    /// out as `(r, r, r, g)`, the luminance/alpha layout the Basis encodes store, so
+    /// every format has luminance in red and alpha in alpha. Single channel textures
+    /// come out as opaque `(r, r, r)`. The 16-bit and float formats are clamped to
+    /// `[0, 1]`.
+    pub fn decode(&self, level: usize, layer: u32) -> Result<image::RgbaImage, String> {
+        self.decode_image(level, layer.saturating_mul(self.faces))
+    }
+
+    /// Decodes one image of a mip level like [`Self::decode`], the images being the
+    /// faces of every layer, or the slices of a 3D texture, one after the other.
+    pub fn decode_image(&self, level: usize, image: u32) -> Result<image::RgbaImage, String> {
+        let data = self.level_data(level)?;
+        let size = |base: u32| base.checked_shr(level as u32).unwrap_or(0).max(1);
+        let (width, height) = (size(self.width), size(self.height));
+        //
+        // with that many texels even the 16 byte formats would not fit in the address space
+        let texels = width as usize * height as usize;
+        if texels.checked_mul(16).is_none() {
+            return Err(format!("{}x{} is too large to decode", width, height));
+        }
+
+        let layer_data = |layer_size: usize| {
+            layer_size
+                .checked_mul(image as usize)
+                .and_then(|start| data.get(start..start.checked_add(layer_size)?))
+                .ok_or_else(|| format!("image {} is out of range", image))
+        };
+
+        let unorm = |v: f32| (v.clamp(0.0, 1.0) * 255.0).round() as u8;
+        let rgba = match self.vk_format {
+            VK_FORMAT_R8_UNORM | VK_FORMAT_R8_SRGB => layer_data(texels)?
+                .iter()
+                .flat_map(|&r| [r, r, r, 255])
+                .collect(),
+            VK_FORMAT_R8G8B8_UNORM | VK_FORMAT_R8G8B8_SRGB => layer_data(texels * 3)?
+                .chunks_exact(3)
+                .flat_map(|p| [p[0], p[1], p[2], 255])
+                .collect(),
+            VK_FORMAT_R8G8_UNORM => layer_data(texels * 2)?
+                .chunks_exact(2)
+                .flat_map(|p| [p[0], p[0], p[0], p[1]])
+                .collect(),
+            VK_FORMAT_R8G8B8A8_UNORM | VK_FORMAT_R8G8B8A8_SRGB => layer_data(texels * 4)?.to_vec(),
+            VK_FORMAT_R16G16B16A16_UNORM => layer_data(texels * 8)?
+                .chunks_exact(2)
+                .map(|c| (u16::from_le_bytes([c[0], c[1]]) >> 8) as u8)
+                .collect(),
+            VK_FORMAT_R16G16B16A16_SFLOAT => layer_data(texels * 8)?
+                .chunks_exact(2)
+                .map(|c| unorm(f16_to_f32(u16::from_le_bytes([c[0], c[1]]))))
+                .collect(),
+            VK_FORMAT_R32G32B32A32_SFLOAT => layer_data(texels * 16)?
+                .chunks_exact(4)
+                .map(|c| unorm(f32::from_le_bytes([c[0], c[1], c[2], c[3]])))
+                .collect(),
+            _ => self.decode_blocks(&layer_data, width, height)?,
+        };
+
+        image::RgbaImage::from_raw(width, height, rgba)
+            .ok_or_else(|| "decoded layer has the wrong size".to_string())
+    }
+
+    #[cfg(feature = "transcode")]
+    fn decode_blocks<'a>(
+        &self,
+        layer_data: &dyn Fn(usize) -> Result<&'a [u8], String>,
+        width: u32,
+        height: u32,
+    ) -> Result<Vec<u8>, String> {
+        let blocks = width.div_ceil(4) as usize * height.div_ceil(4) as usize;
+
+        if self.vk_format == 0 && self.color_model == KHR_DF_MODEL_UASTC {
+            use basis_universal::{
+                DecodeFlags, LowLevelUastcTranscoder, SliceParametersUastc, TranscoderBlockFormat,
+            };
+
+            return LowLevelUastcTranscoder::new()
+                .transcode_slice(
+                    layer_data(blocks * 16)?,
+                    SliceParametersUastc {
+                        num_blocks_x: width.div_ceil(4),
+                        num_blocks_y: height.div_ceil(4),
+                        has_alpha: true,
+                        original_width: width,
+                        original_height: height,
+                    },
+                    DecodeFlags::HIGH_QUALITY,
+                    TranscoderBlockFormat::RGBA32,
+                )
+                .map_err(|e| format!("Failed to transcode UASTC: {:?}", e));
+        }
+
+        type Decoder = fn(&[u8], usize, usize, &mut [u32]) -> Result<(), &'static str>;
+        let (decoder, block_size): (Decoder, usize) = match self.vk_format {
+            131 | 132 => (texture2ddecoder::decode_bc1, 8),
+            133 | 134 => (texture2ddecoder::decode_bc1a, 8),
+            135 | 136 => (texture2ddecoder::decode_bc2, 16),
+            137 | 138 => (texture2ddecoder::decode_bc3, 16),
+            139 | 140 => (texture2ddecoder::decode_bc4, 8),
+            141 | 142 => (texture2ddecoder::decode_bc5, 16),
+            145 | 146 => (texture2ddecoder::decode_bc7, 16),
+            _ => return Err(self.unsupported()),
+        };
+
+        let mut pixels = vec![0u32; width as usize * height as usize];
+        decoder(
+            layer_data(blocks * block_size)?,
+            width as usize,
+            height as usize,
+            &mut pixels,
+        )
+        .map_err(|e| format!("Failed to decode vkFormat {}: {}", self.vk_format, e))?;
+
+        //
+        // the decoder packs BGRA
+        Ok(pixels
+            .into_iter()
+            .flat_map(|p| {
+                let [b, g, r, a] = p.to_le_bytes();
+                [r, g, b, a]
+            })
+            .collect())
+    }
+
+    #[cfg(not(feature = "transcode"))]
+    fn decode_blocks<'a>(
+        &self,
+        _layer_data: &dyn Fn(usize) -> Result<&'a [u8], String>,
+        _width: u32,
+        _height: u32,
+    ) -> Result<Vec<u8>, String> {
+        match self.is_block_compressed() {
+            true => Err(format!(
+                "{}, rebuild with the transcode feature to read it back",
+                self.unsupported()
+            )),
+            false => Err(self.unsupported()),
+        }
+    }
+
+    #[cfg(not(feature = "transcode"))]
+    fn is_block_compressed(&self) -> bool {
+        (VK_FORMAT_BC1_RGB_UNORM_BLOCK..=VK_FORMAT_BC7_SRGB_BLOCK).contains(&self.vk_format)
+            || matches!(self.color_model, KHR_DF_MODEL_UASTC | KHR_DF_MODEL_ETC1S)
+    }
+
+    fn unsupported(&self) -> String {
+        match (self.vk_format, self.color_model) {
+            (0, KHR_DF_MODEL_UASTC) => "UASTC texture".to_string(),
+            (0, KHR_DF_MODEL_ETC1S) => "ETC1S texture".to_string(),
+            (format, _) => format!("unsupported vkFormat {}", format),
+        }
+    }
+}
+
+fn f16_to_f32(bits: u16) -> f32 {
+    let sign = if bits & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exp = (bits >> 10 & 0x1f) as i32;
+    let mant = (bits & 0x3ff) as f32;
+
+    sign * match exp {
+        0 => mant * 2f32.powi(-24),
+        0x1f if mant == 0.0 => f32::INFINITY,
+        0x1f => f32::NAN,
+        _ => (1.0 + mant / 1024.0) * 2f32.powi(exp - 15),
+    }
+}
+
+/// RGBA texel layout of [`write`].
+#[derive(Copy, Clone, Debug)]
+pub struct RgbaFormat {
+    pub vk_format: u32,
+    /// Bytes per channel.
+    pub channel_size: u32,
+    pub float: bool,
+    pub premultiplied: bool,
+}
+
+/// Basic data format descriptor for four linear RGBA channels.
+fn rgba_dfd(format: RgbaFormat) -> Vec<u8> {
+    const SAMPLE_COUNT: u32 = 4;
+    let block_size = 24 + 16 * SAMPLE_COUNT;
+    let bits = format.channel_size * 8;
+
+    let mut dfd = Vec::new();
+    dfd.extend((4 + block_size).to_le_bytes());
+    dfd.extend(0u32.to_le_bytes()); // vendor KHR, basic descriptor block
+    dfd.extend(2u16.to_le_bytes()); // version
+    dfd.extend((block_size as u16).to_le_bytes());
+    dfd.extend([
+        1, // RGBSDA color model
+        1, // BT.709 primaries
+        1, // linear transfer
+        format.premultiplied as u8,
+    ]);
+    dfd.extend([0u8; 4]); // 1x1x1x1 texel block
+    dfd.extend([(format.channel_size * 4) as u8, 0, 0, 0, 0, 0, 0, 0]);
+
+    for (idx, channel) in [0u8, 1, 2, 15].into_iter().enumerate() {
+        let qualifiers = match format.float {
+            true => 0x80 | 0x40, // float, signed
+            false => 0,
+        };
+        let (lower, upper) = match format.float {
+            true => ((-1.0f32).to_bits(), 1.0f32.to_bits()),
+            false => (0, u32::MAX >> (32 - bits)),
+        };
+
+        dfd.extend(((idx as u32 * bits) as u16).to_le_bytes());
+        dfd.push((bits - 1) as u8);
+        dfd.push(channel | qualifiers);
+        dfd.extend([0u8; 4]);
+        dfd.extend(lower.to_le_bytes());
+        dfd.extend(upper.to_le_bytes());
+    }
+
+    dfd
+}
+
+/// How the images of a texture are arranged.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Shape {
+    /// A 2D array of that many layers, a plain 2D texture for one.
+    Layers(u32),
+    /// A 3D texture of that many slices.
+    Volume(u32),
+    /// A cubemap, six faces in the order +X, -X, +Y, -Y, +Z, -Z.
+    Cube,
+}
+
+/// Writes an uncompressed texture. `levels[level]` holds the texels of all images of a
+/// mip level (layers, slices or faces), one after the other.
+pub fn write(
+    path: &std::path::Path,
+    format: RgbaFormat,
+    (width, height): (u32, u32),
+    shape: Shape,
+    levels: &[Vec<u8>],
+) -> Result<(), String> {
+    std::fs::write(path, encode(format, (width, height), shape, levels))
+        .map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+}
+
+/// The bytes of the file [`write`] writes.
+fn encode(
+    format: RgbaFormat,
+    (width, height): (u32, u32),
+    shape: Shape,
+    levels: &[Vec<u8>],
+) -> Vec<u8> {
+    let (depth, layers, faces) = match shape {
+        Shape::Layers(layers) if layers > 1 => (0, layers, 1),
+        Shape::Layers(_) => (0, 0, 1),
+        Shape::Volume(depth) => (depth, 0, 1),
+        Shape::Cube => (0, 0, 6),
+    };
+    let dfd = rgba_dfd(format);
+    let level_index_end = 80 + 24 * levels.len();
+    let dfd_offset = level_index_end;
+    let texel_size = format.channel_size as usize * 4;
+
+    //
+    // level data is stored smallest level first, aligned to the texel size
+    let mut offsets = vec![0usize; levels.len()];
+    let mut end = dfd_offset + dfd.len();
+    for (level, data) in levels.iter().enumerate().rev() {
+        end = end.div_ceil(texel_size) * texel_size;
+        offsets[level] = end;
+        end += data.len();
+    }
+
+    let mut out = Vec::with_capacity(end);
+    out.extend(IDENTIFIER);
+    for v in [
+        format.vk_format,
+        format.channel_size,
+        width,
+        height,
+        depth,
+        layers,
+        faces,
+        levels.len() as u32,
+        0, // supercompression
+        dfd_offset as u32,
+        dfd.len() as u32,
+        0, // key/value data
+        0,
+    ] {
+        out.extend(v.to_le_bytes());
+    }
+    out.extend(0u64.to_le_bytes()); // supercompression global data
+    out.extend(0u64.to_le_bytes());
+
+    for (level, data) in levels.iter().enumerate() {
+        out.extend((offsets[level] as u64).to_le_bytes());
+        out.extend((data.len() as u64).to_le_bytes());
+        out.extend((data.len() as u64).to_le_bytes());
+    }
+    out.extend(&dfd);
+
+    for (level, data) in levels.iter().enumerate().rev() {
+        out.resize(offsets[level], 0);
+        out.extend(data);
+    }
+
+    out
+}
+
+/// Adds a key/value entry to a KTX2 file, replacing an entry with the same key. The
+/// level data is moved as needed, keeping its alignment.
+pub fn set_key_value(data: &[u8], key: &str, value: &[u8]) -> Result<Vec<u8>, String> {
+    if data.len() < 80 || data[..12] != IDENTIFIER {
+        return Err("not a KTX2 file".to_string());
+    }
+
+    let level_count = read_u32(data, 40)?.max(1) as usize;
+    let dfd_offset = read_u32(data, 48)? as usize;
+    let dfd_length = read_u32(data, 52)? as usize;
+    let kvd_offset = read_u32(data, 56)? as usize;
+    let kvd_length = read_u32(data, 60)? as usize;
+    let sgd_offset = read_u64(data, 64)? as usize;
+    let sgd_length = read_u64(data, 72)? as usize;
+    let sgd = match sgd_length {
+        0 => &[][..],
+        _ => sgd_offset
+            .checked_add(sgd_length)
+            .and_then(|sgd_end| data.get(sgd_offset..sgd_end))
+            .ok_or_else(|| "truncated KTX2 supercompression data".to_string())?,
+    };
+
+    let mut entries = match kvd_length {
+        0 => Vec::new(),
+        _ => parse_key_values(
+            data.get(kvd_offset..kvd_offset + kvd_length)
+                .ok_or_else(|| "truncated KTX2 key/value data".to_string())?,
+        )?,
+    };
+    entries.retain(|(k, _)| k != key);
+    entries.push((key.to_string(), value.to_vec()));
+    //
+    // the spec wants the entries sorted by key
+    entries.sort_by(|a, b| a.0.as_bytes().cmp(b.0.as_bytes()));
+
+    let mut kvd = Vec::new();
+    for (idx, (k, v)) in entries.iter().enumerate() {
+        kvd.extend(((k.len() + 1 + v.len()) as u32).to_le_bytes());
+        kvd.extend(k.as_bytes());
+        kvd.push(0);
+        kvd.extend(v);
+        if idx + 1 < entries.len() {
+            kvd.resize(kvd.len().div_ceil(4) * 4, 0);
+        }
+    }
+
+    let levels = (0..level_count)
+        .map(|i| read_u64(data, 80 + i * 24))
+        .collect::<Result<Vec<_>, String>>()?;
+    let data_start = levels.iter().copied().min().unwrap_or(data.len() as u64) as usize;
+    if data_start > data.len() {
+        return Err("truncated KTX2 file".to_string());
+    }
+
+    //
+    // the descriptor is copied along with the header and level index before it
+    let new_kvd_offset = dfd_offset + dfd_length;
+    if new_kvd_offset < 80 + level_count * 24 || new_kvd_offset > data.len() {
+        return Err("KTX2 data format descriptor is out of range".to_string());
+    }
+    let mut end = new_kvd_offset + kvd.len();
+    let new_sgd_offset = match sgd.len() {
+        0 => 0,
+        _ => {
+            end = end.div_ceil(8) * 8;
+            let offset = end;
+            end += sgd.len();
+            offset
+        }
+    };
+    //
+    // keep the level data at the same offset modulo 16, which covers every texel
+    // block alignment
+    let new_data_start = end + (data_start + 16 - end % 16) % 16;
+    let shift = new_data_start as i64 - data_start as i64;
+
+    let mut out = data[..dfd_offset + dfd_length].to_vec();
+    out[56..60].copy_from_slice(&(new_kvd_offset as u32).to_le_bytes());
+    out[60..64].copy_from_slice(&(kvd.len() as u32).to_le_bytes());
+    out[64..72].copy_from_slice(&(new_sgd_offset as u64).to_le_bytes());
+    for (i, offset) in levels.iter().enumerate() {
+        let at = 80 + i * 24;
+        out[at..at + 8].copy_from_slice(&((*offset as i64 + shift) as u64).to_le_bytes());
+    }
+
+    out.extend(&kvd);
+    if !sgd.is_empty() {
+        out.resize(new_sgd_offset, 0);
+        out.extend(sgd);
+    }
+    out.resize(new_data_start, 0);
+    out.extend(&data[data_start..]);
+
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::atlas::KTX2_KEY as KEY;
+
+    const RGBA8: RgbaFormat = RgbaFormat {
+        vk_format: VK_FORMAT_R8G8B8A8_UNORM,
+        channel_size: 1,
+        float: false,
+        premultiplied: false,
+    };
+
+    /// A 2x2 RGBA texture of `layers` layers with a 1x1 second level, every texel of
+    /// layer `n` being `n + 1` (and `n + 101` in the second level).
+    fn layered(layers: u32) -> Vec<u8> {
+        let level = |shade: u8, texels: usize| {
+            (0..layers as u8)
+                .flat_map(|layer| vec![shade + layer; texels * 4])
+                .collect::<Vec<_>>()
+        };
+        encode(
+            RGBA8,
+            (2, 2),
+            Shape::Layers(layers),
+            &[level(1, 4), level(101, 1)],
+        )
+    }
+
+    fn set_u32(data: &mut [u8], at: usize, v: u32) {
+        data[at..at + 4].copy_from_slice(&v.to_le_bytes());
+    }
+
+    fn set_u64(data: &mut [u8], at: usize, v: u64) {
+        data[at..at + 8].copy_from_slice(&v.to_le_bytes());
+    }
+
+    #[test]
+    fn layers_and_levels_decode_in_bounds() {
+        let ktx = Ktx2::parse(layered(3)).unwrap();
+        assert_eq!((ktx.width, ktx.height, ktx.layers), (2, 2, 3));
+        assert_eq!(ktx.levels.len(), 2);
+
+        let base = ktx.decode(0, 0).unwrap();
+        assert_eq!(base.dimensions(), (2, 2));
+        assert!(base.pixels().all(|p| p.0 == [1; 4]));
+        assert!(ktx.decode(0, 2).unwrap().pixels().all(|p| p.0 == [3; 4]));
+
+        let mip = ktx.decode(1, 1).unwrap();
+        assert_eq!(mip.dimensions(), (1, 1));
+        assert_eq!(mip.get_pixel(0, 0).0, [102; 4]);
+        assert_eq!(ktx.layer_pixels(1).unwrap(), &[2; 16][..]);
+
+        assert!(ktx.decode(0, 3).is_err());
+        assert!(ktx.decode(2, 0).is_err());
+        assert!(ktx.decode(usize::MAX, 0).is_err());
+        assert!(ktx.decode(0, u32::MAX).is_err());
+        assert!(ktx.layer_pixels(3).is_err());
+    }
+
+    #[test]
+    fn single_layer_textures_decode_layer_0() {
+        let ktx = Ktx2::parse(layered(1)).unwrap();
+        assert_eq!(ktx.layers, 1);
+        assert!(ktx.decode(0, 0).unwrap().pixels().all(|p| p.0 == [1; 4]));
+        assert!(ktx.decode(0, 1).is_err());
+    }
+
+    #[test]
+    fn unknown_supercompression_is_refused() {
+        for scheme in [SUPERCOMPRESSION_BASIS_LZ, 7] {
+            let mut data = layered(1);
+            set_u32(&mut data, 44, scheme);
+            let ktx = Ktx2::parse(data).unwrap();
+
+            assert_eq!(ktx.supercompression, scheme);
+            assert!(ktx.decode(0, 0).is_err());
+            assert!(ktx.layer_pixels(0).is_err());
+        }
+    }
+
+    #[test]
+    fn truncated_files_are_errors() {
+        let data = layered(2);
+        for len in 0..data.len() {
+            //
+            // passes along what level data there is, as long as it doesn't panic
+            let _ = set_key_value(&data[..len], KEY, b"value");
+
+            let Ok(ktx) = Ktx2::parse(data[..len].to_vec()) else {
+                continue;
+            };
+            //
+            // the header and level index made it, the level data didn't
+            assert!(ktx.decode(0, 1).is_err(), "decoded {} bytes", len);
+        }
+        assert!(Ktx2::parse(data[..79].to_vec()).is_err());
+        assert!(set_key_value(&data[..79], KEY, b"value").is_err());
+    }
+
+    #[test]
+    fn out_of_range_headers_are_errors() {
+        let data = layered(2);
+
+        //
+        // more levels than the index holds
+        let mut levels = data.clone();
+        set_u32(&mut levels, 40, u32::MAX);
+        assert!(Ktx2::parse(levels.clone()).is_err());
+        assert!(set_key_value(&levels, KEY, b"value").is_err());
+
+        //
+        // level data past the end of the file, or wrapping around
+        for (offset, length) in [(data.len() as u64, 16), (u64::MAX - 4, 16), (80, u64::MAX)] {
+            let mut level = data.clone();
+            set_u64(&mut level, 80, offset);
+            set_u64(&mut level, 88, length);
+            let ktx = Ktx2::parse(level).unwrap();
+            assert!(ktx.decode(0, 0).is_err());
+        }
+
+        //
+        // a size no level data can hold
+        for (width, height) in [(u32::MAX, u32::MAX), (u32::MAX, 1)] {
+            let mut size = data.clone();
+            set_u32(&mut size, 20, width);
+            set_u32(&mut size, 24, height);
+            let ktx = Ktx2::parse(size).unwrap();
+            assert!(ktx.decode(0, 0).is_err());
+            assert!(ktx.decode(1, 1).is_err());
+            assert!(ktx.layer_pixels(1).is_err());
+        }
+
+        //
+        // key/value and descriptor data outside of the file
+        let mut kvd = data.clone();
+        set_u32(&mut kvd, 56, data.len() as u32);
+        set_u32(&mut kvd, 60, 16);
+        assert!(Ktx2::parse(kvd.clone()).is_err());
+        assert!(set_key_value(&kvd, KEY, b"value").is_err());
+
+        let mut dfd = data.clone();
+        set_u32(&mut dfd, 48, u32::MAX);
+        assert!(set_key_value(&dfd, KEY, b"value").is_err());
+        set_u32(&mut dfd, 48, 0);
+        set_u32(&mut dfd, 52, 0);
+        assert!(set_key_value(&dfd, KEY, b"value").is_err());
+
+        let mut sgd = data.clone();
+        set_u64(&mut sgd, 64, 8);
+        set_u64(&mut sgd, 72, u64::MAX);
+        assert!(set_key_value(&sgd, KEY, b"value").is_err());
+    }
+
+    #[test]
+    fn key_values_survive_a_round_trip() {
+        let data = set_key_value(&layered(2), KEY, b"first\0").unwrap();
+        let data = set_key_value(&data, KEY, b"second\0").unwrap();
+        let ktx = Ktx2::parse(data).unwrap();
+
+        assert_eq!(ktx.key_values.len(), 1);
+        assert_eq!(ktx.key_value(KEY), Some(&b"second\0"[..]));
+        assert!(ktx.decode(1, 1).unwrap().pixels().all(|p| p.0 == [102; 4]));
+    }
+}
@@ -0,0 +1,76 @@
+//! Where the outputs of a pack go inside the output directory.
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum OutputLayout {
+    /// Everything in the output directory.
+    Flat,
+    /// `textures/`, `meta/` and `preview/` subdirectories.
+    ByType,
+    /// A subdirectory named after the atlas.
+    ByAtlas,
+}
+
+pub struct OutputPaths {
+    /// Encoded textures.
+    pub textures: std::path::PathBuf,
+    /// Atlas descriptors.
+    pub meta: std::path::PathBuf,
+    /// Page previews.
+    pub preview: std::path::PathBuf,
+}
+
+impl OutputLayout {
+    pub fn paths(self, output_dir: &std::path::Path, atlas_name: &str) -> OutputPaths {
+        match self {
+            OutputLayout::Flat => OutputPaths {
+                textures: output_dir.to_path_buf(),
+                meta: output_dir.to_path_buf(),
+                preview: output_dir.to_path_buf(),
+            },
+            OutputLayout::ByType => OutputPaths {
+                textures: output_dir.join("textures"),
+                meta: output_dir.join("meta"),
+                preview: output_dir.join("preview"),
+            },
+            OutputLayout::ByAtlas => {
+                let dir = output_dir.join(atlas_name);
+                OutputPaths {
+                    textures: dir.clone(),
+                    meta: dir.clone(),
+                    preview: dir,
+                }
+            }
+        }
+    }
+}
+
+/// Path of `to` relative to the directory `from`, both being below the same root.
+pub fn relative_path(from: &std::path::Path, to: &std::path::Path) -> std::path::PathBuf {
+    let from = from.components().collect::<Vec<_>>();
+    let to = to.components().collect::<Vec<_>>();
+    let common = from
+        .iter()
+        .zip(to.iter())
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    from[common..]
+        .iter()
+        .map(|_| std::path::Component::ParentDir)
+        .chain(to[common..].iter().copied())
+        .collect()
+}
+
+/// `path` relative to the directory `dir`, as [`relative_path`] makes them, resolved
+/// without touching the file system.
+pub fn resolve_path(dir: &std::path::Path, path: &std::path::Path) -> std::path::PathBuf {
+    let mut resolved = dir.to_path_buf();
+    for component in path.components() {
+        match component {
+            std::path::Component::ParentDir if resolved.pop() => {}
+            std::path::Component::CurDir => {}
+            c => resolved.push(c),
+        }
+    }
+    resolved
+}
@@ -0,0 +1,16 @@
+//! Reading side of the atlases produced by the `texture-packer` tool: the atlas
+//! description types and their JSON Schema, a minimal KTX2 reader, atlas deltas, the
+//! exporter registry, the frame post-processor registry and the naming of staged
+//! outputs.
+//! With the `gpu` feature, helpers uploading atlases to wgpu.
+
+pub mod atlas;
+pub mod delta;
+pub mod exporter;
+#[cfg(feature = "gpu")]
+pub mod gpu;
+pub mod ktx2;
+pub mod model;
+pub mod processor;
+pub mod schema;
+pub mod staging;
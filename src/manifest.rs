@@ -0,0 +1,83 @@
+//! Explicit lists of the sprites to pack, given with `--manifest` instead of (or along
+//! with) input folders, for build systems driving the packer from their own dependency
+//! graphs. A `.txt` manifest has a path per line, blank lines and lines starting with
+//! `#` are skipped. A `.json` manifest is an array of paths or of entries overriding
+//! what the packer would pick:
+//!
+//! ```json
+//! [
+//!     "ui/button.png",
+//!     { "path": "chars/hero.gif", "key": "hero", "pivot": [0.5, 1.0], "padding": 2, "tag": "chars", "layer": 1 }
+//! ]
+//! ```
+//!
+//! Paths are relative to the manifest.
+
+use crate::source::Pivot;
+
+#[derive(Clone, Debug, Default, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ManifestEntry {
+    pub path: std::path::PathBuf,
+    /// Key in place of the file stem, over the sidecar's.
+    pub key: Option<String>,
+    /// Normalized pivot, over the sidecar's and `--pivot`.
+    pub pivot: Option<(f32, f32)>,
+    /// Transparent pixels kept free around the sprite on the page.
+    pub padding: Option<u32>,
+    /// Tag of the frames, as given with `tag:path` for folders.
+    pub tag: Option<String>,
+    /// Page (layer) the frames have to go on, over the sidecar's.
+    pub layer: Option<u32>,
+}
+
+/// Reads a manifest, its paths resolved against its directory.
+pub fn load(path: &std::path::Path) -> Result<Vec<ManifestEntry>, String> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read manifest {}: {}", path.display(), e))?;
+    let dir = path.parent().unwrap_or(std::path::Path::new(""));
+
+    let failed =
+        |e: &dyn std::fmt::Display| format!("Failed to parse manifest {}: {}", path.display(), e);
+    let entries = match path
+        .extension()
+        .is_some_and(|e| e.eq_ignore_ascii_case("json"))
+    {
+        true => serde_json::from_str::<Vec<serde_json::Value>>(&text)
+            .map_err(|e| failed(&e))?
+            .into_iter()
+            .enumerate()
+            .map(|(idx, entry)| match entry {
+                serde_json::Value::String(path) => Ok(ManifestEntry {
+                    path: path.into(),
+                    ..Default::default()
+                }),
+                entry => serde_json::from_value(entry)
+                    .map_err(|e| failed(&format_args!("entry {}: {}", idx, e))),
+            })
+            .collect::<Result<Vec<_>, _>>()?,
+        false => text
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(|line| ManifestEntry {
+                path: line.into(),
+                ..Default::default()
+            })
+            .collect(),
+    };
+
+    Ok(entries
+        .into_iter()
+        .map(|entry| ManifestEntry {
+            path: dir.join(&entry.path),
+            ..entry
+        })
+        .collect())
+}
+
+impl ManifestEntry {
+    pub fn pivot(&self) -> Option<Pivot> {
+        self.pivot.map(|(x, y)| Pivot(x, y))
+    }
+}
@@ -0,0 +1,158 @@
+//! Combines atlases with `texture-packer merge a.ron b.ron -a combined -o out`: the
+//! regions of every atlas are cut from its pages and packed again with the sprites of
+//! the inputs, identical ones once. Frames keep their key, pivot, insets, tag, events,
+//! trim and variants; the animations, fonts and credits of the atlases are carried over
+//! and their aliases point at the frames again.
+
+use crate::atlas::{
+    Animation, AttributionGroup, ColorSpace, Content, FontFace, PixelFormat, TextureAtlas,
+};
+use crate::color;
+use crate::meta::SpriteVariant;
+use crate::pack::{self, Alias, PackOptions};
+use crate::source::{Pivot, SourceSprite};
+use std::path::{Path, PathBuf};
+
+#[derive(clap::Args, Debug)]
+pub struct MergeOptions {
+    /// Atlas description files (.ron, or KTX2 with the descriptor embedded).
+    #[arg(required = true)]
+    atlases: Vec<PathBuf>,
+    #[command(flatten)]
+    pack: PackOptions,
+}
+
+/// What an atlas brings to the merged one.
+pub struct MergedAtlas {
+    pub sprites: Vec<SourceSprite>,
+    pub animations: Vec<Animation>,
+    pub fonts: Vec<FontFace>,
+    pub attributions: Vec<AttributionGroup>,
+}
+
+pub fn run(options: MergeOptions) -> Result<(), String> {
+    let mut pack_options = options.pack;
+    for path in options.atlases.iter() {
+        let atlas = TextureAtlas::load(path)?;
+        pack_options.add_aliases(atlas.frames.iter().filter_map(|frame| {
+            frame.alias_of.as_ref().map(|target| Alias {
+                key: frame.key.clone(),
+                target: target.clone(),
+            })
+        }));
+    }
+
+    pack_options.merge_atlases(options.atlases);
+    pack::run(pack_options)
+}
+
+/// Cuts the frames of the atlas at `path` from its pages, as sprites of `content`
+/// encoded in `color_space` without premultiplied alpha. Variants go with the frame
+/// they are drawn from, aliases are left to [`run`].
+pub fn load(path: &Path, content: Content, color_space: ColorSpace) -> Result<MergedAtlas, String> {
+    let atlas = TextureAtlas::load(path)?;
+    if atlas.pixel_format != PixelFormat::La8 {
+        return Err(format!(
+            "{} is {:?}, only la8 atlases can be merged",
+            path.display(),
+            atlas.pixel_format
+        ));
+    }
+    if atlas.content != content {
+        return Err(format!(
+            "{} holds {:?}, the merged atlas {:?}",
+            path.display(),
+            atlas.content,
+            content
+        ));
+    }
+
+    let pages = atlas
+        .load_pages(path.parent().unwrap_or(Path::new("")))?
+        .into_iter()
+        .map(|page| page.into_luma_alpha8())
+        .collect::<Vec<_>>();
+
+    let pixel = |px: image::LumaA<u8>| {
+        let px = match atlas.premultiplied {
+            true => color::unpremultiply(px, atlas.color_space),
+            false => px,
+        };
+        match atlas.color_space == color_space {
+            true => px,
+            false => image::LumaA([
+                color::encode(color::decode(px.0[0], atlas.color_space), color_space),
+                px.0[1],
+            ]),
+        }
+    };
+
+    let mut sprites = Vec::<SourceSprite>::new();
+    for (idx, frame) in atlas.frames.iter().enumerate() {
+        if frame.alias_of.is_some() || frame.variant_of.is_some() {
+            continue;
+        }
+        let page = pages.get(frame.layer as usize).ok_or_else(|| {
+            format!(
+                "{}: {} is on layer {}, the atlas has {} page(s)",
+                path.display(),
+                frame.key,
+                frame.layer,
+                pages.len()
+            )
+        })?;
+        if frame.x + frame.width > page.width() || frame.y + frame.height > page.height() {
+            return Err(format!(
+                "{}: {} reaches past its page",
+                path.display(),
+                frame.key
+            ));
+        }
+
+        let mut pixels =
+            image::imageops::crop_imm(page, frame.x, frame.y, frame.width, frame.height).to_image();
+        pixels.pixels_mut().for_each(|px| *px = pixel(*px));
+
+        sprites.push(SourceSprite {
+            source: path.to_path_buf(),
+            frame: Some(idx as u32),
+            key: frame.key.clone(),
+            pixels,
+            tag: frame.tag.clone(),
+            nine_slice: frame.nine_slice,
+            events: frame.events.clone(),
+            pivot: Pivot(frame.pivot_x, frame.pivot_y),
+            split: frame.split.clone(),
+            baked_transform: frame.baked_transform,
+            variants: Vec::new(),
+            delta: frame.delta.clone(),
+            max_mip: frame.max_mip,
+            colored: false,
+            trim: frame.trim,
+            filter: frame.filter,
+            alpha: frame.alpha,
+            motion: None,
+            hdr: None,
+            tone: frame.tone,
+            source_size: (frame.width, frame.height),
+        });
+    }
+
+    for frame in atlas.frames.iter() {
+        if let (Some(of), Some(transform)) = (frame.variant_of.as_ref(), frame.draw_transform) {
+            if let Some(sprite) = sprites.iter_mut().find(|s| &s.key == of) {
+                sprite.variants.push(SpriteVariant {
+                    key: frame.key.clone(),
+                    transform,
+                });
+            }
+        }
+    }
+
+    Ok(MergedAtlas {
+        sprites,
+        animations: atlas.animations,
+        fonts: atlas.fonts,
+        attributions: atlas.attributions,
+    })
+}
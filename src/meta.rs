@@ -0,0 +1,124 @@
+//! Optional per-sprite sidecars, `<image file>.meta.ron` next to the source, e.g.
+//! `hero.png.meta.ron` containing
+//!
+//! ```ron
+//! (
+//!     pivot: (0.5, 1.0),
+//!     rotated: 90,
+//!     transform: (flip_x: true),
+//!     variants: [(key: "hero_left", transform: (flip_x: true))],
+//!     max_mip: 0,
+//! )
+//! ```
+//!
+//! or overriding what the options give every sprite:
+//!
+//! ```ron
+//! (
+//!     key: "ui_panel",
+//!     classify: (trim: false),
+//!     extrude: 2,
+//!     nine_slice: (left: 8, right: 8, top: 6, bottom: 6),
+//!     layer: 0,
+//! )
+//! ```
+//!
+//! A sidecar next to an input folder (`assets/ui.meta.ron` for `assets/ui`) gives the
+//! `attribution`, `tone` and `layer` of every sprite in the folder that has none of its
+//! own, e.g. `(tone: (exposure: -0.5, gamma: 1.1))` to match sprites rendered with
+//! another tool, or `(layer: 0)` to keep all the terrain tiles on the first layer.
+
+use crate::atlas::{Attribution, NineSlice, SpriteTransform, ToneAdjustment};
+use crate::classify::ClassifyOverrides;
+use crate::sheet::SheetSlicing;
+
+#[derive(Clone, Default, serde::Deserialize)]
+#[serde(default)]
+pub struct SpriteMeta {
+    /// Normalized pivot, `(0, 0)` is the top left corner of the sprite.
+    pub pivot: Option<(f32, f32)>,
+    /// Clockwise degrees (90, 180 or 270) the source was exported rotated by, undone
+    /// when it is decoded.
+    pub rotated: Option<u32>,
+    /// Applied to the source pixels before packing.
+    pub transform: Option<SpriteTransform>,
+    /// Extra frames sharing the sprite's pixels, drawn with a transform.
+    pub variants: Vec<SpriteVariant>,
+    /// Highest mip level the sprite should be sampled from, 0 for no mipmapping.
+    pub max_mip: Option<u32>,
+    /// Slice the image as a spritesheet, see [`crate::sheet`].
+    pub sheet: Option<SheetSlicing>,
+    /// Overrides for what `--classify` decides, see [`crate::classify`].
+    pub classify: Option<ClassifyOverrides>,
+    /// License and credits, collected into the descriptor.
+    pub attribution: Option<Attribution>,
+    /// Exposure and gamma correction of the colors, recorded on the frames.
+    pub tone: Option<ToneAdjustment>,
+    /// Key in place of the file stem, frames of animated sources keep their number.
+    pub key: Option<String>,
+    /// Edge pixels repeated around the sprite, over `--extrude`.
+    pub extrude: Option<u32>,
+    /// Nine-slice insets, over the markers of a `.9.png`.
+    pub nine_slice: Option<NineSlice>,
+    /// Page (layer) the sprite has to go on, whatever else the planner puts there.
+    pub layer: Option<u32>,
+}
+
+#[derive(Clone, serde::Deserialize)]
+pub struct SpriteVariant {
+    pub key: String,
+    pub transform: SpriteTransform,
+}
+
+impl SpriteMeta {
+    /// The transform turning a `rotated` source upright.
+    pub fn upright(&self) -> Option<SpriteTransform> {
+        match self.rotated? {
+            90 => Some(SpriteTransform::new(true, true, true)),
+            180 => Some(SpriteTransform::new(true, true, false)),
+            270 => Some(SpriteTransform::new(false, false, true)),
+            _ => None,
+        }
+    }
+}
+
+pub fn sidecar_path(source: &std::path::Path) -> std::path::PathBuf {
+    let mut file_name = source.file_name().unwrap_or_default().to_os_string();
+    file_name.push(".meta.ron");
+    source.with_file_name(file_name)
+}
+
+/// Loads the sidecar for a source, if there is one.
+pub fn load(source: &std::path::Path) -> Result<Option<SpriteMeta>, String> {
+    let path = sidecar_path(source);
+    if !path.is_file() {
+        return Ok(None);
+    }
+
+    let contents = std::fs::read_to_string(&path)
+        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    //
+    // sidecars are hand written, allow `pivot: (0.5, 1.0)` instead of `pivot: Some((0.5, 1.0))`
+    let meta: SpriteMeta = ron::Options::default()
+        .with_default_extension(ron::extensions::Extensions::IMPLICIT_SOME)
+        .from_str(&contents)
+        .map_err(|e| format!("Failed to parse {}: {}", path.display(), e))?;
+
+    if let Some(rotated) = meta.rotated.filter(|r| ![0, 90, 180, 270].contains(r)) {
+        return Err(format!(
+            "Invalid rotation {} in {}, expected 90, 180 or 270",
+            rotated,
+            path.display()
+        ));
+    }
+    if let Some(tone) = meta.tone {
+        if !tone.exposure.is_finite() || !tone.gamma.is_finite() || tone.gamma <= 0.0 {
+            return Err(format!(
+                "Invalid tone in {}: the exposure has to be finite and the gamma positive",
+                path.display()
+            ));
+        }
+    }
+
+    Ok(Some(meta))
+}
@@ -0,0 +1,90 @@
+//! Mip chain generation for the atlas pages.
+
+use crate::color;
+use crate::encode::PageFormat;
+
+type PagePixels = image::ImageBuffer<image::LumaA<u8>, Vec<u8>>;
+
+/// Halves the image with a box filter. Color is averaged in linear light, weighted by
+/// alpha (unless already premultiplied) so transparent texels don't bleed into the
+/// edges of sprites.
+fn downsample(src: &PagePixels, format: PageFormat) -> PagePixels {
+    let (sw, sh) = src.dimensions();
+    let (w, h) = ((sw / 2).max(1), (sh / 2).max(1));
+
+    let decode = |c: u8| color::decode(c, format.color_space);
+    let encode = |c: f32| color::encode(c, format.color_space);
+
+    image::ImageBuffer::from_fn(w, h, |x, y| {
+        let texels = [(0, 0), (1, 0), (0, 1), (1, 1)]
+            .map(|(dx, dy)| *src.get_pixel((x * 2 + dx).min(sw - 1), (y * 2 + dy).min(sh - 1)));
+
+        //
+        // the second channel of normal maps and motion vectors is Y, not alpha
+        if !format.content.is_color() {
+            let avg = |c: usize| texels.iter().map(|t| t.0[c] as f32).sum::<f32>() / 4.0;
+            return image::LumaA([avg(0).round() as u8, avg(1).round() as u8]);
+        }
+
+        let alpha = texels.iter().map(|t| t.0[1] as f32 / 255.0).sum::<f32>();
+        let color = match alpha > 0.0 && !format.premultiplied {
+            true => {
+                texels
+                    .iter()
+                    .map(|t| decode(t.0[0]) * t.0[1] as f32 / 255.0)
+                    .sum::<f32>()
+                    / alpha
+            }
+            false => texels.iter().map(|t| decode(t.0[0])).sum::<f32>() / 4.0,
+        };
+
+        image::LumaA([encode(color), (alpha / 4.0 * 255.0).round() as u8])
+    })
+}
+
+/// The mip levels below `base`, down to 1x1.
+pub fn mip_chain(base: &PagePixels, format: PageFormat) -> Vec<PagePixels> {
+    let mut levels = Vec::<PagePixels>::new();
+
+    while levels.last().unwrap_or(base).dimensions() != (1, 1) {
+        let next = downsample(levels.last().unwrap_or(base), format);
+        levels.push(next);
+    }
+
+    levels
+}
+
+/// Halves a full precision page with a box filter, weighting color by alpha unless
+/// premultiplied. Values are taken as linear.
+fn downsample_hdr(src: &image::Rgba32FImage, premultiplied: bool) -> image::Rgba32FImage {
+    let (sw, sh) = src.dimensions();
+    let (w, h) = ((sw / 2).max(1), (sh / 2).max(1));
+
+    image::ImageBuffer::from_fn(w, h, |x, y| {
+        let texels = [(0, 0), (1, 0), (0, 1), (1, 1)].map(|(dx, dy)| {
+            src.get_pixel((x * 2 + dx).min(sw - 1), (y * 2 + dy).min(sh - 1))
+                .0
+        });
+
+        let alpha = texels.iter().map(|t| t[3]).sum::<f32>();
+        let weighted = alpha > 0.0 && !premultiplied;
+        let channel = |c: usize| match weighted {
+            true => texels.iter().map(|t| t[c] * t[3]).sum::<f32>() / alpha,
+            false => texels.iter().map(|t| t[c]).sum::<f32>() / 4.0,
+        };
+
+        image::Rgba([channel(0), channel(1), channel(2), alpha / 4.0])
+    })
+}
+
+/// The mip levels below a full precision `base`, down to 1x1.
+pub fn mip_chain_hdr(base: &image::Rgba32FImage, premultiplied: bool) -> Vec<image::Rgba32FImage> {
+    let mut levels = Vec::<image::Rgba32FImage>::new();
+
+    while levels.last().unwrap_or(base).dimensions() != (1, 1) {
+        let next = downsample_hdr(levels.last().unwrap_or(base), premultiplied);
+        levels.push(next);
+    }
+
+    levels
+}
@@ -0,0 +1,450 @@
+//! The descriptor types. They are `#[non_exhaustive]`, so fields can be added to them
+//! without breaking the crates making them: those start from the constructors and set
+//! the fields they need.
+
+use crate::atlas::{
+    hashed_key, region_name_hash, AlphaUsage, Animation, AttributionGroup, ColorSpace, Content,
+    FilterHint, FloatPrecision, FontFace, FrameDelta, MotionVectorTexture, NineSlice, PageChannels,
+    PixelFormat, Polygon, Provenance, SplitPart, SpriteTransform, ToneAdjustment, Trim,
+    SCHEMA_VERSION,
+};
+
+/// A packed frame. Made with [`NamedTextureRegion::new`], the optional fields are set on
+/// the result.
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+#[non_exhaustive]
+pub struct NamedTextureRegion {
+    pub name: u64,
+    /// Name the region was packed under (source file stem), `name` is its hash.
+    #[serde(default)]
+    pub key: String,
+    pub layer: u32,
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tag: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nine_slice: Option<NineSlice>,
+    /// Frame events/notes authored in the source (Aseprite cel user data).
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub events: Vec<String>,
+    /// Normalized pivot inside the region, `(0, 0)` is the top left corner.
+    #[serde(default = "default_pivot")]
+    pub pivot_x: f32,
+    #[serde(default = "default_pivot")]
+    pub pivot_y: f32,
+    /// Set when the region is one tile of a source image that was larger than a page.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub split: Option<SplitPart>,
+    /// Transform that was applied to the source pixels before packing.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub baked_transform: Option<SpriteTransform>,
+    /// Set on variant frames: the key of the frame whose pixels they share.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub variant_of: Option<String>,
+    /// Transform to apply to the region when drawing it (variant frames).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub draw_transform: Option<SpriteTransform>,
+    /// Set on alias frames: the key of the frame they keep reachable under another name.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub alias_of: Option<String>,
+    /// Set on raw GIF/APNG frames: where the stored delta goes on the animation canvas.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub delta: Option<FrameDelta>,
+    /// Highest mip level to sample the region from, 0 for none.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_mip: Option<u32>,
+    /// Cell index in grid layouts, row-major across pages.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tile: Option<u32>,
+    /// Transparent borders cropped before packing.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trim: Option<Trim>,
+    /// Outline of the visible pixels (`--polygons`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub polygon: Option<Polygon>,
+    /// Suggested sampler filter.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub filter: Option<FilterHint>,
+    /// How the sprite uses alpha, to pick a blend mode.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub alpha: Option<AlphaUsage>,
+    /// The same region of the atlas' motion vector texture holds this frame's vectors.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub motion_vectors: bool,
+    /// Exposure and gamma from the sidecars, applied to the source colors.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tone: Option<ToneAdjustment>,
+    /// blake3 of the source file the frame was cut from.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source_hash: Option<String>,
+    /// That source file, relative to the descriptor.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<std::path::PathBuf>,
+    /// Size and modification time of that file's image (`--with-provenance`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provenance: Option<Provenance>,
+    /// Normalized texture coordinates of the region, with the atlas `uv_inset` applied.
+    #[serde(default)]
+    pub u0: f32,
+    #[serde(default)]
+    pub v0: f32,
+    #[serde(default)]
+    pub u1: f32,
+    #[serde(default)]
+    pub v1: f32,
+}
+
+impl NamedTextureRegion {
+    /// A region at `(x, y, width, height)` on page `layer`, named after `key`, with the
+    /// pivot centered and nothing else set. The UVs are filled in by [`Self::with_uvs`].
+    pub fn new(key: impl Into<String>, layer: u32, pixel_rect: (u32, u32, u32, u32)) -> Self {
+        let key = key.into();
+        let (x, y, width, height) = pixel_rect;
+        NamedTextureRegion {
+            name: region_name_hash(&key),
+            key,
+            layer,
+            x,
+            y,
+            width,
+            height,
+            tag: None,
+            nine_slice: None,
+            events: Vec::new(),
+            pivot_x: default_pivot(),
+            pivot_y: default_pivot(),
+            split: None,
+            baked_transform: None,
+            variant_of: None,
+            draw_transform: None,
+            alias_of: None,
+            delta: None,
+            max_mip: None,
+            tile: None,
+            trim: None,
+            polygon: None,
+            filter: None,
+            alpha: None,
+            motion_vectors: false,
+            tone: None,
+            source_hash: None,
+            source: None,
+            provenance: None,
+            u0: 0.0,
+            v0: 0.0,
+            u1: 0.0,
+            v1: 0.0,
+        }
+    }
+
+    /// Sets the UVs for an atlas page of the given size, shrunk by `inset` texels.
+    pub fn with_uvs(mut self, atlas_size: (u32, u32), inset: f32) -> Self {
+        (self.u0, self.v0, self.u1, self.v1) = self.uv_rect_for(atlas_size, inset);
+        self
+    }
+
+    /// A variant drawing the same pixels under another key, with `transform` applied.
+    pub fn variant(&self, key: impl Into<String>, transform: SpriteTransform) -> Self {
+        let key = key.into();
+        NamedTextureRegion {
+            name: region_name_hash(&key),
+            key,
+            variant_of: Some(self.key.clone()),
+            draw_transform: Some(transform),
+            ..self.clone()
+        }
+    }
+
+    /// An alias looking up the same frame under another key. Aliases of aliases name
+    /// the original frame.
+    pub fn alias(&self, key: impl Into<String>) -> Self {
+        let key = key.into();
+        NamedTextureRegion {
+            name: region_name_hash(&key),
+            key,
+            alias_of: Some(self.alias_of.clone().unwrap_or_else(|| self.key.clone())),
+            ..self.clone()
+        }
+    }
+
+    /// Array layer (page) holding the region.
+    pub fn layer(&self) -> u32 {
+        self.layer
+    }
+
+    /// `(x, y, width, height)` of the region in pixels, from the top left of its page.
+    pub fn pixel_rect(&self) -> (u32, u32, u32, u32) {
+        (self.x, self.y, self.width, self.height)
+    }
+
+    /// The stored `(u0, v0, u1, v1)`, with the atlas `uv_inset` applied. Descriptors
+    /// from before UVs were written hold zeros, [`Self::uv_rect_for`] works for those.
+    pub fn uv_rect(&self) -> (f32, f32, f32, f32) {
+        (self.u0, self.v0, self.u1, self.v1)
+    }
+
+    /// Normalized `(u0, v0, u1, v1)` of the region on an atlas page of the given size,
+    /// shrunk by `inset` texels on every side.
+    pub fn uv_rect_for(&self, atlas_size: (u32, u32), inset: f32) -> (f32, f32, f32, f32) {
+        let (w, h) = (atlas_size.0 as f32, atlas_size.1 as f32);
+        (
+            (self.x as f32 + inset) / w,
+            (self.y as f32 + inset) / h,
+            ((self.x + self.width) as f32 - inset) / w,
+            ((self.y + self.height) as f32 - inset) / h,
+        )
+    }
+}
+
+/// An atlas descriptor. Made with [`TextureAtlas::new`], the other fields are set on the
+/// result.
+#[derive(serde::Serialize, serde::Deserialize)]
+#[non_exhaustive]
+pub struct TextureAtlas {
+    /// Schema version, [`SCHEMA_VERSION`](crate::atlas::SCHEMA_VERSION) when written by
+    /// this version of the tool, 0 for descriptors from before versioning. Written as
+    /// `schema_version`, version 1 descriptors have it as `version`.
+    #[serde(default, rename = "schema_version", alias = "version")]
+    pub version: u32,
+    pub frames: Vec<NamedTextureRegion>,
+    pub size: (u32, u32),
+    pub file: std::path::PathBuf,
+    /// blake3 of the texture file followed by the page images, as encoded (before a
+    /// descriptor is embedded).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub atlas_hash: Option<String>,
+    /// Inset in texels applied to the frame UVs (0.5 for a half texel).
+    #[serde(default)]
+    pub uv_inset: f32,
+    /// Per layer page images, or KTX2 textures with `--layout pages`, relative to the
+    /// descriptor.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub pages: Vec<std::path::PathBuf>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub animations: Vec<Animation>,
+    /// What the texture channels hold.
+    #[serde(default, skip_serializing_if = "Content::is_color")]
+    pub content: Content,
+    /// Transfer function of the texture data.
+    #[serde(default)]
+    pub color_space: ColorSpace,
+    /// Whether the color is premultiplied by alpha.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub premultiplied: bool,
+    /// Scale of the frames relative to the source images.
+    #[serde(default = "default_scale", skip_serializing_if = "is_unscaled")]
+    pub scale: f32,
+    /// Number of mip levels in the texture, including the base level.
+    #[serde(default = "default_mip_levels")]
+    pub mip_levels: u32,
+    /// Set when the pages are the slices of a 3D texture (`--layout volume`), the frame
+    /// layers then being slices.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub volume: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub motion_vectors: Option<MotionVectorTexture>,
+    #[serde(default, skip_serializing_if = "PixelFormat::is_la8")]
+    pub pixel_format: PixelFormat,
+    /// What every page holds (`--mixed-formats`), all luminance and alpha when empty.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub page_channels: Vec<PageChannels>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub attributions: Vec<AttributionGroup>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub fonts: Vec<FontFace>,
+    /// Set when the sprites were converted to signed distance fields (in alpha, 0.5 on
+    /// the outline): the distance in pixels from the outline to either end of the range.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sdf_spread: Option<u32>,
+}
+
+impl TextureAtlas {
+    /// A descriptor of the current schema version for a texture `file` with pages of
+    /// `size`, without frames.
+    pub fn new(file: impl Into<std::path::PathBuf>, size: (u32, u32)) -> Self {
+        TextureAtlas {
+            version: SCHEMA_VERSION,
+            frames: Vec::new(),
+            size,
+            file: file.into(),
+            atlas_hash: None,
+            uv_inset: 0.0,
+            pages: Vec::new(),
+            animations: Vec::new(),
+            content: Content::default(),
+            color_space: ColorSpace::default(),
+            premultiplied: false,
+            scale: default_scale(),
+            mip_levels: default_mip_levels(),
+            volume: false,
+            motion_vectors: None,
+            pixel_format: PixelFormat::default(),
+            page_channels: Vec::new(),
+            attributions: Vec::new(),
+            fonts: Vec::new(),
+            sdf_spread: None,
+        }
+    }
+
+    /// The frame packed under `key`. Version 0 frames without a key are found by its
+    /// hash.
+    pub fn frame(&self, key: &str) -> Option<&NamedTextureRegion> {
+        self.frames
+            .iter()
+            .find(|frame| frame.key == key)
+            .or_else(|| {
+                let name = region_name_hash(key);
+                self.frames
+                    .iter()
+                    .find(|frame| frame.name == name && frame.key == hashed_key(name))
+            })
+    }
+
+    /// Rounds the UVs, pivots, scales and font metrics to `precision`.
+    pub fn round_floats(&mut self, precision: FloatPrecision) {
+        let round = |v: &mut f32| *v = precision.round(*v);
+
+        round(&mut self.uv_inset);
+        round(&mut self.scale);
+        for frame in self.frames.iter_mut() {
+            for v in [
+                &mut frame.pivot_x,
+                &mut frame.pivot_y,
+                &mut frame.u0,
+                &mut frame.v0,
+                &mut frame.u1,
+                &mut frame.v1,
+            ] {
+                round(v);
+            }
+            if let Some(tone) = frame.tone.as_mut() {
+                round(&mut tone.exposure);
+                round(&mut tone.gamma);
+            }
+        }
+        for font in self.fonts.iter_mut() {
+            for v in [
+                &mut font.size,
+                &mut font.ascent,
+                &mut font.descent,
+                &mut font.line_height,
+            ] {
+                round(v);
+            }
+            for glyph in font.glyphs.iter_mut() {
+                round(&mut glyph.advance);
+                round(&mut glyph.bearing_x);
+                round(&mut glyph.bearing_y);
+            }
+            for kerning in font.kerning.iter_mut() {
+                round(&mut kerning.amount);
+            }
+        }
+    }
+
+    /// The descriptor of page `layer` on its own, for atlases written with a texture per
+    /// page (`--layout pages`): its frames moved to layer 0, and the animations, fonts and
+    /// attributions of those frames. Animations and fonts with frames on other pages are
+    /// left out.
+    pub fn page(&self, layer: u32) -> TextureAtlas {
+        let keys = self
+            .frames
+            .iter()
+            .filter(|f| f.layer == layer)
+            .map(|f| f.key.as_str())
+            .collect::<std::collections::HashSet<_>>();
+        let file = |file: &std::path::PathBuf, pages: &[std::path::PathBuf]| {
+            pages.get(layer as usize).unwrap_or(file).clone()
+        };
+        let page_file = file(&self.file, &self.pages);
+
+        TextureAtlas {
+            version: self.version,
+            frames: self
+                .frames
+                .iter()
+                .filter(|f| f.layer == layer)
+                .map(|f| NamedTextureRegion {
+                    layer: 0,
+                    ..f.clone()
+                })
+                .collect(),
+            size: self.size,
+            file: page_file.clone(),
+            atlas_hash: None,
+            uv_inset: self.uv_inset,
+            pages: vec![page_file],
+            animations: self
+                .animations
+                .iter()
+                .filter(|a| a.frames.iter().all(|key| keys.contains(key.as_str())))
+                .cloned()
+                .collect(),
+            content: self.content,
+            color_space: self.color_space,
+            premultiplied: self.premultiplied,
+            scale: self.scale,
+            mip_levels: self.mip_levels,
+            volume: self.volume,
+            motion_vectors: self.motion_vectors.as_ref().map(|mv| {
+                let mv_file = file(&mv.file, &mv.pages);
+                MotionVectorTexture {
+                    file: mv_file.clone(),
+                    pages: vec![mv_file],
+                }
+            }),
+            pixel_format: self.pixel_format,
+            page_channels: self
+                .page_channels
+                .get(layer as usize)
+                .into_iter()
+                .copied()
+                .collect(),
+            attributions: self
+                .attributions
+                .iter()
+                .map(|group| AttributionGroup {
+                    attribution: group.attribution.clone(),
+                    frames: group
+                        .frames
+                        .iter()
+                        .filter(|key| keys.contains(key.as_str()))
+                        .cloned()
+                        .collect(),
+                })
+                .filter(|group| !group.frames.is_empty())
+                .collect(),
+            fonts: self
+                .fonts
+                .iter()
+                .filter(|font| {
+                    font.glyphs
+                        .iter()
+                        .filter_map(|g| g.key.as_deref())
+                        .all(|key| keys.contains(key))
+                })
+                .cloned()
+                .collect(),
+            sdf_spread: self.sdf_spread,
+        }
+    }
+}
+
+fn default_pivot() -> f32 {
+    0.5
+}
+
+fn default_mip_levels() -> u32 {
+    1
+}
+
+fn default_scale() -> f32 {
+    1.0
+}
+
+fn is_unscaled(scale: &f32) -> bool {
+    *scale == 1.0
+}
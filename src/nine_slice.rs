@@ -0,0 +1,52 @@
+//! Android style nine-patch (`.9.png`) inputs.
+//!
+//! The source carries a 1 pixel marker border: opaque black pixels on the top row mark the
+//! horizontally stretchable span, the ones on the left column the vertically stretchable
+//! span. The border is stripped before packing and the spans are turned into insets.
+
+use crate::atlas::NineSlice;
+
+pub fn is_nine_patch(path: &std::path::Path) -> bool {
+    path.file_name()
+        .map(|f| f.to_string_lossy().to_lowercase().ends_with(".9.png"))
+        .unwrap_or(false)
+}
+
+fn is_marker(px: &image::Rgba<u8>) -> bool {
+    px.0 == [0, 0, 0, 255]
+}
+
+/// First and one past last marker position along a border line.
+fn marker_span(markers: impl Iterator<Item = bool>) -> Option<(u32, u32)> {
+    markers
+        .enumerate()
+        .filter(|(_, marked)| *marked)
+        .fold(None, |span, (i, _)| match span {
+            None => Some((i as u32, i as u32 + 1)),
+            Some((first, _)) => Some((first, i as u32 + 1)),
+        })
+}
+
+/// Strips the marker border, returning the inner image and the insets derived from the
+/// markers. Returns `None` if the image is too small to carry a border.
+pub fn split_nine_patch(img: &image::RgbaImage) -> Option<(image::RgbaImage, NineSlice)> {
+    let (w, h) = img.dimensions();
+    if w < 3 || h < 3 {
+        return None;
+    }
+
+    let (inner_w, inner_h) = (w - 2, h - 2);
+    let horz = marker_span((1..w - 1).map(|x| is_marker(img.get_pixel(x, 0))));
+    let vert = marker_span((1..h - 1).map(|y| is_marker(img.get_pixel(0, y))));
+
+    let (left, right) = horz
+        .map(|(first, last)| (first, inner_w - last))
+        .unwrap_or((0, 0));
+    let (top, bottom) = vert
+        .map(|(first, last)| (first, inner_h - last))
+        .unwrap_or((0, 0));
+
+    let inner = image::imageops::crop_imm(img, 1, 1, inner_w, inner_h).to_image();
+
+    Some((inner, NineSlice::new(left, right, top, bottom)))
+}
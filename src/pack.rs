@@ -0,0 +1,3577 @@
+use crate::animated::FrameMode;
+use crate::append::AppendedAtlas;
+use crate::aseprite;
+use crate::atlas::{
+    self, Animation, AttributionGroup, ColorSpace, Content, FloatPrecision, FontFace, GlyphMode,
+    MotionVectorTexture, NamedTextureRegion, PageChannels, PixelFormat, Provenance, SplitPart,
+    TextureAtlas,
+};
+use crate::classify::{self, Decision};
+use crate::color::{self, ColorKey};
+use crate::dedup::{self, Merge};
+use crate::diagnostic::{self, Code};
+use crate::encode::{self, ColorSpaceOption, Encode, EncoderOptions, PageFormat, TextureLayout};
+use crate::export::{self, ExportInput};
+use crate::flipbook;
+use crate::font::{self, Charset, FontOptions};
+use crate::grid::{self, GridSize};
+use crate::ktx2;
+use crate::layout::{relative_path, resolve_path, OutputLayout};
+use crate::manifest::{self, ManifestEntry};
+use crate::merge;
+use crate::meta::{self, SpriteMeta, SpriteVariant};
+use crate::mips;
+use crate::parallel;
+use crate::planner::{self, Packing, PlacementHeuristic, PlanRect, SortOrder};
+use crate::polygon::{self, PolygonMode};
+use crate::portability;
+use crate::postprocess;
+use crate::progress;
+use crate::publish::{Overwrite, Publish};
+use crate::quarantine::{self, Rejection};
+use crate::repro;
+use crate::scratch::ScratchDir;
+use crate::sdf;
+use crate::sheet;
+use crate::source::{
+    load_source, probe_dimensions, InputFolder, LoadOptions, LoadedSource, Pivot, ResizeFilter,
+    SourceSprite,
+};
+use crate::spill::Spill;
+use crate::split::{self, Split, SplitBy};
+use crate::stable::PreviousLayout;
+use crate::stats::{self, AtlasStats};
+use crate::svg::{self, SvgOptions};
+use crate::usage::Usage;
+use crate::verify;
+use crate::waste;
+
+type SpritePixels = image::ImageBuffer<image::LumaA<u8>, Vec<u8>>;
+
+/// Resizes a normal map stored as XY, resampling the full vectors and renormalizing them.
+/// Resizing XY alone shortens the averaged normals.
+fn resize_normals(
+    pixels: &SpritePixels,
+    width: u32,
+    height: u32,
+    filter: ResizeFilter,
+) -> SpritePixels {
+    let decode = |c: u8| c as f32 / 255.0 * 2.0 - 1.0;
+    let encode = |v: f32| ((v * 0.5 + 0.5) * 255.0).round().clamp(0.0, 255.0) as u8;
+
+    let vectors = image::ImageBuffer::from_fn(pixels.width(), pixels.height(), |x, y| {
+        let [nx, ny] = pixels.get_pixel(x, y).0.map(decode);
+        let nz = (1.0 - nx * nx - ny * ny).max(0.0).sqrt();
+        image::Rgb([nx, ny, nz])
+    });
+
+    let resized = image::imageops::resize(&vectors, width, height, filter.filter_type());
+
+    image::ImageBuffer::from_fn(width, height, |x, y| {
+        let [nx, ny, nz] = resized.get_pixel(x, y).0;
+        let len = (nx * nx + ny * ny + nz * nz).sqrt().max(f32::EPSILON);
+        image::LumaA([encode(nx / len), encode(ny / len)])
+    })
+}
+
+/// Resamples the pixels of a sprite, and the planes aligned with them, to `w`x`h`, about
+/// `scale` times their size.
+fn resample(sprite: &mut SourceSprite, w: u32, h: u32, scale: f32, options: &PackOptions) {
+    let filter = ResizeFilter::for_scale(options.filter, scale);
+    sprite.pixels = if options.content == Content::NormalMap && options.renormalize {
+        resize_normals(&sprite.pixels, w, h, filter)
+    } else {
+        image::imageops::resize(&sprite.pixels, w, h, filter.filter_type())
+    };
+    sprite.resize_aligned(w, h, filter);
+}
+
+/// Resamples a sprite by `scale`, adjusting everything measured in its pixels.
+fn scale_sprite(mut sprite: SourceSprite, scale: f32, options: &PackOptions) -> SourceSprite {
+    let px = |v: u32| (v as f32 * scale).round() as u32;
+    let (width, height) = sprite.pixels.dimensions();
+    let (w, h) = (px(width).max(1), px(height).max(1));
+    resample(&mut sprite, w, h, scale, options);
+
+    if let Some(ns) = sprite.nine_slice.as_mut() {
+        (ns.left, ns.right, ns.top, ns.bottom) =
+            (px(ns.left), px(ns.right), px(ns.top), px(ns.bottom));
+    }
+
+    if let Some(delta) = sprite.delta.as_mut() {
+        (delta.x, delta.y) = (px(delta.x), px(delta.y));
+        (delta.canvas_width, delta.canvas_height) =
+            (px(delta.canvas_width), px(delta.canvas_height));
+    }
+
+    sprite
+}
+
+fn positive_factor(s: &str) -> Result<f32, String> {
+    match s.trim().parse::<f32>() {
+        Ok(f) if f > 0.0 && f.is_finite() => Ok(f),
+        _ => Err(format!("invalid scale factor '{}'", s)),
+    }
+}
+
+/// Makes a sprite larger than a page fit, by shrinking it or cutting it into page sized tiles.
+/// `margin` is kept on every side of it, so the tiles are the page less twice that.
+/// Sprites that fit are returned unchanged.
+fn fit_oversized(
+    mut sprite: SourceSprite,
+    margin: u32,
+    options: &PackOptions,
+) -> Vec<SourceSprite> {
+    let (width, height) = sprite.pixels.dimensions();
+    let page = options.sheet_size.saturating_sub(2 * margin);
+
+    //
+    // with margins taking the whole page nothing fits, left for the planner to report
+    if (width <= page && height <= page) || page == 0 {
+        return vec![sprite];
+    }
+
+    if options.downscale_oversized {
+        let scale = (page as f32 / width as f32).min(page as f32 / height as f32);
+        let (w, h) = (
+            ((width as f32 * scale).round() as u32).clamp(1, page),
+            ((height as f32 * scale).round() as u32).clamp(1, page),
+        );
+
+        log::info!(
+            "Downscaling {} from {}x{} to {}x{}",
+            sprite.label(),
+            width,
+            height,
+            w,
+            h
+        );
+
+        resample(&mut sprite, w, h, scale, options);
+        return vec![sprite];
+    }
+
+    if options.split_oversized {
+        log::info!(
+            "Splitting {} ({}x{}) into {}x{} tiles",
+            sprite.label(),
+            width,
+            height,
+            page,
+            page
+        );
+
+        let mut tiles = Vec::new();
+
+        for y in (0..height).step_by(page as usize) {
+            for x in (0..width).step_by(page as usize) {
+                let (w, h) = ((width - x).min(page), (height - y).min(page));
+
+                tiles.push(SourceSprite {
+                    key: format!("{}_{}_{}", sprite.key, x / page, y / page),
+                    split: Some(SplitPart::new(sprite.key.clone(), x, y, (width, height))),
+                    ..sprite.crop(x, y, w, h)
+                });
+            }
+        }
+
+        return tiles;
+    }
+
+    //
+    // left for the planner to report
+    vec![sprite]
+}
+
+#[derive(clap::Args, Clone, Debug)]
+pub struct PackOptions {
+    /// Folders with the images to pack, as `path` or `tag:path`.
+    #[arg(short, long)]
+    input_folders: Vec<InputFolder>,
+    /// A `.txt` or `.json` list of images to pack, with optional overrides of their
+    /// key, pivot, padding and tag in the JSON.
+    #[arg(long)]
+    manifest: Option<std::path::PathBuf>,
+    #[arg(short, long)]
+    atlas_name: String,
+    #[arg(short, long, default_value_t = 2048)]
+    sheet_size: u32,
+    /// Use the smallest pages, at most `--sheet-size` on a side, that hold the sprites
+    /// on no more pages than `--sheet-size` ones (or on `--target-pages`).
+    #[arg(long, conflicts_with_all = ["grid", "append"])]
+    optimize_size: bool,
+    /// With `--optimize-size`, only try power of two sides.
+    #[arg(long, requires = "optimize_size")]
+    power_of_two: bool,
+    /// Maximum number of array layers (pages) the atlas may use.
+    #[arg(long, default_value_t = planner::MAX_PAGES, value_parser = clap::value_parser!(u32).range(1..))]
+    max_layers: u32,
+    /// Use at least this many pages, spreading the sprites evenly over them when they
+    /// would fit on fewer.
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+    min_pages: u32,
+    /// Use exactly this many pages, like `--min-pages` and `--max-layers` together.
+    #[arg(long, conflicts_with_all = ["min_pages", "max_layers"], value_parser = clap::value_parser!(u32).range(1..))]
+    target_pages: Option<u32>,
+    /// Created with its parents when missing.
+    #[arg(short, long)]
+    output_dir: std::path::PathBuf,
+    /// Remove the outputs an earlier pack of the atlas wrote that this one does not,
+    /// e.g. the pages past the new page count.
+    #[arg(long)]
+    clean: bool,
+    /// Rewrite every output, unchanged ones too.
+    #[arg(long, conflicts_with = "no_overwrite")]
+    force: bool,
+    /// Fail instead of replacing outputs already on disk that would change.
+    #[arg(long)]
+    no_overwrite: bool,
+    /// Keep all sprites sharing a tag on the same array layer.
+    #[arg(long)]
+    group_tags: bool,
+    /// What sprites are ordered by before they are placed, largest first.
+    #[arg(long, value_enum, default_value_t = SortOrder::Area)]
+    sort: SortOrder,
+    /// Place sprites in the largest free rects left around the others, picked by this,
+    /// instead of cutting the pages up guillotine style.
+    #[arg(long, value_enum)]
+    placement_heuristic: Option<PlacementHeuristic>,
+    /// Pack with every sort and placement heuristic and keep the layout with the fewest
+    /// pages and the best occupancy.
+    #[arg(long, conflicts_with_all = ["sort", "placement_heuristic", "grid"])]
+    try_all: bool,
+    /// JSON file mapping scene names to the sprites drawn in them, from gameplay
+    /// telemetry. Sprites used together are kept on the same pages where they fit.
+    #[arg(long)]
+    usage: Option<std::path::PathBuf>,
+    /// Default pivot for sprites without one in their `.meta.ron` sidecar, a preset
+    /// (center, bottom-center, top-left, ...) or normalized `x,y`.
+    #[arg(long, default_value = "center")]
+    pivot: Pivot,
+    /// Where to put temporary files, defaults to the system temp directory.
+    #[arg(long)]
+    scratch_dir: Option<std::path::PathBuf>,
+    /// Don't delete temporary files when done, for debugging.
+    #[arg(long)]
+    keep_temp: bool,
+    /// Shrink the frame UVs by this many texels on every side, 0.5 for a half texel inset.
+    #[arg(long, default_value_t = 0.0)]
+    uv_inset: f32,
+    /// Repeat the edge pixels of every sprite this many times around it, so filtering
+    /// at its edges does not blend in the neighbors. Sidecars can give their own.
+    #[arg(long, default_value_t = 0)]
+    extrude: u32,
+    /// Shrink images larger than a page so they fit.
+    #[arg(long, conflicts_with = "split_oversized")]
+    downscale_oversized: bool,
+    /// Cut images larger than a page into page sized tiles.
+    #[arg(long)]
+    split_oversized: bool,
+    /// How to extract the frames of animated GIF/APNG inputs.
+    #[arg(long, value_enum, default_value_t = FrameMode::Composite)]
+    animated_frames: FrameMode,
+    /// Make pixels of this color (`RRGGBB`) transparent, for sources without alpha.
+    #[arg(long)]
+    color_key: Option<ColorKey>,
+    /// Maximum per channel difference from `--color-key` still made transparent.
+    #[arg(long, default_value_t = 0)]
+    color_key_tolerance: u8,
+    /// Whether Aseprite layers are flattened or packed as separate sprites
+    /// (`<name>_<layer>`).
+    #[arg(long, value_enum, default_value_t = aseprite::LayerMode::Merge)]
+    aseprite_layers: aseprite::LayerMode,
+    /// Resolution SVG inputs are rasterized at.
+    #[arg(long, default_value_t = svg::CSS_DPI, value_parser = positive_factor)]
+    svg_dpi: f32,
+    /// Extra scale factor for SVG inputs, on top of `--svg-dpi`.
+    #[arg(long, default_value_t = 1.0, value_parser = positive_factor)]
+    svg_scale: f32,
+    /// What the input images hold, `normal-map` keeps their X/Y channels untouched
+    /// instead of converting to luminance.
+    #[arg(long, value_enum, default_value_t = Content::Color)]
+    content: Content,
+    /// Renormalize normal map vectors after scaling.
+    #[arg(long)]
+    renormalize: bool,
+    /// How sprites are resampled by `--scale`, `--variants` and `--downscale-oversized`;
+    /// nearest for integer factors and Lanczos3 for the others by default.
+    #[arg(long, value_enum)]
+    filter: Option<ResizeFilter>,
+    /// How to encode the atlas texture, a KTX2 or an image per page.
+    #[arg(long, alias = "output-format", value_enum, default_value_t = Encode::Raw)]
+    encode: Encode,
+    /// When the sprites cannot be packed, write the sizes handed to the planner, the
+    /// options and the versions (no pixels) to this tar, for `replay` and bug reports.
+    #[arg(long, value_name = "TAR")]
+    dump_repro: Option<std::path::PathBuf>,
+    /// Copy the inputs left out of the build (undecodable, larger than a page) and the
+    /// ones sharing a name with another to `<DIR>/<atlas>/`, each with a
+    /// `.reason.txt`. Oversized sprites are left out instead of failing the build.
+    #[arg(long, value_name = "DIR")]
+    quarantine_dir: Option<std::path::PathBuf>,
+    /// Hold at most this many MiB of sprite pixels in memory, the others go to a file
+    /// in the scratch directory until they are blitted. The pages are not counted.
+    #[arg(
+        long,
+        value_name = "MIB",
+        conflicts_with_all = ["dedup", "lossy_dedup", "mixed_formats", "grid", "page_stats"]
+    )]
+    max_memory: Option<u64>,
+    /// What to do about sprites of different files getting the same key.
+    #[arg(long, value_enum, default_value_t = KeyConflict::Error)]
+    on_key_conflict: KeyConflict,
+    /// What to do about inputs that cannot be read (corrupt, unknown formats, fonts
+    /// and merged atlases failing to load).
+    #[arg(long, value_enum, default_value_t = OnInputError::Error)]
+    on_input_error: OnInputError,
+    /// The toktx binary to run, e.g. of a specific KTX-Software install, instead of
+    /// `toktx` from the `PATH`.
+    #[arg(long, value_name = "PATH")]
+    toktx_path: Option<std::path::PathBuf>,
+    /// Extra argument for toktx, repeated for several (`--toktx-arg --zcmp --toktx-arg
+    /// 19`). Given after the ones of the encode, so they take precedence.
+    #[arg(long = "toktx-arg", value_name = "ARG", allow_hyphen_values = true)]
+    toktx_args: Vec<String>,
+    /// Kill toktx runs taking longer than this many seconds.
+    #[arg(long, value_name = "SECONDS")]
+    encoder_timeout: Option<u64>,
+    /// Cap the address space of toktx at this many MiB (Unix only).
+    #[arg(long, value_name = "MIB")]
+    encoder_memory: Option<u64>,
+    /// Keep writing the other targets and atlases when one fails to encode, and probe
+    /// every page of a failed encode. The run still fails at the end.
+    #[arg(long)]
+    keep_going: bool,
+    /// Threads blitting, mipmapping and encoding the pages, one per core by default.
+    #[arg(long, short = 'j', default_value_t = 0, hide_default_value = true)]
+    jobs: usize,
+    /// Keep the frame indices and layers of the descriptor the last build wrote to the
+    /// output directory where possible, new frames take the places of removed ones.
+    #[arg(long)]
+    stable_indices: bool,
+    /// Pack only the sprites this atlas (a descriptor written by an earlier build) has
+    /// no frame for, into the space left on its pages, and write it again with them.
+    /// What the atlas holds stays where it is.
+    #[arg(long, value_name = "ATLAS", conflicts_with_all = ["grid", "mixed_formats", "stable_indices"])]
+    append: Option<std::path::PathBuf>,
+    /// Atlases whose frames are packed along with the inputs, given to `merge`.
+    #[arg(skip)]
+    merged: Vec<std::path::PathBuf>,
+    /// Pack a second time next to the output directory and fail unless every file comes
+    /// out byte for byte the same.
+    #[arg(long)]
+    check_deterministic: bool,
+    /// Transfer function of the texture, `auto` picks linear for normal maps and
+    /// sRGB otherwise.
+    #[arg(long, value_enum, default_value_t = ColorSpaceOption::Linear)]
+    color_space: ColorSpaceOption,
+    /// Generate a full mip chain, filtered in linear light for sRGB textures.
+    #[arg(long)]
+    mipmaps: bool,
+    /// Multiply the color by alpha when composing the pages.
+    #[arg(long)]
+    premultiply_alpha: bool,
+    /// Lay sprites out in uniform `WxH` cells in row-major order instead of packing them.
+    #[arg(long)]
+    grid: Option<GridSize>,
+    /// With `--grid`, cut images larger than a cell into cells first.
+    #[arg(long, requires = "grid")]
+    slice_tilesheets: bool,
+    /// Put sprites with a `max_mip` hint into a separate `<atlas>_nomip` atlas without
+    /// mipmaps, instead of mipmapping them with the rest.
+    #[arg(long, requires = "mipmaps")]
+    isolate_mip_capped: bool,
+    #[arg(skip)]
+    subset: Subset,
+    /// Pack every folder, tag or pixel format into an atlas of its own,
+    /// `<atlas>_<group>`, the sprites of none into `<atlas>`.
+    #[arg(long, value_enum, conflicts_with = "append")]
+    split_by: Option<SplitBy>,
+    #[arg(skip)]
+    split: Split,
+    /// Report input names that would break on other platforms (case clashes, characters
+    /// or names invalid on Windows, overlong paths).
+    #[arg(long)]
+    audit_portability: bool,
+    /// Fail instead of warning when an audit finds problems.
+    #[arg(long)]
+    strict: bool,
+    /// Scale all inputs by this factor before packing.
+    #[arg(long, default_value_t = 1.0, value_parser = positive_factor)]
+    scale: f32,
+    /// Build one atlas per scale factor, named `<atlas>@<factor>x`, e.g. `1,0.5,0.25`.
+    #[arg(long, value_delimiter = ',', value_parser = positive_factor)]
+    variants: Vec<f32>,
+    /// How outputs are arranged in the output directory.
+    #[arg(long, value_enum, default_value_t = OutputLayout::Flat)]
+    output_layout: OutputLayout,
+    /// Round the floats of the descriptors and exports to `N` decimal places, or with
+    /// `fixed:N` to multiples of `1 / 2^N`.
+    #[arg(long)]
+    float_precision: Option<FloatPrecision>,
+    /// How the pages are arranged in the textures: one texture array, a texture and a
+    /// descriptor per page, a single texture with the pages side by side, or the slices
+    /// of a 3D texture.
+    #[arg(long, value_enum, default_value_t = TextureLayout::Array)]
+    layout: TextureLayout,
+    /// Pack opaque sprites, masks (white with alpha) and the others on pages of their
+    /// own, stored with only luminance, only alpha, or both. Needs `--layout pages`, a
+    /// texture per page.
+    #[arg(long)]
+    mixed_formats: bool,
+    /// Also write the pages as PNG previews (`<atlas>_page<N>.png`).
+    #[arg(long)]
+    preview: bool,
+    /// Also write a picture of the used and free space of every page
+    /// (`<atlas>_waste<N>.png`), the free space as the rects left to fill.
+    #[arg(long)]
+    waste_map: bool,
+    /// Also write the pages the encoder is given, which otherwise only live in the
+    /// scratch directory: `<atlas>_page<N>.png`, their mips as
+    /// `<atlas>_page<N>_mip<L>.png` and the motion vector pages as
+    /// `<atlas>_mv_page<N>.png`, next to the previews.
+    #[arg(long)]
+    keep_pages: bool,
+    /// Record for every frame the size of the image it was cut from and the
+    /// modification time of its file, next to its `source` and `source_hash`.
+    #[arg(long)]
+    with_provenance: bool,
+    /// Also write the JSON Schema of the descriptor format as `<atlas>.schema.json`,
+    /// next to the descriptor.
+    #[arg(long)]
+    emit_schema: bool,
+    /// Where `--keep-pages` writes the pages instead of next to the previews, implies
+    /// `--keep-pages`.
+    #[arg(long)]
+    pages_dir: Option<std::path::PathBuf>,
+    /// Pair frame images with their motion vector images (`smoke_03.png` and
+    /// `smoke_mv_03.png`) and pack the vectors into a second texture sharing the layout.
+    #[arg(long)]
+    motion_vectors: bool,
+    /// Texel format of the atlas. The 16-bit and float formats keep the full RGBA of
+    /// 16-bit PNG, EXR and HDR inputs and can only be encoded `raw` or `png`.
+    #[arg(long, value_enum, default_value_t = PixelFormat::La8)]
+    pixel_format: PixelFormat,
+    /// Also write the collected attributions as `<atlas>.ATTRIBUTION.txt`.
+    #[arg(long)]
+    attribution_file: bool,
+    /// Pick trimming, filter and alpha hints per sprite from its content.
+    #[arg(long)]
+    classify: bool,
+    /// Write an outline of the visible pixels of every frame, triangulated, for
+    /// drawing sprites as tight meshes. The sprites are trimmed to it.
+    #[arg(long, value_enum)]
+    polygons: Option<PolygonMode>,
+    /// Pixels an outline may stay off the visible ones, more for fewer vertices.
+    #[arg(long, default_value_t = 2.0, requires = "polygons", value_parser = positive_factor)]
+    polygon_tolerance: f32,
+    /// Write the classification decisions to `<atlas>.classify.ron`.
+    #[arg(long, requires = "classify")]
+    classify_report: bool,
+    /// Pack sprites with identical pixels once, their frames share the region.
+    #[arg(long)]
+    dedup: bool,
+    /// Also merge sprites closer than `--dedup-psnr`, for lossy encodes where the
+    /// difference would not survive encoding anyway. Writes the merges to
+    /// `<atlas>.dedup.ron`.
+    #[arg(long)]
+    lossy_dedup: bool,
+    /// Minimum PSNR in dB at which `--lossy-dedup` merges two sprites.
+    #[arg(long, default_value_t = 45.0, requires = "lossy_dedup")]
+    dedup_psnr: f64,
+    /// TTF/OTF fonts whose glyphs are packed along with the sprites.
+    #[arg(long = "font")]
+    fonts: Vec<std::path::PathBuf>,
+    /// Characters to rasterize, `U+XXXX` codepoints, `U+XXXX-U+YYYY` ranges or literal
+    /// characters, separated by commas.
+    #[arg(long, default_value = "U+0020-U+007E")]
+    charset: Charset,
+    /// Pixel size glyphs are rasterized at.
+    #[arg(long, default_value_t = 32.0, value_parser = positive_factor)]
+    font_size: f32,
+    #[arg(long, value_enum, default_value_t = GlyphMode::Plain)]
+    glyph_mode: GlyphMode,
+    /// Pixels from the outline to either end of the distance range with `--glyph-mode sdf`.
+    #[arg(long, default_value_t = 4, value_parser = clap::value_parser!(u32).range(1..))]
+    glyph_spread: u32,
+    /// Where to write the descriptor, embedding it keeps the texture and its frames in
+    /// one file.
+    #[arg(long, value_enum, default_value_t = DescriptorOutput::File)]
+    descriptor: DescriptorOutput,
+    /// Keep a frame reachable under an old name, `old=new`. The alias is a descriptor
+    /// entry sharing the frame's region.
+    #[arg(long = "alias", value_name = "OLD=NEW")]
+    aliases: Vec<Alias>,
+    /// Also describe the atlas in these engine formats, comma separated or repeated.
+    #[arg(long = "export", value_delimiter = ',', value_parser = export::format_parser())]
+    exports: Vec<String>,
+    /// Run these passes over the pixels of every frame once it is placed, comma
+    /// separated or repeated, in that order.
+    #[arg(long, value_delimiter = ',', value_parser = postprocess::name_parser())]
+    post_process: Vec<String>,
+    /// Write page counts and usage to `<atlas>.stats.json`.
+    #[arg(long)]
+    stats: bool,
+    /// Add per-page content statistics (alpha coverage, luminance, histogram, black
+    /// sprites) to the stats.
+    #[arg(long, requires = "stats")]
+    page_stats: bool,
+    /// Convert the (monochrome) sprites to signed distance fields.
+    #[arg(long, conflicts_with_all = ["motion_vectors", "pixel_format"])]
+    sdf: bool,
+    /// Pixels from the outline to either end of the distance range, the sprites grow
+    /// by as much on every side.
+    #[arg(long, default_value_t = 4, requires = "sdf", value_parser = clap::value_parser!(u32).range(1..))]
+    sdf_spread: u32,
+}
+
+impl PackOptions {
+    /// How the planner places the sprites, without `--try-all`.
+    pub fn packing(&self) -> Packing {
+        Packing {
+            sort: self.sort,
+            placement: self.placement_heuristic,
+        }
+    }
+
+    /// Resolves relative paths against `dir`.
+    pub fn rebase(&mut self, dir: &std::path::Path) {
+        for folder in self.input_folders.iter_mut() {
+            folder.path = dir.join(&folder.path);
+        }
+        for font in self.fonts.iter_mut() {
+            *font = dir.join(&*font);
+        }
+        self.usage = self.usage.as_ref().map(|p| dir.join(p));
+        self.manifest = self.manifest.as_ref().map(|p| dir.join(p));
+        self.append = self.append.as_ref().map(|p| dir.join(p));
+        self.output_dir = dir.join(&self.output_dir);
+        self.scratch_dir = self.scratch_dir.as_ref().map(|p| dir.join(p));
+        self.pages_dir = self.pages_dir.as_ref().map(|p| dir.join(p));
+        self.dump_repro = self.dump_repro.as_ref().map(|p| dir.join(p));
+        self.quarantine_dir = self.quarantine_dir.as_ref().map(|p| dir.join(p));
+        //
+        // a bare name is looked up in the PATH
+        self.toktx_path = self
+            .toktx_path
+            .as_ref()
+            .map(|p| match p.components().count() > 1 {
+                true => dir.join(p),
+                false => p.clone(),
+            });
+    }
+
+    pub fn add_aliases(&mut self, aliases: impl IntoIterator<Item = Alias>) {
+        self.aliases.extend(aliases);
+    }
+
+    /// Packs the frames of the atlases with the inputs, identical pixels once.
+    pub fn merge_atlases(&mut self, atlases: impl IntoIterator<Item = std::path::PathBuf>) {
+        self.merged.extend(atlases);
+        self.dedup = true;
+    }
+
+    fn overwrite(&self) -> Overwrite {
+        match (self.force, self.no_overwrite) {
+            (true, _) => Overwrite::Always,
+            (false, true) => Overwrite::Never,
+            (false, false) => Overwrite::Changed,
+        }
+    }
+
+    /// The smallest and largest number of pages the atlas may use.
+    fn page_range(&self) -> (u32, u32) {
+        match self.target_pages {
+            Some(pages) => (pages, pages),
+            None => (self.min_pages, self.max_layers),
+        }
+    }
+
+    pub fn input_folders(&self) -> &[InputFolder] {
+        &self.input_folders
+    }
+
+    pub fn manifest(&self) -> Option<&std::path::Path> {
+        self.manifest.as_deref()
+    }
+
+    pub fn fonts(&self) -> &[std::path::PathBuf] {
+        &self.fonts
+    }
+
+    pub fn encode(&self) -> Encode {
+        self.encode
+    }
+
+    pub fn keep_going(&self) -> bool {
+        self.keep_going
+    }
+
+    fn encoder_options(&self) -> EncoderOptions {
+        EncoderOptions {
+            toktx: self.toktx_path.clone(),
+            toktx_args: self.toktx_args.clone(),
+            timeout: self.encoder_timeout.map(std::time::Duration::from_secs),
+            memory_mib: self.encoder_memory,
+            keep_going: self.keep_going,
+            jobs: self.jobs,
+        }
+    }
+}
+
+/// Modification times of a source, its sidecar and its motion vectors.
+type SourceStamp = [Option<std::time::SystemTime>; 3];
+
+fn source_stamp(path: &std::path::Path, motion: Option<&std::path::Path>) -> SourceStamp {
+    let modified = |p: &std::path::Path| std::fs::metadata(p).and_then(|m| m.modified()).ok();
+    [
+        modified(path),
+        modified(&meta::sidecar_path(path)),
+        motion.and_then(modified),
+    ]
+}
+
+struct CachedSource {
+    stamp: SourceStamp,
+    meta: SpriteMeta,
+    loaded: LoadedSource,
+}
+
+/// State kept between the packs of a long running process: the decoded sources of
+/// unchanged files and the page buffers of the previous pack.
+#[derive(Default)]
+pub struct PackCache {
+    sources: std::collections::HashMap<std::path::PathBuf, CachedSource>,
+    page_pool: Vec<SpritePixels>,
+}
+
+impl PackCache {
+    pub fn cached_sources(&self) -> usize {
+        self.sources.len()
+    }
+
+    pub fn pooled_pages(&self) -> usize {
+        self.page_pool.len()
+    }
+
+    /// A cleared page buffer, reusing one of the previous pack if possible.
+    fn take_page(&mut self, width: u32, height: u32) -> SpritePixels {
+        match self
+            .page_pool
+            .iter()
+            .position(|p| p.dimensions() == (width, height))
+        {
+            Some(idx) => {
+                let mut page = self.page_pool.swap_remove(idx);
+                page.fill(0);
+                page
+            }
+            None => image::ImageBuffer::new(width, height),
+        }
+    }
+}
+
+/// Another key for a frame, `old=new` on the command line.
+#[derive(Clone, Debug)]
+pub struct Alias {
+    pub key: String,
+    pub target: String,
+}
+
+impl std::str::FromStr for Alias {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once('=') {
+            Some((key, target)) if !key.is_empty() && !target.is_empty() => Ok(Alias {
+                key: key.to_string(),
+                target: target.to_string(),
+            }),
+            _ => Err(format!("invalid alias '{}', expected OLD=NEW", s)),
+        }
+    }
+}
+
+/// Where the atlas descriptor is written.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum DescriptorOutput {
+    /// `<atlas>.ron`.
+    File,
+    /// The KTX2 key/value data, under `texture-packer.atlas`.
+    Embedded,
+    Both,
+}
+
+/// What happens to a sprite named like one loaded before it.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum KeyConflict {
+    /// Fail, listing the files.
+    Error,
+    /// Name the frames of the later file `<folder>/<key>`, the folder's path below
+    /// where the input folders part. Keys clashing still fail.
+    Prefix,
+    /// Leave the later sprite out.
+    Skip,
+}
+
+/// What happens to inputs that cannot be read.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum OnInputError {
+    /// Fail once all inputs are loaded, listing every one that could not be read.
+    Error,
+    /// Leave them out, listing them in one warning.
+    Warn,
+    /// Leave them out without a word.
+    Skip,
+}
+
+/// Which sprites a pack includes, when sprites are split over several atlases.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+enum Subset {
+    #[default]
+    All,
+    Uncapped,
+    MipCapped,
+}
+
+impl Subset {
+    fn includes(self, max_mip: Option<u32>) -> bool {
+        match self {
+            Subset::All => true,
+            Subset::Uncapped => max_mip.is_none(),
+            Subset::MipCapped => max_mip.is_some(),
+        }
+    }
+}
+
+/// One texture output of a pack, `name` selects a subdirectory of the output directory.
+#[derive(Clone, Debug)]
+pub struct EncodeTarget {
+    pub name: Option<String>,
+    pub encode: Encode,
+}
+
+pub fn run(packer_args: PackOptions) -> Result<(), String> {
+    let targets = [EncodeTarget {
+        name: None,
+        encode: packer_args.encode,
+    }];
+
+    run_targets(&packer_args, &targets)
+}
+
+/// Packs once and writes the pages out for every target.
+pub fn run_targets(packer_args: &PackOptions, targets: &[EncodeTarget]) -> Result<(), String> {
+    run_cached(packer_args, targets, None)?;
+    match packer_args.check_deterministic {
+        true => check_deterministic(packer_args, targets),
+        false => Ok(()),
+    }
+}
+
+/// Every file below `root`, relative to it.
+fn list_files(root: &std::path::Path) -> Result<Vec<std::path::PathBuf>, String> {
+    let mut files = Vec::new();
+    let mut pending = vec![std::path::PathBuf::new()];
+
+    while let Some(dir) = pending.pop() {
+        let entries = std::fs::read_dir(root.join(&dir))
+            .map_err(|e| format!("Failed to read {}: {}", root.join(&dir).display(), e))?;
+        for entry in entries.filter_map(|e| e.ok()) {
+            let path = dir.join(entry.file_name());
+            match entry.file_type().map(|t| t.is_dir()) {
+                Ok(true) => pending.push(path),
+                _ => files.push(path),
+            }
+        }
+    }
+
+    files.sort();
+    Ok(files)
+}
+
+/// Packs again into a directory next to the output directory, where the paths relative
+/// to the descriptors stay the same, and compares what was written with the output.
+fn check_deterministic(packer_args: &PackOptions, targets: &[EncodeTarget]) -> Result<(), String> {
+    let output_dir = std::path::absolute(&packer_args.output_dir).map_err(|e| {
+        format!(
+            "Failed to resolve {}: {}",
+            packer_args.output_dir.display(),
+            e
+        )
+    })?;
+    let check_dir = ScratchDir::create(output_dir.parent(), false)?;
+    log::info!(
+        "Packing {} again to check the output is deterministic",
+        packer_args.atlas_name
+    );
+
+    let options = PackOptions {
+        output_dir: check_dir.path().to_path_buf(),
+        quarantine_dir: None,
+        dump_repro: None,
+        check_deterministic: false,
+        ..packer_args.clone()
+    };
+    run_cached(&options, targets, None)?;
+
+    let files = list_files(check_dir.path())?;
+    let differing = files
+        .iter()
+        .filter(|file| {
+            std::fs::read(check_dir.path().join(file)).ok()
+                != std::fs::read(output_dir.join(file)).ok()
+        })
+        .collect::<Vec<_>>();
+
+    match differing.is_empty() {
+        true => {
+            log::info!("The {} file(s) came out the same", files.len());
+            Ok(())
+        }
+        false => Err(Code::NotDeterministic.error(format_args!(
+            "{} of {} file(s) came out different when packed again: {}",
+            differing.len(),
+            files.len(),
+            differing
+                .iter()
+                .map(|f| f.display().to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        ))),
+    }
+}
+
+/// Like `run_targets`, reusing and updating `cache` for repeated packs.
+pub fn run_cached(
+    packer_args: &PackOptions,
+    targets: &[EncodeTarget],
+    cache: Option<&mut PackCache>,
+) -> Result<(), String> {
+    check_targets(packer_args, targets)?;
+    //
+    // the atlases of variants and splits are published together, one that fails to
+    // encode leaves all of them as they were
+    let mut publish = Publish::new(packer_args.overwrite());
+    let (mut failed, mut encoded) = (0, 0);
+    run_packed(packer_args, cache, &mut |packed| {
+        let encoding = progress::phase("Encoding", targets.len());
+        for target in targets {
+            //
+            // a failed target leaves nothing staged
+            let mut staged = Publish::new(packer_args.overwrite());
+            match packed.stage_target(target, &mut staged) {
+                Ok(()) => publish.append(staged),
+                Err(e) if packer_args.keep_going => {
+                    diagnostic::report_error(&e);
+                    failed += 1;
+                }
+                Err(e) => return Err(e),
+            }
+            encoded += 1;
+            encoding.inc();
+        }
+        Ok(())
+    })?;
+    publish.commit()?;
+
+    match failed {
+        0 => Ok(()),
+        _ => Err(Code::EncoderFailed.error(format_args!(
+            "{} of {} targets of {} failed",
+            failed, encoded, packer_args.atlas_name
+        ))),
+    }
+}
+
+/// Fails early for targets that cannot take the pixel format.
+pub fn check_targets(packer_args: &PackOptions, targets: &[EncodeTarget]) -> Result<(), String> {
+    if let Some(target) = targets
+        .iter()
+        .find(|t| packer_args.lossy_dedup && !t.encode.is_lossy())
+    {
+        return Err(Code::UnsupportedTarget.error(format_args!(
+            "--lossy-dedup needs a lossy encode, {:?} keeps the differences visible",
+            target.encode
+        )));
+    }
+
+    if let Some(target) = targets
+        .iter()
+        .find(|t| packer_args.descriptor == DescriptorOutput::Embedded && !t.encode.is_ktx2())
+    {
+        return Err(Code::UnsupportedTarget.error(format_args!(
+            "The {:?} encode has no KTX2 to embed the descriptor in, use --descriptor both",
+            target.encode
+        )));
+    }
+
+    if packer_args.layout == TextureLayout::Volume {
+        if let Some(target) = targets.iter().find(|t| !t.encode.is_ktx2()) {
+            return Err(Code::UnsupportedTarget.error(format_args!(
+                "The {:?} encode writes no KTX2 to make a 3D texture of",
+                target.encode
+            )));
+        }
+        if packer_args.mipmaps {
+            return Err(Code::UnsupportedTarget.error(format_args!(
+                "--layout volume cannot take --mipmaps, the levels of a 3D texture halve the \
+                 slice count as well"
+            )));
+        }
+    }
+
+    if !packer_args.post_process.is_empty() && !packer_args.pixel_format.is_la8() {
+        return Err(Code::UnsupportedTarget.error(
+            "--post-process only runs on la8 pages, the 16-bit and float pages are composed \
+             from the sources",
+        ));
+    }
+
+    if packer_args.mixed_formats {
+        if !packer_args.pixel_format.is_la8()
+            || packer_args.content != Content::Color
+            || packer_args.grid.is_some()
+        {
+            return Err(Code::UnsupportedTarget
+                .error("--mixed-formats only takes la8 color sprites, without --grid"));
+        }
+        if packer_args.layout != TextureLayout::Pages {
+            return Err(Code::UnsupportedTarget.error(
+                "--mixed-formats needs --layout pages, pages of different formats cannot \
+                 share a texture",
+            ));
+        }
+    }
+
+    match targets.iter().find(|t| {
+        !packer_args.pixel_format.is_la8() && !matches!(t.encode, Encode::Raw | Encode::Png)
+    }) {
+        Some(target) => Err(Code::UnsupportedTarget.error(format_args!(
+            "The {:?} encode only takes the la8 pixel format, use raw or png",
+            target.encode
+        ))),
+        None => Ok(()),
+    }
+}
+
+/// Packs every atlas the options describe (groups, resolution variants, mip subsets)
+/// and hands them to `deliver` to be written out.
+pub fn run_packed(
+    packer_args: &PackOptions,
+    cache: Option<&mut PackCache>,
+    deliver: &mut dyn FnMut(PackedAtlas) -> Result<(), String>,
+) -> Result<(), String> {
+    let Some(by) = packer_args.split_by else {
+        return pack_variants(packer_args, cache, deliver);
+    };
+
+    let manifest = match packer_args.manifest.as_deref() {
+        Some(path) => manifest::load(path)?,
+        None => Vec::new(),
+    };
+    //
+    // every group is packed on its own, the sources are decoded once for all of them
+    let mut own_cache = PackCache::default();
+    let cache = cache.unwrap_or(&mut own_cache);
+    for group in split::groups(by, &packer_args.input_folders, &manifest) {
+        let options = PackOptions {
+            atlas_name: match group.as_deref() {
+                Some(group) => format!("{}_{}", packer_args.atlas_name, group),
+                None => packer_args.atlas_name.clone(),
+            },
+            split: Split::Group(group),
+            ..packer_args.clone()
+        };
+
+        pack_variants(&options, Some(&mut *cache), deliver)?;
+    }
+
+    Ok(())
+}
+
+fn pack_variants(
+    packer_args: &PackOptions,
+    mut cache: Option<&mut PackCache>,
+    deliver: &mut dyn FnMut(PackedAtlas) -> Result<(), String>,
+) -> Result<(), String> {
+    if packer_args.variants.is_empty() {
+        return pack_subsets(packer_args, cache, deliver);
+    }
+
+    //
+    // every resolution is packed on its own, sharing the decoded sources
+    for factor in packer_args.variants.iter() {
+        let options = PackOptions {
+            atlas_name: format!("{}@{}x", packer_args.atlas_name, factor),
+            scale: packer_args.scale * factor,
+            variants: Vec::new(),
+            ..packer_args.clone()
+        };
+
+        pack_subsets(&options, cache.as_deref_mut(), deliver)?;
+    }
+
+    Ok(())
+}
+
+fn pack_subsets(
+    packer_args: &PackOptions,
+    mut cache: Option<&mut PackCache>,
+    deliver: &mut dyn FnMut(PackedAtlas) -> Result<(), String>,
+) -> Result<(), String> {
+    if !packer_args.isolate_mip_capped {
+        return pack_once(packer_args, cache, deliver);
+    }
+
+    let uncapped = PackOptions {
+        subset: Subset::Uncapped,
+        ..packer_args.clone()
+    };
+    let capped = PackOptions {
+        atlas_name: format!("{}_nomip", packer_args.atlas_name),
+        subset: Subset::MipCapped,
+        mipmaps: false,
+        ..packer_args.clone()
+    };
+
+    pack_once(&uncapped, cache.as_deref_mut(), deliver)?;
+    pack_once(&capped, cache, deliver)
+}
+
+fn pack_once(
+    packer_args: &PackOptions,
+    cache: Option<&mut PackCache>,
+    deliver: &mut dyn FnMut(PackedAtlas) -> Result<(), String>,
+) -> Result<(), String> {
+    match compose(packer_args, cache)? {
+        Some(packed) => deliver(packed),
+        None => Ok(()),
+    }
+}
+
+/// Sprites loaded from the input folders, with their rects for the planner.
+pub struct Inputs {
+    pub sprites: Vec<SourceSprite>,
+    pub rects: Vec<PlanRect<usize>>,
+    pub animations: Vec<Animation>,
+    pub decisions: Vec<Decision>,
+    pub attributions: Vec<AttributionGroup>,
+    pub fonts: Vec<FontFace>,
+    /// Files left out, or clashing, for `--quarantine-dir`.
+    pub rejected: Vec<Rejection>,
+    /// Padding and extrusion of the sprites having some, included in their rects.
+    pub margins: fnv::FnvHashMap<usize, Margin>,
+    /// Pixels of the sprites over `--max-memory`.
+    pub spill: Spill,
+}
+
+/// Space kept around a sprite on its page.
+#[derive(Copy, Clone, Default)]
+pub struct Margin {
+    /// Transparent pixels, outside the extrusion.
+    pub padding: u32,
+    /// Copies of the edge pixels.
+    pub extrude: u32,
+}
+
+impl Margin {
+    pub fn total(&self) -> u32 {
+        self.padding + self.extrude
+    }
+}
+
+impl Inputs {
+    /// Leaves out the sprites `keep` is false for, renumbering the others.
+    fn retain_sprites(&mut self, mut keep: impl FnMut(&SourceSprite) -> bool) {
+        let mut ids = Vec::with_capacity(self.sprites.len());
+        let mut kept = 0;
+        for sprite in self.sprites.iter() {
+            ids.push(keep(sprite).then(|| {
+                kept += 1;
+                kept - 1
+            }));
+        }
+
+        let mut sprites = std::mem::take(&mut self.sprites).into_iter();
+        self.sprites = ids
+            .iter()
+            .filter_map(|id| sprites.next().filter(|_| id.is_some()))
+            .collect();
+        self.rects.retain_mut(|r| match ids[r.id] {
+            Some(id) => {
+                r.id = id;
+                true
+            }
+            None => false,
+        });
+        self.margins = std::mem::take(&mut self.margins)
+            .into_iter()
+            .filter_map(|(id, margin)| ids[id].map(|id| (id, margin)))
+            .collect();
+        self.spill.renumber(&ids);
+    }
+}
+
+/// The rects [`load_inputs`] hands to the planner, sized from the image headers.
+pub struct ProbedInputs {
+    pub rects: Vec<PlanRect<usize>>,
+    /// What the errors of the planner name each rect by.
+    pub labels: Vec<String>,
+}
+
+/// Sizes the sprites of the input folders from their image headers, for predictions
+/// that only need the layout. None when decoding could change a size: sidecars,
+/// trimming, scaling, grids, distance fields, fonts, manifests, merged atlases, sources
+/// only sized once decoded and sprites larger than a page.
+pub fn probe_inputs(packer_args: &PackOptions) -> Option<ProbedInputs> {
+    if packer_args.classify
+        || packer_args.polygons.is_some()
+        || packer_args.sdf
+        || packer_args.scale != 1.0
+        || packer_args.grid.is_some()
+        || packer_args.motion_vectors
+        || packer_args.manifest.is_some()
+        || !packer_args.fonts.is_empty()
+        || !packer_args.merged.is_empty()
+        || !packer_args.subset.includes(None)
+        || packer_args.on_key_conflict == KeyConflict::Skip
+    {
+        return None;
+    }
+
+    let mut probed = ProbedInputs {
+        rects: Vec::new(),
+        labels: Vec::new(),
+    };
+    let page = packer_args.sheet_size;
+    for folder in packer_args.input_folders.iter() {
+        let Ok(dir_iter) = std::fs::read_dir(&folder.path) else {
+            continue;
+        };
+        //
+        // a folder sidecar can pin its sprites to a layer
+        if meta::load(&folder.path).is_ok_and(|m| m.is_some_and(|m| m.layer.is_some())) {
+            return None;
+        }
+        //
+        // in the order load_inputs takes them, it decides the layout
+        let mut paths = dir_iter
+            .filter_map(|de| de.ok().map(|d| d.path()))
+            .filter(|path| path.is_file() && !path.to_string_lossy().ends_with(".meta.ron"))
+            .collect::<Vec<_>>();
+        paths.sort();
+
+        for path in paths {
+            if !matches!(meta::load(&path), Ok(None)) {
+                return None;
+            }
+            let (width, height) =
+                probe_dimensions(&path).filter(|&(w, h)| w <= page && h <= page)?;
+            let pad = packer_args.extrude;
+            probed.rects.push(PlanRect {
+                id: probed.rects.len(),
+                width: width + 2 * pad,
+                height: height + 2 * pad,
+                group: folder.tag.clone().filter(|_| packer_args.group_tags),
+                page: None,
+            });
+            probed.labels.push(path.display().to_string());
+        }
+    }
+
+    Some(probed)
+}
+
+/// Files loaded with the tag and folder sidecar of their input folder, or a manifest
+/// entry.
+struct InputGroup<'a> {
+    tag: Option<String>,
+    meta: SpriteMeta,
+    paths: Vec<std::path::PathBuf>,
+    entry: Option<&'a ManifestEntry>,
+}
+
+/// Loads the sprites of all input folders and manifest entries, preprocessed (sidecar
+/// transform, scaling, oversize handling) and ready for placement.
+pub fn load_inputs(
+    packer_args: &PackOptions,
+    manifest: &[ManifestEntry],
+    cache: Option<&mut PackCache>,
+) -> Result<Inputs, String> {
+    let mut rects_to_place = Vec::<PlanRect<usize>>::new();
+    let mut src_img_bytes = Vec::<SourceSprite>::new();
+    let mut keys = fnv::FnvHashSet::<String>::default();
+    let mut spill = Spill::new(packer_args.max_memory, packer_args.scratch_dir.as_deref());
+    let mut spill_failed = None;
+    //
+    // the cache would hold every decoded source anyway
+    let mut cache = cache.filter(|_| packer_args.max_memory.is_none());
+    let mut animations = Vec::<Animation>::new();
+    let mut seen = std::collections::HashSet::<std::path::PathBuf>::new();
+    let mut decisions = Vec::<Decision>::new();
+    let mut attributions = Vec::<AttributionGroup>::new();
+    let mut rejected = Vec::<Rejection>::new();
+    let mut margins = fnv::FnvHashMap::<usize, Margin>::default();
+    //
+    // grid cells have a fixed size, trimming would move the tiles
+    let allow_trim = packer_args.grid.is_none();
+    let classifiers = match packer_args.classify {
+        true => classify::classifiers(allow_trim && packer_args.content.is_color()),
+        false => Vec::new(),
+    };
+    let trim_only: Vec<Box<dyn classify::Classifier>> =
+        vec![Box::new(classify::AlphaClassifier { trim: allow_trim })];
+    //
+    // how the 8 bit pixels are encoded, for the tone adjustments
+    let tone_space = packer_args.color_space.resolve(packer_args.content);
+    let load_options = LoadOptions {
+        frame_mode: packer_args.animated_frames,
+        content: packer_args.content,
+        color_key: packer_args.color_key.map(|key| ColorKey {
+            tolerance: packer_args.color_key_tolerance,
+            ..key
+        }),
+        aseprite_layers: packer_args.aseprite_layers,
+        pixel_format: packer_args.pixel_format,
+        svg: SvgOptions {
+            dpi: packer_args.svg_dpi,
+            scale: packer_args.svg_scale,
+        },
+    };
+
+    let is_source =
+        |path: &std::path::Path| path.is_file() && !path.to_string_lossy().ends_with(".meta.ron");
+    let groups = packer_args
+        .input_folders
+        .iter()
+        .filter_map(|folder| {
+            let dir_iter = std::fs::read_dir(&folder.path).ok()?;
+            let folder_meta = meta::load(&folder.path)
+                .unwrap_or_else(|e| {
+                    diagnostic::warn(Code::InvalidMetadata, e);
+                    None
+                })
+                .unwrap_or_default();
+            //
+            // listings come in no particular order, the sprite order decides the layout
+            let mut paths = dir_iter
+                .filter_map(|de| de.ok().map(|d| d.path()))
+                .filter(|path| is_source(path))
+                .collect::<Vec<_>>();
+            paths.sort();
+
+            Some(InputGroup {
+                tag: folder.tag.clone(),
+                meta: folder_meta,
+                paths,
+                entry: None,
+            })
+        })
+        .chain(manifest.iter().map(|entry| InputGroup {
+            tag: entry.tag.clone(),
+            meta: SpriteMeta::default(),
+            paths: vec![entry.path.clone()],
+            entry: Some(entry),
+        }))
+        .collect::<Vec<_>>();
+    let decoding = progress::phase("Decoding", groups.iter().map(|g| g.paths.len()).sum());
+    //
+    // prefixed keys name the folder from where the input folders part
+    let prefix_root = groups
+        .iter()
+        .flat_map(|g| g.paths.iter())
+        .filter_map(|p| p.parent())
+        .fold(None, |root: Option<std::path::PathBuf>, dir| {
+            Some(match root {
+                None => dir.to_path_buf(),
+                Some(root) => root
+                    .components()
+                    .zip(dir.components())
+                    .take_while(|(a, b)| a == b)
+                    .map(|(a, _)| a)
+                    .collect(),
+            })
+        })
+        .unwrap_or_default();
+
+    groups
+        .into_iter()
+        .for_each(|InputGroup { tag, meta: folder_meta, paths, entry }| {
+            let mut unreadable = Vec::new();
+
+            paths
+                .into_iter()
+                .inspect(|_| decoding.inc())
+                //
+                // motion vectors are loaded along with their frame
+                .filter(|de| {
+                    !packer_args.motion_vectors
+                        || !flipbook::color_partner(de).is_some_and(|c| c.is_file())
+                })
+                .filter_map(|path| {
+                    let motion_path = Some(flipbook::motion_partner(&path))
+                        .filter(|p| packer_args.motion_vectors && p.is_file());
+                    let stamp = cache
+                        .as_ref()
+                        .map(|_| source_stamp(&path, motion_path.as_deref()));
+                    if let Some(cached) = cache
+                        .as_deref()
+                        .and_then(|c| c.sources.get(&path))
+                        .filter(|c| Some(c.stamp) == stamp)
+                    {
+                        seen.insert(path);
+                        return Some((cached.loaded.clone(), cached.meta.clone()));
+                    }
+
+                    let sprite_meta = meta::load(&path)
+                        .unwrap_or_else(|e| {
+                            diagnostic::warn(Code::InvalidMetadata, e);
+                            None
+                        })
+                        .unwrap_or_default();
+
+                    let mut reject = |path: &std::path::Path, reason: String| {
+                        unreadable.push(Rejection {
+                            path: path.to_path_buf(),
+                            code: Code::UnreadableInput,
+                            reason,
+                        });
+                    };
+
+                    let mut loaded = load_source(&path, tag.clone(), load_options)
+                        .map_err(|e| reject(&path, e))
+                        .ok()?;
+
+                    if let Some(motion) = motion_path.as_deref().and_then(|motion_path| {
+                        load_source(
+                            motion_path,
+                            None,
+                            LoadOptions {
+                                content: Content::MotionVectors,
+                                color_key: None,
+                                ..load_options
+                            },
+                        )
+                        .map_err(|e| reject(motion_path, e))
+                        .ok()
+                    }) {
+                        if let Err(e) = flipbook::attach(&mut loaded, motion) {
+                            diagnostic::warn(
+                                Code::MotionVectorsIgnored,
+                                format_args!(
+                                    "Ignoring motion vectors of {}: {}",
+                                    path.display(),
+                                    e
+                                ),
+                            );
+                        }
+                    }
+
+                    if let (Some(cache), Some(stamp)) = (cache.as_deref_mut(), stamp) {
+                        cache.sources.insert(
+                            path.clone(),
+                            CachedSource {
+                                stamp,
+                                meta: sprite_meta.clone(),
+                                loaded: loaded.clone(),
+                            },
+                        );
+                        seen.insert(path);
+                    }
+
+                    Some((loaded, sprite_meta))
+                })
+                .filter(|(loaded, sprite_meta)| {
+                    packer_args.subset.includes(sprite_meta.max_mip)
+                        && packer_args
+                            .split
+                            .includes(packer_args.split_by, tag.as_deref(), loaded)
+                })
+                .for_each(|(mut loaded, sprite_meta)| {
+                    let path = loaded.sprites.first().map(|s| s.source.clone());
+                    if let (Some(path), Some(key)) = (
+                        path.as_deref(),
+                        entry
+                            .and_then(|e| e.key.as_deref())
+                            .or(sprite_meta.key.as_deref()),
+                    ) {
+                        loaded.rename(path, key);
+                    }
+                    //
+                    // the whole file is renamed, its animations go by the keys of its frames
+                    if let Some(folder) = path
+                        .as_deref()
+                        .and_then(|p| p.parent())
+                        .map(|dir| key_prefix(&prefix_root, dir))
+                        .filter(|folder| {
+                            !folder.is_empty()
+                                && packer_args.on_key_conflict == KeyConflict::Prefix
+                                && loaded.sprites.iter().any(|s| keys.contains(&s.key))
+                        })
+                    {
+                        loaded.prefix(&folder);
+                    }
+                    let pivot = entry
+                        .and_then(|e| e.pivot())
+                        .or(sprite_meta.pivot.map(|(x, y)| Pivot(x, y)))
+                        .unwrap_or(packer_args.pivot);
+                    //
+                    // grid cells are the sprites' own
+                    let margin = Margin {
+                        padding: entry.and_then(|e| e.padding).unwrap_or(0),
+                        extrude: sprite_meta.extrude.unwrap_or(packer_args.extrude),
+                    };
+                    let margin = Some(margin)
+                        .filter(|m| m.total() > 0 && packer_args.grid.is_none());
+
+                    let upright = sprite_meta.upright();
+                    let transform = sprite_meta.transform.filter(|t| !t.is_identity());
+                    let multi_frame = loaded.sprites.len() > 1;
+                    let tone = sprite_meta.tone.or(folder_meta.tone);
+
+                    for mut sprite in loaded
+                        .sprites
+                        .into_iter()
+                        //
+                        // pre-rotated sources are turned upright first, sheet rects are in the upright image
+                        .map(|mut sprite| {
+                            if let Some(upright) = upright {
+                                sprite.reorient(upright);
+                            }
+                            sprite
+                        })
+                        .flat_map(|sprite| match sprite_meta.sheet.as_ref() {
+                            Some(slicing) => {
+                                let label = sprite.label();
+                                sheet::slice(sprite, slicing).unwrap_or_else(|e| {
+                                    diagnostic::warn(
+                                        Code::SheetSliceFailed,
+                                        format_args!("Failed to slice sheet {}: {}", label, e),
+                                    );
+                                    Vec::new()
+                                })
+                            }
+                            None => vec![sprite],
+                        })
+                        .map(|mut sprite| {
+                            if let Some(nine_slice) = sprite_meta.nine_slice {
+                                sprite.nine_slice = Some(nine_slice);
+                            }
+                            match tone {
+                                Some(tone) if packer_args.content.is_color() => {
+                                    sprite.adjust_tone(tone, tone_space)
+                                }
+                                Some(_) => diagnostic::warn(
+                                    Code::InvalidMetadata,
+                                    format_args!(
+                                        "Ignoring the tone of {}, {:?} content has no colors",
+                                        sprite.label(),
+                                        packer_args.content
+                                    ),
+                                ),
+                                None => {}
+                            }
+                            if let Some(transform) = transform {
+                                sprite.transform(transform);
+                            }
+                            sprite
+                        })
+                        .map(|sprite| match packer_args.scale {
+                            1.0 => sprite,
+                            scale => scale_sprite(sprite, scale, packer_args),
+                        })
+                        .flat_map(|sprite| {
+                            fit_oversized(sprite, margin.map_or(0, |m| m.total()), packer_args)
+                        })
+                        .flat_map(|sprite| match packer_args.grid {
+                            Some(cell) if packer_args.slice_tilesheets => grid::slice(sprite, cell),
+                            _ => vec![sprite],
+                        })
+                    {
+                        sprite.pivot = pivot;
+                        sprite.max_mip = sprite_meta.max_mip;
+                        sprite.variants = sprite_meta
+                            .variants
+                            .iter()
+                            .map(|v| SpriteVariant {
+                                key: match sprite.frame {
+                                    Some(frame) if multi_frame => format!("{}_{}", v.key, frame),
+                                    _ => v.key.clone(),
+                                },
+                                transform: v.transform,
+                            })
+                            .collect();
+
+                        if packer_args.sdf {
+                            if sprite.colored {
+                                diagnostic::warn(
+                                    Code::ColorsLost,
+                                    format_args!(
+                                        "{} is not monochrome, its colors are lost in the distance field",
+                                        sprite.label()
+                                    ),
+                                );
+                            }
+                            sdf::convert(&mut sprite, packer_args.sdf_spread);
+                        }
+
+                        let overrides = sprite_meta.classify.as_ref();
+                        let polygons = packer_args.polygons.is_some();
+                        if packer_args.classify || polygons || overrides.is_some() {
+                            //
+                            // without `--classify` the sidecar can still ask for a trim,
+                            // outlines are packed by their bounds unless it says no
+                            let classifiers = match overrides.and_then(|o| o.trim) {
+                                Some(true) if !packer_args.classify => &trim_only,
+                                None if polygons && !packer_args.classify => &trim_only,
+                                _ => &classifiers,
+                            };
+                            let decision = classify::decide(&sprite, classifiers, overrides);
+                            classify::apply(&mut sprite, &decision);
+                            decisions.push(decision);
+                        }
+
+                        let (width, height) = sprite.pixels.dimensions();
+                        let pad = margin.map_or(0, |m| m.total());
+
+                        //
+                        // left for the planner to report without a quarantine
+                        let page = packer_args.sheet_size;
+                        if packer_args.quarantine_dir.is_some()
+                            && packer_args.grid.is_none()
+                            && (width + 2 * pad > page || height + 2 * pad > page)
+                        {
+                            let reason = format!(
+                                "{} is {}x{} with {} pixels of margin, larger than a {}x{} page",
+                                sprite.label(),
+                                width,
+                                height,
+                                pad,
+                                page,
+                                page
+                            );
+                            diagnostic::warn(Code::OversizedSprite, &reason);
+                            rejected.push(Rejection {
+                                path: sprite.source.clone(),
+                                code: Code::OversizedSprite,
+                                reason,
+                            });
+                            continue;
+                        }
+
+                        if let Some(margin) = margin {
+                            margins.insert(src_img_bytes.len(), margin);
+                        }
+                        rects_to_place.push(PlanRect {
+                            id: src_img_bytes.len(),
+                            width: width + 2 * pad,
+                            height: height + 2 * pad,
+                            group: tag.clone().filter(|_| packer_args.group_tags),
+                            page: entry
+                                .and_then(|e| e.layer)
+                                .or(sprite_meta.layer)
+                                .or(folder_meta.layer),
+                        });
+
+                        if let Some(attribution) = sprite_meta
+                            .attribution
+                            .as_ref()
+                            .or(folder_meta.attribution.as_ref())
+                        {
+                            let keys = std::iter::once(sprite.key.clone())
+                                .chain(sprite.variants.iter().map(|v| v.key.clone()));
+                            match attributions
+                                .iter_mut()
+                                .find(|g| g.attribution == *attribution)
+                            {
+                                Some(group) => group.frames.extend(keys),
+                                None => attributions.push(AttributionGroup::new(
+                                    attribution.clone(),
+                                    keys.collect(),
+                                )),
+                            }
+                        }
+
+                        keys.insert(sprite.key.clone());
+                        if let Err(e) = spill.add(&mut sprite) {
+                            spill_failed.get_or_insert(e);
+                        }
+                        src_img_bytes.push(sprite);
+                    }
+
+                    animations.extend(loaded.animations);
+                });
+
+            rejected.extend(unreadable);
+        });
+
+    let font_options = FontOptions {
+        charset: packer_args.charset.clone(),
+        size: packer_args.font_size,
+        mode: packer_args.glyph_mode,
+        spread: packer_args.glyph_spread,
+    };
+    let mut fonts = Vec::new();
+
+    for path in packer_args
+        .fonts
+        .iter()
+        .filter(|_| packer_args.subset.includes(None) && packer_args.split.includes_ungrouped())
+    {
+        match font::rasterize(path, &font_options, packer_args.scale) {
+            Ok((glyphs, face)) => {
+                for mut glyph in glyphs {
+                    if !packer_args.pixel_format.is_la8() {
+                        glyph.hdr = Some(
+                            image::DynamicImage::ImageLumaA8(glyph.pixels.clone()).to_rgba32f(),
+                        );
+                    }
+
+                    let (width, height) = glyph.pixels.dimensions();
+                    rects_to_place.push(PlanRect {
+                        id: src_img_bytes.len(),
+                        width,
+                        height,
+                        group: None,
+                        page: None,
+                    });
+                    if let Err(e) = spill.add(&mut glyph) {
+                        spill_failed.get_or_insert(e);
+                    }
+                    src_img_bytes.push(glyph);
+                }
+                fonts.push(face);
+            }
+            Err(e) => {
+                let reason = format!("Failed to load font {}: {}", path.display(), e);
+                rejected.push(Rejection {
+                    path: path.clone(),
+                    code: Code::UnreadableInput,
+                    reason,
+                });
+            }
+        }
+    }
+
+    for path in packer_args
+        .merged
+        .iter()
+        .filter(|_| packer_args.split.includes_ungrouped())
+    {
+        match merge::load(path, packer_args.content, tone_space) {
+            Ok(merged) => {
+                for mut sprite in merged
+                    .sprites
+                    .into_iter()
+                    .filter(|s| packer_args.subset.includes(s.max_mip))
+                {
+                    let pad = match packer_args.grid {
+                        None => packer_args.extrude,
+                        Some(_) => 0,
+                    };
+                    if pad > 0 {
+                        margins.insert(
+                            src_img_bytes.len(),
+                            Margin {
+                                padding: 0,
+                                extrude: pad,
+                            },
+                        );
+                    }
+
+                    let (width, height) = sprite.pixels.dimensions();
+                    rects_to_place.push(PlanRect {
+                        id: src_img_bytes.len(),
+                        width: width + 2 * pad,
+                        height: height + 2 * pad,
+                        group: sprite.tag.clone().filter(|_| packer_args.group_tags),
+                        page: None,
+                    });
+                    if let Err(e) = spill.add(&mut sprite) {
+                        spill_failed.get_or_insert(e);
+                    }
+                    src_img_bytes.push(sprite);
+                }
+                animations.extend(merged.animations);
+                fonts.extend(merged.fonts);
+                for group in merged.attributions {
+                    match attributions
+                        .iter_mut()
+                        .find(|g| g.attribution == group.attribution)
+                    {
+                        Some(same) => same.frames.extend(group.frames),
+                        None => attributions.push(group),
+                    }
+                }
+            }
+            Err(e) => {
+                let reason = format!("Failed to merge {}: {}", path.display(), e);
+                rejected.push(Rejection {
+                    path: path.clone(),
+                    code: Code::UnreadableInput,
+                    reason,
+                });
+            }
+        }
+    }
+
+    //
+    // frames are looked up by key, a clash leaves all but the first unreachable
+    let mut by_key = fnv::FnvHashMap::<&str, &SourceSprite>::default();
+    let mut clashes = Vec::new();
+    for sprite in src_img_bytes.iter() {
+        match by_key.entry(&sprite.key) {
+            std::collections::hash_map::Entry::Occupied(first) => {
+                let reason = format!(
+                    "{} and {} are both named '{}'",
+                    first.get().label(),
+                    sprite.label(),
+                    sprite.key
+                );
+                match packer_args.on_key_conflict {
+                    KeyConflict::Error | KeyConflict::Prefix => {
+                        clashes.push(reason);
+                        continue;
+                    }
+                    KeyConflict::Skip => diagnostic::warn(
+                        Code::NameClash,
+                        format_args!("Leaving out {}: {}", sprite.label(), reason),
+                    ),
+                }
+                rejected.push(Rejection {
+                    path: sprite.source.clone(),
+                    code: Code::NameClash,
+                    reason,
+                });
+            }
+            std::collections::hash_map::Entry::Vacant(entry) => {
+                entry.insert(sprite);
+            }
+        }
+    }
+
+    //
+    // forget sources that were removed
+    if let Some(cache) = cache {
+        cache.sources.retain(|path, _| seen.contains(path));
+    }
+
+    if let Some(e) = spill_failed {
+        return Err(e);
+    }
+    if spill.spilled() > 0 {
+        log::info!(
+            "Keeping the pixels of {} sprite(s) on disk, over --max-memory",
+            spill.spilled()
+        );
+    }
+
+    if !clashes.is_empty() {
+        return Err(Code::NameClash.error(format_args!(
+            "{} sprite(s) named like another{}:\n  {}",
+            clashes.len(),
+            match packer_args.on_key_conflict {
+                KeyConflict::Prefix => " after prefixing their folders, rename them",
+                _ => ", rename them or pack with --on-key-conflict prefix",
+            },
+            clashes.join("\n  ")
+        )));
+    }
+
+    let mut inputs = Inputs {
+        sprites: src_img_bytes,
+        rects: rects_to_place,
+        animations,
+        decisions,
+        attributions,
+        fonts,
+        rejected,
+        margins,
+        spill,
+    };
+    if packer_args.on_key_conflict == KeyConflict::Skip {
+        let mut kept = fnv::FnvHashSet::default();
+        inputs.retain_sprites(|sprite| kept.insert(sprite.key.clone()));
+    }
+    Ok(inputs)
+}
+
+/// Folder of `dir` below `root` for prefixing keys, `/` separated, the folder's name
+/// when it is the root.
+fn key_prefix(root: &std::path::Path, dir: &std::path::Path) -> String {
+    let normal = |path: &std::path::Path| {
+        path.components()
+            .filter_map(|c| match c {
+                std::path::Component::Normal(name) => Some(name.to_string_lossy().into_owned()),
+                _ => None,
+            })
+            .collect::<Vec<_>>()
+    };
+    let relative = normal(dir.strip_prefix(root).unwrap_or(dir));
+    match relative.is_empty() {
+        true => normal(dir).pop().unwrap_or_default(),
+        false => relative.join("/"),
+    }
+}
+
+/// Fails, or warns, about the inputs that could not be read, all of them at once.
+pub fn report_unreadable(packer_args: &PackOptions, rejected: &[Rejection]) -> Result<(), String> {
+    let unreadable = rejected
+        .iter()
+        .filter(|r| r.code == Code::UnreadableInput)
+        .map(|r| r.reason.as_str())
+        .collect::<Vec<_>>();
+    if unreadable.is_empty() {
+        return Ok(());
+    }
+
+    match packer_args.on_input_error {
+        OnInputError::Error => Err(Code::UnreadableInput.error(format_args!(
+            "{} input(s) could not be read, fix them or pack with --on-input-error warn \
+             to leave them out:\n  {}",
+            unreadable.len(),
+            unreadable.join("\n  ")
+        ))),
+        OnInputError::Warn => {
+            diagnostic::warn(
+                Code::UnreadableInput,
+                format_args!(
+                    "Leaving out {} input(s) that could not be read:\n  {}",
+                    unreadable.len(),
+                    unreadable.join("\n  ")
+                ),
+            );
+            Ok(())
+        }
+        OnInputError::Skip => {
+            for reason in unreadable {
+                log::debug!("Left out: {}", reason);
+            }
+            Ok(())
+        }
+    }
+}
+
+fn audit_inputs(packer_args: &PackOptions, manifest: &[ManifestEntry]) -> Result<(), String> {
+    let mut files = packer_args
+        .input_folders
+        .iter()
+        .filter_map(|folder| std::fs::read_dir(&folder.path).ok())
+        .flatten()
+        .filter_map(|de| de.ok().map(|d| d.path()))
+        .chain(manifest.iter().map(|entry| entry.path.clone()))
+        .filter(|p| p.is_file())
+        .collect::<Vec<_>>();
+    files.sort();
+
+    let problems = portability::audit(&files);
+    for (code, problem) in problems.iter() {
+        diagnostic::warn(*code, format_args!("Portability: {}", problem));
+    }
+
+    match problems.is_empty() || !packer_args.strict {
+        true => Ok(()),
+        false => Err(Code::NotPortable.error(format_args!(
+            "Portability audit found {} problem(s)",
+            problems.len()
+        ))),
+    }
+}
+
+/// Copies `src` onto `page` at `(x, y)` through `map`, with its edge pixels repeated
+/// `extrude` times around it.
+fn blit<P: image::Pixel>(
+    page: &mut image::ImageBuffer<P, Vec<P::Subpixel>>,
+    src: &image::ImageBuffer<P, Vec<P::Subpixel>>,
+    (x, y): (u32, u32),
+    extrude: u32,
+    map: impl Fn(P) -> P,
+) {
+    let (w, h) = src.dimensions();
+    if w == 0 || h == 0 {
+        return;
+    }
+
+    for j in 0..h + 2 * extrude {
+        for i in 0..w + 2 * extrude {
+            let px = *src.get_pixel(
+                i.saturating_sub(extrude).min(w - 1),
+                j.saturating_sub(extrude).min(h - 1),
+            );
+            page.put_pixel(x + i - extrude, y + j - extrude, map(px));
+        }
+    }
+}
+
+/// Composes the full precision pages, and their mip chains with `--mipmaps`, from the
+/// sprites' `hdr` planes, a page per thread. Returns the pages of every level.
+fn compose_hdr_pages(
+    sprites: &[SourceSprite],
+    placement: &planner::Placement<usize>,
+    margins: &fnv::FnvHashMap<usize, Margin>,
+    page_size: (u32, u32),
+    page_count: usize,
+    premultiplied: bool,
+    packer_args: &PackOptions,
+) -> Vec<Vec<image::Rgba32FImage>> {
+    let mut on_page = vec![Vec::new(); page_count];
+    for (bin_id, placed) in placement.iter() {
+        on_page[placed.page as usize].push((*bin_id, placed));
+    }
+
+    let page_levels = parallel::map(on_page.into_iter(), packer_args.jobs, |on_page| {
+        let mut page = image::Rgba32FImage::new(page_size.0, page_size.1);
+        for (bin_id, placed) in on_page {
+            let Some(hdr) = sprites[bin_id].hdr.as_ref() else {
+                continue;
+            };
+
+            let extrude = margins.get(&bin_id).map_or(0, |m| m.extrude);
+            blit(&mut page, hdr, (placed.x, placed.y), extrude, |px| {
+                let [r, g, b, a] = px.0;
+                match premultiplied {
+                    true => image::Rgba([r * a, g * a, b * a, a]),
+                    false => px,
+                }
+            });
+        }
+
+        match packer_args.mipmaps {
+            true => {
+                let mips = mips::mip_chain_hdr(&page, premultiplied);
+                std::iter::once(page).chain(mips).collect()
+            }
+            false => vec![page],
+        }
+    });
+
+    (0..page_levels[0].len())
+        .map(|level| page_levels.iter().map(|l| l[level].clone()).collect())
+        .collect()
+}
+
+/// Writes pages and, with `mipmaps`, their mip chains to the scratch dir as
+/// `<prefix><page>.png` and `<prefix><page>_mip<level>.png`, with only the `channels`
+/// of every page (luminance and alpha for those not listed), on up to `jobs` threads.
+/// Returns the files of every level.
+pub fn save_levels(
+    pages: &[SpritePixels],
+    format: PageFormat,
+    channels: &[PageChannels],
+    mipmaps: bool,
+    scratch_dir: &std::path::Path,
+    prefix: &str,
+    jobs: usize,
+) -> Result<Vec<Vec<std::path::PathBuf>>, String> {
+    let page_files = parallel::map(pages.iter().enumerate(), jobs, |(idx, img_buf)| {
+        let levels = match mipmaps {
+            true => std::iter::once(img_buf.clone())
+                .chain(mips::mip_chain(img_buf, format))
+                .collect(),
+            false => vec![img_buf.clone()],
+        };
+        let channels = channels.get(idx).copied().unwrap_or_default();
+
+        levels
+            .iter()
+            .enumerate()
+            .map(|(level, pixels)| {
+                let file_name = scratch_dir.join(match level {
+                    0 => format!("{}{}.png", prefix, idx),
+                    _ => format!("{}{}_mip{}.png", prefix, idx, level),
+                });
+                encode::write_png(&channels.reduce(pixels), &file_name)?;
+
+                Ok(file_name)
+            })
+            .collect::<Result<Vec<_>, String>>()
+    })
+    .into_iter()
+    .collect::<Result<Vec<_>, String>>()?;
+
+    //
+    // one list of page files per mip level
+    let mip_levels = page_files.first().map(|l| l.len()).unwrap_or(1);
+    Ok((0..mip_levels)
+        .map(|level| {
+            page_files
+                .iter()
+                .map(|files| files[level].clone())
+                .collect()
+        })
+        .collect())
+}
+
+/// Packs the inputs into pages, ready to be written out by [`PackedAtlas::stage`].
+/// `None` when there is nothing to pack for a subset.
+fn compose(
+    packer_args: &PackOptions,
+    mut cache: Option<&mut PackCache>,
+) -> Result<Option<PackedAtlas>, String> {
+    log::trace!("Program args {:?}", packer_args);
+    let started = std::time::Instant::now();
+
+    let manifest = match packer_args.manifest.as_deref() {
+        Some(path) => manifest::load(path)?,
+        None => Vec::new(),
+    };
+
+    if packer_args.audit_portability {
+        audit_inputs(packer_args, &manifest)?;
+    }
+
+    let (min_pages, max_pages) = packer_args.page_range();
+    if min_pages > max_pages {
+        return Err(format!(
+            "--min-pages {} is more than --max-layers {}",
+            min_pages, max_pages
+        ));
+    }
+
+    let page_format = PageFormat {
+        content: packer_args.content,
+        //
+        // the 16-bit and float formats have no sRGB variants
+        color_space: match packer_args.pixel_format {
+            PixelFormat::La8 => packer_args.color_space.resolve(packer_args.content),
+            _ => ColorSpace::Linear,
+        },
+        //
+        // normal maps have Y in place of alpha
+        premultiplied: packer_args.premultiply_alpha && packer_args.content == Content::Color,
+        channels: PageChannels::LuminanceAlpha,
+    };
+
+    let mut appended = match packer_args.append.as_deref() {
+        Some(path) => {
+            let appended = AppendedAtlas::load(path, page_format, packer_args.pixel_format)?;
+            if packer_args.layout == TextureLayout::Mega {
+                return Err(format!(
+                    "Cannot append to {}: --layout mega stitches the pages",
+                    path.display()
+                ));
+            }
+            if appended.page_count() > max_pages {
+                return Err(format!(
+                    "Cannot append to {}: it has {} pages, more than --max-layers {}",
+                    path.display(),
+                    appended.page_count(),
+                    max_pages
+                ));
+            }
+            Some(appended)
+        }
+        None => None,
+    };
+
+    //
+    // merge dedups, which compares the pixels of all sprites
+    if packer_args.max_memory.is_some() && !packer_args.merged.is_empty() {
+        return Err("merge cannot be combined with --max-memory".to_string());
+    }
+
+    let mut inputs = load_inputs(packer_args, &manifest, cache.as_deref_mut())?;
+    if let Some(appended) = appended.as_ref() {
+        let loaded = inputs.sprites.len();
+        inputs.retain_sprites(|sprite| !appended.contains(&sprite.key));
+        log::info!(
+            "Appending {} of {} sprite(s) to {}, it has the others",
+            inputs.sprites.len(),
+            loaded,
+            appended.path().display()
+        );
+    }
+    let Inputs {
+        sprites: src_img_bytes,
+        rects: rects_to_place,
+        animations,
+        decisions,
+        attributions,
+        fonts,
+        rejected,
+        margins,
+        spill,
+    } = inputs;
+    log::debug!(
+        "Loaded {} sprite(s) in {:.2?}",
+        src_img_bytes.len(),
+        started.elapsed()
+    );
+
+    //
+    // written before planning, a failed build is when they are wanted most
+    if let Some(dir) = packer_args.quarantine_dir.as_deref() {
+        quarantine::write(dir, &packer_args.atlas_name, &rejected)?;
+    }
+    report_unreadable(packer_args, &rejected)?;
+
+    //
+    // duplicates are left out of the layout and get the region of the sprite they share
+    let (shared, merges) = match packer_args.dedup || packer_args.lossy_dedup {
+        true => dedup::find(
+            &src_img_bytes,
+            Some(packer_args.dedup_psnr).filter(|_| packer_args.lossy_dedup),
+        ),
+        false => (vec![None; src_img_bytes.len()], Vec::new()),
+    };
+    for merge in merges.iter() {
+        match merge.psnr {
+            Some(psnr) => log::debug!("Merged {} into {} ({:.1} dB)", merge.key, merge.kept, psnr),
+            None => log::debug!("Merged {} into {} (identical)", merge.key, merge.kept),
+        }
+    }
+    let unique = (0..src_img_bytes.len())
+        .filter(|&id| shared[id].is_none())
+        .collect::<Vec<_>>();
+    let mut rects_to_place = rects_to_place
+        .into_iter()
+        .filter(|r| shared[r.id].is_none())
+        .collect::<Vec<_>>();
+
+    //
+    // the grid and the formats decide the pages of the sprites there
+    let pinned = rects_to_place.iter().filter(|r| r.page.is_some()).count();
+    if pinned > 0 && (packer_args.grid.is_some() || packer_args.mixed_formats) {
+        diagnostic::warn(
+            Code::InvalidMetadata,
+            format_args!(
+                "Ignoring the pinned layer of {} sprite(s), {} decides their pages",
+                pinned,
+                match packer_args.grid {
+                    Some(_) => "--grid",
+                    None => "--mixed-formats",
+                }
+            ),
+        );
+    }
+
+    if src_img_bytes.is_empty()
+        && (packer_args.subset != Subset::All || packer_args.split != Split::All)
+    {
+        log::info!("No sprites for {}, skipping", packer_args.atlas_name);
+        return Ok(None);
+    }
+
+    //
+    // a single image covering most of a page (backgrounds, splash screens) is used as
+    // the page itself, sized to the image
+    let sheet = packer_args.sheet_size as u64;
+    let pass_through = match unique.as_slice() {
+        [single]
+            if packer_args.grid.is_none()
+                && appended.is_none()
+                && !margins.contains_key(single) =>
+        {
+            let (w, h) = spill.dimensions(*single, &src_img_bytes[*single]);
+            (w as u64) <= sheet && (h as u64) <= sheet && w as u64 * h as u64 * 2 > sheet * sheet
+        }
+        _ => false,
+    };
+
+    //
+    // pages only hold what their sprites need
+    let sprite_channels = match packer_args.mixed_formats {
+        true => src_img_bytes
+            .iter()
+            .map(|s| PageChannels::of(&s.pixels))
+            .collect(),
+        false => Vec::new(),
+    };
+    //
+    // the sprites of an appended atlas keep their space, with the margin new ones get
+    let free = appended
+        .as_ref()
+        .map(|appended| appended.free_space(packer_args.extrude));
+    let plan_with = |rects: &[PlanRect<usize>], page_size, packing| match (
+        packer_args.mixed_formats,
+        appended.as_ref(),
+    ) {
+        (true, _) => planner::plan_segregated(
+            rects,
+            |&id| sprite_channels[id],
+            page_size,
+            max_pages,
+            packing,
+        ),
+        (false, Some(appended)) => planner::plan_around(
+            rects,
+            free.as_deref().unwrap_or_default(),
+            appended.page_count(),
+            page_size,
+            max_pages,
+            packing,
+        )
+        .map(|p| (p, Vec::new())),
+        (false, None) => {
+            planner::plan(rects, page_size, min_pages, max_pages, packing).map(|p| (p, Vec::new()))
+        }
+    };
+
+    let page_size = match (pass_through, appended.as_ref()) {
+        (true, _) => spill.dimensions(unique[0], &src_img_bytes[unique[0]]),
+        (false, Some(appended)) => appended.page_size(),
+        (false, None) if packer_args.optimize_size => {
+            let requested = packer_args.sheet_size;
+            let page_size = planner::smallest_page(
+                &rects_to_place,
+                requested,
+                packer_args.power_of_two,
+                |page_size| {
+                    plan_with(&rects_to_place, page_size, packer_args.packing())
+                        .ok()
+                        .map(|(placement, _)| {
+                            placement.values().map(|p| p.page + 1).max().unwrap_or(1)
+                        })
+                },
+            );
+            if page_size != (requested, requested) {
+                let texels = |(w, h): (u32, u32)| w as f64 * h as f64;
+                log::info!(
+                    "Sized the pages {}x{} instead of {}x{}, {:.1}% fewer texels per page",
+                    page_size.0,
+                    page_size.1,
+                    requested,
+                    requested,
+                    (1.0 - texels(page_size) / texels((requested, requested))) * 100.0
+                );
+            }
+            page_size
+        }
+        (false, None) => (packer_args.sheet_size, packer_args.sheet_size),
+    };
+    let plan = |rects: &[PlanRect<usize>]| {
+        if !packer_args.try_all {
+            return plan_with(rects, page_size, packer_args.packing());
+        }
+
+        //
+        // of layouts on as many pages the earlier packing wins a tie, the default first
+        let tries = parallel::map(Packing::all().into_iter(), packer_args.jobs, |packing| {
+            (packing, plan_with(rects, page_size, packing))
+        });
+        let mut best = None::<(Packing, (planner::Placement<usize>, Vec<PageChannels>))>;
+        let mut failed = None;
+        for (packing, result) in tries {
+            let placed = match result {
+                Ok(placed) => placed,
+                Err(e) => {
+                    failed.get_or_insert(e);
+                    continue;
+                }
+            };
+            let score = |placement: &planner::Placement<usize>| {
+                let pages = placement.values().map(|p| p.page + 1).max().unwrap_or(0);
+                (std::cmp::Reverse(pages), planner::occupancy(placement))
+            };
+            if best
+                .as_ref()
+                .is_none_or(|(_, (b, _))| score(&placed.0) > score(b))
+            {
+                best = Some((packing, placed));
+            }
+        }
+
+        match (best, failed) {
+            (Some((packing, placed)), _) => {
+                log::info!(
+                    "Kept the layout of {} at {:.1}% occupancy",
+                    packing,
+                    planner::occupancy(&placed.0) * 100.0
+                );
+                Ok(placed)
+            }
+            (None, failed) => Err(failed.expect("every packing was tried")),
+        }
+    };
+
+    let packing = progress::phase("Packing", 0);
+    let (placement, mut page_channels) = match packer_args.grid {
+        //
+        // directory listings come in no particular order, tiles go by file name
+        Some(cell) => {
+            let mut order = unique.clone();
+            order.sort_by(|&a, &b| {
+                let (a, b) = (&src_img_bytes[a], &src_img_bytes[b]);
+                (&a.source, a.frame).cmp(&(&b.source, b.frame))
+            });
+
+            let placement = grid::place(&src_img_bytes, &order, cell, page_size, max_pages)
+                .map_err(|e| {
+                    Code::GridLayoutFailed.error(format_args!("Failed to lay out grid: {}", e))
+                })?;
+            (placement, Vec::new())
+        }
+        None => {
+            let usage = packer_args.usage.as_deref().map(Usage::load).transpose()?;
+            let keys = src_img_bytes
+                .iter()
+                .map(|s| s.key.as_str())
+                .collect::<Vec<_>>();
+
+            //
+            // usage clusters are a preference, a layout that only fits without them wins
+            let ungrouped = usage.as_ref().map(|usage| {
+                let ungrouped = rects_to_place
+                    .iter()
+                    .map(|r| r.group.clone())
+                    .collect::<Vec<_>>();
+                let clusters = usage.group(&mut rects_to_place, &keys, &shared, page_size);
+                log::info!("Grouped co-used sprites into {} cluster(s)", clusters);
+                ungrouped
+            });
+
+            let placement = match (plan(&rects_to_place), ungrouped) {
+                (Err(_), Some(ungrouped)) => {
+                    log::info!("Sprites do not fit with the usage clusters, packing without them");
+                    for (rect, group) in rects_to_place.iter_mut().zip(ungrouped) {
+                        rect.group = group;
+                    }
+                    plan(&rects_to_place)
+                }
+                (result, _) => result,
+            };
+            let placement = match placement {
+                Ok(placement) => placement,
+                Err(e) => {
+                    if let Some(path) = packer_args.dump_repro.as_deref() {
+                        //
+                        // the bundle names rects by their position, not by file
+                        let position = |id| rects_to_place.iter().position(|r| r.id == id);
+                        let anonymous = e
+                            .clone()
+                            .map_ids(|id| format!("rect #{}", position(id).unwrap_or(id)));
+                        repro::dump(
+                            path,
+                            packer_args,
+                            packer_args.encoder_options().toktx(),
+                            &rects_to_place,
+                            page_size,
+                            (min_pages, max_pages),
+                            &anonymous.to_string(),
+                        )?;
+                        log::info!("Wrote a repro bundle to {}", path.display());
+                    }
+                    return Err(e.code().error(format_args!(
+                        "Failed to pack atlas: {}",
+                        e.map_ids(|id| src_img_bytes[id].label())
+                    )));
+                }
+            };
+
+            if let Some(usage) = usage.as_ref() {
+                for (scene, pages) in usage.scene_pages(&keys, &shared, &placement.0) {
+                    log::debug!("Scene {} draws from {} page(s)", scene, pages);
+                }
+            }
+
+            placement
+        }
+    };
+
+    drop(packing);
+    let mut placement = placement;
+    //
+    // the sprites go inside their padded rects
+    for (id, placed) in placement.iter_mut() {
+        if let Some(pad) = margins.get(id).map(Margin::total) {
+            placed.x += pad;
+            placed.y += pad;
+            placed.width -= 2 * pad;
+            placed.height -= 2 * pad;
+        }
+    }
+    let mut page_size = page_size;
+    log::debug!("Planned the layout in {:.2?}", started.elapsed());
+    let mut page_count = placement
+        .values()
+        .map(|placed| placed.page + 1)
+        .max()
+        .unwrap_or(1)
+        .max(min_pages)
+        .max(appended.as_ref().map_or(0, |a| a.page_count()));
+    if packer_args.mixed_formats {
+        page_channels.resize(page_count as usize, PageChannels::LuminanceAlpha);
+    }
+
+    let previous = match packer_args.stable_indices {
+        true => PreviousLayout::load(
+            &packer_args.output_dir,
+            packer_args.output_layout,
+            &packer_args.atlas_name,
+        ),
+        false => None,
+    };
+    //
+    // pinned sprites hold their pages as given
+    if let Some(previous) = previous.as_ref().filter(|_| !pass_through && pinned == 0) {
+        let moved_to = previous.keep_pages(&mut placement, page_count, |&id| {
+            src_img_bytes[id].key.clone()
+        });
+        if packer_args.mixed_formats {
+            let mut moved = page_channels.clone();
+            for (page, &to) in moved_to.iter().enumerate() {
+                moved[to as usize] = page_channels[page];
+            }
+            page_channels = moved;
+        }
+    }
+
+    //
+    // the pages are laid out on their own and only moved onto the one texture afterwards
+    if packer_args.layout == TextureLayout::Mega && page_count > 1 {
+        page_size = planner::stitch(&mut placement, page_size, page_count);
+        log::info!(
+            "Stitched {} pages into one {}x{} texture",
+            page_count,
+            page_size.0,
+            page_size.1
+        );
+        page_count = 1;
+    }
+
+    let premultiply = |px| match page_format.premultiplied {
+        true => color::premultiply(px, page_format.color_space),
+        false => px,
+    };
+
+    let mut output_images: Vec<SpritePixels> = match pass_through {
+        true => {
+            log::info!(
+                "Using {} as the only page",
+                src_img_bytes[unique[0]].label()
+            );
+            let mut page = spill
+                .pixels(unique[0], &src_img_bytes[unique[0]])?
+                .into_owned();
+            page.pixels_mut().for_each(|px| *px = premultiply(*px));
+            vec![page]
+        }
+        false => {
+            let mut kept = appended
+                .as_mut()
+                .map(|appended| appended.take_pages())
+                .unwrap_or_default()
+                .into_iter();
+            (0..page_count)
+                .map(|_| match (kept.next(), cache.as_deref_mut()) {
+                    (Some(page), _) => page,
+                    (None, Some(cache)) => cache.take_page(page_size.0, page_size.1),
+                    (None, None) => image::ImageBuffer::new(page_size.0, page_size.1),
+                })
+                .collect()
+        }
+    };
+
+    let blitting = progress::phase(
+        "Blitting",
+        match pass_through {
+            true => 0,
+            false => placement.len(),
+        },
+    );
+    //
+    // the pages are independent, each is blitted on a thread of its own
+    let mut on_page = vec![Vec::new(); output_images.len()];
+    for (bin_id, placed) in placement.iter().filter(|_| !pass_through) {
+        on_page[placed.page as usize].push((*bin_id, placed));
+    }
+    parallel::map(
+        output_images.iter_mut().zip(on_page),
+        packer_args.jobs,
+        |(page, on_page)| {
+            for (bin_id, placed) in on_page {
+                let src = &src_img_bytes[bin_id];
+                log::debug!(
+                    "Placed {} on page {} at ({}, {})",
+                    src.label(),
+                    placed.page,
+                    placed.x,
+                    placed.y
+                );
+
+                blit(
+                    page,
+                    &*spill.pixels(bin_id, src)?,
+                    (placed.x, placed.y),
+                    margins.get(&bin_id).map_or(0, |m| m.extrude),
+                    premultiply,
+                );
+                blitting.inc();
+            }
+            Ok(())
+        },
+    )
+    .into_iter()
+    .collect::<Result<(), String>>()?;
+    drop(blitting);
+
+    //
+    // blake3 of every source file, shared by the frames cut from it
+    let mut source_hashes = fnv::FnvHashMap::<&std::path::Path, String>::default();
+    for src in src_img_bytes.iter() {
+        if !source_hashes.contains_key(src.source.as_path()) {
+            let data = std::fs::read(&src.source)
+                .map_err(|e| format!("Failed to read {}: {}", src.source.display(), e))?;
+            source_hashes.insert(&src.source, blake3::hash(&data).to_hex().to_string());
+        }
+    }
+    //
+    // sources without a modification time (some file systems) go without one
+    let modified = |path: &std::path::Path| {
+        std::fs::metadata(path)
+            .and_then(|m| m.modified())
+            .ok()
+            .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+            .map(|d| d.as_secs())
+    };
+
+    let polygons = match packer_args.polygons {
+        Some(mode) => placement
+            .keys()
+            .map(|&id| {
+                let pixels = spill.pixels(id, &src_img_bytes[id])?;
+                Ok((
+                    id,
+                    polygon::outline(&pixels, mode, packer_args.polygon_tolerance),
+                ))
+            })
+            .collect::<Result<fnv::FnvHashMap<_, _>, String>>()?,
+        None => Default::default(),
+    };
+
+    let frames = placement
+        .iter()
+        .map(|(bin_id, placed)| (*bin_id, placed))
+        .chain(
+            shared
+                .iter()
+                .enumerate()
+                .filter_map(|(id, kept)| kept.map(|k| (id, &placement[&k]))),
+        )
+        .flat_map(|(bin_id, placed)| {
+            let src = &src_img_bytes[bin_id];
+
+            log::trace!("Texture region {}", src.key);
+
+            let mut region = NamedTextureRegion::new(
+                src.key.clone(),
+                placed.page,
+                (placed.x, placed.y, placed.width, placed.height),
+            );
+            region.tag = src.tag.clone();
+            region.nine_slice = src.nine_slice;
+            region.events = src.events.clone();
+            (region.pivot_x, region.pivot_y) = (src.pivot.0, src.pivot.1);
+            region.split = src.split.clone();
+            region.baked_transform = src.baked_transform;
+            region.delta = src.delta.clone();
+            region.max_mip = src.max_mip;
+            region.tile = packer_args
+                .grid
+                .map(|cell| cell.tile_index(placed, page_size));
+            region.trim = src.trim;
+            region.polygon = polygons
+                .get(&shared[bin_id].unwrap_or(bin_id))
+                .cloned()
+                .flatten();
+            region.filter = src.filter;
+            region.alpha = src.alpha;
+            region.motion_vectors = src.motion.is_some();
+            region.tone = src.tone;
+            region.source_hash = source_hashes.get(src.source.as_path()).cloned();
+            //
+            // made relative to the descriptor when it is written
+            region.source = Some(std::path::absolute(&src.source).unwrap_or(src.source.clone()));
+            region.provenance = packer_args
+                .with_provenance
+                .then(|| Provenance::new(src.source_size, modified(&src.source)));
+            let region = region.with_uvs(page_size, packer_args.uv_inset);
+
+            //
+            // variants reuse the region, the renderer applies the transform when drawing
+            let variants = src
+                .variants
+                .iter()
+                .map(|v| region.variant(v.key.clone(), v.transform))
+                .collect::<Vec<_>>();
+
+            std::iter::once(region).chain(variants)
+        })
+        .collect::<Vec<_>>();
+    let frames = match appended.as_ref() {
+        Some(appended) => appended.frames(frames),
+        None => frames,
+    };
+    let frames = add_aliases(frames, packer_args)?;
+    let frames = match previous.as_ref() {
+        Some(previous) => previous.order_frames(frames),
+        None => frames,
+    };
+
+    //
+    // a planner bug would otherwise go out as a corrupt atlas without a word
+    let frame_margins = placement
+        .keys()
+        .filter_map(|id| {
+            margins
+                .get(id)
+                .map(|m| (src_img_bytes[*id].key.as_str(), m.total()))
+        })
+        .collect::<fnv::FnvHashMap<_, _>>();
+    let problems = verify::problems(
+        &frames,
+        |frame| frame_margins.get(frame.key.as_str()).copied().unwrap_or(0),
+        page_size,
+        Some(output_images.len() as u32),
+    );
+    if !problems.is_empty() {
+        return Err(Code::InvalidLayout.error(format_args!(
+            "The layout of {} is broken, please report it with --dump-repro:\n  {}",
+            packer_args.atlas_name,
+            problems.join("\n  ")
+        )));
+    }
+
+    let mut waste_images = Vec::new();
+    if packer_args.waste_map {
+        let maps = waste::maps(
+            &frames,
+            |frame| frame_margins.get(frame.key.as_str()).copied().unwrap_or(0),
+            page_size,
+            &output_images,
+        );
+        for (idx, (map, waste)) in maps.into_iter().enumerate() {
+            log::info!(
+                "Page {}: {:.1}% free, the largest free rect {}x{}",
+                idx,
+                waste.free * 100.0,
+                waste.largest.0,
+                waste.largest.1
+            );
+            waste_images.push(map);
+        }
+    }
+
+    //
+    // the passes see every region once, duplicates, variants and aliases share them
+    if !packer_args.post_process.is_empty() {
+        let processors = postprocess::registry();
+        let mut done = std::collections::HashSet::new();
+        for frame in frames.iter() {
+            //
+            // what an appended atlas holds went through its passes when it was packed
+            if appended.as_ref().is_some_and(|a| a.contains(&frame.key))
+                || !done.insert((frame.layer, frame.pixel_rect()))
+            {
+                continue;
+            }
+            let page = &mut output_images[frame.layer as usize];
+            let mut pixels =
+                image::imageops::crop_imm(page, frame.x, frame.y, frame.width, frame.height)
+                    .to_image();
+            processors.process(&packer_args.post_process, frame, &mut pixels)?;
+            image::imageops::replace(page, &pixels, frame.x as i64, frame.y as i64);
+        }
+    }
+
+    //
+    // write individual atlas sheets to the scratch dir and merge them into a texture array using toktx
+    let scratch_dir =
+        ScratchDir::create(packer_args.scratch_dir.as_deref(), packer_args.keep_temp)?;
+
+    let mut waste_maps = Vec::with_capacity(waste_images.len());
+    for (idx, map) in waste_images.into_iter().enumerate() {
+        let path = scratch_dir.path().join(format!("waste{}.png", idx));
+        encode::write_png(&map.into(), &path)?;
+        waste_maps.push(path);
+    }
+
+    let hdr_levels = match packer_args.pixel_format {
+        PixelFormat::La8 => None,
+        _ => Some(compose_hdr_pages(
+            &src_img_bytes,
+            &placement,
+            &margins,
+            page_size,
+            output_images.len(),
+            page_format.premultiplied,
+            packer_args,
+        )),
+    };
+
+    let atlas_sheet_images = save_levels(
+        &output_images,
+        page_format,
+        &page_channels,
+        packer_args.mipmaps,
+        scratch_dir.path(),
+        "atlas",
+        packer_args.jobs,
+    )?;
+    let mip_levels = atlas_sheet_images.len();
+
+    //
+    // flipbook motion vectors go to pages of their own, at the same places
+    let motion_format = PageFormat {
+        content: Content::MotionVectors,
+        color_space: ColorSpace::Linear,
+        premultiplied: false,
+        channels: PageChannels::LuminanceAlpha,
+    };
+    let motion_sheet_images = match src_img_bytes.iter().any(|s| s.motion.is_some()) {
+        true => {
+            let mut motion_pages =
+                vec![SpritePixels::new(page_size.0, page_size.1); output_images.len()];
+            for (bin_id, placed) in placement.iter() {
+                if let Some(motion) = src_img_bytes[*bin_id].motion.as_ref() {
+                    image::imageops::replace(
+                        &mut motion_pages[placed.page as usize],
+                        motion,
+                        placed.x as i64,
+                        placed.y as i64,
+                    );
+                }
+            }
+
+            Some(save_levels(
+                &motion_pages,
+                motion_format,
+                &[],
+                packer_args.mipmaps,
+                scratch_dir.path(),
+                "motion",
+                packer_args.jobs,
+            )?)
+        }
+        false => None,
+    };
+
+    let stats = packer_args.stats.then(|| AtlasStats {
+        atlas: packer_args.atlas_name.clone(),
+        page_size,
+        page_count: output_images.len(),
+        frames: frames.len(),
+        mip_levels,
+        pages: stats::pages(
+            &src_img_bytes,
+            &placement,
+            &output_images,
+            hdr_levels.as_ref().map(|levels| levels[0].as_slice()),
+            packer_args.page_stats,
+        ),
+    });
+
+    log::info!(
+        "Packed {} frame(s) of {} on {} {}x{} page(s) in {:.2?}",
+        frames.len(),
+        packer_args.atlas_name,
+        output_images.len(),
+        page_size.0,
+        page_size.1,
+        started.elapsed()
+    );
+
+    if let Some(cache) = cache {
+        cache.page_pool = output_images;
+    }
+
+    let (animations, attributions, fonts) = match appended.as_ref() {
+        Some(appended) => (
+            appended.animations(animations),
+            appended.attributions(attributions),
+            appended.fonts(fonts),
+        ),
+        None => (animations, attributions, fonts),
+    };
+
+    Ok(Some(PackedAtlas {
+        options: packer_args.clone(),
+        scratch_dir,
+        atlas_sheet_images,
+        hdr_levels,
+        motion_sheet_images,
+        motion_format,
+        page_format,
+        page_channels,
+        page_size,
+        mip_levels,
+        frames,
+        animations,
+        decisions,
+        attributions,
+        merges,
+        fonts,
+        stats,
+        waste_maps,
+    }))
+}
+
+/// Appends an entry sharing the region of its target for every alias. Targets packed
+/// into the other atlas of a split are left to it.
+fn add_aliases(
+    mut frames: Vec<NamedTextureRegion>,
+    packer_args: &PackOptions,
+) -> Result<Vec<NamedTextureRegion>, String> {
+    for alias in packer_args.aliases.iter() {
+        if frames.iter().any(|f| f.key == alias.key) {
+            return Err(Code::NameClash.error(format_args!(
+                "alias {} is already the name of a frame",
+                alias.key
+            )));
+        }
+
+        match frames.iter().find(|f| f.key == alias.target) {
+            Some(target) => {
+                let alias = target.alias(alias.key.clone());
+                frames.push(alias);
+            }
+            None if packer_args.subset != Subset::All || packer_args.split != Split::All => (),
+            None => {
+                return Err(format!(
+                    "Alias {} refers to {}, which is not a frame of the atlas",
+                    alias.key, alias.target
+                ))
+            }
+        }
+    }
+
+    Ok(frames)
+}
+
+/// Human readable credits, one paragraph per attribution.
+fn attribution_text(groups: &[AttributionGroup]) -> String {
+    groups
+        .iter()
+        .map(|group| {
+            let a = &group.attribution;
+            let mut text = String::new();
+            for (label, value) in [
+                ("", &a.title),
+                ("Author: ", &a.author),
+                ("License: ", &a.license),
+                ("Source: ", &a.url),
+                ("", &a.notice),
+            ] {
+                if let Some(value) = value {
+                    text += &format!("{}{}\n", label, value);
+                }
+            }
+            text + &format!("Used for: {}\n", group.frames.join(", "))
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Pages of a packed atlas, kept in the scratch dir until every target is written.
+pub struct PackedAtlas {
+    options: PackOptions,
+    /// Removed along with the page files when the atlas is dropped.
+    scratch_dir: ScratchDir,
+    atlas_sheet_images: Vec<Vec<std::path::PathBuf>>,
+    hdr_levels: Option<Vec<Vec<image::Rgba32FImage>>>,
+    motion_sheet_images: Option<Vec<Vec<std::path::PathBuf>>>,
+    motion_format: PageFormat,
+    page_format: PageFormat,
+    /// What every page holds with `--mixed-formats`, empty otherwise.
+    page_channels: Vec<PageChannels>,
+    page_size: (u32, u32),
+    mip_levels: usize,
+    frames: Vec<NamedTextureRegion>,
+    animations: Vec<Animation>,
+    decisions: Vec<Decision>,
+    attributions: Vec<AttributionGroup>,
+    merges: Vec<Merge>,
+    fonts: Vec<FontFace>,
+    stats: Option<AtlasStats>,
+    /// `--waste-map` images of the pages, in the scratch dir.
+    waste_maps: Vec<std::path::PathBuf>,
+}
+
+impl PackedAtlas {
+    pub fn name(&self) -> &str {
+        &self.options.atlas_name
+    }
+
+    /// Encodes the pages for a target and stages its files, to commit when they are
+    /// still wanted.
+    pub fn stage(&self, target: &EncodeTarget) -> Result<Publish, String> {
+        let mut publish = Publish::new(self.options.overwrite());
+        self.stage_target(target, &mut publish)?;
+        Ok(publish)
+    }
+
+    /// Encodes the pages for a target and stages its files in `publish`.
+    pub fn stage_target(&self, target: &EncodeTarget, publish: &mut Publish) -> Result<(), String> {
+        let PackedAtlas {
+            options: packer_args,
+            scratch_dir,
+            atlas_sheet_images,
+            hdr_levels,
+            motion_sheet_images,
+            motion_format,
+            page_format,
+            page_channels,
+            page_size,
+            mip_levels,
+            frames,
+            animations,
+            decisions,
+            attributions,
+            merges,
+            fonts,
+            stats,
+            waste_maps,
+        } = self;
+        let (page_format, motion_format) = (*page_format, *motion_format);
+
+        let output_dir = match target.name.as_deref() {
+            Some(name) => packer_args.output_dir.join(name),
+            None => packer_args.output_dir.clone(),
+        };
+
+        let paths = packer_args
+            .output_layout
+            .paths(&output_dir, &packer_args.atlas_name);
+
+        let pages_dir = packer_args
+            .pages_dir
+            .as_ref()
+            .or(packer_args.keep_pages.then_some(&paths.preview));
+        for dir in [&paths.textures, &paths.meta]
+            .into_iter()
+            .chain(Some(&paths.preview).filter(|_| packer_args.preview || packer_args.waste_map))
+            .chain(pages_dir)
+        {
+            std::fs::create_dir_all(dir)
+                .map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+        }
+
+        //
+        // textures are encoded into the scratch dir first, so outputs that come out the
+        // same are not rewritten and nothing is published before the encode succeeded
+        let staging = scratch_dir.path().join(format!(
+            "target-{}",
+            target.name.as_deref().unwrap_or("default")
+        ));
+        std::fs::create_dir_all(&staging)
+            .map_err(|e| format!("Failed to create {}: {}", staging.display(), e))?;
+
+        //
+        // with a texture per page the pages are encoded one by one
+        let separate = packer_args.layout == TextureLayout::Pages && target.encode.is_ktx2();
+        let encode_ldr = |levels: &[Vec<std::path::PathBuf>], format, name: &str| {
+            encode::encode_pages(
+                levels,
+                target.encode,
+                format,
+                packer_args.layout.shape(),
+                &packer_args.encoder_options(),
+                &staging,
+                name,
+            )
+        };
+
+        let started = std::time::Instant::now();
+        let texture = match hdr_levels.as_ref() {
+            Some(levels) => encode::encode_layers(
+                levels,
+                separate,
+                packer_args.jobs,
+                &packer_args.atlas_name,
+                |levels, name, _| {
+                    encode::encode_hdr_pages(
+                        levels,
+                        target.encode,
+                        packer_args.layout.shape(),
+                        packer_args.pixel_format,
+                        page_format.premultiplied,
+                        &staging,
+                        name,
+                    )
+                },
+            )?,
+            None => encode::encode_layers(
+                atlas_sheet_images,
+                separate,
+                packer_args.jobs,
+                &packer_args.atlas_name,
+                |levels, name, pages| {
+                    let channels = page_channels.get(pages.start).copied().unwrap_or_default();
+                    encode_ldr(
+                        levels,
+                        PageFormat {
+                            channels,
+                            ..page_format
+                        },
+                        name,
+                    )
+                },
+            )?,
+        };
+        log::debug!(
+            "Encoded {} as {:?} in {:.2?}",
+            packer_args.atlas_name,
+            target.encode,
+            started.elapsed()
+        );
+
+        let motion_texture = motion_sheet_images
+            .as_ref()
+            .map(|levels| {
+                encode::encode_layers(
+                    levels,
+                    separate,
+                    packer_args.jobs,
+                    &format!("{}_mv", packer_args.atlas_name),
+                    |levels, name, _| encode_ldr(levels, motion_format, name),
+                )
+            })
+            .transpose()?;
+
+        let mut hasher = blake3::Hasher::new();
+        let mut page_hashes = Vec::new();
+        for (idx, file) in std::iter::once(&texture.file)
+            .chain(texture.pages.iter())
+            .enumerate()
+        {
+            let path = staging.join(file);
+            let data = std::fs::read(&path)
+                .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+            hasher.update(&data);
+            if idx > 0 {
+                page_hashes.push(blake3::hash(&data).to_hex().to_string());
+            }
+        }
+        let atlas_hash = hasher.finalize().to_hex().to_string();
+
+        if packer_args.preview {
+            for (idx, page) in atlas_sheet_images[0].iter().enumerate() {
+                let file = paths
+                    .preview
+                    .join(format!("{}_page{}.png", packer_args.atlas_name, idx));
+                let data = std::fs::read(page)
+                    .map_err(|e| format!("Failed to read {}: {}", page.display(), e))?;
+                publish.write(&file, &data)?;
+            }
+        }
+        if let Some(pages_dir) = pages_dir {
+            let motion = motion_sheet_images.iter().map(|levels| (levels, "_mv"));
+            for (levels, role) in std::iter::once((atlas_sheet_images, "")).chain(motion) {
+                for (level, pages) in levels.iter().enumerate() {
+                    for (idx, page) in pages.iter().enumerate() {
+                        let file = pages_dir.join(match level {
+                            0 => format!("{}{}_page{}.png", packer_args.atlas_name, role, idx),
+                            _ => format!(
+                                "{}{}_page{}_mip{}.png",
+                                packer_args.atlas_name, role, idx, level
+                            ),
+                        });
+                        let data = std::fs::read(page)
+                            .map_err(|e| format!("Failed to read {}: {}", page.display(), e))?;
+                        publish.write(&file, &data)?;
+                    }
+                }
+            }
+        }
+        for (idx, map) in waste_maps.iter().enumerate() {
+            let file = paths
+                .preview
+                .join(format!("{}_waste{}.png", packer_args.atlas_name, idx));
+            let data = std::fs::read(map)
+                .map_err(|e| format!("Failed to read {}: {}", map.display(), e))?;
+            publish.write(&file, &data)?;
+        }
+
+        //
+        // descriptor paths are relative to the descriptor
+        let texture_dir = relative_path(&paths.meta, &paths.textures);
+        let source_dir = |dir: &std::path::Path| {
+            //
+            // `dir` is empty for descriptors next to the textures
+            let base = match dir.as_os_str().is_empty() {
+                true => &paths.textures,
+                false => &paths.meta,
+            };
+            std::path::absolute(base).unwrap_or(base.clone())
+        };
+
+        //
+        // the descriptor with its paths relative to `dir`
+        let descriptor = |dir: &std::path::Path| {
+            let mut atlas = TextureAtlas::new(dir.join(&texture.file), *page_size);
+            atlas.atlas_hash = Some(atlas_hash.clone());
+            atlas.uv_inset = packer_args.uv_inset;
+            atlas.pages = texture.pages.iter().map(|page| dir.join(page)).collect();
+            let base = source_dir(dir);
+            atlas.frames = frames
+                .iter()
+                .cloned()
+                .map(|mut frame| {
+                    frame.source = frame.source.map(|source| relative_path(&base, &source));
+                    frame
+                })
+                .collect();
+            atlas.animations = animations.clone();
+            atlas.content = packer_args.content;
+            atlas.color_space = page_format.color_space;
+            atlas.premultiplied = page_format.premultiplied;
+            atlas.scale = packer_args.scale;
+            //
+            // page images only carry the base level
+            atlas.mip_levels = match target.encode.is_ktx2() {
+                true => *mip_levels as u32,
+                false => 1,
+            };
+            atlas.motion_vectors = motion_texture.as_ref().map(|t| {
+                MotionVectorTexture::new(
+                    dir.join(&t.file),
+                    t.pages.iter().map(|page| dir.join(page)).collect(),
+                )
+            });
+            atlas.pixel_format = packer_args.pixel_format;
+            atlas.attributions = attributions.clone();
+            atlas.fonts = fonts.clone();
+            atlas.sdf_spread = Some(packer_args.sdf_spread).filter(|_| packer_args.sdf);
+            atlas.volume = packer_args.layout == TextureLayout::Volume;
+            atlas.page_channels = page_channels.clone();
+            if let Some(precision) = packer_args.float_precision {
+                atlas.round_floats(precision);
+            }
+            atlas
+        };
+
+        //
+        // with a texture per page every page also gets a descriptor of its own
+        let page_descriptors = |dir: &std::path::Path| match packer_args.layout {
+            TextureLayout::Pages => {
+                let atlas = descriptor(dir);
+                (0..texture.pages.len())
+                    .map(|idx| {
+                        let mut page = atlas.page(idx as u32);
+                        page.atlas_hash = Some(page_hashes[idx].clone());
+                        (idx, page)
+                    })
+                    .collect()
+            }
+            _ => Vec::new(),
+        };
+
+        //
+        // embedded descriptors sit next to their files
+        if packer_args.descriptor != DescriptorOutput::File && target.encode.is_ktx2() {
+            let embedded = match separate {
+                true => page_descriptors(std::path::Path::new(""))
+                    .into_iter()
+                    .map(|(idx, page)| (&texture.pages[idx], page))
+                    .collect(),
+                false => vec![(&texture.file, descriptor(std::path::Path::new("")))],
+            };
+
+            for (file, embedded) in embedded {
+                let texture_path = staging.join(file);
+                let mut value = ron::to_string(&embedded)
+                    .map_err(|e| format!("Failed to serialize atlas data: {}", e))?
+                    .into_bytes();
+                value.push(0);
+
+                let data = std::fs::read(&texture_path)
+                    .map_err(|e| format!("Failed to read {}: {}", texture_path.display(), e))?;
+                let data = ktx2::set_key_value(&data, atlas::KTX2_KEY, &value).map_err(|e| {
+                    format!("Failed to embed descriptor in {}: {}", file.display(), e)
+                })?;
+                std::fs::write(&texture_path, data)
+                    .map_err(|e| format!("Failed to write {}: {}", texture_path.display(), e))?;
+            }
+        }
+
+        for file in std::iter::once(&texture.file)
+            .chain(texture.pages.iter())
+            .chain(
+                motion_texture
+                    .iter()
+                    .flat_map(|t| std::iter::once(&t.file).chain(&t.pages)),
+            )
+        {
+            let path = staging.join(file);
+            let data = std::fs::read(&path)
+                .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+            publish.write(&paths.textures.join(file), &data)?;
+        }
+
+        if packer_args.emit_schema {
+            let schema = texture_packer::schema::descriptor_schema();
+            publish.write(
+                &paths
+                    .meta
+                    .join(format!("{}.schema.json", packer_args.atlas_name)),
+                serde_json::to_string_pretty(&schema)
+                    .map_err(|e| format!("Failed to serialize the schema: {}", e))?
+                    .as_bytes(),
+            )?;
+        }
+
+        //
+        // the descriptor is one of the formats the exporters write
+        let formats = Some("ron")
+            .filter(|_| packer_args.descriptor != DescriptorOutput::Embedded)
+            .into_iter()
+            .chain(packer_args.exports.iter().map(String::as_str))
+            .collect::<Vec<_>>();
+        if !formats.is_empty() {
+            //
+            // the engine formats take an image per page, the PNG encode has them already
+            let page_images = match target.encode == Encode::Png
+                && texture
+                    .pages
+                    .iter()
+                    .all(|p| p.extension().is_some_and(|e| e == "png"))
+            {
+                true => texture.pages.clone(),
+                false if packer_args.exports.is_empty() => Vec::new(),
+                false => atlas_sheet_images[0]
+                    .iter()
+                    .enumerate()
+                    .map(|(idx, page)| {
+                        let file = std::path::PathBuf::from(format!(
+                            "{}_{}.png",
+                            packer_args.atlas_name, idx
+                        ));
+                        let data = std::fs::read(page)
+                            .map_err(|e| format!("Failed to read {}: {}", page.display(), e))?;
+                        publish.write(&paths.textures.join(&file), &data)?;
+                        Ok(file)
+                    })
+                    .collect::<Result<Vec<_>, String>>()?,
+            };
+
+            let input = ExportInput {
+                name: &packer_args.atlas_name,
+                atlas: &descriptor(&texture_dir),
+                page_atlases: &page_descriptors(&texture_dir)
+                    .into_iter()
+                    .map(|(_, page)| page)
+                    .collect::<Vec<_>>(),
+                page_images: &page_images
+                    .iter()
+                    .map(|page| texture_dir.join(page))
+                    .collect::<Vec<_>>(),
+            };
+            for file in export::files(&formats, &input)? {
+                let path = paths.meta.join(&file.path);
+                if let Some(dir) = path.parent() {
+                    std::fs::create_dir_all(dir)
+                        .map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+                }
+                publish.write(&path, &file.data)?;
+            }
+        }
+
+        if packer_args.attribution_file {
+            let path = paths
+                .meta
+                .join(format!("{}.ATTRIBUTION.txt", packer_args.atlas_name));
+            publish.write(&path, attribution_text(attributions).as_bytes())?;
+        }
+
+        if packer_args.classify_report {
+            let report_path = paths
+                .meta
+                .join(format!("{}.classify.ron", packer_args.atlas_name));
+            publish.write(
+                &report_path,
+                ron::ser::to_string_pretty(&decisions, ron::ser::PrettyConfig::new())
+                    .map_err(|e| format!("Failed to serialize classification report: {}", e))?
+                    .as_bytes(),
+            )?;
+        }
+
+        if let Some(stats) = stats {
+            let stats_path = paths
+                .meta
+                .join(format!("{}.stats.json", packer_args.atlas_name));
+            publish.write(
+                &stats_path,
+                serde_json::to_string_pretty(stats)
+                    .map_err(|e| format!("Failed to serialize stats: {}", e))?
+                    .as_bytes(),
+            )?;
+        }
+
+        if packer_args.lossy_dedup {
+            let report_path = paths
+                .meta
+                .join(format!("{}.dedup.ron", packer_args.atlas_name));
+            publish.write(
+                &report_path,
+                ron::ser::to_string_pretty(&merges, ron::ser::PrettyConfig::new())
+                    .map_err(|e| format!("Failed to serialize dedup report: {}", e))?
+                    .as_bytes(),
+            )?;
+        }
+
+        //
+        // every output is recorded for --clean, which only removes files that an
+        // earlier pack of the atlas is known to have written
+        let record = paths
+            .meta
+            .join(format!(".{}.outputs", packer_args.atlas_name));
+        let previous = std::fs::read_to_string(&record).unwrap_or_default();
+        let listed = publish
+            .outputs()
+            .iter()
+            .filter(|path| **path != record)
+            .map(|path| {
+                relative_path(&paths.meta, path)
+                    .to_string_lossy()
+                    .into_owned()
+            })
+            .collect::<Vec<_>>();
+        publish.write(&record, format!("{}\n", listed.join("\n")).as_bytes())?;
+
+        if packer_args.clean {
+            //
+            // packs from before the record name their textures in the descriptor
+            let descriptor =
+                TextureAtlas::load(&paths.meta.join(format!("{}.ron", packer_args.atlas_name)))
+                    .ok();
+            let named = descriptor.iter().flat_map(|atlas| {
+                std::iter::once(&atlas.file)
+                    .chain(&atlas.pages)
+                    .chain(
+                        atlas
+                            .motion_vectors
+                            .iter()
+                            .flat_map(|m| std::iter::once(&m.file).chain(&m.pages)),
+                    )
+                    .cloned()
+            });
+            let candidates = previous
+                .lines()
+                .filter(|line| !line.is_empty())
+                .map(std::path::PathBuf::from)
+                .chain(named)
+                .map(|path| resolve_path(&paths.meta, &path))
+                .collect::<Vec<_>>();
+            publish.remove_stale(candidates);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    #[derive(clap::Parser)]
+    struct Args {
+        #[command(flatten)]
+        pack: PackOptions,
+    }
+
+    fn args(inputs: &[&std::path::Path], extra: &[&str]) -> Args {
+        let mut argv = vec![std::ffi::OsString::from("texture-packer")];
+        for input in inputs {
+            argv.extend(["-i".into(), input.as_os_str().to_owned()]);
+        }
+        argv.extend(["-a".into(), "atlas".into(), "-o".into()]);
+        argv.push(std::env::temp_dir().join("tp-tests-out").into());
+        argv.extend(extra.iter().map(std::ffi::OsString::from));
+        Args::parse_from(argv)
+    }
+
+    fn sprite(path: &std::path::Path, shade: u8) {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        image::RgbaImage::from_pixel(4, 4, image::Rgba([shade, shade, shade, 255]))
+            .save(path)
+            .unwrap();
+    }
+
+    #[test]
+    fn sprites_sharing_a_key_fail_listing_both_files() {
+        let dir = std::env::temp_dir().join(format!("tp-key-conflict-{}", std::process::id()));
+        let (ui, icons) = (dir.join("ui"), dir.join("icons"));
+        for folder in [&ui, &icons] {
+            sprite(&folder.join("close.png"), 255);
+        }
+
+        let args = args(&[&ui, &icons], &[]);
+        let error = load_inputs(&args.pack, &[], None).err().unwrap();
+        assert!(error.contains("TP0102"), "{}", error);
+        for folder in [&ui, &icons] {
+            let file = folder.join("close.png");
+            assert!(error.contains(&file.display().to_string()), "{}", error);
+        }
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn prefixed_keys_name_the_folder_below_the_inputs() {
+        let dir = std::env::temp_dir().join(format!("tp-prefix-{}", std::process::id()));
+        let folders = ["a", "b", "c"].map(|tree| dir.join(tree).join("sub"));
+        for (idx, folder) in folders.iter().enumerate() {
+            sprite(&folder.join("hero.png"), idx as u8 * 50);
+        }
+
+        let inputs = folders.iter().map(|f| f.as_path()).collect::<Vec<_>>();
+        let args = args(&inputs, &["--on-key-conflict", "prefix"]);
+        let loaded = load_inputs(&args.pack, &[], None).unwrap();
+        let mut keys = loaded
+            .sprites
+            .iter()
+            .map(|s| s.key.as_str())
+            .collect::<Vec<_>>();
+        keys.sort();
+        assert_eq!(keys, ["b/sub/hero", "c/sub/hero", "hero"]);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn keys_clashing_after_prefixing_fail() {
+        let dir = std::env::temp_dir().join(format!("tp-prefix-clash-{}", std::process::id()));
+        let folder = dir.join("sub");
+        for (idx, file) in ["hero.png", "hero.bmp", "hero.qoi"].iter().enumerate() {
+            sprite(&folder.join(file), idx as u8 * 50);
+        }
+
+        let args = args(&[&folder], &["--on-key-conflict", "prefix"]);
+        let error = load_inputs(&args.pack, &[], None).err().unwrap();
+        assert!(
+            error.contains("TP0102") && error.contains("sub/hero"),
+            "{}",
+            error
+        );
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    fn solid(path: &std::path::Path, (width, height): (u32, u32)) {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        image::RgbaImage::from_fn(width, height, |x, y| {
+            image::Rgba([x as u8, y as u8, 0, 255])
+        })
+        .save(path)
+        .unwrap();
+    }
+
+    #[test]
+    fn oversized_sprites_are_fitted_inside_their_margins() {
+        let dir = std::env::temp_dir().join(format!("tp-oversized-{}", std::process::id()));
+        solid(&dir.join("wide.png"), (68, 64));
+        solid(&dir.join("flat.png"), (68, 42));
+
+        for mode in ["--split-oversized", "--downscale-oversized"] {
+            for margin in [["--extrude", "2"], ["--extrude", "5"]] {
+                let args = args(&[&dir], &["-s", "64", mode, margin[0], margin[1]]);
+                let inputs = load_inputs(&args.pack, &[], None).unwrap();
+                for rect in inputs.rects.iter() {
+                    assert!(
+                        rect.width <= 64 && rect.height <= 64,
+                        "{} {:?}",
+                        mode,
+                        margin
+                    );
+                }
+                run_packed(&args.pack, None, &mut |_| Ok(())).unwrap();
+            }
+        }
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn oversized_sprites_keep_their_manifest_padding() {
+        let dir = std::env::temp_dir().join(format!("tp-oversized-padding-{}", std::process::id()));
+        solid(&dir.join("wide.png"), (68, 64));
+        let list = dir.join("sprites.json");
+        std::fs::write(
+            &list,
+            serde_json::json!([{ "path": dir.join("wide.png"), "padding": 3 }]).to_string(),
+        )
+        .unwrap();
+        let entries = manifest::load(&list).unwrap();
+
+        for mode in ["--split-oversized", "--downscale-oversized"] {
+            let args = args(&[], &["-s", "64", mode, "--extrude", "1"]);
+            let inputs = load_inputs(&args.pack, &entries, None).unwrap();
+            assert!(!inputs.rects.is_empty());
+            for rect in inputs.rects.iter() {
+                assert!(rect.width <= 64 && rect.height <= 64, "{}", mode);
+            }
+        }
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn sprites_too_large_with_their_margin_are_quarantined() {
+        let dir = std::env::temp_dir().join(format!("tp-margin-quarantine-{}", std::process::id()));
+        solid(&dir.join("in").join("edge.png"), (62, 62));
+        let quarantine = dir.join("rejected");
+
+        let args = args(
+            &[&dir.join("in")],
+            &[
+                "-s",
+                "64",
+                "--extrude",
+                "2",
+                "--quarantine-dir",
+                quarantine.to_str().unwrap(),
+            ],
+        );
+        let inputs = load_inputs(&args.pack, &[], None).unwrap();
+        assert!(inputs.rects.is_empty());
+        assert_eq!(inputs.rejected.len(), 1);
+        assert!(inputs.rejected[0].code == Code::OversizedSprite);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn clean_removes_only_outputs_of_the_atlas() {
+        let dir = std::env::temp_dir().join(format!("tp-clean-{}", std::process::id()));
+        let (input, output) = (dir.join("in"), dir.join("out"));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&input).unwrap();
+        std::fs::create_dir_all(&output).unwrap();
+        for (name, shade) in [("a.png", 255), ("b.png", 128)] {
+            image::RgbaImage::from_pixel(40, 40, image::Rgba([shade, shade, shade, 255]))
+                .save(input.join(name))
+                .unwrap();
+        }
+
+        let pack = || {
+            let args = Args::parse_from([
+                std::ffi::OsStr::new("texture-packer"),
+                "-i".as_ref(),
+                input.as_os_str(),
+                "-a".as_ref(),
+                "atlas".as_ref(),
+                "-o".as_ref(),
+                output.as_os_str(),
+                "-s".as_ref(),
+                "64".as_ref(),
+                "--encode".as_ref(),
+                "png".as_ref(),
+                "--clean".as_ref(),
+            ]);
+            let targets = [EncodeTarget {
+                name: None,
+                encode: Encode::Png,
+            }];
+            run_cached(&args.pack, &targets, None).unwrap();
+        };
+
+        pack();
+        assert!(output.join("atlas_1.png").is_file());
+        std::fs::write(output.join("atlas.notes.txt"), "kept").unwrap();
+        std::fs::write(output.join("atlas_2.png"), "not written by a pack").unwrap();
+
+        std::fs::remove_file(input.join("b.png")).unwrap();
+        pack();
+        assert!(output.join("atlas_0.png").is_file());
+        assert!(!output.join("atlas_1.png").exists());
+        assert!(output.join("atlas.notes.txt").is_file());
+        assert!(output.join("atlas_2.png").is_file());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,44 @@
+//! Work spread over threads, for the pages of an atlas: they are blitted, mipmapped and
+//! encoded independently of each other.
+
+/// Threads to use for `jobs`, one per core for 0.
+pub fn threads(jobs: usize) -> usize {
+    match jobs {
+        0 => std::thread::available_parallelism().map_or(1, |n| n.get()),
+        jobs => jobs,
+    }
+}
+
+/// Runs `f` on the `items` on up to [`threads`]`(jobs)` threads, the results in the
+/// order of the items.
+pub fn map<I, R>(items: I, jobs: usize, f: impl Fn(I::Item) -> R + Sync) -> Vec<R>
+where
+    I: Iterator + Send,
+    I::Item: Send,
+    R: Send,
+{
+    let threads = threads(jobs).min(items.size_hint().1.unwrap_or(usize::MAX));
+    if threads <= 1 {
+        return items.map(f).collect();
+    }
+
+    //
+    // items are handed out one at a time, pages take very different times
+    let queue = std::sync::Mutex::new(items.enumerate());
+    let results = std::sync::Mutex::new(Vec::new());
+    std::thread::scope(|scope| {
+        for _ in 0..threads {
+            scope.spawn(|| loop {
+                let Some((idx, item)) = queue.lock().unwrap().next() else {
+                    break;
+                };
+                let result = f(item);
+                results.lock().unwrap().push((idx, result));
+            });
+        }
+    });
+
+    let mut results = results.into_inner().unwrap();
+    results.sort_by_key(|(idx, _)| *idx);
+    results.into_iter().map(|(_, result)| result).collect()
+}
@@ -0,0 +1,80 @@
+use texture_packer::delta::{self, AtlasDelta, Change};
+
+#[derive(clap::Args, Debug)]
+pub struct DeltaOptions {
+    /// Output directory of the shipped build.
+    old: std::path::PathBuf,
+    /// Output directory of the new build.
+    new: std::path::PathBuf,
+    /// The patch file to write, e.g. `patch.tpdelta`.
+    #[arg(short, long)]
+    output: std::path::PathBuf,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct ApplyDeltaOptions {
+    /// Patch file written by `delta`.
+    patch: std::path::PathBuf,
+    /// Output directory to update in place.
+    dir: std::path::PathBuf,
+}
+
+pub fn run(options: DeltaOptions) -> Result<(), String> {
+    let delta = delta::diff(&options.old, &options.new)?;
+
+    for change in delta.changes.iter() {
+        match change {
+            Change::File { path, data } => {
+                println!("{}: replaced ({} bytes)", path.display(), data.length)
+            }
+            Change::Removed { path } => println!("{}: removed", path.display()),
+            Change::Texture { path, patches, .. } => println!(
+                "{}: {} regions ({} bytes)",
+                path.display(),
+                patches.len(),
+                patches.iter().map(|p| p.data.length).sum::<u64>()
+            ),
+            Change::Descriptor {
+                path,
+                atlas,
+                frames,
+                removed,
+                ..
+            } => println!(
+                "{}: {} frames changed, {} removed{}",
+                path.display(),
+                frames.len(),
+                removed.len(),
+                if atlas.is_some() {
+                    ", header changed"
+                } else {
+                    ""
+                }
+            ),
+        }
+    }
+
+    delta.write(&options.output)?;
+    let size = std::fs::metadata(&options.output)
+        .map(|m| m.len())
+        .unwrap_or(0);
+    println!(
+        "Wrote {} ({} bytes, {} bytes of patch data)",
+        options.output.display(),
+        size,
+        delta.payload_size()
+    );
+
+    Ok(())
+}
+
+pub fn run_apply(options: ApplyDeltaOptions) -> Result<(), String> {
+    let delta = AtlasDelta::read(&options.patch)?;
+    delta::apply(&delta, &options.dir)?;
+    println!(
+        "Applied {} changes to {}",
+        delta.changes.len(),
+        options.dir.display()
+    );
+    Ok(())
+}
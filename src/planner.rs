@@ -0,0 +1,1329 @@
+//! Decides how many pages an atlas needs and places the sprites on them.
+
+use crate::diagnostic::Code;
+use rectangle_pack::{
+    contains_smallest_box, pack_rects, volume_heuristic, GroupedRectsToPlace, PackedLocation,
+    RectToInsert, TargetBin,
+};
+use std::collections::BTreeMap;
+
+/// Default upper bound on the number of pages the planner will try.
+pub const MAX_PAGES: u32 = 32;
+
+pub struct PlanRect<Id> {
+    pub id: Id,
+    pub width: u32,
+    pub height: u32,
+    /// Rects sharing a group always end up on the same page.
+    pub group: Option<String>,
+    /// Page the rect has to go on, whatever its group.
+    pub page: Option<u32>,
+}
+
+/// Where a rect ended up.
+#[derive(Copy, Clone, Debug)]
+pub struct Placed {
+    pub page: u32,
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Placement of every rect, keyed by id.
+pub type Placement<Id> = BTreeMap<Id, Placed>;
+
+/// What rects are ordered by before they are placed, largest first. Groups go by the sum
+/// over their rects.
+#[derive(
+    Copy,
+    Clone,
+    Debug,
+    Default,
+    PartialEq,
+    Eq,
+    clap::ValueEnum,
+    serde::Serialize,
+    serde::Deserialize,
+)]
+pub enum SortOrder {
+    #[default]
+    Area,
+    /// The longer side.
+    MaxSide,
+    Height,
+    Width,
+    Perimeter,
+    /// The area scaled by how elongated the rect is, long thin rects first.
+    Pathological,
+}
+
+impl SortOrder {
+    fn key(self, width: u32, height: u32) -> u64 {
+        let (w, h) = (width as u64, height as u64);
+        match self {
+            SortOrder::Area => w * h,
+            SortOrder::MaxSide => w.max(h),
+            SortOrder::Height => h,
+            SortOrder::Width => w,
+            SortOrder::Perimeter => 2 * (w + h),
+            SortOrder::Pathological => w * h * w.max(h) / w.min(h).max(1),
+        }
+    }
+}
+
+/// Where the free-rectangle packer puts a rect among the free space of a page.
+#[derive(
+    Copy, Clone, Debug, PartialEq, Eq, clap::ValueEnum, serde::Serialize, serde::Deserialize,
+)]
+pub enum PlacementHeuristic {
+    /// The free rect it leaves the least area of.
+    BestArea,
+    /// The free rect it leaves the shortest side of.
+    BestShortSide,
+    /// The spot it ends lowest at, then the leftmost, filling the page row by row from
+    /// the top.
+    BottomLeft,
+}
+
+impl PlacementHeuristic {
+    /// How well a rect of `width` x `height` fits the free rect, lower is better.
+    fn score(self, free: &Free, width: u32, height: u32) -> (u64, u64) {
+        let (dw, dh) = ((free.width - width) as u64, (free.height - height) as u64);
+        match self {
+            PlacementHeuristic::BestArea => (
+                free.width as u64 * free.height as u64 - width as u64 * height as u64,
+                dw.min(dh),
+            ),
+            PlacementHeuristic::BestShortSide => (dw.min(dh), dw.max(dh)),
+            PlacementHeuristic::BottomLeft => ((free.y + height) as u64, free.x as u64),
+        }
+    }
+}
+
+/// How the planner places rects. The default is the guillotine packer of rectangle-pack
+/// with the rects by area.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct Packing {
+    pub sort: SortOrder,
+    /// Places with the free-rectangle packer and this heuristic.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub placement: Option<PlacementHeuristic>,
+}
+
+impl Packing {
+    /// Every sort with every packer and heuristic, the default first.
+    pub fn all() -> Vec<Packing> {
+        let placements = std::iter::once(None).chain(
+            <PlacementHeuristic as clap::ValueEnum>::value_variants()
+                .iter()
+                .map(|&h| Some(h)),
+        );
+        placements
+            .flat_map(|placement| {
+                <SortOrder as clap::ValueEnum>::value_variants()
+                    .iter()
+                    .map(move |&sort| Packing { sort, placement })
+            })
+            .collect()
+    }
+}
+
+impl std::fmt::Display for Packing {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = |v: Option<clap::builder::PossibleValue>| {
+            v.map(|v| v.get_name().to_string()).unwrap_or_default()
+        };
+        write!(
+            f,
+            "--sort {}",
+            name(clap::ValueEnum::to_possible_value(&self.sort))
+        )?;
+        match self.placement {
+            Some(h) => write!(
+                f,
+                " --placement-heuristic {}",
+                name(clap::ValueEnum::to_possible_value(&h))
+            ),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Share of the pages the rects cover, every page counted up to the right and bottom
+/// edges of its rects: of layouts on as many pages, the one leaving the most room in one
+/// piece scores highest.
+pub fn occupancy<Id>(placement: &Placement<Id>) -> f64 {
+    let mut pages = BTreeMap::<u32, (u64, u32, u32)>::new();
+    for p in placement.values() {
+        let (used, right, bottom) = pages.entry(p.page).or_default();
+        *used += p.width as u64 * p.height as u64;
+        *right = (*right).max(p.x + p.width);
+        *bottom = (*bottom).max(p.y + p.height);
+    }
+    let used = pages.values().map(|(used, _, _)| *used).sum::<u64>();
+    let covered = pages
+        .values()
+        .map(|&(_, right, bottom)| right as u64 * bottom as u64)
+        .sum::<u64>();
+    match covered {
+        0 => 0.0,
+        covered => used as f64 / covered as f64,
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum PlanError<Id> {
+    /// Rects larger than a page.
+    Oversized(Vec<(Id, u32, u32)>),
+    /// Groups whose combined area exceeds a single page.
+    GroupTooLarge(Vec<(String, u64)>),
+    /// Everything fits a page individually but not in the allowed number of pages.
+    OutOfPages { max_pages: u32, unplaced: Vec<Id> },
+}
+
+impl<Id> PlanError<Id> {
+    pub fn code(&self) -> Code {
+        match self {
+            PlanError::Oversized(_) => Code::OversizedSprite,
+            PlanError::GroupTooLarge(_) => Code::GroupTooLarge,
+            PlanError::OutOfPages { .. } => Code::OutOfPages,
+        }
+    }
+
+    pub fn map_ids<T>(self, f: impl Fn(Id) -> T) -> PlanError<T> {
+        match self {
+            PlanError::Oversized(rects) => {
+                PlanError::Oversized(rects.into_iter().map(|(id, w, h)| (f(id), w, h)).collect())
+            }
+            PlanError::GroupTooLarge(groups) => PlanError::GroupTooLarge(groups),
+            PlanError::OutOfPages {
+                max_pages,
+                unplaced,
+            } => PlanError::OutOfPages {
+                max_pages,
+                unplaced: unplaced.into_iter().map(f).collect(),
+            },
+        }
+    }
+}
+
+impl<Id: std::fmt::Display> std::fmt::Display for PlanError<Id> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PlanError::Oversized(rects) => {
+                writeln!(f, "{} sprite(s) are larger than a page:", rects.len())?;
+                for (id, w, h) in rects {
+                    writeln!(f, "  {} ({}x{})", id, w, h)?;
+                }
+            }
+            PlanError::GroupTooLarge(groups) => {
+                writeln!(f, "{} group(s) do not fit on a single page:", groups.len())?;
+                for (group, area) in groups {
+                    writeln!(f, "  {} ({} pixels)", group, area)?;
+                }
+            }
+            PlanError::OutOfPages {
+                max_pages,
+                unplaced,
+            } => {
+                writeln!(
+                    f,
+                    "Sprites do not fit in {} pages, {} could not be placed:",
+                    max_pages,
+                    unplaced.len()
+                )?;
+                for id in unplaced {
+                    writeln!(f, "  {}", id)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// What is left of the pages of an earlier build, to place rects around its sprites.
+#[derive(Clone, Copy, Default)]
+struct Taken<'a> {
+    /// Pages of the earlier build.
+    pages: u32,
+    /// The space left on them, as disjoint rects.
+    free: &'a [Placed],
+}
+
+/// The position of a bin: its page and where it starts on the page.
+type BinId = (u32, u32, u32);
+
+/// Bins by page and position on the page: the free rects of the taken pages, and a full
+/// page for the others.
+type Bins<B> = BTreeMap<BinId, B>;
+
+/// The space left in a bin, for one of the packers.
+trait Bin: Clone + Sized {
+    fn new(width: u32, height: u32) -> Self;
+
+    /// Packs the rects into the space left in the bins, taking it from them.
+    fn pack<Id>(
+        rects: &[&PlanRect<Id>],
+        bins: &mut Bins<Self>,
+        packing: Packing,
+    ) -> Option<Placement<Id>>
+    where
+        Id: std::fmt::Debug + std::hash::Hash + Eq + Ord + Clone;
+}
+
+fn try_pack<Id>(
+    rects: &[&PlanRect<Id>],
+    page_size: (u32, u32),
+    pages: u32,
+    taken: Taken,
+    packing: Packing,
+) -> Option<Placement<Id>>
+where
+    Id: std::fmt::Debug + std::hash::Hash + Eq + Ord + Clone,
+{
+    match packing.placement {
+        None => try_pack_into::<Id, TargetBin>(rects, page_size, pages, taken, packing),
+        Some(_) => try_pack_into::<Id, FreeRects>(rects, page_size, pages, taken, packing),
+    }
+}
+
+fn try_pack_into<Id, B: Bin>(
+    rects: &[&PlanRect<Id>],
+    page_size: (u32, u32),
+    pages: u32,
+    taken: Taken,
+    packing: Packing,
+) -> Option<Placement<Id>>
+where
+    Id: std::fmt::Debug + std::hash::Hash + Eq + Ord + Clone,
+{
+    let mut target_bins = taken
+        .free
+        .iter()
+        .filter(|f| f.page < pages)
+        .map(|f| ((f.page, f.x, f.y), B::new(f.width, f.height)))
+        .chain((taken.pages..pages).map(|page| ((page, 0, 0), B::new(page_size.0, page_size.1))))
+        .collect::<Bins<B>>();
+
+    let mut pinned = BTreeMap::<u32, Vec<&PlanRect<Id>>>::new();
+    let mut free = Vec::new();
+    for &r in rects {
+        match r.page {
+            Some(page) => pinned.entry(page).or_default().push(r),
+            None => free.push(r),
+        }
+    }
+
+    //
+    // pinned rects go first, each page's alone in its bin, the others fill the space left
+    let mut placement = Placement::new();
+    for (page, rects) in pinned {
+        let mut bins = target_bins
+            .range((page, 0, 0)..(page + 1, 0, 0))
+            .map(|(&at, bin)| (at, bin.clone()))
+            .collect::<Bins<B>>();
+        if bins.is_empty() {
+            return None;
+        }
+        placement.extend(B::pack(&rects, &mut bins, packing)?);
+        target_bins.append(&mut bins);
+    }
+    if !free.is_empty() {
+        placement.extend(B::pack(&free, &mut target_bins, packing)?);
+    }
+
+    Some(placement)
+}
+
+/// The rects by `sort`, largest first, a group together at the place of its sum.
+fn units<'a, Id>(rects: &[&'a PlanRect<Id>], sort: SortOrder) -> Vec<Vec<&'a PlanRect<Id>>> {
+    let key = |r: &PlanRect<Id>| sort.key(r.width, r.height);
+
+    let mut groups = BTreeMap::<&str, Vec<&PlanRect<Id>>>::new();
+    let mut units = Vec::new();
+    for &r in rects {
+        match r.group.as_deref() {
+            Some(group) => groups.entry(group).or_default().push(r),
+            None => units.push(vec![r]),
+        }
+    }
+    units.extend(groups.into_values());
+    for unit in units.iter_mut() {
+        unit.sort_by_key(|&r| std::cmp::Reverse(key(r)));
+    }
+    units.sort_by_key(|unit| std::cmp::Reverse(unit.iter().map(|&r| key(r)).sum::<u64>()));
+    units
+}
+
+impl Bin for TargetBin {
+    fn new(width: u32, height: u32) -> Self {
+        TargetBin::new(width, height, 1)
+    }
+
+    fn pack<Id>(
+        rects: &[&PlanRect<Id>],
+        bins: &mut Bins<Self>,
+        packing: Packing,
+    ) -> Option<Placement<Id>>
+    where
+        Id: std::fmt::Debug + std::hash::Hash + Eq + Ord + Clone,
+    {
+        match packing.sort {
+            //
+            // rectangle-pack orders by volume itself
+            SortOrder::Area => pack_into(rects, bins),
+            sort => {
+                let mut placement = Placement::new();
+                for unit in units(rects, sort) {
+                    placement.extend(pack_into(&unit, bins)?);
+                }
+                Some(placement)
+            }
+        }
+    }
+}
+
+/// Packs the rects into the space left in the bins with rectangle-pack.
+fn pack_into<Id>(
+    rects: &[&PlanRect<Id>],
+    target_bins: &mut Bins<TargetBin>,
+) -> Option<Placement<Id>>
+where
+    Id: std::fmt::Debug + std::hash::Hash + Eq + Ord + Clone,
+{
+    let mut rects_to_place = GroupedRectsToPlace::<Id, String>::new();
+    for r in rects {
+        rects_to_place.push_rect(
+            r.id.clone(),
+            r.group.clone().map(|g| vec![g]),
+            RectToInsert::new(r.width, r.height, 1),
+        );
+    }
+
+    pack_rects(
+        &rects_to_place,
+        target_bins,
+        &volume_heuristic,
+        &contains_smallest_box,
+    )
+    .ok()
+    .map(|placements| {
+        placements
+            .packed_locations()
+            .iter()
+            .map(
+                |(id, ((page, x, y), loc)): (&Id, &(BinId, PackedLocation))| {
+                    (
+                        id.clone(),
+                        Placed {
+                            page: *page,
+                            x: x + loc.x(),
+                            y: y + loc.y(),
+                            width: loc.width(),
+                            height: loc.height(),
+                        },
+                    )
+                },
+            )
+            .collect()
+    })
+}
+
+/// An empty rect in a bin, relative to the bin.
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct Free {
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+}
+
+impl Free {
+    fn contains(&self, other: &Free) -> bool {
+        other.x >= self.x
+            && other.y >= self.y
+            && other.x + other.width <= self.x + self.width
+            && other.y + other.height <= self.y + self.height
+    }
+}
+
+/// The space left in a bin for the free-rectangle packer: every largest empty rect of
+/// it, overlapping each other.
+#[derive(Clone)]
+struct FreeRects(Vec<Free>);
+
+impl FreeRects {
+    /// Where a rect of `width` x `height` goes by `heuristic`.
+    fn find(&self, width: u32, height: u32, heuristic: PlacementHeuristic) -> Option<(u32, u32)> {
+        self.0
+            .iter()
+            .filter(|f| f.width >= width && f.height >= height)
+            .min_by_key(|f| heuristic.score(f, width, height))
+            .map(|f| (f.x, f.y))
+    }
+
+    /// Takes the rect from the free space: every free rect it overlaps is cut into the
+    /// parts around it, those inside another are dropped.
+    fn take(&mut self, used: Free) {
+        let mut split = Vec::with_capacity(self.0.len() + 4);
+        for f in self.0.drain(..) {
+            if used.x >= f.x + f.width
+                || used.x + used.width <= f.x
+                || used.y >= f.y + f.height
+                || used.y + used.height <= f.y
+            {
+                split.push(f);
+                continue;
+            }
+            if used.x > f.x {
+                split.push(Free {
+                    width: used.x - f.x,
+                    ..f
+                });
+            }
+            if used.x + used.width < f.x + f.width {
+                split.push(Free {
+                    x: used.x + used.width,
+                    width: f.x + f.width - used.x - used.width,
+                    ..f
+                });
+            }
+            if used.y > f.y {
+                split.push(Free {
+                    height: used.y - f.y,
+                    ..f
+                });
+            }
+            if used.y + used.height < f.y + f.height {
+                split.push(Free {
+                    y: used.y + used.height,
+                    height: f.y + f.height - used.y - used.height,
+                    ..f
+                });
+            }
+        }
+
+        let mut kept = Vec::<Free>::with_capacity(split.len());
+        for (idx, f) in split.iter().enumerate() {
+            //
+            // of two equal rects, the first stays
+            let covered = split
+                .iter()
+                .enumerate()
+                .any(|(other, o)| other != idx && o.contains(f) && (o != f || other < idx));
+            if !covered {
+                kept.push(*f);
+            }
+        }
+        self.0 = kept;
+    }
+
+    /// Places all the rects in the bin, or none of them.
+    fn place<Id>(
+        &mut self,
+        unit: &[&PlanRect<Id>],
+        heuristic: PlacementHeuristic,
+    ) -> Option<Vec<Free>> {
+        let mut bin = self.clone();
+        let mut placed = Vec::with_capacity(unit.len());
+        for r in unit {
+            let (x, y) = bin.find(r.width, r.height, heuristic)?;
+            let used = Free {
+                x,
+                y,
+                width: r.width,
+                height: r.height,
+            };
+            bin.take(used);
+            placed.push(used);
+        }
+        *self = bin;
+        Some(placed)
+    }
+}
+
+impl Bin for FreeRects {
+    fn new(width: u32, height: u32) -> Self {
+        FreeRects(vec![Free {
+            x: 0,
+            y: 0,
+            width,
+            height,
+        }])
+    }
+
+    fn pack<Id>(
+        rects: &[&PlanRect<Id>],
+        bins: &mut Bins<Self>,
+        packing: Packing,
+    ) -> Option<Placement<Id>>
+    where
+        Id: std::fmt::Debug + std::hash::Hash + Eq + Ord + Clone,
+    {
+        let heuristic = packing.placement.unwrap_or(PlacementHeuristic::BestArea);
+
+        //
+        // a unit goes into the first bin it fits, so the first pages fill up before
+        // the next ones are used
+        let mut placement = Placement::new();
+        for unit in units(rects, packing.sort) {
+            let ((page, x, y), placed) = bins
+                .iter_mut()
+                .find_map(|(&at, bin)| bin.place(&unit, heuristic).map(|placed| (at, placed)))?;
+            for (r, used) in unit.iter().zip(placed) {
+                placement.insert(
+                    r.id.clone(),
+                    Placed {
+                        page,
+                        x: x + used.x,
+                        y: y + used.y,
+                        width: used.width,
+                        height: used.height,
+                    },
+                );
+            }
+        }
+        Some(placement)
+    }
+}
+
+/// Spreads the rects evenly over `pages` pages, instead of filling the first pages and
+/// leaving the last ones (almost) empty: the largest rects (and groups, which stay
+/// together) go first, each to the page with the least area so far, then every page is
+/// packed on its own. `None` when a page's share does not fit it, or rects are pinned
+/// to their page.
+fn spread<Id>(
+    rects: &[&PlanRect<Id>],
+    page_size: (u32, u32),
+    pages: u32,
+    packing: Packing,
+) -> Option<Placement<Id>>
+where
+    Id: std::fmt::Debug + std::hash::Hash + Eq + Ord + Clone,
+{
+    if rects.iter().any(|r| r.page.is_some()) {
+        return None;
+    }
+
+    let area = |r: &PlanRect<Id>| r.width as u64 * r.height as u64;
+
+    let mut groups = BTreeMap::<&str, Vec<&PlanRect<Id>>>::new();
+    let mut units = Vec::new();
+    for &r in rects {
+        match r.group.as_deref() {
+            Some(group) => groups.entry(group).or_default().push(r),
+            None => units.push(vec![r]),
+        }
+    }
+    units.extend(groups.into_values());
+    units.sort_by_key(|unit| std::cmp::Reverse(unit.iter().map(|&r| area(r)).sum::<u64>()));
+
+    let mut shares = vec![(0u64, Vec::new()); pages as usize];
+    for unit in units {
+        let (used, share) = shares.iter_mut().min_by_key(|(used, _)| *used).unwrap();
+        *used += unit.iter().map(|&r| area(r)).sum::<u64>();
+        share.extend(unit);
+    }
+
+    let mut placement = Placement::new();
+    for (page, (_, share)) in shares.iter().enumerate() {
+        for (id, placed) in try_pack(share, page_size, 1, Taken::default(), packing)? {
+            placement.insert(
+                id,
+                Placed {
+                    page: page as u32,
+                    ..placed
+                },
+            );
+        }
+    }
+
+    Some(placement)
+}
+
+/// Places the rects on as few pages as possible, between `min_pages` and `max_pages`.
+/// When they need fewer than `min_pages`, they are spread evenly over that many.
+/// Rects pinned to a page past `max_pages` are left unplaced.
+pub fn plan<Id>(
+    rects: &[PlanRect<Id>],
+    page_size: (u32, u32),
+    min_pages: u32,
+    max_pages: u32,
+    packing: Packing,
+) -> Result<Placement<Id>, PlanError<Id>>
+where
+    Id: std::fmt::Debug + std::hash::Hash + Eq + Ord + Clone,
+{
+    plan_taken(
+        rects,
+        page_size,
+        (min_pages, max_pages),
+        Taken::default(),
+        packing,
+    )
+}
+
+/// Places the rects like [`plan`] in the `free` space left on the `pages` of an earlier
+/// build, and on new pages after those, up to `max_pages` in all.
+pub fn plan_around<Id>(
+    rects: &[PlanRect<Id>],
+    free: &[Placed],
+    pages: u32,
+    page_size: (u32, u32),
+    max_pages: u32,
+    packing: Packing,
+) -> Result<Placement<Id>, PlanError<Id>>
+where
+    Id: std::fmt::Debug + std::hash::Hash + Eq + Ord + Clone,
+{
+    let taken = Taken { pages, free };
+    plan_taken(rects, page_size, (pages, max_pages), taken, packing)
+}
+
+fn plan_taken<Id>(
+    rects: &[PlanRect<Id>],
+    page_size: (u32, u32),
+    (min_pages, max_pages): (u32, u32),
+    taken: Taken,
+    packing: Packing,
+) -> Result<Placement<Id>, PlanError<Id>>
+where
+    Id: std::fmt::Debug + std::hash::Hash + Eq + Ord + Clone,
+{
+    let oversized = rects
+        .iter()
+        .filter(|r| r.width > page_size.0 || r.height > page_size.1)
+        .map(|r| (r.id.clone(), r.width, r.height))
+        .collect::<Vec<_>>();
+
+    if !oversized.is_empty() {
+        return Err(PlanError::Oversized(oversized));
+    }
+
+    let area = |r: &PlanRect<Id>| r.width as u64 * r.height as u64;
+    let page_area = page_size.0 as u64 * page_size.1 as u64;
+
+    let mut group_areas = BTreeMap::<&str, u64>::new();
+    for r in rects {
+        if let Some(group) = r.group.as_deref() {
+            *group_areas.entry(group).or_default() += area(r);
+        }
+    }
+
+    let too_large = group_areas
+        .into_iter()
+        .filter(|(_, a)| *a > page_area)
+        .map(|(g, a)| (g.to_string(), a))
+        .collect::<Vec<_>>();
+
+    if !too_large.is_empty() {
+        return Err(PlanError::GroupTooLarge(too_large));
+    }
+
+    let past_last = rects
+        .iter()
+        .filter(|r| r.page.is_some_and(|page| page >= max_pages))
+        .map(|r| r.id.clone())
+        .collect::<Vec<_>>();
+
+    if !past_last.is_empty() {
+        return Err(PlanError::OutOfPages {
+            max_pages,
+            unplaced: past_last,
+        });
+    }
+
+    if rects.is_empty() {
+        return Ok(Placement::new());
+    }
+
+    //
+    // no layout can use fewer pages than the area lower bound, or than the last pinned
+    // page, start there
+    let total_area = rects.iter().map(area).sum::<u64>();
+    let free_area = taken
+        .free
+        .iter()
+        .map(|f| f.width as u64 * f.height as u64)
+        .sum::<u64>();
+    let lower_bound = (taken.pages
+        + total_area.saturating_sub(free_area).div_ceil(page_area) as u32)
+        .max(
+            rects
+                .iter()
+                .filter_map(|r| r.page)
+                .max()
+                .map_or(0, |p| p + 1),
+        )
+        .max(1);
+
+    let all = rects.iter().collect::<Vec<_>>();
+
+    //
+    // grow the page count exponentially from the lower bound until everything fits,
+    // then bisect back down to the smallest count that still works
+    let mut failed = lower_bound - 1;
+    let mut pages = lower_bound.min(max_pages);
+    let mut fitted = None;
+
+    while failed < max_pages {
+        match try_pack(&all, page_size, pages, taken, packing) {
+            Some(placement) => {
+                fitted = Some((pages, placement));
+                break;
+            }
+            None => {
+                failed = pages;
+                pages = (pages * 2).min(max_pages);
+            }
+        }
+    }
+
+    if let Some((mut best_pages, mut best)) = fitted {
+        while best_pages - failed > 1 {
+            let mid = failed + (best_pages - failed) / 2;
+            match try_pack(&all, page_size, mid, taken, packing) {
+                Some(placement) => {
+                    best_pages = mid;
+                    best = placement;
+                }
+                None => failed = mid,
+            }
+        }
+
+        if best_pages < min_pages && taken.pages == 0 {
+            return Ok(spread(&all, page_size, min_pages, packing).unwrap_or(best));
+        }
+        return Ok(best);
+    }
+
+    //
+    // find the largest set of the smallest sprites that still fits, the rest is unplaceable
+    let mut by_area = all;
+    by_area.sort_by_key(|r| area(r));
+
+    let (mut lo, mut hi) = (0, by_area.len());
+    while lo < hi {
+        let mid = (lo + hi).div_ceil(2);
+        if try_pack(&by_area[..mid], page_size, max_pages, taken, packing).is_some() {
+            lo = mid;
+        } else {
+            hi = mid - 1;
+        }
+    }
+
+    Err(PlanError::OutOfPages {
+        max_pages,
+        unplaced: by_area[lo..].iter().map(|r| r.id.clone()).collect(),
+    })
+}
+
+/// Places the rects like [`plan`], the rects of every `kind` on pages of their own. The
+/// kinds take the pages in order, together at most `max_pages`, so rects are not pinned
+/// to pages. Returns the kind of every page.
+pub fn plan_segregated<Id, K>(
+    rects: &[PlanRect<Id>],
+    kind: impl Fn(&Id) -> K,
+    page_size: (u32, u32),
+    max_pages: u32,
+    packing: Packing,
+) -> Result<(Placement<Id>, Vec<K>), PlanError<Id>>
+where
+    Id: std::fmt::Debug + std::hash::Hash + Eq + Ord + Clone,
+    K: Ord + Copy,
+{
+    let mut by_kind = BTreeMap::<K, Vec<PlanRect<Id>>>::new();
+    for r in rects {
+        by_kind.entry(kind(&r.id)).or_default().push(PlanRect {
+            id: r.id.clone(),
+            width: r.width,
+            height: r.height,
+            group: r.group.clone(),
+            page: None,
+        });
+    }
+
+    let mut placement = Placement::new();
+    let mut kinds = Vec::new();
+    for (k, rects) in by_kind {
+        let left = max_pages - kinds.len() as u32;
+        let placed = match left {
+            0 => Err(PlanError::OutOfPages {
+                max_pages,
+                unplaced: rects.iter().map(|r| r.id.clone()).collect(),
+            }),
+            _ => plan(&rects, page_size, 1, left, packing),
+        }
+        .map_err(|e| match e {
+            PlanError::OutOfPages { unplaced, .. } => PlanError::OutOfPages {
+                max_pages,
+                unplaced,
+            },
+            e => e,
+        })?;
+
+        let first = kinds.len() as u32;
+        let pages = placed.values().map(|p| p.page + 1).max().unwrap_or(0);
+        placement.extend(placed.into_iter().map(|(id, mut p)| {
+            p.page += first;
+            (id, p)
+        }));
+        kinds.extend(std::iter::repeat_n(k, pages as usize));
+    }
+
+    Ok((placement, kinds))
+}
+
+/// The smallest page at most `largest` on a side the rects fit on without needing more
+/// pages than on `largest` x `largest` ones, `fit` giving the pages they take: the
+/// smallest square first, then as little of its height as they allow. With
+/// `power_of_two`, both sides are powers of two. `largest` x `largest` when they do not
+/// fit that.
+pub fn smallest_page<Id>(
+    rects: &[PlanRect<Id>],
+    largest: u32,
+    power_of_two: bool,
+    fit: impl Fn((u32, u32)) -> Option<u32>,
+) -> (u32, u32) {
+    let Some(pages) = Some(largest).filter(|&l| l > 0).and_then(|l| fit((l, l))) else {
+        return (largest, largest);
+    };
+    let fits = |page_size| fit(page_size).is_some_and(|p| p <= pages);
+
+    let widest = rects.iter().map(|r| r.width).max().unwrap_or(1);
+    let tallest = rects.iter().map(|r| r.height).max().unwrap_or(1);
+    let area = rects
+        .iter()
+        .map(|r| r.width as u64 * r.height as u64)
+        .sum::<u64>()
+        .div_ceil(pages.max(1) as u64);
+    let side = (area as f64).sqrt().ceil() as u32;
+
+    //
+    // the sides tried, smallest first, none of which can hold fewer than the largest
+    // rect and a share of the total area
+    let sides = |least: u32| -> Vec<u32> {
+        let least = least.max(1);
+        match power_of_two {
+            true => std::iter::successors(Some(least.next_power_of_two()), |s| s.checked_mul(2))
+                .take_while(|&s| s <= largest)
+                .collect(),
+            false => (least..=largest).collect(),
+        }
+    };
+    //
+    // fitting is taken to only get easier with the size, bisect for the first that does
+    let first = |sides: &[u32], fits: &dyn Fn(u32) -> bool| {
+        let (mut lo, mut hi) = (0, sides.len());
+        while lo < hi {
+            let mid = lo + (hi - lo) / 2;
+            match fits(sides[mid]) {
+                true => hi = mid,
+                false => lo = mid + 1,
+            }
+        }
+        sides.get(lo).copied()
+    };
+
+    let square = sides(widest.max(tallest).max(side));
+    let Some(width) = first(&square, &|s| s == largest || fits((s, s))) else {
+        return (largest, largest);
+    };
+    let heights = sides(tallest.max(area.div_ceil(width as u64) as u32))
+        .into_iter()
+        .filter(|&h| h <= width)
+        .collect::<Vec<_>>();
+    let height = first(&heights, &|h| h == width || fits((width, h))).unwrap_or(width);
+    (width, height)
+}
+
+/// The space of `pages` pages of `page_size` not covered by the `taken` rects, as
+/// disjoint rects: the free runs of every row of the pages, rows ending where a taken
+/// rect starts or ends, and runs at the same place in consecutive rows joined.
+pub fn free_space(taken: &[Placed], page_size: (u32, u32), pages: u32) -> Vec<Placed> {
+    let mut free = Vec::new();
+    for page in 0..pages {
+        let on_page = taken.iter().filter(|t| t.page == page).collect::<Vec<_>>();
+        let mut rows = on_page
+            .iter()
+            .flat_map(|t| [t.y, t.y + t.height])
+            .chain([0, page_size.1])
+            .map(|y| y.min(page_size.1))
+            .collect::<Vec<_>>();
+        rows.sort();
+        rows.dedup();
+
+        let mut open = Vec::<Placed>::new();
+        for row in rows.windows(2) {
+            let (top, bottom) = (row[0], row[1]);
+            let mut spans = on_page
+                .iter()
+                .filter(|t| t.y < bottom && t.y + t.height > top)
+                .map(|t| (t.x, t.x + t.width))
+                .collect::<Vec<_>>();
+            spans.sort();
+
+            let mut runs = Vec::new();
+            let mut x = 0;
+            for (start, end) in spans {
+                if start > x {
+                    runs.push((x, start));
+                }
+                x = x.max(end);
+            }
+            if x < page_size.0 {
+                runs.push((x, page_size.0));
+            }
+
+            let mut next = Vec::new();
+            for (start, end) in runs {
+                let joined = open
+                    .iter()
+                    .position(|o| o.x == start && o.x + o.width == end && o.y + o.height == top);
+                next.push(match joined {
+                    Some(idx) => {
+                        let mut run = open.swap_remove(idx);
+                        run.height += bottom - top;
+                        run
+                    }
+                    None => Placed {
+                        page,
+                        x: start,
+                        y: top,
+                        width: end - start,
+                        height: bottom - top,
+                    },
+                });
+            }
+            free.append(&mut open);
+            open = next;
+        }
+        free.append(&mut open);
+    }
+
+    free
+}
+
+/// Moves `pages` pages of `page_size` onto one page, a grid of as many columns as
+/// needed for it to be about square, going row by row. Returns the size of the page.
+pub fn stitch<Id>(placement: &mut Placement<Id>, page_size: (u32, u32), pages: u32) -> (u32, u32) {
+    let columns = (1..=pages).find(|c| c * c >= pages).unwrap_or(1);
+    let rows = pages.div_ceil(columns);
+
+    for placed in placement.values_mut() {
+        placed.x += placed.page % columns * page_size.0;
+        placed.y += placed.page / columns * page_size.1;
+        placed.page = 0;
+    }
+
+    (columns * page_size.0, rows * page_size.1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rect(id: u32, width: u32, height: u32) -> PlanRect<u32> {
+        PlanRect {
+            id,
+            width,
+            height,
+            group: None,
+            page: None,
+        }
+    }
+
+    fn pages(placement: &Placement<u32>) -> u32 {
+        placement.values().map(|p| p.page + 1).max().unwrap_or(0)
+    }
+
+    fn overlap(a: &Placed, b: &Placed) -> bool {
+        a.page == b.page
+            && a.x < b.x + b.width
+            && b.x < a.x + a.width
+            && a.y < b.y + b.height
+            && b.y < a.y + a.height
+    }
+
+    /// Every rect on its page and apart from the others.
+    fn assert_valid(placement: &Placement<u32>, page_size: (u32, u32)) {
+        let placed = placement.values().collect::<Vec<_>>();
+        for (idx, a) in placed.iter().enumerate() {
+            assert!(a.x + a.width <= page_size.0 && a.y + a.height <= page_size.1);
+            for b in placed[idx + 1..].iter() {
+                assert!(!overlap(a, b), "{:?} overlaps {:?}", a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn oversized_rects_are_listed() {
+        let rects = [rect(0, 300, 10), rect(1, 10, 10), rect(2, 10, 257)];
+        match plan(&rects, (256, 256), 1, MAX_PAGES, Packing::default()) {
+            Err(PlanError::Oversized(oversized)) => {
+                let ids = oversized.iter().map(|(id, _, _)| *id).collect::<Vec<_>>();
+                assert_eq!(ids, [0, 2]);
+            }
+            _ => panic!("expected oversized rects"),
+        }
+    }
+
+    #[test]
+    fn group_too_large_for_one_page() {
+        let rects = (0..2)
+            .map(|id| PlanRect {
+                group: Some("ui".to_string()),
+                ..rect(id, 200, 200)
+            })
+            .collect::<Vec<_>>();
+        match plan(&rects, (256, 256), 1, MAX_PAGES, Packing::default()) {
+            Err(PlanError::GroupTooLarge(groups)) => {
+                assert_eq!(groups, [("ui".to_string(), 80_000)]);
+            }
+            _ => panic!("expected the group to be too large"),
+        }
+    }
+
+    #[test]
+    fn groups_share_a_page() {
+        let mut rects = (0..6).map(|id| rect(id, 100, 100)).collect::<Vec<_>>();
+        for r in rects.iter_mut().filter(|r| r.id % 2 == 0) {
+            r.group = Some("even".to_string());
+        }
+        let placement = plan(&rects, (256, 256), 1, MAX_PAGES, Packing::default()).unwrap();
+        assert_valid(&placement, (256, 256));
+        let even = [0, 2, 4].map(|id| placement[&id].page);
+        assert!(even.iter().all(|&page| page == even[0]));
+    }
+
+    #[test]
+    fn pinned_past_max_pages_are_unplaced() {
+        let rects = [
+            rect(0, 10, 10),
+            PlanRect {
+                page: Some(4),
+                ..rect(1, 10, 10)
+            },
+        ];
+        match plan(&rects, (256, 256), 1, 2, Packing::default()) {
+            Err(PlanError::OutOfPages {
+                max_pages,
+                unplaced,
+            }) => {
+                assert_eq!(max_pages, 2);
+                assert_eq!(unplaced, [1]);
+            }
+            _ => panic!("expected the pinned rect to be unplaced"),
+        }
+    }
+
+    #[test]
+    fn pinned_rects_stay_on_their_page() {
+        let rects = [
+            rect(0, 10, 10),
+            PlanRect {
+                page: Some(2),
+                ..rect(1, 10, 10)
+            },
+        ];
+        let placement = plan(&rects, (256, 256), 1, MAX_PAGES, Packing::default()).unwrap();
+        assert_eq!(placement[&1].page, 2);
+        assert_eq!(pages(&placement), 3);
+    }
+
+    #[test]
+    fn min_pages_spreads_the_rects() {
+        let rects = (0..4).map(|id| rect(id, 64, 64)).collect::<Vec<_>>();
+        let placement = plan(&rects, (256, 256), 4, MAX_PAGES, Packing::default()).unwrap();
+        assert_valid(&placement, (256, 256));
+        let mut used = placement.values().map(|p| p.page).collect::<Vec<_>>();
+        used.sort();
+        assert_eq!(used, [0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn smallest_rects_are_placed_when_out_of_pages() {
+        let rects = [
+            rect(0, 200, 200),
+            rect(1, 200, 200),
+            rect(2, 200, 200),
+            rect(3, 20, 20),
+        ];
+        match plan(&rects, (256, 256), 1, 2, Packing::default()) {
+            Err(PlanError::OutOfPages { unplaced, .. }) => {
+                assert_eq!(unplaced.len(), 1);
+                assert_ne!(unplaced[0], 3);
+            }
+            _ => panic!("expected a rect to be out of pages"),
+        }
+    }
+
+    #[test]
+    fn exact_minimal_page_count() {
+        //
+        // two never fit side by side, the area bound of 2 pages is far too low
+        let rects = (0..5).map(|id| rect(id, 129, 129)).collect::<Vec<_>>();
+        let placement = plan(&rects, (256, 256), 1, MAX_PAGES, Packing::default()).unwrap();
+        assert_valid(&placement, (256, 256));
+        assert_eq!(pages(&placement), 5);
+
+        let rects = (0..9).map(|id| rect(id, 128, 128)).collect::<Vec<_>>();
+        let placement = plan(&rects, (256, 256), 1, MAX_PAGES, Packing::default()).unwrap();
+        assert_valid(&placement, (256, 256));
+        assert_eq!(pages(&placement), 3);
+    }
+
+    #[test]
+    fn max_pages_bounds_the_growth() {
+        let rects = (0..5).map(|id| rect(id, 129, 129)).collect::<Vec<_>>();
+        match plan(&rects, (256, 256), 1, 3, Packing::default()) {
+            Err(PlanError::OutOfPages { unplaced, .. }) => assert_eq!(unplaced.len(), 2),
+            _ => panic!("expected two rects to be out of pages"),
+        }
+    }
+
+    #[test]
+    fn free_space_is_disjoint_and_complete() {
+        let taken = [
+            Placed {
+                page: 0,
+                x: 0,
+                y: 0,
+                width: 100,
+                height: 256,
+            },
+            Placed {
+                page: 0,
+                x: 120,
+                y: 40,
+                width: 50,
+                height: 30,
+            },
+        ];
+        let free = free_space(&taken, (256, 256), 2);
+        for (idx, a) in free.iter().enumerate() {
+            assert!(taken.iter().all(|t| !overlap(a, t)));
+            assert!(free[idx + 1..].iter().all(|b| !overlap(a, b)));
+        }
+        let area = free
+            .iter()
+            .map(|f| f.width as u64 * f.height as u64)
+            .sum::<u64>();
+        assert_eq!(area, 2 * 256 * 256 - 100 * 256 - 50 * 30);
+    }
+
+    #[test]
+    fn plan_around_keeps_clear_of_taken_rects() {
+        let taken = [Placed {
+            page: 0,
+            x: 0,
+            y: 0,
+            width: 200,
+            height: 256,
+        }];
+        let free = free_space(&taken, (256, 256), 1);
+        let rects = [rect(0, 50, 50), rect(1, 100, 100)];
+        let placement =
+            plan_around(&rects, &free, 1, (256, 256), MAX_PAGES, Packing::default()).unwrap();
+        assert_valid(&placement, (256, 256));
+        assert_eq!(placement[&0].page, 0);
+        assert!(!overlap(&placement[&0], &taken[0]));
+        //
+        // only the small one fits next to the old sprites, the other takes a new page
+        assert_eq!(placement[&1].page, 1);
+    }
+
+    #[test]
+    fn free_rects_stay_clear_of_taken_rects() {
+        let mut bin = <FreeRects as Bin>::new(256, 256);
+        let mut used = Vec::new();
+        for (width, height) in [
+            (100, 60),
+            (30, 200),
+            (80, 80),
+            (120, 20),
+            (50, 50),
+            (10, 90),
+        ] {
+            let Some((x, y)) = bin.find(width, height, PlacementHeuristic::BestArea) else {
+                continue;
+            };
+            let rect = Free {
+                x,
+                y,
+                width,
+                height,
+            };
+            bin.take(rect);
+            used.push(rect);
+
+            for f in bin.0.iter() {
+                assert!(f.x + f.width <= 256 && f.y + f.height <= 256);
+                for u in used.iter() {
+                    let apart = f.x >= u.x + u.width
+                        || u.x >= f.x + f.width
+                        || f.y >= u.y + u.height
+                        || u.y >= f.y + f.height;
+                    assert!(apart, "a free rect overlaps a taken one");
+                }
+            }
+            //
+            // only the largest rects are kept
+            for (idx, f) in bin.0.iter().enumerate() {
+                assert!(bin
+                    .0
+                    .iter()
+                    .enumerate()
+                    .all(|(other, o)| other == idx || !o.contains(f)));
+            }
+        }
+        assert_eq!(used.len(), 6);
+    }
+
+    #[test]
+    fn every_packing_places_apart() {
+        let rects = (0..40)
+            .map(|id| rect(id, 8 + id * 7 % 90, 8 + id * 13 % 70))
+            .collect::<Vec<_>>();
+        for packing in Packing::all() {
+            let placement = plan(&rects, (256, 256), 1, MAX_PAGES, packing).unwrap();
+            assert_eq!(placement.len(), rects.len());
+            assert_valid(&placement, (256, 256));
+        }
+    }
+
+    #[test]
+    fn smallest_page_fits_the_rects() {
+        let rects = (0..4).map(|id| rect(id, 64, 64)).collect::<Vec<_>>();
+        let fit = |page_size| {
+            plan(&rects, page_size, 1, MAX_PAGES, Packing::default())
+                .ok()
+                .map(|placement| pages(&placement))
+        };
+        assert_eq!(smallest_page(&rects, 1024, true, fit), (128, 128));
+
+        let rects = (0..3).map(|id| rect(id, 60, 60)).collect::<Vec<_>>();
+        let fit = |page_size| {
+            plan(&rects, page_size, 1, MAX_PAGES, Packing::default())
+                .ok()
+                .map(|placement| pages(&placement))
+        };
+        assert_eq!(smallest_page(&rects, 1024, true, fit), (128, 128));
+        let (width, height) = smallest_page(&rects, 1024, false, fit);
+        assert!(width < 128 && height <= width);
+        assert_eq!(fit((width, height)), Some(1));
+    }
+
+    #[test]
+    fn smallest_page_is_the_largest_when_nothing_fits() {
+        let rects = [rect(0, 300, 300)];
+        let fit = |page_size| {
+            plan(&rects, page_size, 1, MAX_PAGES, Packing::default())
+                .ok()
+                .map(|placement| pages(&placement))
+        };
+        assert_eq!(smallest_page(&rects, 256, true, fit), (256, 256));
+    }
+}
@@ -0,0 +1,388 @@
+//! Outlines of the visible pixels of sprites for `--polygons`, triangulated for drawing
+//! them as meshes. The outline follows the rows of the sprite, each row spanning from
+//! its leftmost to its rightmost visible pixel: its left and right sides are fitted
+//! with as few straight edges as stay within the tolerance outside the pixels, never
+//! cutting into them.
+
+use crate::atlas::Polygon;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum PolygonMode {
+    /// The convex hull of the outline, the fewest triangles.
+    Convex,
+    /// The outline itself, following the dents of the sides.
+    Concave,
+}
+
+/// Where one side of the outline may cross a row boundary: anywhere from the edge of the
+/// pixels out to the tolerance.
+#[derive(Clone, Copy)]
+struct Span {
+    edge: f32,
+    out: f32,
+}
+
+impl Span {
+    /// The points a vertex may take: on the pixels, halfway and furthest out.
+    fn points(&self) -> [f32; 3] {
+        [self.edge, (self.edge + self.out) / 2.0, self.out]
+    }
+
+    fn lo(&self) -> f32 {
+        self.edge.min(self.out)
+    }
+
+    fn hi(&self) -> f32 {
+        self.edge.max(self.out)
+    }
+}
+
+/// The polyline with the fewest vertices, one on every boundary it bends at, that crosses
+/// every boundary within its span. The vertices are picked from [`Span::points`]; an edge
+/// from one to a later one is possible while some slope from it keeps all the
+/// boundaries in between in their spans.
+fn fit(spans: &[Span]) -> Vec<(usize, f32)> {
+    let n = spans.len();
+    //
+    // fewest edges to every point of every boundary, and the point they come from
+    let mut best = vec![[(u32::MAX, (0, 0)); 3]; n];
+    best[0] = [(0, (0, 0)); 3];
+
+    for from in 0..n - 1 {
+        for (a, &v) in spans[from].points().iter().enumerate() {
+            let edges = best[from][a].0;
+            if edges == u32::MAX {
+                continue;
+            }
+
+            let (mut min, mut max) = (f32::NEG_INFINITY, f32::INFINITY);
+            for to in from + 1..n {
+                let dy = (to - from) as f32;
+                for (b, &w) in spans[to].points().iter().enumerate() {
+                    let slope = (w - v) / dy;
+                    if slope >= min && slope <= max && edges + 1 < best[to][b].0 {
+                        best[to][b] = (edges + 1, (from, a));
+                    }
+                }
+                min = min.max((spans[to].lo() - v) / dy);
+                max = max.min((spans[to].hi() - v) / dy);
+                if min > max {
+                    break;
+                }
+            }
+        }
+    }
+
+    let mut at = (n - 1, (0..3).min_by_key(|&b| best[n - 1][b].0).unwrap_or(0));
+    let mut points = vec![(at.0, spans[at.0].points()[at.1])];
+    while at.0 > 0 {
+        at = best[at.0][at.1].1;
+        points.push((at.0, spans[at.0].points()[at.1]));
+    }
+    points.reverse();
+    points
+}
+
+/// Twice the signed area of the triangle, positive when it turns clockwise on screen.
+fn cross(o: (f32, f32), a: (f32, f32), b: (f32, f32)) -> f32 {
+    (a.0 - o.0) * (b.1 - o.1) - (a.1 - o.1) * (b.0 - o.0)
+}
+
+/// The outline of the pixels with any alpha, `None` for fully transparent ones.
+pub fn outline(
+    pixels: &image::GrayAlphaImage,
+    mode: PolygonMode,
+    tolerance: f32,
+) -> Option<Polygon> {
+    let (width, height) = pixels.dimensions();
+    let rows = (0..height)
+        .map(|y| {
+            let mut visible = (0..width).filter(|&x| pixels.get_pixel(x, y).0[1] != 0);
+            visible
+                .next()
+                .map(|first| (first, visible.next_back().unwrap_or(first) + 1))
+        })
+        .collect::<Vec<_>>();
+    let top = rows.iter().position(|r| r.is_some())?;
+    let bottom = rows.iter().rposition(|r| r.is_some())? + 1;
+
+    //
+    // empty rows in between take the span of the row above, the outline stays in one
+    // piece
+    let mut spans = Vec::with_capacity(bottom - top);
+    for row in rows[top..bottom].iter() {
+        spans.push(row.or(spans.last().copied()).unwrap_or_default());
+    }
+
+    //
+    // a boundary touches the rows above and below it, the sides have to clear both
+    let (width, tolerance) = (width as f32, tolerance.max(0.0));
+    let boundary = |y: usize| {
+        let near = spans[y.saturating_sub(1)..(y + 1).min(spans.len())].iter();
+        let left = near.clone().map(|s| s.0).min().unwrap_or(0) as f32;
+        let right = near.map(|s| s.1).max().unwrap_or(0) as f32;
+        (left, right)
+    };
+    let (lefts, rights): (Vec<_>, Vec<_>) = (0..=spans.len())
+        .map(|y| {
+            let (left, right) = boundary(y);
+            (
+                Span {
+                    edge: left,
+                    out: (left - tolerance).max(0.0),
+                },
+                Span {
+                    edge: right,
+                    out: (right + tolerance).min(width),
+                },
+            )
+        })
+        .unzip();
+
+    let y = |idx: usize| (top + idx) as f32;
+    let mut vertices = fit(&lefts)
+        .into_iter()
+        .map(|(idx, x)| (x, y(idx)))
+        .collect::<Vec<_>>();
+    vertices.extend(fit(&rights).into_iter().rev().map(|(idx, x)| (x, y(idx))));
+
+    //
+    // straight runs through a vertex are one edge
+    let mut idx = 0;
+    while vertices.len() > 3 && idx < vertices.len() {
+        let n = vertices.len();
+        let (prev, next) = (vertices[(idx + n - 1) % n], vertices[(idx + 1) % n]);
+        match cross(prev, vertices[idx], next).abs() < 1e-4 {
+            true => {
+                vertices.remove(idx);
+                idx = idx.saturating_sub(1);
+            }
+            false => idx += 1,
+        }
+    }
+
+    let (vertices, triangles) = match mode {
+        PolygonMode::Convex => {
+            let hull = convex_hull(vertices);
+            let triangles = (1..hull.len().saturating_sub(1) as u32)
+                .flat_map(|i| [0, i, i + 1])
+                .collect();
+            (hull, triangles)
+        }
+        PolygonMode::Concave => {
+            let triangles = ear_clip(&vertices);
+            (vertices, triangles)
+        }
+    };
+
+    Some(Polygon::new(vertices, triangles))
+}
+
+/// The convex hull of the points, going the same way round as the outline.
+fn convex_hull(mut points: Vec<(f32, f32)>) -> Vec<(f32, f32)> {
+    points.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+    points.dedup();
+    if points.len() < 3 {
+        return points;
+    }
+
+    let mut hull = Vec::<(f32, f32)>::with_capacity(points.len() + 1);
+    for pass in 0..2 {
+        let start = hull.len();
+        for &p in points.iter() {
+            while hull.len() >= start + 2
+                && cross(hull[hull.len() - 2], hull[hull.len() - 1], p) >= 0.0
+            {
+                hull.pop();
+            }
+            hull.push(p);
+        }
+        hull.pop();
+        if pass == 0 {
+            points.reverse();
+        }
+    }
+    hull
+}
+
+/// Triangles covering the simple polygon, cut off it one ear (a corner whose triangle
+/// holds no other vertex) at a time.
+fn ear_clip(vertices: &[(f32, f32)]) -> Vec<u32> {
+    let area = (0..vertices.len())
+        .map(|i| {
+            let (a, b) = (vertices[i], vertices[(i + 1) % vertices.len()]);
+            a.0 * b.1 - b.0 * a.1
+        })
+        .sum::<f32>();
+    let turn = area.signum();
+
+    let mut left = (0..vertices.len()).collect::<Vec<_>>();
+    let mut triangles = Vec::with_capacity(3 * vertices.len().saturating_sub(2));
+    while left.len() > 3 {
+        let n = left.len();
+        let ear = (0..n).find(|&i| {
+            let (a, b, c) = (left[(i + n - 1) % n], left[i], left[(i + 1) % n]);
+            let (pa, pb, pc) = (vertices[a], vertices[b], vertices[c]);
+            if cross(pa, pb, pc) * turn <= 0.0 {
+                return false;
+            }
+            //
+            // vertices on the edges count as inside, they would be cut off
+            !left.iter().any(|&v| {
+                v != a
+                    && v != b
+                    && v != c
+                    && cross(pa, pb, vertices[v]) * turn >= 0.0
+                    && cross(pb, pc, vertices[v]) * turn >= 0.0
+                    && cross(pc, pa, vertices[v]) * turn >= 0.0
+            })
+        });
+
+        //
+        // only degenerate outlines have no ear, the rest goes as a fan
+        let Some(i) = ear else {
+            break;
+        };
+        let (a, b, c) = (left[(i + n - 1) % n], left[i], left[(i + 1) % n]);
+        triangles.extend([a as u32, b as u32, c as u32]);
+        left.remove(i);
+    }
+    for i in 1..left.len().saturating_sub(1) {
+        triangles.extend([left[0] as u32, left[i] as u32, left[i + 1] as u32]);
+    }
+    triangles
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A `width`x`height` sprite with the pixels of `rows` visible, a row being the
+    /// `(start, end)` of its visible run.
+    fn sprite(width: u32, rows: &[(u32, u32)]) -> image::GrayAlphaImage {
+        image::GrayAlphaImage::from_fn(width, rows.len() as u32, |x, y| {
+            let (start, end) = rows[y as usize];
+            image::LumaA([255, if (start..end).contains(&x) { 255 } else { 0 }])
+        })
+    }
+
+    fn triangles(polygon: &Polygon) -> Vec<[(f32, f32); 3]> {
+        assert_eq!(polygon.triangles.len() % 3, 0);
+        polygon
+            .triangles
+            .chunks_exact(3)
+            .map(|t| [t[0], t[1], t[2]].map(|i| polygon.vertices[i as usize]))
+            .collect()
+    }
+
+    fn area(polygon: &Polygon) -> f32 {
+        triangles(polygon)
+            .iter()
+            .map(|[a, b, c]| cross(*a, *b, *c).abs() / 2.0)
+            .sum()
+    }
+
+    fn covers(polygon: &Polygon, p: (f32, f32)) -> bool {
+        triangles(polygon).iter().any(|&[a, b, c]| {
+            let sides = [cross(a, b, p), cross(b, c, p), cross(c, a, p)];
+            sides.iter().all(|&s| s >= 0.0) || sides.iter().all(|&s| s <= 0.0)
+        })
+    }
+
+    /// Every visible pixel is inside the triangles, the outline never cuts into them.
+    fn covers_pixels(polygon: &Polygon, pixels: &image::GrayAlphaImage) -> bool {
+        pixels
+            .enumerate_pixels()
+            .filter(|(.., p)| p.0[1] != 0)
+            .all(|(x, y, _)| {
+                let (x, y) = (x as f32, y as f32);
+                [
+                    (0.5, 0.5),
+                    (0.05, 0.05),
+                    (0.95, 0.05),
+                    (0.05, 0.95),
+                    (0.95, 0.95),
+                ]
+                .iter()
+                .all(|(dx, dy)| covers(polygon, (x + dx, y + dy)))
+            })
+    }
+
+    #[test]
+    fn squares_are_their_corners() {
+        let pixels = sprite(8, &[(0, 0), (2, 6), (2, 6), (2, 6), (2, 6), (0, 0)]);
+        for mode in [PolygonMode::Convex, PolygonMode::Concave] {
+            let polygon = outline(&pixels, mode, 1.0).unwrap();
+
+            let mut corners = polygon.vertices.clone();
+            corners.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            assert_eq!(corners, [(2.0, 1.0), (2.0, 5.0), (6.0, 1.0), (6.0, 5.0)]);
+            assert_eq!(polygon.triangles.len(), 6);
+            assert_eq!(area(&polygon), 16.0);
+        }
+    }
+
+    #[test]
+    fn convex_outlines_hull_the_pixels() {
+        //
+        // a diamond, the tolerance lets the sides go straight
+        let pixels = sprite(8, &[(3, 5), (2, 6), (1, 7), (0, 8), (1, 7), (2, 6), (3, 5)]);
+        let polygon = outline(&pixels, PolygonMode::Convex, 1.0).unwrap();
+
+        assert!(covers_pixels(&polygon, &pixels));
+        assert!(polygon.vertices.len() < 12, "{:?}", polygon.vertices);
+        assert!(area(&polygon) < 8.0 * 7.0);
+        let (width, height) = (8.0, 7.0);
+        assert!(polygon
+            .vertices
+            .iter()
+            .all(|&(x, y)| (0.0..=width).contains(&x) && (0.0..=height).contains(&y)));
+    }
+
+    #[test]
+    fn concave_outlines_follow_the_dents() {
+        //
+        // an hourglass, whose hull is the whole sprite
+        let pixels = sprite(6, &[(0, 6), (0, 6), (2, 4), (2, 4), (0, 6), (0, 6)]);
+        let convex = outline(&pixels, PolygonMode::Convex, 0.0).unwrap();
+        let concave = outline(&pixels, PolygonMode::Concave, 0.0).unwrap();
+
+        assert_eq!(area(&convex), 36.0);
+        assert!(covers_pixels(&convex, &pixels));
+        assert!(covers_pixels(&concave, &pixels));
+        assert!(area(&concave) < 36.0, "{}", area(&concave));
+        //
+        // the triangles cover the outline once, without overlapping
+        let shoelace = (0..concave.vertices.len())
+            .map(|i| {
+                let n = concave.vertices.len();
+                let (a, b) = (concave.vertices[i], concave.vertices[(i + 1) % n]);
+                a.0 * b.1 - b.0 * a.1
+            })
+            .sum::<f32>()
+            .abs()
+            / 2.0;
+        assert!((area(&concave) - shoelace).abs() < 1e-4);
+        assert!(!covers(&concave, (0.5, 3.0)) && !covers(&concave, (5.5, 3.0)));
+    }
+
+    #[test]
+    fn transparent_sprites_have_no_outline() {
+        for mode in [PolygonMode::Convex, PolygonMode::Concave] {
+            assert!(outline(&sprite(4, &[(0, 0); 4]), mode, 1.0).is_none());
+            assert!(outline(&image::GrayAlphaImage::new(0, 0), mode, 1.0).is_none());
+            assert!(outline(&image::GrayAlphaImage::new(4, 0), mode, 1.0).is_none());
+        }
+    }
+
+    #[test]
+    fn single_pixels_are_squares() {
+        let pixels = sprite(3, &[(0, 0), (1, 2), (0, 0)]);
+        for mode in [PolygonMode::Convex, PolygonMode::Concave] {
+            let polygon = outline(&pixels, mode, 4.0).unwrap();
+            assert!(covers_pixels(&polygon, &pixels));
+            assert!(polygon.triangles.len() >= 3);
+            assert!(area(&polygon) <= 9.0);
+        }
+    }
+}
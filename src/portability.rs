@@ -0,0 +1,102 @@
+//! Checks for input names that work on the build machine but not everywhere else, so an
+//! atlas built on Linux still builds when the sources are checked out on Windows or
+//! macOS.
+
+use crate::diagnostic::Code;
+
+/// Longest path classic Windows APIs accept.
+const WINDOWS_MAX_PATH: usize = 260;
+/// Longest file name most file systems accept.
+const MAX_NAME: usize = 255;
+
+const WINDOWS_RESERVED: [&str; 22] = [
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+fn name_problems(name: &str) -> Vec<String> {
+    let mut problems = Vec::new();
+
+    let invalid = name
+        .chars()
+        .filter(|c| matches!(c, '<' | '>' | ':' | '"' | '|' | '?' | '*' | '\\') || c.is_control())
+        .collect::<String>();
+    if !invalid.is_empty() {
+        problems.push(format!(
+            "contains characters invalid on Windows: {:?}",
+            invalid
+        ));
+    }
+
+    if name.ends_with(['.', ' ']) {
+        problems.push("ends with a dot or space, which Windows strips".to_string());
+    }
+
+    let base = name.split('.').next().unwrap_or_default();
+    if WINDOWS_RESERVED
+        .iter()
+        .any(|r| r.eq_ignore_ascii_case(base.trim_end()))
+    {
+        problems.push(format!("'{}' is a reserved device name on Windows", base));
+    }
+
+    if name.len() > MAX_NAME {
+        problems.push(format!("name is longer than {} bytes", MAX_NAME));
+    }
+
+    problems
+}
+
+/// Returns one message per problem found with the given input files.
+pub fn audit(files: &[std::path::PathBuf]) -> Vec<(Code, String)> {
+    let mut messages = Vec::new();
+
+    for file in files {
+        let name = file.file_name().unwrap_or_default().to_string_lossy();
+        for problem in name_problems(&name) {
+            messages.push((
+                Code::NotPortable,
+                format!("{}: {}", file.display(), problem),
+            ));
+        }
+
+        let len = file.to_string_lossy().len();
+        if len > WINDOWS_MAX_PATH {
+            messages.push((
+                Code::NotPortable,
+                format!(
+                    "{}: path is {} characters, over the Windows limit of {}",
+                    file.display(),
+                    len,
+                    WINDOWS_MAX_PATH
+                ),
+            ));
+        }
+    }
+
+    //
+    // names that collide on case insensitive file systems (Windows, macOS)
+    let mut by_lowercase = std::collections::BTreeMap::<String, Vec<&std::path::PathBuf>>::new();
+    for file in files {
+        by_lowercase
+            .entry(file.to_string_lossy().to_lowercase())
+            .or_default()
+            .push(file);
+    }
+
+    for clashing in by_lowercase.values().filter(|files| files.len() > 1) {
+        messages.push((
+            Code::CaseClash,
+            format!(
+                "names differ only by case: {}",
+                clashing
+                    .iter()
+                    .map(|f| f.display().to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+        ));
+    }
+
+    messages
+}
@@ -0,0 +1,88 @@
+//! The built in passes over the frame pixels, selected with `--post-process`.
+
+use texture_packer::atlas::NamedTextureRegion;
+use texture_packer::processor::{ProcessorRegistry, RegionPixels, RegionProcessor};
+
+const BUILTIN: &[Builtin] = &[
+    Builtin {
+        name: "watermark",
+        description: "Inverts the luminance along diagonal stripes, to tell dev builds apart",
+        process: watermark,
+    },
+    Builtin {
+        name: "border",
+        description: "Draws an opaque white line around every region, to check the bounds",
+        process: border,
+    },
+    Builtin {
+        name: "swizzle",
+        description: "Swaps luminance and alpha, for shaders reading the mask from the first \
+             channel",
+        process: swizzle,
+    },
+];
+
+#[derive(Copy, Clone)]
+struct Builtin {
+    name: &'static str,
+    description: &'static str,
+    process: fn(&NamedTextureRegion, &mut RegionPixels),
+}
+
+impl RegionProcessor for Builtin {
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    fn description(&self) -> &str {
+        self.description
+    }
+
+    fn process(&self, frame: &NamedTextureRegion, pixels: &mut RegionPixels) {
+        (self.process)(frame, pixels)
+    }
+}
+
+/// Stripes of 2 pixels every 16, going up to the right.
+fn watermark(_: &NamedTextureRegion, pixels: &mut RegionPixels) {
+    for (x, y, px) in pixels.enumerate_pixels_mut() {
+        if (x + y) % 16 < 2 {
+            //
+            // with premultiplied alpha the luminance is at most alpha
+            px.0[0] = px.0[1] - px.0[0].min(px.0[1]);
+        }
+    }
+}
+
+fn border(_: &NamedTextureRegion, pixels: &mut RegionPixels) {
+    let (w, h) = pixels.dimensions();
+    for (x, y, px) in pixels.enumerate_pixels_mut() {
+        if x == 0 || y == 0 || x + 1 == w || y + 1 == h {
+            *px = image::LumaA([255, 255]);
+        }
+    }
+}
+
+fn swizzle(_: &NamedTextureRegion, pixels: &mut RegionPixels) {
+    for px in pixels.pixels_mut() {
+        px.0.swap(0, 1);
+    }
+}
+
+/// A registry with the built in passes.
+pub fn registry() -> ProcessorRegistry {
+    let mut registry = ProcessorRegistry::default();
+    for &processor in BUILTIN {
+        registry.register(processor);
+    }
+    registry
+}
+
+/// Parses `--post-process` values, listing the passes in the help.
+pub fn name_parser() -> clap::builder::PossibleValuesParser {
+    clap::builder::PossibleValuesParser::new(
+        BUILTIN
+            .iter()
+            .map(|p| clap::builder::PossibleValue::new(p.name).help(p.description)),
+    )
+}
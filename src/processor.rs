@@ -0,0 +1,96 @@
+//! Passes over the pixels of every frame, run after the sprites are placed on their
+//! pages and before the pages are encoded, looked up by name. The tool registers its
+//! passes (`--post-process`) in a [`ProcessorRegistry`]; crates using the library can
+//! implement [`RegionProcessor`] for passes of their own, watermarking dev builds,
+//! swizzling channels or drawing borders, and register them the same way:
+//!
+//! ```ignore
+//! let mut registry = ProcessorRegistry::default();
+//! registry.register(Watermark);
+//! registry.process(&["watermark"], &frame, &mut pixels)?;
+//! ```
+
+use crate::atlas::NamedTextureRegion;
+use std::collections::BTreeMap;
+
+/// The luminance/alpha pixels of a frame's region, as they go on the page: with alpha
+/// premultiplied when the atlas is.
+pub type RegionPixels = image::ImageBuffer<image::LumaA<u8>, Vec<u8>>;
+
+pub trait RegionProcessor {
+    /// The name the pass is selected by, e.g. `watermark`.
+    fn name(&self) -> &str;
+
+    /// One line on what the pass does, for listings.
+    fn description(&self) -> &str {
+        ""
+    }
+
+    /// Changes the pixels of `frame`, whose descriptor entry is final apart from the
+    /// UVs being recomputed for the page size later. The pixels keep their size.
+    fn process(&self, frame: &NamedTextureRegion, pixels: &mut RegionPixels);
+}
+
+/// Processors keyed by their name.
+#[derive(Default)]
+pub struct ProcessorRegistry {
+    processors: BTreeMap<String, Box<dyn RegionProcessor>>,
+}
+
+impl ProcessorRegistry {
+    /// Adds a processor, replacing (and returning) one registered under the same name.
+    pub fn register(
+        &mut self,
+        processor: impl RegionProcessor + 'static,
+    ) -> Option<Box<dyn RegionProcessor>> {
+        self.processors
+            .insert(processor.name().to_string(), Box::new(processor))
+    }
+
+    pub fn get(&self, name: &str) -> Option<&dyn RegionProcessor> {
+        self.processors.get(name).map(|p| p.as_ref())
+    }
+
+    /// The registered processors, by name.
+    pub fn iter(&self) -> impl Iterator<Item = &dyn RegionProcessor> {
+        self.processors.values().map(|p| p.as_ref())
+    }
+
+    /// Runs the processors in `names` over the pixels of a frame, in the order given.
+    pub fn process(
+        &self,
+        names: &[impl AsRef<str>],
+        frame: &NamedTextureRegion,
+        pixels: &mut RegionPixels,
+    ) -> Result<(), String> {
+        for name in names.iter().map(|n| n.as_ref()) {
+            let processor = self.get(name).ok_or_else(|| {
+                format!(
+                    "Unknown post-processor '{}', expected one of {}",
+                    name,
+                    self.processors
+                        .keys()
+                        .map(|k| k.as_str())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                )
+            })?;
+
+            let size = pixels.dimensions();
+            processor.process(frame, pixels);
+            if pixels.dimensions() != size {
+                return Err(format!(
+                    "Post-processor '{}' resized {} from {}x{} to {}x{}",
+                    name,
+                    frame.key,
+                    size.0,
+                    size.1,
+                    pixels.width(),
+                    pixels.height()
+                ));
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,123 @@
+//! A progress bar on stderr for the phases of a pack (decode, pack, blit, encode), so
+//! packs of thousands of sprites show how far along they are. Only drawn when stdout
+//! and stderr are terminals and the log is not quiet.
+
+use std::io::{IsTerminal, Write};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+struct Bar {
+    phase: &'static str,
+    done: u64,
+    total: u64,
+    started: Instant,
+    drawn: Option<Instant>,
+}
+
+static BAR: Mutex<Option<Bar>> = Mutex::new(None);
+
+const WIDTH: u64 = 30;
+const REDRAW: Duration = Duration::from_millis(100);
+
+fn enabled() -> bool {
+    log::log_enabled!(log::Level::Info)
+        && std::io::stdout().is_terminal()
+        && std::io::stderr().is_terminal()
+}
+
+impl Bar {
+    fn draw(&mut self) {
+        let elapsed = self.started.elapsed();
+        let line = match self.total {
+            //
+            // no steps to count, only the time spent
+            0 => format!("{:>8} ... {:.1?}", self.phase, elapsed),
+            total => {
+                let done = self.done.min(total);
+                let filled = (WIDTH * done / total) as usize;
+                let eta = match done {
+                    0 => String::from("?"),
+                    _ => format!(
+                        "{}s",
+                        elapsed
+                            .mul_f64((total - done) as f64 / done as f64)
+                            .as_secs()
+                    ),
+                };
+                format!(
+                    "{:>8} [{}{}] {}/{} ETA {}",
+                    self.phase,
+                    "#".repeat(filled),
+                    "-".repeat(WIDTH as usize - filled),
+                    done,
+                    total,
+                    eta
+                )
+            }
+        };
+
+        let mut err = std::io::stderr().lock();
+        let _ = write!(err, "\r\x1b[2K{}", line);
+        let _ = err.flush();
+        self.drawn = Some(Instant::now());
+    }
+}
+
+/// Clears the bar from the terminal before a line is printed, it comes back on the next
+/// step.
+pub fn suspend() {
+    if let Some(bar) = BAR.lock().unwrap().as_mut().filter(|b| b.drawn.is_some()) {
+        let mut err = std::io::stderr().lock();
+        let _ = write!(err, "\r\x1b[2K");
+        let _ = err.flush();
+        bar.drawn = None;
+    }
+}
+
+/// A phase shown by the bar until it is dropped.
+pub struct Phase {
+    active: bool,
+}
+
+/// Starts a phase of `total` steps, 0 when they cannot be counted. Replaces the phase
+/// shown so far.
+pub fn phase(name: &'static str, total: usize) -> Phase {
+    if !enabled() {
+        return Phase { active: false };
+    }
+
+    let mut bar = Bar {
+        phase: name,
+        done: 0,
+        total: total as u64,
+        started: Instant::now(),
+        drawn: None,
+    };
+    bar.draw();
+    *BAR.lock().unwrap() = Some(bar);
+    Phase { active: true }
+}
+
+impl Phase {
+    /// Counts a step done.
+    pub fn inc(&self) {
+        if !self.active {
+            return;
+        }
+        if let Some(bar) = BAR.lock().unwrap().as_mut() {
+            bar.done += 1;
+            if bar.drawn.is_none_or(|at| at.elapsed() >= REDRAW) || bar.done == bar.total {
+                bar.draw();
+            }
+        }
+    }
+}
+
+impl Drop for Phase {
+    fn drop(&mut self) {
+        if self.active {
+            suspend();
+            *BAR.lock().unwrap() = None;
+        }
+    }
+}
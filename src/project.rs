@@ -0,0 +1,120 @@
+//! Project files describing several atlases, built with `texture-packer build project.ron`:
+//!
+//! ```ron
+//! (
+//!     atlases: [
+//!         (
+//!             args: ["-i", "ui:assets/ui", "-a", "ui", "-o", "out"],
+//!             targets: [
+//!                 (name: "desktop", encode: "uastc-zstd"),
+//!                 (name: "mobile", encode: "etc1s"),
+//!                 (name: "web", encode: "png"),
+//!             ],
+//!             aliases: {"ui/ok": "ui/confirm"},
+//!         ),
+//!     ],
+//! )
+//! ```
+//!
+//! `args` are the same arguments `pack` takes, relative paths are resolved against the
+//! project file's directory. `aliases` keep frames reachable under old names, like
+//! `--alias`.
+
+use clap::Parser;
+
+use crate::diagnostic;
+use crate::encode::Encode;
+use crate::pack::{self, Alias, EncodeTarget, PackOptions};
+
+#[derive(clap::Args, Debug)]
+pub struct BuildOptions {
+    /// Project file (.ron) to build.
+    project: std::path::PathBuf,
+}
+
+#[derive(serde::Deserialize)]
+struct ProjectFile {
+    atlases: Vec<ProjectAtlas>,
+}
+
+#[derive(serde::Deserialize)]
+struct ProjectAtlas {
+    args: Vec<String>,
+    /// Encode targets, each written to its own subdirectory. Without any the atlas is
+    /// written like `pack` would.
+    #[serde(default)]
+    targets: Vec<ProjectTarget>,
+    /// Old frame names mapped to the frames they stand in for.
+    #[serde(default)]
+    aliases: std::collections::BTreeMap<String, String>,
+}
+
+#[derive(serde::Deserialize)]
+struct ProjectTarget {
+    name: String,
+    encode: String,
+}
+
+#[derive(clap::Parser)]
+#[command(no_binary_name = true)]
+struct AtlasArgs {
+    #[command(flatten)]
+    pack: PackOptions,
+}
+
+pub fn run(options: BuildOptions) -> Result<(), String> {
+    let contents = std::fs::read_to_string(&options.project)
+        .map_err(|e| format!("Failed to read {}: {}", options.project.display(), e))?;
+    let project: ProjectFile = ron::from_str(&contents)
+        .map_err(|e| format!("Failed to parse {}: {}", options.project.display(), e))?;
+
+    let project_dir = options
+        .project
+        .parent()
+        .unwrap_or_else(|| std::path::Path::new("."));
+
+    let mut failed = 0;
+    for (idx, atlas) in project.atlases.iter().enumerate() {
+        let mut pack_options = AtlasArgs::try_parse_from(&atlas.args)
+            .map_err(|e| format!("Invalid arguments for atlas #{}: {}", idx, e))?
+            .pack;
+        pack_options.rebase(project_dir);
+        pack_options.add_aliases(atlas.aliases.iter().map(|(key, target)| Alias {
+            key: key.clone(),
+            target: target.clone(),
+        }));
+
+        let targets = atlas
+            .targets
+            .iter()
+            .map(|t| {
+                Ok(EncodeTarget {
+                    name: Some(t.name.clone()),
+                    encode: t.encode.parse::<Encode>()?,
+                })
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+
+        let keep_going = pack_options.keep_going();
+        let result = match targets.is_empty() {
+            true => pack::run(pack_options),
+            false => pack::run_targets(&pack_options, &targets),
+        };
+        match result {
+            Err(e) if keep_going => {
+                diagnostic::report_error(&e);
+                failed += 1;
+            }
+            result => result?,
+        }
+    }
+
+    match failed {
+        0 => Ok(()),
+        _ => Err(format!(
+            "{} of {} atlases failed",
+            failed,
+            project.atlases.len()
+        )),
+    }
+}
@@ -0,0 +1,305 @@
+//! Outputs of a run are staged next to their destinations and only moved into place
+//! once every one of them was written, for every atlas of the run, so a failing encode
+//! or a crash while encoding never leaves a descriptor pointing at stale or missing
+//! textures. Stale outputs of earlier runs are only removed then too.
+
+use crate::diagnostic::Code;
+use std::io::Write;
+use std::sync::{Condvar, Mutex};
+use std::thread::ThreadId;
+use texture_packer::staging::staged_path;
+
+/// Destinations staged and not committed yet, by the thread staging them and how many
+/// of its `Publish`es did. Another thread waits on [`RELEASED`] for them.
+static CLAIMS: Mutex<Vec<(std::path::PathBuf, ThreadId, usize)>> = Mutex::new(Vec::new());
+static RELEASED: Condvar = Condvar::new();
+
+/// Waits until no other thread has `path` staged and claims it for this one.
+fn claim(path: &std::path::Path) {
+    let thread = std::thread::current().id();
+    let mut claims = CLAIMS.lock().unwrap_or_else(|e| e.into_inner());
+    loop {
+        match claims.iter_mut().find(|(p, _, _)| p == path) {
+            Some((_, owner, count)) if *owner == thread => {
+                *count += 1;
+                return;
+            }
+            Some(_) => claims = RELEASED.wait(claims).unwrap_or_else(|e| e.into_inner()),
+            None => {
+                claims.push((path.to_path_buf(), thread, 1));
+                return;
+            }
+        }
+    }
+}
+
+fn release(path: &std::path::Path) {
+    let mut claims = CLAIMS.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(idx) = claims.iter().position(|(p, _, _)| p == path) {
+        claims[idx].2 -= 1;
+        if claims[idx].2 == 0 {
+            claims.swap_remove(idx);
+            RELEASED.notify_all();
+        }
+    }
+}
+
+/// What happens to outputs already on disk.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Overwrite {
+    /// Replaced when they changed.
+    #[default]
+    Changed,
+    /// Rewritten, unchanged ones too.
+    Always,
+    /// Kept, an output that would replace one fails the run.
+    Never,
+}
+
+/// Files staged for publication, moved into place in the order they were staged.
+#[derive(Default)]
+pub struct Publish {
+    overwrite: Overwrite,
+    /// Staged file and its destination.
+    staged: Vec<(std::path::PathBuf, std::path::PathBuf)>,
+    /// Every output of the run, unchanged ones included.
+    outputs: Vec<std::path::PathBuf>,
+    /// Outputs of earlier runs to remove.
+    stale: Vec<std::path::PathBuf>,
+    /// Destinations claimed from other threads until this is committed or dropped.
+    claimed: Vec<std::path::PathBuf>,
+}
+
+impl Publish {
+    pub fn new(overwrite: Overwrite) -> Publish {
+        Publish {
+            overwrite,
+            staged: Vec::new(),
+            outputs: Vec::new(),
+            stale: Vec::new(),
+            claimed: Vec::new(),
+        }
+    }
+
+    /// Stages `data` for `path`, unless the file already holds exactly that. Keeping
+    /// unchanged outputs keeps their modification time for the tools looking at it.
+    /// Staging `path` again replaces what was staged for it.
+    ///
+    /// Other threads staging `path` wait until this is committed or dropped.
+    pub fn write(&mut self, path: &std::path::Path, data: &[u8]) -> Result<(), String> {
+        if !self.claimed.iter().any(|p| p == path) {
+            claim(path);
+            self.claimed.push(path.to_path_buf());
+        }
+        if !self.outputs.iter().any(|p| p == path) {
+            self.outputs.push(path.to_path_buf());
+        }
+        match std::fs::read(path) {
+            Ok(existing)
+                if self.overwrite != Overwrite::Always
+                    && blake3::hash(&existing) == blake3::hash(data) =>
+            {
+                log::debug!("{} is unchanged", path.display());
+                return Ok(());
+            }
+            Ok(_) if self.overwrite == Overwrite::Never => {
+                return Err(Code::OutputExists.error(format_args!(
+                    "{} exists and would change, pack without --no-overwrite to replace it",
+                    path.display()
+                )));
+            }
+            _ => {}
+        }
+
+        let staged = staged_path(path);
+
+        //
+        // flushed to disk before the rename, a crash right after it never leaves an
+        // empty or truncated file in place
+        std::fs::File::create(&staged)
+            .and_then(|mut file| {
+                file.write_all(data)?;
+                file.sync_all()
+            })
+            .map_err(|e| format!("Failed to write {}: {}", staged.display(), e))?;
+        self.stage(staged, path.to_path_buf());
+        Ok(())
+    }
+
+    /// Records `staged` as the file to move to `path`, discarding the one staged for it
+    /// before.
+    fn stage(&mut self, staged: std::path::PathBuf, path: std::path::PathBuf) {
+        match self.staged.iter_mut().find(|(_, dest)| *dest == path) {
+            //
+            // single page PNG encodes name their page as the texture too, the page is
+            // staged twice
+            Some(entry) => {
+                let _ = std::fs::remove_file(&entry.0);
+                entry.0 = staged;
+            }
+            None => self.staged.push((staged, path)),
+        }
+    }
+
+    /// Every output staged so far, unchanged ones included.
+    pub fn outputs(&self) -> &[std::path::PathBuf] {
+        &self.outputs
+    }
+
+    /// Removes, once committed, the `candidates` an earlier run wrote and this one did
+    /// not.
+    pub fn remove_stale(&mut self, candidates: impl IntoIterator<Item = std::path::PathBuf>) {
+        for path in candidates {
+            if path.is_file() && !self.outputs.contains(&path) && !self.stale.contains(&path) {
+                self.stale.push(path);
+            }
+        }
+    }
+
+    /// Takes over the files staged in `other`, and its claims. Those replace the ones
+    /// staged here for the same destinations.
+    pub fn append(&mut self, mut other: Publish) {
+        for (staged, path) in std::mem::take(&mut other.staged) {
+            self.stage(staged, path);
+        }
+        self.claimed.append(&mut other.claimed);
+        for path in std::mem::take(&mut other.outputs) {
+            if !self.outputs.contains(&path) {
+                self.outputs.push(path);
+            }
+        }
+        for path in std::mem::take(&mut other.stale) {
+            if !self.stale.contains(&path) {
+                self.stale.push(path);
+            }
+        }
+    }
+
+    /// Moves the staged files into place, textures and images of every atlas first and
+    /// the descriptors, exports and reports naming them after, then removes the stale
+    /// ones. Each rename is atomic, the set is not: a crash while moving them can leave
+    /// some outputs replaced and others not, but no descriptor replaced before its
+    /// textures.
+    pub fn commit(mut self) -> Result<(), String> {
+        self.staged.sort_by_key(|(_, path)| !is_image(path));
+        for (staged, path) in self.staged.iter() {
+            std::fs::rename(staged, path)
+                .map_err(|e| format!("Failed to move {} into place: {}", path.display(), e))?;
+        }
+        self.staged.clear();
+
+        for path in self.stale.iter().filter(|p| !self.outputs.contains(p)) {
+            std::fs::remove_file(path)
+                .map_err(|e| format!("Failed to remove {}: {}", path.display(), e))?;
+            log::info!("Removed the stale {}", path.display());
+        }
+        Ok(())
+    }
+}
+
+/// Textures and page images, the files descriptors name.
+fn is_image(path: &std::path::Path) -> bool {
+    image::ImageFormat::from_path(path).is_ok()
+        || path
+            .extension()
+            .is_some_and(|e| e.eq_ignore_ascii_case("ktx2") || e.eq_ignore_ascii_case("basis"))
+}
+
+impl Drop for Publish {
+    /// Files of a run that did not get to commit are discarded, and the destinations
+    /// released either way.
+    fn drop(&mut self) {
+        for (staged, _) in self.staged.iter() {
+            let _ = std::fs::remove_file(staged);
+        }
+        for path in self.claimed.iter() {
+            release(path);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_dir(name: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("tp-publish-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn staged_files(dir: &std::path::Path) -> usize {
+        std::fs::read_dir(dir)
+            .unwrap()
+            .filter(|e| {
+                e.as_ref()
+                    .is_ok_and(|e| e.file_name().to_string_lossy().ends_with(".staged"))
+            })
+            .count()
+    }
+
+    #[test]
+    fn stagings_of_a_destination_keep_apart() {
+        let dir = test_dir("apart");
+        let path = dir.join("atlas.ron");
+
+        let mut first = Publish::new(Overwrite::Changed);
+        first.write(&path, b"first").unwrap();
+        let mut second = Publish::new(Overwrite::Changed);
+        second.write(&path, b"second").unwrap();
+        assert_eq!(staged_files(&dir), 2);
+
+        drop(first);
+        second.commit().unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"second");
+        assert_eq!(staged_files(&dir), 0);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn restaging_replaces_the_staged_file() {
+        let dir = test_dir("restage");
+        let path = dir.join("atlas.png");
+
+        let mut publish = Publish::new(Overwrite::Changed);
+        publish.write(&path, b"first").unwrap();
+        publish.write(&path, b"second").unwrap();
+        let mut other = Publish::new(Overwrite::Changed);
+        other.write(&path, b"third").unwrap();
+        publish.append(other);
+        assert_eq!(staged_files(&dir), 1);
+
+        publish.commit().unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"third");
+        assert_eq!(staged_files(&dir), 0);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn other_threads_wait_for_the_commit() {
+        let dir = test_dir("wait");
+        let path = dir.join("atlas.ron");
+
+        let mut publish = Publish::new(Overwrite::Changed);
+        publish.write(&path, b"first").unwrap();
+
+        let done = std::sync::Arc::new(std::sync::atomic::AtomicBool::new(false));
+        let other = {
+            let (path, done) = (path.clone(), done.clone());
+            std::thread::spawn(move || {
+                let mut publish = Publish::new(Overwrite::Changed);
+                publish.write(&path, b"second").unwrap();
+                publish.commit().unwrap();
+                done.store(true, std::sync::atomic::Ordering::SeqCst);
+            })
+        };
+
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        assert!(!done.load(std::sync::atomic::Ordering::SeqCst));
+        publish.commit().unwrap();
+        other.join().unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"second");
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
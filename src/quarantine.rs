@@ -0,0 +1,100 @@
+//! Inputs left out of a build, copied with `--quarantine-dir` to
+//! `<dir>/<atlas>/` along with a `<file>.reason.txt` listing why, so they can be fixed
+//! without going through the build log.
+
+use crate::diagnostic::Code;
+
+/// A source file left out of the build, or only partly usable.
+#[derive(Clone, Debug)]
+pub struct Rejection {
+    pub path: std::path::PathBuf,
+    pub code: Code,
+    pub reason: String,
+}
+
+const REASON_SUFFIX: &str = ".reason.txt";
+
+/// Removes what an earlier build quarantined in `dir`: the reason files and the copies
+/// they are named after. Nothing else in the directory is touched.
+fn clear(dir: &std::path::Path) -> Result<(), String> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return Ok(());
+    };
+
+    for path in entries.filter_map(|de| de.ok().map(|d| d.path())) {
+        let Some(copy) = path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .and_then(|n| n.strip_suffix(REASON_SUFFIX))
+            .map(|n| dir.join(n))
+        else {
+            continue;
+        };
+
+        for path in [&path, &copy] {
+            match std::fs::remove_file(path) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                    return Err(format!("Failed to remove {}: {}", path.display(), e))
+                }
+                _ => (),
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Replaces the quarantine of `atlas` under `dir` with copies of the `rejected` files.
+pub fn write(dir: &std::path::Path, atlas: &str, rejected: &[Rejection]) -> Result<(), String> {
+    let dir = dir.join(atlas);
+    clear(&dir)?;
+    if rejected.is_empty() {
+        return Ok(());
+    }
+
+    std::fs::create_dir_all(&dir)
+        .map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+
+    //
+    // a file can be rejected for several reasons, once per frame
+    let mut by_path = std::collections::BTreeMap::<&std::path::Path, Vec<&Rejection>>::new();
+    for rejection in rejected {
+        by_path.entry(&rejection.path).or_default().push(rejection);
+    }
+
+    let mut names = std::collections::HashSet::new();
+    for (path, rejections) in by_path.iter() {
+        //
+        // files of the same name from different input folders get a number
+        let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+        let mut name = file_name.to_string();
+        let mut n = 1;
+        while !names.insert(name.clone()) {
+            name = match file_name.rsplit_once('.') {
+                Some((stem, extension)) => format!("{}_{}.{}", stem, n, extension),
+                None => format!("{}_{}", file_name, n),
+            };
+            n += 1;
+        }
+
+        let copy = dir.join(&name);
+        std::fs::copy(path, &copy).map_err(|e| {
+            format!(
+                "Failed to copy {} to {}: {}",
+                path.display(),
+                copy.display(),
+                e
+            )
+        })?;
+
+        let mut report = format!("{}\n", path.display());
+        for rejection in rejections {
+            report.push_str(&format!("{}: {}\n", rejection.code, rejection.reason));
+        }
+        let report_path = dir.join(format!("{}{}", name, REASON_SUFFIX));
+        std::fs::write(&report_path, report)
+            .map_err(|e| format!("Failed to write {}: {}", report_path.display(), e))?;
+    }
+
+    log::info!("Quarantined {} file(s) in {}", by_path.len(), dir.display());
+    Ok(())
+}
@@ -0,0 +1,225 @@
+//! Bundles for reproducing packing failures without the assets, written with
+//! `--dump-repro out.tar` and run again with `texture-packer replay out.tar`.
+//!
+//! The bundle is a plain tar holding
+//!
+//! - `repro.ron`: the page size and count limits, the sort and placement heuristic, and
+//!   the size, group and forced page of every rect handed to the planner, in the order it
+//!   got them. Keys are left out, groups are numbered.
+//! - `options.txt`: the pack options.
+//! - `versions.txt`: the tool version, the platform and what `toktx --version` prints.
+//!
+//! No pixels are included. Planning has no random seed, the same rects in the same
+//! order always get the same layout.
+
+use crate::pack::PackOptions;
+use crate::planner::{self, Packing, PlanRect};
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct ReproRect {
+    width: u32,
+    height: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    group: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    page: Option<u32>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct Repro {
+    version: String,
+    page_size: (u32, u32),
+    min_pages: u32,
+    max_pages: u32,
+    rects: Vec<ReproRect>,
+    /// How the planner placed them, bundles of older versions had no choice.
+    #[serde(default)]
+    packing: Packing,
+    /// What the planner reported.
+    error: String,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct ReplayOptions {
+    /// Bundle written by `--dump-repro`.
+    bundle: std::path::PathBuf,
+}
+
+/// A ustar archive of `files`.
+fn tar(files: &[(&str, &[u8])]) -> Vec<u8> {
+    let mut out = Vec::new();
+    for (name, data) in files {
+        let mut header = [0u8; 512];
+        let field = |header: &mut [u8; 512], offset: usize, value: &[u8]| {
+            header[offset..offset + value.len()].copy_from_slice(value);
+        };
+        field(&mut header, 0, name.as_bytes());
+        field(&mut header, 100, b"0000644\0");
+        field(&mut header, 108, b"0000000\0");
+        field(&mut header, 116, b"0000000\0");
+        field(
+            &mut header,
+            124,
+            format!("{:011o}\0", data.len()).as_bytes(),
+        );
+        field(&mut header, 136, b"00000000000\0");
+        field(&mut header, 156, b"0");
+        field(&mut header, 257, b"ustar\x0000");
+        //
+        // the checksum is taken with its own field as spaces
+        field(&mut header, 148, b"        ");
+        let checksum = header.iter().map(|&b| b as u32).sum::<u32>();
+        field(&mut header, 148, format!("{:06o}\0 ", checksum).as_bytes());
+
+        out.extend_from_slice(&header);
+        out.extend_from_slice(data);
+        out.resize(out.len().div_ceil(512) * 512, 0);
+    }
+    out.resize(out.len() + 1024, 0);
+    out
+}
+
+/// The files of a ustar archive, by name.
+fn untar(mut data: &[u8]) -> Result<Vec<(String, Vec<u8>)>, String> {
+    let mut files = Vec::new();
+    while data.len() >= 512 && data[0] != 0 {
+        let text = |range: std::ops::Range<usize>| {
+            let field = &data[range];
+            let end = field.iter().position(|&b| b == 0).unwrap_or(field.len());
+            String::from_utf8_lossy(&field[..end]).trim().to_string()
+        };
+        let name = text(0..100);
+        let size = usize::from_str_radix(&text(124..136), 8)
+            .map_err(|_| format!("Invalid size of {} in the bundle", name))?;
+
+        let body = data
+            .get(512..512 + size)
+            .ok_or_else(|| format!("{} is cut short in the bundle", name))?;
+        files.push((name, body.to_vec()));
+        data = data
+            .get(512 + size.div_ceil(512) * 512..)
+            .unwrap_or_default();
+    }
+    Ok(files)
+}
+
+fn versions(toktx: &std::path::Path) -> String {
+    let toktx = std::process::Command::new(toktx)
+        .arg("--version")
+        .output()
+        .map(|out| String::from_utf8_lossy(&out.stdout).trim().to_string())
+        .unwrap_or_else(|e| format!("not runnable ({})", e));
+
+    format!(
+        "texture-packer {}\nplatform {}-{}\ntoktx {}\n",
+        env!("CARGO_PKG_VERSION"),
+        std::env::consts::ARCH,
+        std::env::consts::OS,
+        toktx
+    )
+}
+
+/// Writes the bundle for a failed plan of `rects`.
+pub fn dump<Id>(
+    path: &std::path::Path,
+    options: &PackOptions,
+    toktx: &std::path::Path,
+    rects: &[PlanRect<Id>],
+    page_size: (u32, u32),
+    (min_pages, max_pages): (u32, u32),
+    error: &str,
+) -> Result<(), String> {
+    let mut groups = std::collections::BTreeMap::new();
+    let rects = rects
+        .iter()
+        .map(|r| ReproRect {
+            width: r.width,
+            height: r.height,
+            group: r.group.as_deref().map(|group| {
+                let next = groups.len() as u32;
+                *groups.entry(group).or_insert(next)
+            }),
+            page: r.page,
+        })
+        .collect();
+
+    let repro = ron::ser::to_string_pretty(
+        &Repro {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            page_size,
+            min_pages,
+            max_pages,
+            rects,
+            packing: options.packing(),
+            error: error.to_string(),
+        },
+        ron::ser::PrettyConfig::new(),
+    )
+    .map_err(|e| format!("Failed to serialize the repro: {}", e))?;
+
+    let data = tar(&[
+        ("repro.ron", repro.as_bytes()),
+        ("options.txt", format!("{:#?}\n", options).as_bytes()),
+        ("versions.txt", versions(toktx).as_bytes()),
+    ]);
+    std::fs::write(path, data).map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+}
+
+/// Plans the rects of a bundle again and reports how it went.
+pub fn run_replay(options: ReplayOptions) -> Result<(), String> {
+    let data = std::fs::read(&options.bundle)
+        .map_err(|e| format!("Failed to read {}: {}", options.bundle.display(), e))?;
+    let files = untar(&data)?;
+    let file = |name: &str| {
+        files
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, data)| String::from_utf8_lossy(data).into_owned())
+            .ok_or_else(|| format!("{} has no {}", options.bundle.display(), name))
+    };
+
+    let repro: Repro = ron::from_str(&file("repro.ron")?)
+        .map_err(|e| format!("Failed to parse repro.ron: {}", e))?;
+    print!("{}", file("versions.txt")?);
+    println!(
+        "{} rects on {}x{} pages, {} to {} pages, recorded with {}",
+        repro.rects.len(),
+        repro.page_size.0,
+        repro.page_size.1,
+        repro.min_pages,
+        repro.max_pages,
+        repro.version
+    );
+    println!("Recorded error: {}", repro.error);
+
+    let rects = repro
+        .rects
+        .iter()
+        .enumerate()
+        .map(|(id, r)| PlanRect {
+            id,
+            width: r.width,
+            height: r.height,
+            group: r.group.map(|g| format!("group{}", g)),
+            page: r.page,
+        })
+        .collect::<Vec<_>>();
+
+    match planner::plan(
+        &rects,
+        repro.page_size,
+        repro.min_pages,
+        repro.max_pages,
+        repro.packing,
+    ) {
+        Ok(placement) => println!(
+            "Replayed: packed on {} page(s)",
+            placement.values().map(|p| p.page + 1).max().unwrap_or(1)
+        ),
+        Err(e) => print!(
+            "Replayed: {}",
+            e.map_ids(|id| format!("rect #{} ({}x{})", id, rects[id].width, rects[id].height))
+        ),
+    }
+    Ok(())
+}
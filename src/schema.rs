@@ -0,0 +1,441 @@
+//! JSON Schema of the atlas descriptor, [`TextureAtlas`](crate::atlas::TextureAtlas), at
+//! [`SCHEMA_VERSION`]. It describes the descriptor in the serde data model as JSON
+//! has it: `Some(x)` is `x`, tuples are arrays and enum variants their names as strings.
+//!
+//! Fields not listed as required may be left out. Later versions of the tool may add
+//! optional fields without a new schema version, so readers should ignore the ones
+//! they don't know; anything else changing bumps the version.
+
+use crate::atlas::SCHEMA_VERSION;
+use serde_json::{json, Value};
+
+fn uint() -> Value {
+    json!({ "type": "integer", "minimum": 0 })
+}
+
+fn number() -> Value {
+    json!({ "type": "number" })
+}
+
+fn string() -> Value {
+    json!({ "type": "string" })
+}
+
+fn boolean() -> Value {
+    json!({ "type": "boolean" })
+}
+
+fn character() -> Value {
+    json!({ "type": "string", "minLength": 1, "maxLength": 1 })
+}
+
+fn array(items: Value) -> Value {
+    json!({ "type": "array", "items": items })
+}
+
+fn pair(item: Value) -> Value {
+    json!({
+        "type": "array",
+        "prefixItems": [item.clone(), item],
+        "minItems": 2,
+        "maxItems": 2,
+    })
+}
+
+fn variants(names: &[&str]) -> Value {
+    json!({ "type": "string", "enum": names })
+}
+
+fn def(name: &str) -> Value {
+    json!({ "$ref": format!("#/$defs/{}", name) })
+}
+
+fn object(description: &str, properties: Value, required: &[&str]) -> Value {
+    json!({
+        "type": "object",
+        "description": description,
+        "properties": properties,
+        "required": required,
+    })
+}
+
+/// The schema, as a JSON Schema (draft 2020-12) document.
+pub fn descriptor_schema() -> Value {
+    let frame = object(
+        "A packed frame.",
+        json!({
+            "name": uint(),
+            "key": string(),
+            "layer": uint(),
+            "x": uint(),
+            "y": uint(),
+            "width": uint(),
+            "height": uint(),
+            "tag": string(),
+            "nine_slice": def("NineSlice"),
+            "events": array(string()),
+            "pivot_x": number(),
+            "pivot_y": number(),
+            "split": def("SplitPart"),
+            "baked_transform": def("SpriteTransform"),
+            "variant_of": string(),
+            "draw_transform": def("SpriteTransform"),
+            "alias_of": string(),
+            "delta": def("FrameDelta"),
+            "max_mip": uint(),
+            "tile": uint(),
+            "trim": def("Trim"),
+            "polygon": def("Polygon"),
+            "filter": variants(&["Nearest", "Linear"]),
+            "alpha": variants(&["Opaque", "Binary", "Blended"]),
+            "motion_vectors": boolean(),
+            "tone": def("ToneAdjustment"),
+            "source_hash": string(),
+            "source": string(),
+            "provenance": def("Provenance"),
+            "u0": number(),
+            "v0": number(),
+            "u1": number(),
+            "v1": number(),
+        }),
+        &["name", "layer", "x", "y", "width", "height"],
+    );
+
+    let defs = json!({
+        "Frame": frame,
+        "NineSlice": object(
+            "Nine-slice border insets in pixels, from the region edges.",
+            json!({ "left": uint(), "right": uint(), "top": uint(), "bottom": uint() }),
+            &["left", "right", "top", "bottom"],
+        ),
+        "SplitPart": object(
+            "Where a tile sits in the source image it was cut from.",
+            json!({
+                "parent": string(),
+                "x": uint(),
+                "y": uint(),
+                "parent_width": uint(),
+                "parent_height": uint(),
+            }),
+            &["parent", "x", "y", "parent_width", "parent_height"],
+        ),
+        "SpriteTransform": object(
+            "Flips, then a 90 degree clockwise rotation.",
+            json!({ "flip_x": boolean(), "flip_y": boolean(), "rotate90": boolean() }),
+            &[],
+        ),
+        "FrameDelta": object(
+            "Placement and compositing of an uncomposited animation frame.",
+            json!({
+                "x": uint(),
+                "y": uint(),
+                "canvas_width": uint(),
+                "canvas_height": uint(),
+                "dispose": variants(&["None", "Background", "Previous"]),
+                "blend": variants(&["Over", "Source"]),
+            }),
+            &["x", "y", "canvas_width", "canvas_height", "dispose", "blend"],
+        ),
+        "Trim": object(
+            "Where the packed pixels sit in the image before it was trimmed.",
+            json!({
+                "x": uint(),
+                "y": uint(),
+                "source_width": uint(),
+                "source_height": uint(),
+            }),
+            &["x", "y", "source_width", "source_height"],
+        ),
+        "Polygon": object(
+            "Outline of the visible pixels, in pixels from the top left of the region.",
+            json!({ "vertices": array(pair(number())), "triangles": array(uint()) }),
+            &["vertices", "triangles"],
+        ),
+        "ToneAdjustment": object(
+            "Exposure in stops and gamma applied to the source colors.",
+            json!({ "exposure": number(), "gamma": number() }),
+            &[],
+        ),
+        "Provenance": object(
+            "Size of the source image and modification time of its file.",
+            json!({ "source_width": uint(), "source_height": uint(), "modified": uint() }),
+            &["source_width", "source_height"],
+        ),
+        "Animation": object(
+            "A named sequence of frames.",
+            json!({
+                "name": string(),
+                "frames": array(string()),
+                "durations_ms": array(uint()),
+                "direction": variants(&["Forward", "Reverse", "PingPong", "PingPongReverse"]),
+                "repeat": uint(),
+                "events": array(string()),
+            }),
+            &["name", "frames"],
+        ),
+        "MotionVectorTexture": object(
+            "Motion vectors laid out like the color texture.",
+            json!({ "file": string(), "pages": array(string()) }),
+            &["file"],
+        ),
+        "Attribution": object(
+            "License and credits of packed artwork.",
+            json!({
+                "title": string(),
+                "author": string(),
+                "license": string(),
+                "url": string(),
+                "notice": string(),
+            }),
+            &[],
+        ),
+        "AttributionGroup": object(
+            "Frames sharing an attribution.",
+            json!({ "attribution": def("Attribution"), "frames": array(string()) }),
+            &["attribution", "frames"],
+        ),
+        "FontFace": object(
+            "A font rasterized into the atlas, metrics in pixels, y up from the baseline.",
+            json!({
+                "name": string(),
+                "size": number(),
+                "ascent": number(),
+                "descent": number(),
+                "line_height": number(),
+                "mode": variants(&["Plain", "Sdf"]),
+                "spread": uint(),
+                "glyphs": array(def("Glyph")),
+                "kerning": array(def("Kerning")),
+            }),
+            &["name", "size", "ascent", "descent", "line_height", "glyphs"],
+        ),
+        "Glyph": object(
+            "A glyph of a font, `key` naming its frame.",
+            json!({
+                "codepoint": character(),
+                "key": string(),
+                "advance": number(),
+                "bearing_x": number(),
+                "bearing_y": number(),
+            }),
+            &["codepoint", "advance", "bearing_x", "bearing_y"],
+        ),
+        "Kerning": object(
+            "Extra advance between two glyphs.",
+            json!({ "left": character(), "right": character(), "amount": number() }),
+            &["left", "right", "amount"],
+        ),
+    });
+
+    let mut schema = object(
+        "A texture-packer atlas descriptor.",
+        json!({
+            "schema_version": { "type": "integer", "minimum": 0, "maximum": SCHEMA_VERSION },
+            "frames": array(def("Frame")),
+            "size": pair(uint()),
+            "file": string(),
+            "atlas_hash": string(),
+            "uv_inset": number(),
+            "pages": array(string()),
+            "animations": array(def("Animation")),
+            "content": variants(&["Color", "NormalMap", "MotionVectors"]),
+            "color_space": variants(&["Srgb", "Linear"]),
+            "premultiplied": boolean(),
+            "scale": number(),
+            "mip_levels": { "type": "integer", "minimum": 1 },
+            "volume": boolean(),
+            "motion_vectors": def("MotionVectorTexture"),
+            "pixel_format": variants(&["La8", "Rgba16", "Rgba16f", "Rgba32f"]),
+            "page_channels": array(variants(&["LuminanceAlpha", "Luminance", "Alpha"])),
+            "attributions": array(def("AttributionGroup")),
+            "fonts": array(def("FontFace")),
+            "sdf_spread": uint(),
+        }),
+        &["frames", "size", "file"],
+    );
+    schema["$schema"] = json!("https://json-schema.org/draft/2020-12/schema");
+    schema["$id"] = json!(format!("urn:texture-packer:atlas:{}", SCHEMA_VERSION));
+    schema["title"] = json!(format!("texture-packer atlas, version {}", SCHEMA_VERSION));
+    schema["$defs"] = defs;
+    schema
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::atlas::*;
+
+    /// Where `value` breaks `schema`: keys it doesn't declare, required keys it lacks and
+    /// values of the wrong type.
+    fn check(root: &Value, schema: &Value, value: &Value, at: &str, problems: &mut Vec<String>) {
+        if let Some(name) = schema["$ref"].as_str() {
+            let name = name.trim_start_matches("#/$defs/");
+            return check(root, &root["$defs"][name], value, at, problems);
+        }
+        if let Some(names) = schema["enum"].as_array() {
+            if !names.contains(value) {
+                problems.push(format!("{}: {} is not one of {:?}", at, value, names));
+            }
+        }
+
+        let fits = match schema["type"].as_str() {
+            Some("object") => value.is_object(),
+            Some("array") => value.is_array(),
+            Some("integer") => value.is_u64(),
+            Some("number") => value.is_number(),
+            Some("string") => value.is_string(),
+            Some("boolean") => value.is_boolean(),
+            _ => true,
+        };
+        if !fits {
+            problems.push(format!("{}: {} is not a {}", at, value, schema["type"]));
+            return;
+        }
+
+        if let Some(fields) = value.as_object() {
+            for (key, field) in fields {
+                match schema["properties"].get(key) {
+                    Some(property) => {
+                        check(root, property, field, &format!("{}.{}", at, key), problems)
+                    }
+                    None => problems.push(format!("{}.{} is not in the schema", at, key)),
+                }
+            }
+            for key in schema["required"].as_array().into_iter().flatten() {
+                if !fields.contains_key(key.as_str().unwrap()) {
+                    problems.push(format!("{} lacks the required {}", at, key));
+                }
+            }
+        }
+        if let Some(items) = value.as_array() {
+            for (idx, item) in items.iter().enumerate() {
+                let item_schema = match schema["prefixItems"].get(idx) {
+                    Some(prefix) => prefix,
+                    None => &schema["items"],
+                };
+                check(
+                    root,
+                    item_schema,
+                    item,
+                    &format!("{}[{}]", at, idx),
+                    problems,
+                );
+            }
+        }
+    }
+
+    fn problems(atlas: &TextureAtlas) -> Vec<String> {
+        let schema = descriptor_schema();
+        let value = serde_json::to_value(atlas).unwrap();
+        let mut problems = Vec::new();
+        check(&schema, &schema, &value, "atlas", &mut problems);
+        problems
+    }
+
+    #[test]
+    fn every_field_of_a_full_descriptor_is_in_the_schema() {
+        let mut frame = NamedTextureRegion::new("hero", 1, (0, 0, 16, 16));
+        frame.tag = Some("idle".to_string());
+        frame.nine_slice = Some(NineSlice::new(1, 2, 3, 4));
+        frame.events = vec!["step".to_string()];
+        frame.split = Some(SplitPart::new("big", 16, 0, (64, 16)));
+        frame.baked_transform = Some(SpriteTransform::new(true, true, true));
+        frame.variant_of = Some("villain".to_string());
+        frame.draw_transform = Some(SpriteTransform::new(true, false, false));
+        frame.alias_of = Some("villain".to_string());
+        frame.delta = Some(FrameDelta::new(
+            2,
+            3,
+            (32, 32),
+            FrameDispose::Previous,
+            FrameBlend::Source,
+        ));
+        frame.max_mip = Some(2);
+        frame.tile = Some(5);
+        frame.trim = Some(Trim::new(1, 1, (18, 18)));
+        frame.polygon = Some(Polygon::new(
+            vec![(0.0, 0.0), (16.0, 0.0), (0.0, 16.0)],
+            vec![0, 1, 2],
+        ));
+        frame.filter = Some(FilterHint::Nearest);
+        frame.alpha = Some(AlphaUsage::Binary);
+        frame.motion_vectors = true;
+        frame.tone = Some(ToneAdjustment::new(1.0, 2.2));
+        frame.source_hash = Some("abc".to_string());
+        frame.source = Some("hero.png".into());
+        frame.provenance = Some(Provenance::new((18, 18), Some(1_700_000_000)));
+        let frame = frame.with_uvs((64, 64), 0.5);
+
+        let mut animation = Animation::new("walk", vec!["hero".to_string()]);
+        animation.durations_ms = vec![100];
+        animation.direction = PlaybackDirection::PingPong;
+        animation.repeat = 2;
+        animation.events = vec!["loop".to_string()];
+
+        let attribution = Attribution {
+            title: Some("Hero".to_string()),
+            author: Some("someone".to_string()),
+            license: Some("CC0".to_string()),
+            url: Some("https://example.com".to_string()),
+            notice: Some("as is".to_string()),
+        };
+
+        let mut font = FontFace::new("mono", 16.0, 12.0, -4.0, 18.0);
+        font.mode = GlyphMode::Sdf;
+        font.spread = Some(4);
+        font.glyphs = vec![
+            Glyph::new('A', Some("mono_u0041".to_string()), 9.0, (0.0, 12.0)),
+            Glyph::new(' ', None, 9.0, (0.0, 0.0)),
+        ];
+        font.kerning = vec![Kerning::new('A', 'V', -1.0)];
+
+        let mut atlas = TextureAtlas::new("atlas.ktx2", (64, 64));
+        atlas.frames = vec![frame];
+        atlas.atlas_hash = Some("def".to_string());
+        atlas.uv_inset = 0.5;
+        atlas.pages = vec!["atlas_0.png".into(), "atlas_1.png".into()];
+        atlas.animations = vec![animation];
+        atlas.content = Content::NormalMap;
+        atlas.color_space = ColorSpace::Linear;
+        atlas.premultiplied = true;
+        atlas.scale = 0.5;
+        atlas.mip_levels = 3;
+        atlas.volume = true;
+        atlas.motion_vectors = Some(MotionVectorTexture::new(
+            "atlas_mv.ktx2",
+            vec!["atlas_mv_0.png".into()],
+        ));
+        atlas.pixel_format = PixelFormat::Rgba16f;
+        atlas.page_channels = vec![PageChannels::Alpha, PageChannels::Luminance];
+        atlas.attributions = vec![AttributionGroup::new(attribution, vec!["hero".to_string()])];
+        atlas.fonts = vec![font];
+        atlas.sdf_spread = Some(4);
+
+        assert_eq!(problems(&atlas), Vec::<String>::new());
+
+        //
+        // and the check notices what the schema leaves out
+        let mut value = serde_json::to_value(&atlas).unwrap();
+        value["frames"][0]["unknown"] = json!(1);
+        value["fonts"][0]["glyphs"][0]
+            .as_object_mut()
+            .unwrap()
+            .remove("advance");
+        let schema = descriptor_schema();
+        let mut problems = Vec::new();
+        check(&schema, &schema, &value, "atlas", &mut problems);
+        assert_eq!(problems.len(), 2, "{:?}", problems);
+    }
+
+    #[test]
+    fn version_0_descriptors_validate_once_read() {
+        let text = format!(
+            "(frames: [(name: {}, layer: 0, x: 16, y: 0, width: 16, height: 32)], \
+             size: (64, 32), file: \"atlas.png\")",
+            region_name_hash("hero")
+        );
+        let atlas = TextureAtlas::parse(&text).unwrap();
+
+        assert_eq!(problems(&atlas), Vec::<String>::new());
+    }
+}
@@ -0,0 +1,59 @@
+//! Per run directory for temporary files (intermediate pages fed to the encoder).
+
+use crate::diagnostic::{self, Code};
+
+pub struct ScratchDir {
+    path: std::path::PathBuf,
+    keep: bool,
+}
+
+impl ScratchDir {
+    /// Creates a unique directory for this run under `parent`, or the system temp dir.
+    pub fn create(parent: Option<&std::path::Path>, keep: bool) -> Result<ScratchDir, String> {
+        let parent = parent
+            .map(|p| p.to_path_buf())
+            .unwrap_or_else(std::env::temp_dir);
+        //
+        // long running processes keep several alive at once
+        static NEXT: std::sync::atomic::AtomicU32 = std::sync::atomic::AtomicU32::new(0);
+        let path = parent.join(format!(
+            "texture-packer-{}-{}",
+            std::process::id(),
+            NEXT.fetch_add(1, std::sync::atomic::Ordering::Relaxed)
+        ));
+
+        std::fs::create_dir_all(&path).map_err(|e| {
+            format!(
+                "Failed to create scratch directory {}: {}",
+                path.display(),
+                e
+            )
+        })?;
+
+        Ok(ScratchDir { path, keep })
+    }
+
+    pub fn path(&self) -> &std::path::Path {
+        &self.path
+    }
+}
+
+impl Drop for ScratchDir {
+    fn drop(&mut self) {
+        if self.keep {
+            log::info!("Keeping temporary files in {}", self.path.display());
+            return;
+        }
+
+        if let Err(e) = std::fs::remove_dir_all(&self.path) {
+            diagnostic::warn(
+                Code::ScratchNotRemoved,
+                format_args!(
+                    "Failed to remove scratch directory {}: {}",
+                    self.path.display(),
+                    e
+                ),
+            );
+        }
+    }
+}
@@ -0,0 +1,81 @@
+//! Signed distance fields from coverage masks.
+
+use crate::source::{Pivot, SourceSprite};
+
+/// Converts a coverage mask (anything at or above half coverage is inside) into a
+/// signed distance field, padded by `spread` pixels on every side so the field can
+/// fall off outside the shape. 128 is on the outline, 255 `spread` pixels inside and
+/// 0 `spread` pixels outside.
+///
+/// Distances are searched within `spread` pixels only, which keeps this cheap for
+/// the small spreads fields are used with.
+pub fn distance_field(coverage: &image::GrayImage, spread: u32) -> image::GrayImage {
+    let (width, height) = coverage.dimensions();
+    let (out_width, out_height) = (width + 2 * spread, height + 2 * spread);
+    let pad = spread as i64;
+
+    let inside = |x: i64, y: i64| {
+        let (sx, sy) = (x - pad, y - pad);
+        sx >= 0
+            && sy >= 0
+            && sx < width as i64
+            && sy < height as i64
+            && coverage.get_pixel(sx as u32, sy as u32).0[0] >= 128
+    };
+
+    let reach = pad + 1;
+    image::GrayImage::from_fn(out_width, out_height, |x, y| {
+        let (x, y) = (x as i64, y as i64);
+        let here = inside(x, y);
+
+        let mut nearest = (reach * reach) as f32;
+        for dy in -reach..=reach {
+            for dx in -reach..=reach {
+                let d = (dx * dx + dy * dy) as f32;
+                if d < nearest && inside(x + dx, y + dy) != here {
+                    nearest = d;
+                }
+            }
+        }
+
+        //
+        // the outline runs between the pixel centers
+        let distance = (nearest.sqrt() - 0.5).min(spread as f32);
+        let signed = if here { distance } else { -distance };
+        image::Luma([(128.0 + signed / spread.max(1) as f32 * 127.0)
+            .round()
+            .clamp(0.0, 255.0) as u8])
+    })
+}
+
+/// Turns a monochrome sprite into a distance field, in alpha with the luminance at
+/// full white. The shape is taken from alpha, or from the luminance for fully opaque
+/// sprites (white on black). The sprite grows by `spread` on every side, its pivot and
+/// nine-slice insets are moved along.
+pub fn convert(sprite: &mut SourceSprite, spread: u32) {
+    let (width, height) = sprite.pixels.dimensions();
+    let channel = match sprite.pixels.pixels().all(|p| p.0[1] == 255) {
+        true => 0,
+        false => 1,
+    };
+    let coverage = image::GrayImage::from_fn(width, height, |x, y| {
+        image::Luma([sprite.pixels.get_pixel(x, y).0[channel]])
+    });
+
+    let field = distance_field(&coverage, spread);
+    sprite.pixels = image::ImageBuffer::from_fn(field.width(), field.height(), |x, y| {
+        image::LumaA([255, field.get_pixel(x, y).0[0]])
+    });
+
+    let Pivot(px, py) = sprite.pivot;
+    sprite.pivot = Pivot(
+        (px * width as f32 + spread as f32) / field.width() as f32,
+        (py * height as f32 + spread as f32) / field.height() as f32,
+    );
+    if let Some(insets) = sprite.nine_slice.as_mut() {
+        insets.left += spread;
+        insets.right += spread;
+        insets.top += spread;
+        insets.bottom += spread;
+    }
+}
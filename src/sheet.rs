@@ -0,0 +1,109 @@
+//! Slicing of existing spritesheets into individual sprites, configured by the `sheet`
+//! entry of the image's sidecar:
+//!
+//! ```ron
+//! (sheet: (cell: (32, 32), skip_empty: true))
+//! (sheet: (frames: [(key: "coin", x: 0, y: 0, width: 16, height: 16)]))
+//! (sheet: (atlas: "legacy.ron"))
+//! ```
+
+use crate::atlas::{SplitPart, TextureAtlas};
+use crate::grid::{self, GridSize};
+use crate::source::SourceSprite;
+
+#[derive(Clone, Default, serde::Deserialize)]
+#[serde(default)]
+pub struct SheetSlicing {
+    /// Uniform cells, sliced row by row and keyed `<name>_<index>`.
+    pub cell: Option<(u32, u32)>,
+    /// Explicit frame rects.
+    pub frames: Vec<SheetFrame>,
+    /// Frames of an existing texture-packer descriptor, relative to the sidecar.
+    pub atlas: Option<std::path::PathBuf>,
+    /// Layer of `atlas` the image holds.
+    pub layer: u32,
+    /// Drop cells without any visible pixel.
+    pub skip_empty: bool,
+}
+
+#[derive(Clone, serde::Deserialize)]
+pub struct SheetFrame {
+    pub key: String,
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+fn crop(sheet: &SourceSprite, frame: &SheetFrame) -> Result<SourceSprite, String> {
+    let (width, height) = sheet.pixels.dimensions();
+    if frame.width == 0
+        || frame.height == 0
+        || frame.x as u64 + frame.width as u64 > width as u64
+        || frame.y as u64 + frame.height as u64 > height as u64
+    {
+        return Err(format!(
+            "frame {} ({}, {}, {}x{}) is outside the {}x{} sheet",
+            frame.key, frame.x, frame.y, frame.width, frame.height, width, height
+        ));
+    }
+
+    Ok(SourceSprite {
+        key: frame.key.clone(),
+        split: Some(SplitPart::new(
+            sheet.key.clone(),
+            frame.x,
+            frame.y,
+            (width, height),
+        )),
+        ..sheet.crop(frame.x, frame.y, frame.width, frame.height)
+    })
+}
+
+/// Cuts a sheet into its frames.
+pub fn slice(sheet: SourceSprite, slicing: &SheetSlicing) -> Result<Vec<SourceSprite>, String> {
+    let mut frames = slicing.frames.clone();
+
+    if let Some(atlas) = slicing.atlas.as_ref() {
+        let path = sheet
+            .source
+            .parent()
+            .map(|dir| dir.join(atlas))
+            .unwrap_or_else(|| atlas.clone());
+        let atlas = TextureAtlas::load(&path)?;
+
+        frames.extend(
+            atlas
+                .frames
+                .iter()
+                .filter(|f| {
+                    f.layer == slicing.layer && f.variant_of.is_none() && f.alias_of.is_none()
+                })
+                .map(|f| SheetFrame {
+                    key: f.key.clone(),
+                    x: f.x,
+                    y: f.y,
+                    width: f.width,
+                    height: f.height,
+                }),
+        );
+    }
+
+    let mut sprites = frames
+        .iter()
+        .map(|frame| crop(&sheet, frame))
+        .collect::<Result<Vec<_>, String>>()?;
+
+    if let Some((width, height)) = slicing.cell {
+        if width == 0 || height == 0 {
+            return Err("sheet cells must not be empty".to_string());
+        }
+        sprites.extend(grid::slice(sheet, GridSize { width, height }));
+    }
+
+    if slicing.skip_empty {
+        sprites.retain(|s| s.pixels.pixels().any(|p| p.0[1] != 0));
+    }
+
+    Ok(sprites)
+}
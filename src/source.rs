@@ -0,0 +1,1062 @@
+//! Loading of the input images into sprites.
+
+use crate::animated::{self, FrameMode};
+use crate::aseprite;
+use crate::atlas::{
+    AlphaUsage, Animation, ColorSpace, Content, FilterHint, FrameDelta, NineSlice, PixelFormat,
+    SplitPart, SpriteTransform, ToneAdjustment, Trim,
+};
+use crate::color::{self, ColorKey};
+use crate::diagnostic::{self, Code};
+use crate::ktx2;
+use crate::meta::SpriteVariant;
+use crate::nine_slice;
+use crate::svg::{self, SvgOptions};
+use std::io::Read;
+
+/// An input folder, optionally prefixed with a tag (`ui:assets/ui`).
+#[derive(Clone, Debug)]
+pub struct InputFolder {
+    pub tag: Option<String>,
+    pub path: std::path::PathBuf,
+}
+
+impl std::str::FromStr for InputFolder {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            //
+            // a single letter followed by a separator is a windows drive, not a tag
+            Some((tag, rest))
+                if !tag.is_empty()
+                    && (tag.len() > 1 || !rest.starts_with(['/', '\\']))
+                    && !tag.contains(['/', '\\']) =>
+            {
+                if rest.is_empty() {
+                    return Err(format!("missing folder path after tag '{}'", tag));
+                }
+
+                Ok(InputFolder {
+                    tag: Some(tag.to_string()),
+                    path: rest.into(),
+                })
+            }
+            _ => Ok(InputFolder {
+                tag: None,
+                path: s.into(),
+            }),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct SourceSprite {
+    pub source: std::path::PathBuf,
+    /// Frame index for sources holding several frames.
+    pub frame: Option<u32>,
+    pub key: String,
+    pub pixels: image::ImageBuffer<image::LumaA<u8>, Vec<u8>>,
+    pub tag: Option<String>,
+    pub nine_slice: Option<NineSlice>,
+    pub events: Vec<String>,
+    pub pivot: Pivot,
+    pub split: Option<SplitPart>,
+    pub baked_transform: Option<SpriteTransform>,
+    pub variants: Vec<SpriteVariant>,
+    pub delta: Option<FrameDelta>,
+    pub max_mip: Option<u32>,
+    /// Whether the source has color, before the conversion to luminance.
+    pub colored: bool,
+    pub trim: Option<Trim>,
+    pub filter: Option<FilterHint>,
+    pub alpha: Option<AlphaUsage>,
+    /// Motion vectors of a flipbook frame, aligned with `pixels`.
+    pub motion: Option<image::ImageBuffer<image::LumaA<u8>, Vec<u8>>>,
+    /// Full precision RGBA for the pixel formats other than `La8`, aligned with `pixels`.
+    pub hdr: Option<image::Rgba32FImage>,
+    /// Exposure and gamma applied to the colors.
+    pub tone: Option<ToneAdjustment>,
+    /// Size of the image or frame in the source file, before it was trimmed, scaled or
+    /// cut up.
+    pub source_size: (u32, u32),
+}
+
+impl SourceSprite {
+    /// A part of the sprite, with everything aligned with its pixels cropped alike.
+    pub fn crop(&self, x: u32, y: u32, width: u32, height: u32) -> SourceSprite {
+        SourceSprite {
+            source: self.source.clone(),
+            frame: self.frame,
+            key: self.key.clone(),
+            pixels: image::imageops::crop_imm(&self.pixels, x, y, width, height).to_image(),
+            tag: self.tag.clone(),
+            nine_slice: self.nine_slice,
+            events: self.events.clone(),
+            pivot: self.pivot,
+            split: self.split.clone(),
+            baked_transform: self.baked_transform,
+            variants: self.variants.clone(),
+            delta: self.delta.clone(),
+            max_mip: self.max_mip,
+            colored: self.colored,
+            trim: self.trim,
+            filter: self.filter,
+            alpha: self.alpha,
+            motion: self
+                .motion
+                .as_ref()
+                .map(|m| image::imageops::crop_imm(m, x, y, width, height).to_image()),
+            hdr: self
+                .hdr
+                .as_ref()
+                .map(|h| image::imageops::crop_imm(h, x, y, width, height).to_image()),
+            tone: self.tone,
+            source_size: self.source_size,
+        }
+    }
+
+    /// Resamples the planes aligned with the pixels to a new size, the pixels are left
+    /// to the caller.
+    pub fn resize_aligned(&mut self, width: u32, height: u32, filter: ResizeFilter) {
+        let filter = filter.filter_type();
+        self.motion = self
+            .motion
+            .as_ref()
+            .map(|m| image::imageops::resize(m, width, height, filter));
+        self.hdr = self
+            .hdr
+            .as_ref()
+            .map(|h| image::imageops::resize(h, width, height, filter));
+    }
+
+    /// Bakes a transform into the pixels and the planes aligned with them.
+    pub fn transform(&mut self, transform: SpriteTransform) {
+        self.reorient(transform);
+        self.baked_transform = Some(transform);
+    }
+
+    /// Turns the pixels and the planes aligned with them, without recording it: for
+    /// sources stored in another orientation than they are meant to be seen in.
+    pub fn reorient(&mut self, transform: SpriteTransform) {
+        self.pixels = apply_transform(&self.pixels, transform);
+        self.motion = self.motion.as_ref().map(|m| transform_motion(m, transform));
+        self.hdr = self.hdr.as_ref().map(|h| apply_transform(h, transform));
+    }
+
+    /// Applies exposure and gamma to the colors in linear light. The pixels are encoded
+    /// in `color_space`, the full precision plane is linear already.
+    pub fn adjust_tone(&mut self, tone: ToneAdjustment, color_space: ColorSpace) {
+        let scale = tone.exposure.exp2();
+        let adjust = |c: f32| (c * scale).max(0.0).powf(1.0 / tone.gamma);
+
+        for p in self.pixels.pixels_mut() {
+            p.0[0] = color::encode(adjust(color::decode(p.0[0], color_space)), color_space);
+        }
+        if let Some(hdr) = self.hdr.as_mut() {
+            for p in hdr.pixels_mut() {
+                for c in p.0[..3].iter_mut() {
+                    *c = adjust(*c);
+                }
+            }
+        }
+        self.tone = Some(tone);
+    }
+
+    pub fn label(&self) -> String {
+        match self.frame {
+            Some(frame) => format!("{}#{}", self.source.display(), frame),
+            None => self.source.display().to_string(),
+        }
+    }
+}
+
+/// Sprites and animations loaded from one input file.
+#[derive(Clone, Default)]
+pub struct LoadedSource {
+    pub sprites: Vec<SourceSprite>,
+    pub animations: Vec<Animation>,
+}
+
+impl LoadedSource {
+    /// Names the sprites and animations loaded from `path` after `key` in place of the
+    /// file stem, frames of animated sources keep their number.
+    pub fn rename(&mut self, path: &std::path::Path, key: &str) {
+        //
+        // the key of a `.9.png` has no `.9`
+        let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+        let stem = stem.strip_suffix(".9").unwrap_or(&stem);
+        let rename = |name: &mut String| {
+            if let Some(rest) = name.strip_prefix(stem) {
+                *name = format!("{}{}", key, rest);
+            }
+        };
+
+        for sprite in self.sprites.iter_mut() {
+            rename(&mut sprite.key);
+        }
+        for animation in self.animations.iter_mut() {
+            rename(&mut animation.name);
+            animation.frames.iter_mut().for_each(rename);
+        }
+    }
+
+    /// Puts `prefix/` before the names of the sprites and animations.
+    pub fn prefix(&mut self, prefix: &str) {
+        let prefix = |name: &mut String| *name = format!("{}/{}", prefix, name);
+        for sprite in self.sprites.iter_mut() {
+            prefix(&mut sprite.key);
+        }
+        for animation in self.animations.iter_mut() {
+            prefix(&mut animation.name);
+            animation.frames.iter_mut().for_each(prefix);
+        }
+    }
+}
+
+/// How sprites are resampled when they are scaled.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum ResizeFilter {
+    /// The closest source pixel, keeps pixel art crisp.
+    Nearest,
+    Bilinear,
+    CatmullRom,
+    /// The sharpest, with some ringing at hard edges.
+    Lanczos3,
+}
+
+impl ResizeFilter {
+    /// `chosen`, or the default for scaling by `scale`: nearest for integer factors
+    /// (2x, 1/3x), which keep pixel art on its grid, Lanczos3 for the others.
+    pub fn for_scale(chosen: Option<ResizeFilter>, scale: f32) -> ResizeFilter {
+        let factor = match scale >= 1.0 {
+            true => scale,
+            false => 1.0 / scale,
+        };
+        match chosen {
+            Some(filter) => filter,
+            None if (factor - factor.round()).abs() < 1e-3 => ResizeFilter::Nearest,
+            None => ResizeFilter::Lanczos3,
+        }
+    }
+
+    pub fn filter_type(self) -> image::imageops::FilterType {
+        match self {
+            ResizeFilter::Nearest => image::imageops::FilterType::Nearest,
+            ResizeFilter::Bilinear => image::imageops::FilterType::Triangle,
+            ResizeFilter::CatmullRom => image::imageops::FilterType::CatmullRom,
+            ResizeFilter::Lanczos3 => image::imageops::FilterType::Lanczos3,
+        }
+    }
+}
+
+/// How input files are turned into sprites.
+#[derive(Copy, Clone, Debug)]
+pub struct LoadOptions {
+    pub frame_mode: FrameMode,
+    pub content: Content,
+    pub color_key: Option<ColorKey>,
+    pub aseprite_layers: aseprite::LayerMode,
+    pub pixel_format: PixelFormat,
+    pub svg: SvgOptions,
+}
+
+/// Converts decoded pixels to the two channels stored in the pages.
+fn to_pixels(
+    img: &image::DynamicImage,
+    options: LoadOptions,
+) -> image::ImageBuffer<image::LumaA<u8>, Vec<u8>> {
+    match options.content {
+        Content::Color => match options.color_key {
+            Some(key) => {
+                let mut rgba = img.to_rgba8();
+                key.apply(&mut rgba);
+                image::DynamicImage::ImageRgba8(rgba).to_luma_alpha8()
+            }
+            None => img.to_luma_alpha8(),
+        },
+        //
+        // keep X and Y as they are, a luma conversion would mix them
+        Content::NormalMap | Content::MotionVectors => {
+            let rgb = img.to_rgb8();
+            image::ImageBuffer::from_fn(rgb.width(), rgb.height(), |x, y| {
+                let p = rgb.get_pixel(x, y).0;
+                image::LumaA([p[0], p[1]])
+            })
+        }
+    }
+}
+
+/// The full precision plane for the pixel formats that need one. Pixels made
+/// transparent by a color key are transparent in it too.
+fn hdr_plane(
+    img: &image::DynamicImage,
+    pixels: &image::ImageBuffer<image::LumaA<u8>, Vec<u8>>,
+    options: LoadOptions,
+) -> Option<image::Rgba32FImage> {
+    if options.pixel_format.is_la8() {
+        return None;
+    }
+
+    let mut hdr = img.to_rgba32f();
+    if options.color_key.is_some() {
+        for (p, keyed) in hdr.pixels_mut().zip(pixels.pixels()) {
+            if keyed.0[1] == 0 {
+                p.0[3] = 0.0;
+            }
+        }
+    }
+    Some(hdr)
+}
+
+fn is_colored(img: &image::DynamicImage) -> bool {
+    img.color().has_color()
+        && img
+            .to_rgba8()
+            .pixels()
+            .any(|p| p.0[3] != 0 && (p.0[0] != p.0[1] || p.0[1] != p.0[2]))
+}
+
+fn load_image(
+    path: &std::path::Path,
+    header: &[u8],
+    tag: Option<String>,
+    options: LoadOptions,
+) -> Result<LoadedSource, String> {
+    let img = if svg::is_svg(path, header) {
+        svg::rasterize(path, options.svg).map(image::DynamicImage::ImageRgba8)
+    } else if ktx2::is_ktx2(header) {
+        //
+        // the base level of the first layer, block compressed ones need the transcode
+        // feature
+        ktx2::Ktx2::open(path)
+            .and_then(|ktx2| ktx2.decode(0, 0))
+            .map(image::DynamicImage::ImageRgba8)
+    } else {
+        //
+        // by the first bytes, the extension only for formats without a signature (TGA)
+        image::io::Reader::open(path)
+            .and_then(|reader| reader.with_guessed_format())
+            .map_err(|e| e.to_string())
+            .and_then(|reader| match reader.format() {
+                Some(_) => reader.decode().map_err(|e| e.to_string()),
+                None => Err("not an image format the packer reads".to_string()),
+            })
+    };
+    let img = img.map_err(|e| format!("Failed to open image {}: {}", path.display(), e))?;
+    let mut key = path.file_stem().unwrap().to_string_lossy().to_string();
+    let source_size = (img.width(), img.height());
+
+    let (img, nine_slice) = if nine_slice::is_nine_patch(path) {
+        key.truncate(key.len() - ".9".len());
+
+        match nine_slice::split_nine_patch(&img.to_rgba8()) {
+            Some((inner, insets)) => (image::DynamicImage::ImageRgba8(inner), Some(insets)),
+            None => {
+                diagnostic::warn(
+                    Code::NinePatchTooSmall,
+                    format_args!(
+                        "Nine-patch image {} is too small for a marker border",
+                        path.display()
+                    ),
+                );
+                (img, None)
+            }
+        }
+    } else {
+        (img, None)
+    };
+
+    let pixels = to_pixels(&img, options);
+
+    Ok(LoadedSource {
+        sprites: vec![SourceSprite {
+            source: path.to_path_buf(),
+            frame: None,
+            key,
+            hdr: hdr_plane(&img, &pixels, options),
+            tone: None,
+            source_size,
+            pixels,
+            tag,
+            nine_slice,
+            events: Vec::new(),
+            pivot: Pivot::CENTER,
+            split: None,
+            baked_transform: None,
+            variants: Vec::new(),
+            delta: None,
+            max_mip: None,
+            colored: is_colored(&img),
+            trim: None,
+            filter: None,
+            alpha: None,
+            motion: None,
+        }],
+        animations: Vec::new(),
+    })
+}
+
+fn load_aseprite(
+    path: &std::path::Path,
+    tag: Option<String>,
+    options: LoadOptions,
+) -> Result<LoadedSource, String> {
+    let ase = aseprite::load(path, options.aseprite_layers)
+        .map_err(|e| format!("Failed to open aseprite file {}: {}", path.display(), e))?;
+
+    let stem = path.file_stem().unwrap().to_string_lossy().to_string();
+    let frame_count = ase.frames.len();
+
+    //
+    // `(key prefix, frames as (index, image, events))`, one group for the merged
+    // image or one per visible layer
+    let groups = match options.aseprite_layers {
+        aseprite::LayerMode::Merge => vec![(
+            stem.clone(),
+            ase.frames
+                .iter()
+                .enumerate()
+                .map(|(idx, f)| (idx, &f.image, f.user_data.clone()))
+                .collect::<Vec<_>>(),
+        )],
+        aseprite::LayerMode::Split => ase
+            .layers
+            .iter()
+            .enumerate()
+            .filter(|(_, layer)| layer.visible)
+            .map(|(layer_idx, layer)| {
+                (
+                    format!("{}_{}", stem, layer.name),
+                    ase.frames
+                        .iter()
+                        .enumerate()
+                        .filter_map(|(idx, f)| {
+                            f.layers
+                                .iter()
+                                .find(|l| l.layer == layer_idx)
+                                .map(|l| (idx, &l.image, l.user_data.iter().cloned().collect()))
+                        })
+                        .collect::<Vec<_>>(),
+                )
+            })
+            .filter(|(_, frames)| !frames.is_empty())
+            .collect(),
+    };
+
+    let mut sprites = Vec::new();
+    let mut animations = Vec::new();
+
+    for (prefix, frames) in groups.iter() {
+        let frame_key = |idx: usize| match frame_count {
+            1 => prefix.clone(),
+            _ => format!("{}_{}", prefix, idx),
+        };
+        let has_frame = |idx: u32| frames.iter().any(|(f, _, _)| *f == idx as usize);
+
+        let animation = |name: String, range: Vec<u32>| {
+            let mut animation =
+                Animation::new(name, range.iter().map(|&f| frame_key(f as usize)).collect());
+            animation.durations_ms = range
+                .iter()
+                .map(|&f| ase.frames[f as usize].duration_ms)
+                .collect();
+            animation
+        };
+
+        animations.extend(ase.tags.iter().map(|t| {
+            let mut animation = animation(
+                format!("{}_{}", prefix, t.name),
+                (t.from..=t.to).filter(|&f| has_frame(f)).collect(),
+            );
+            animation.direction = t.direction;
+            animation.repeat = t.repeat as u32;
+            animation.events = t
+                .user_data
+                .iter()
+                .filter(|s| !s.is_empty())
+                .cloned()
+                .collect();
+            animation
+        }));
+
+        //
+        // without tags the whole file plays as one animation
+        if ase.tags.is_empty() && frames.len() > 1 {
+            animations.push(animation(
+                prefix.clone(),
+                frames.iter().map(|(f, _, _)| *f as u32).collect(),
+            ));
+        }
+
+        sprites.extend(frames.iter().map(|(idx, image, events)| {
+            let img = image::DynamicImage::ImageRgba8((*image).clone());
+            let pixels = to_pixels(&img, options);
+            SourceSprite {
+                source: path.to_path_buf(),
+                frame: Some(*idx as u32),
+                key: frame_key(*idx),
+                hdr: hdr_plane(&img, &pixels, options),
+                tone: None,
+                source_size: (img.width(), img.height()),
+                pixels,
+                colored: is_colored(&img),
+                trim: None,
+                filter: None,
+                alpha: None,
+                motion: None,
+                tag: tag.clone(),
+                nine_slice: None,
+                events: events.clone(),
+                pivot: Pivot::CENTER,
+                split: None,
+                baked_transform: None,
+                variants: Vec::new(),
+                delta: None,
+                max_mip: None,
+            }
+        }));
+    }
+
+    Ok(LoadedSource {
+        sprites,
+        animations,
+    })
+}
+
+fn load_animated(
+    path: &std::path::Path,
+    tag: Option<String>,
+    options: LoadOptions,
+) -> Result<LoadedSource, String> {
+    let frames = animated::load(path, options.frame_mode)
+        .map_err(|e| format!("Failed to decode animation {}: {}", path.display(), e))?;
+
+    let stem = path.file_stem().unwrap().to_string_lossy().to_string();
+    let frame_count = frames.len();
+
+    let delays = frames.iter().map(|f| f.delay_ms).collect::<Vec<_>>();
+    let sprites = frames
+        .into_iter()
+        .enumerate()
+        .map(|(idx, f)| {
+            let img = image::DynamicImage::ImageRgba8(f.image);
+            let pixels = to_pixels(&img, options);
+            SourceSprite {
+                source: path.to_path_buf(),
+                frame: Some(idx as u32),
+                key: if frame_count == 1 {
+                    stem.clone()
+                } else {
+                    format!("{}_{}", stem, idx)
+                },
+                hdr: hdr_plane(&img, &pixels, options),
+                tone: None,
+                source_size: (img.width(), img.height()),
+                pixels,
+                colored: is_colored(&img),
+                trim: None,
+                filter: None,
+                alpha: None,
+                motion: None,
+                tag: tag.clone(),
+                nine_slice: None,
+                events: Vec::new(),
+                pivot: Pivot::CENTER,
+                split: None,
+                baked_transform: None,
+                variants: Vec::new(),
+                delta: f.delta,
+                max_mip: None,
+            }
+        })
+        .collect();
+
+    //
+    // the whole file plays as one animation
+    let animations = match frame_count {
+        1 => Vec::new(),
+        _ => {
+            let mut animation = Animation::new(
+                stem.clone(),
+                (0..frame_count)
+                    .map(|idx| format!("{}_{}", stem, idx))
+                    .collect(),
+            );
+            animation.durations_ms = delays;
+            vec![animation]
+        }
+    };
+
+    Ok(LoadedSource {
+        sprites,
+        animations,
+    })
+}
+
+/// Normalized pivot point of a sprite, `(0, 0)` is the top left corner.
+#[derive(Copy, Clone, Debug)]
+pub struct Pivot(pub f32, pub f32);
+
+impl Pivot {
+    pub const CENTER: Pivot = Pivot(0.5, 0.5);
+}
+
+impl std::str::FromStr for Pivot {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let named = match s {
+            "top-left" => Some((0.0, 0.0)),
+            "top-center" | "top" => Some((0.5, 0.0)),
+            "top-right" => Some((1.0, 0.0)),
+            "center-left" | "left" => Some((0.0, 0.5)),
+            "center" => Some((0.5, 0.5)),
+            "center-right" | "right" => Some((1.0, 0.5)),
+            "bottom-left" => Some((0.0, 1.0)),
+            "bottom-center" | "bottom" => Some((0.5, 1.0)),
+            "bottom-right" => Some((1.0, 1.0)),
+            _ => None,
+        };
+
+        if let Some((x, y)) = named {
+            return Ok(Pivot(x, y));
+        }
+
+        s.split_once(',')
+            .and_then(|(x, y)| Some(Pivot(x.trim().parse().ok()?, y.trim().parse().ok()?)))
+            .ok_or_else(|| format!("invalid pivot '{}', expected a preset or 'x,y'", s))
+    }
+}
+
+pub fn apply_transform<P: image::Pixel + 'static>(
+    pixels: &image::ImageBuffer<P, Vec<P::Subpixel>>,
+    transform: SpriteTransform,
+) -> image::ImageBuffer<P, Vec<P::Subpixel>> {
+    let mut out = pixels.clone();
+    if transform.flip_x {
+        image::imageops::flip_horizontal_in_place(&mut out);
+    }
+    if transform.flip_y {
+        image::imageops::flip_vertical_in_place(&mut out);
+    }
+    if transform.rotate90 {
+        out = image::imageops::rotate90(&out);
+    }
+    out
+}
+
+/// Applies a transform to motion vectors, turning the vectors along with the pixels.
+fn transform_motion(
+    motion: &image::ImageBuffer<image::LumaA<u8>, Vec<u8>>,
+    transform: SpriteTransform,
+) -> image::ImageBuffer<image::LumaA<u8>, Vec<u8>> {
+    let mut out = apply_transform(motion, transform);
+    for p in out.pixels_mut() {
+        let [mut x, mut y] = p.0;
+        if transform.flip_x {
+            x = 255 - x;
+        }
+        if transform.flip_y {
+            y = 255 - y;
+        }
+        //
+        // clockwise, (x, y) becomes (-y, x)
+        if transform.rotate90 {
+            (x, y) = (255 - y, x);
+        }
+        p.0 = [x, y];
+    }
+    out
+}
+
+/// Width and height of the one sprite `path` loads as, read from the image header
+/// without decoding the pixels. None for the sources only sized once decoded:
+/// animations, Aseprite files, SVGs, KTX2 textures and nine-patches.
+pub fn probe_dimensions(path: &std::path::Path) -> Option<(u32, u32)> {
+    let header = read_header(path).ok()?;
+    if aseprite::is_aseprite(&header)
+        || ktx2::is_ktx2(&header)
+        || animated::is_animated(path)
+        || svg::is_svg(path, &header)
+        || nine_slice::is_nine_patch(path)
+    {
+        return None;
+    }
+
+    image::io::Reader::open(path)
+        .ok()?
+        .with_guessed_format()
+        .ok()?
+        .into_dimensions()
+        .ok()
+}
+
+/// Bytes read from the start of a file to tell its format.
+const HEADER_LEN: u64 = 512;
+
+fn read_header(path: &std::path::Path) -> Result<Vec<u8>, String> {
+    let mut header = Vec::new();
+    std::fs::File::open(path)
+        .and_then(|file| file.take(HEADER_LEN).read_to_end(&mut header))
+        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    Ok(header)
+}
+
+/// Decodes a source file, or tells why it could not be. The format is told by the
+/// first bytes, whatever the extension says.
+pub fn load_source(
+    path: &std::path::Path,
+    tag: Option<String>,
+    options: LoadOptions,
+) -> Result<LoadedSource, String> {
+    let header = read_header(path)?;
+    let loaded = if aseprite::is_aseprite(&header) {
+        load_aseprite(path, tag, options)
+    } else if animated::is_animated(path) {
+        load_animated(path, tag, options)
+    } else {
+        load_image(path, &header, tag, options)
+    };
+    if loaded.is_ok() {
+        log::debug!("Loaded {}", path.display());
+    }
+    loaded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::atlas::{FrameBlend, FrameDispose, PlaybackDirection};
+
+    fn parse(s: &str) -> InputFolder {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn plain_path_has_no_tag() {
+        let folder = parse("assets/ui");
+        assert_eq!(folder.tag, None);
+        assert_eq!(folder.path, std::path::Path::new("assets/ui"));
+    }
+
+    #[test]
+    fn tag_before_the_path() {
+        let folder = parse("ui:assets/ui");
+        assert_eq!(folder.tag.as_deref(), Some("ui"));
+        assert_eq!(folder.path, std::path::Path::new("assets/ui"));
+    }
+
+    #[test]
+    fn empty_tag_is_part_of_the_path() {
+        let folder = parse(":assets");
+        assert_eq!(folder.tag, None);
+        assert_eq!(folder.path, std::path::Path::new(":assets"));
+    }
+
+    #[test]
+    fn tag_without_a_path_fails() {
+        assert!("ui:".parse::<InputFolder>().is_err());
+    }
+
+    #[test]
+    fn separator_inside_the_path_is_no_tag() {
+        let folder = parse("assets/ui:old");
+        assert_eq!(folder.tag, None);
+        assert_eq!(folder.path, std::path::Path::new("assets/ui:old"));
+
+        let drive = parse("C:\\art\\ui");
+        assert_eq!(drive.tag, None);
+        assert_eq!(drive.path, std::path::Path::new("C:\\art\\ui"));
+
+        let tagged_drive = parse("ui:C:\\art\\ui");
+        assert_eq!(tagged_drive.tag.as_deref(), Some("ui"));
+        assert_eq!(tagged_drive.path, std::path::Path::new("C:\\art\\ui"));
+    }
+
+    fn options(aseprite_layers: aseprite::LayerMode) -> LoadOptions {
+        LoadOptions {
+            frame_mode: FrameMode::Composite,
+            content: Content::Color,
+            color_key: None,
+            aseprite_layers,
+            pixel_format: PixelFormat::default(),
+            svg: SvgOptions {
+                dpi: 96.0,
+                scale: 1.0,
+            },
+        }
+    }
+
+    fn ase_string(s: &str) -> Vec<u8> {
+        let mut out = (s.len() as u16).to_le_bytes().to_vec();
+        out.extend(s.as_bytes());
+        out
+    }
+
+    fn ase_chunk(kind: u16, data: Vec<u8>) -> Vec<u8> {
+        let mut out = ((data.len() + 6) as u32).to_le_bytes().to_vec();
+        out.extend(kind.to_le_bytes());
+        out.extend(data);
+        out
+    }
+
+    fn ase_layer(name: &str, visible: bool) -> Vec<Vec<u8>> {
+        let mut data = Vec::new();
+        data.extend((visible as u16).to_le_bytes()); // flags
+        data.extend([0u8; 2 + 2 + 2 + 2 + 2]); // type, child level, size, blend mode
+        data.extend([255, 0, 0, 0]); // opacity
+        data.extend(ase_string(name));
+        vec![ase_chunk(0x2004, data)]
+    }
+
+    /// An opaque 1x1 cel at `(x, y)`, followed by its user data when there is some.
+    fn ase_cel(layer: u16, (x, y): (i16, i16), user_data: Option<&str>) -> Vec<Vec<u8>> {
+        let mut data = Vec::new();
+        data.extend(layer.to_le_bytes());
+        data.extend(x.to_le_bytes());
+        data.extend(y.to_le_bytes());
+        data.push(255); // opacity
+        data.extend([0u8; 2 + 2 + 5]); // raw cel, z-index
+        data.extend([1, 0, 1, 0, 200, 100, 50, 255]); // 1x1 pixels
+        let mut chunks = vec![ase_chunk(0x2005, data)];
+        chunks.extend(user_data.map(ase_user_data));
+        chunks
+    }
+
+    fn ase_user_data(text: &str) -> Vec<u8> {
+        let mut data = 1u32.to_le_bytes().to_vec();
+        data.extend(ase_string(text));
+        ase_chunk(0x2020, data)
+    }
+
+    /// `(name, from, to, direction, repeat, user data)`, the tags chunk followed by the
+    /// user data of every tag.
+    fn ase_tags(tags: &[(&str, u16, u16, u8, u16, &str)]) -> Vec<Vec<u8>> {
+        let mut data = (tags.len() as u16).to_le_bytes().to_vec();
+        data.extend([0u8; 8]);
+        for (name, from, to, direction, repeat, _) in tags.iter() {
+            data.extend(from.to_le_bytes());
+            data.extend(to.to_le_bytes());
+            data.push(*direction);
+            data.extend(repeat.to_le_bytes());
+            data.extend([0u8; 6 + 3 + 1]);
+            data.extend(ase_string(name));
+        }
+        let mut chunks = vec![ase_chunk(0x2018, data)];
+        chunks.extend(tags.iter().map(|(.., user_data)| ase_user_data(user_data)));
+        chunks
+    }
+
+    /// A 32 bit 2x2 file of frames `(duration, chunks)`.
+    fn ase_file(frames: &[(u16, Vec<Vec<u8>>)]) -> Vec<u8> {
+        let mut out = vec![0u8; 4];
+        out.extend(0xA5E0u16.to_le_bytes());
+        out.extend((frames.len() as u16).to_le_bytes());
+        out.extend([2, 0, 2, 0, 32, 0]); // width, height, color depth
+        out.resize(128, 0);
+
+        for (duration, chunks) in frames.iter() {
+            let data = chunks.concat();
+            out.extend((16 + data.len() as u32).to_le_bytes());
+            out.extend(0xF1FAu16.to_le_bytes());
+            out.extend(0xFFFFu16.to_le_bytes());
+            out.extend(duration.to_le_bytes());
+            out.extend([0u8; 2]);
+            out.extend((chunks.len() as u32).to_le_bytes());
+            out.extend(data);
+        }
+        let size = out.len() as u32;
+        out[..4].copy_from_slice(&size.to_le_bytes());
+        out
+    }
+
+    /// `hero.aseprite`: a body in the top left corner of every frame, a hat in the
+    /// bottom right of frames 1 and 2, and a hidden layer in frame 0. Event "step" is
+    /// on the body of frame 0, "walk" plays all frames, "jump" the last two.
+    fn hero(dir: &std::path::Path) -> std::path::PathBuf {
+        let data = ase_file(&[
+            (
+                100,
+                [
+                    ase_layer("body", true),
+                    ase_layer("hat", true),
+                    ase_layer("ghost", false),
+                    ase_tags(&[("walk", 0, 2, 2, 3, "loop"), ("jump", 1, 2, 0, 0, "")]),
+                    ase_cel(0, (0, 0), Some("step")),
+                    ase_cel(2, (0, 1), None),
+                ]
+                .concat(),
+            ),
+            (
+                150,
+                [ase_cel(0, (0, 0), None), ase_cel(1, (1, 1), None)].concat(),
+            ),
+            (
+                200,
+                [ase_cel(0, (0, 0), None), ase_cel(1, (1, 1), None)].concat(),
+            ),
+        ]);
+
+        std::fs::create_dir_all(dir).unwrap();
+        let path = dir.join("hero.aseprite");
+        std::fs::write(&path, data).unwrap();
+        path
+    }
+
+    fn keys(loaded: &LoadedSource) -> Vec<&str> {
+        loaded.sprites.iter().map(|s| s.key.as_str()).collect()
+    }
+
+    fn animation<'a>(loaded: &'a LoadedSource, name: &str) -> &'a Animation {
+        loaded
+            .animations
+            .iter()
+            .find(|a| a.name == name)
+            .unwrap_or_else(|| panic!("no animation {}", name))
+    }
+
+    #[test]
+    fn aseprite_layers_are_merged() {
+        let dir = std::env::temp_dir().join(format!("tp-ase-merge-{}", std::process::id()));
+        let loaded = load_source(&hero(&dir), None, options(aseprite::LayerMode::Merge)).unwrap();
+
+        assert_eq!(keys(&loaded), ["hero_0", "hero_1", "hero_2"]);
+        let alpha = |frame: usize, x, y| loaded.sprites[frame].pixels.get_pixel(x, y).0[1];
+        assert_eq!(
+            [alpha(0, 0, 0), alpha(0, 1, 1), alpha(0, 0, 1)],
+            [255, 0, 0]
+        );
+        assert_eq!([alpha(1, 0, 0), alpha(1, 1, 1)], [255, 255]);
+        assert_eq!(loaded.sprites[0].events, ["step"]);
+        assert!(loaded.sprites[1].events.is_empty());
+
+        let walk = animation(&loaded, "hero_walk");
+        assert_eq!(walk.frames, ["hero_0", "hero_1", "hero_2"]);
+        assert_eq!(walk.durations_ms, [100, 150, 200]);
+        assert_eq!(walk.direction, PlaybackDirection::PingPong);
+        assert_eq!(walk.repeat, 3);
+        assert_eq!(walk.events, ["loop"]);
+
+        let jump = animation(&loaded, "hero_jump");
+        assert_eq!(jump.frames, ["hero_1", "hero_2"]);
+        assert_eq!(jump.durations_ms, [150, 200]);
+        assert!(jump.events.is_empty());
+        assert_eq!(loaded.animations.len(), 2);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn aseprite_layers_are_split() {
+        let dir = std::env::temp_dir().join(format!("tp-ase-split-{}", std::process::id()));
+        let loaded = load_source(&hero(&dir), None, options(aseprite::LayerMode::Split)).unwrap();
+
+        //
+        // the hidden layer is left out, the hat only has the frames it is drawn in
+        assert_eq!(
+            keys(&loaded),
+            [
+                "hero_body_0",
+                "hero_body_1",
+                "hero_body_2",
+                "hero_hat_1",
+                "hero_hat_2"
+            ]
+        );
+        let hat = &loaded.sprites[3].pixels;
+        assert_eq!(
+            [hat.get_pixel(0, 0).0[1], hat.get_pixel(1, 1).0[1]],
+            [0, 255]
+        );
+        assert_eq!(loaded.sprites[0].events, ["step"]);
+
+        let walk = animation(&loaded, "hero_hat_walk");
+        assert_eq!(walk.frames, ["hero_hat_1", "hero_hat_2"]);
+        assert_eq!(walk.durations_ms, [150, 200]);
+        assert_eq!(walk.events, ["loop"]);
+        assert_eq!(
+            animation(&loaded, "hero_body_walk").frames,
+            ["hero_body_0", "hero_body_1", "hero_body_2"]
+        );
+        assert_eq!(
+            animation(&loaded, "hero_hat_jump").frames,
+            ["hero_hat_1", "hero_hat_2"]
+        );
+        assert_eq!(loaded.animations.len(), 4);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// `walk.gif`: a 2x2 red frame shown for 100ms, then a green pixel in the bottom
+    /// right corner for 250ms.
+    fn walk(dir: &std::path::Path) -> std::path::PathBuf {
+        let mut data = Vec::new();
+        {
+            let mut encoder = gif::Encoder::new(&mut data, 2, 2, &[]).unwrap();
+            let mut first = gif::Frame::from_rgba(2, 2, &mut [255, 0, 0, 255].repeat(4));
+            first.delay = 10;
+            first.dispose = gif::DisposalMethod::Keep;
+            encoder.write_frame(&first).unwrap();
+
+            let mut second = gif::Frame::from_rgba(1, 1, &mut [0, 255, 0, 255]);
+            (second.left, second.top, second.delay) = (1, 1, 25);
+            second.dispose = gif::DisposalMethod::Background;
+            encoder.write_frame(&second).unwrap();
+        }
+
+        std::fs::create_dir_all(dir).unwrap();
+        let path = dir.join("walk.gif");
+        std::fs::write(&path, data).unwrap();
+        path
+    }
+
+    #[test]
+    fn gif_frames_are_composited() {
+        let dir = std::env::temp_dir().join(format!("tp-gif-composite-{}", std::process::id()));
+        let loaded = load_source(&walk(&dir), None, options(aseprite::LayerMode::Merge)).unwrap();
+
+        assert_eq!(keys(&loaded), ["walk_0", "walk_1"]);
+        let second = &loaded.sprites[1];
+        assert_eq!(second.source_size, (2, 2));
+        assert_eq!(second.frame, Some(1));
+        assert!(second.delta.is_none());
+        //
+        // the red of the first frame shows around the green pixel
+        let luma = |x, y| second.pixels.get_pixel(x, y).0[0];
+        assert_ne!(luma(0, 0), luma(1, 1));
+        assert_eq!(luma(0, 0), loaded.sprites[0].pixels.get_pixel(0, 0).0[0]);
+
+        assert_eq!(loaded.animations.len(), 1);
+        let animation = animation(&loaded, "walk");
+        assert_eq!(animation.frames, ["walk_0", "walk_1"]);
+        assert_eq!(animation.durations_ms, [100, 250]);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn raw_gif_frames_keep_their_delta() {
+        let dir = std::env::temp_dir().join(format!("tp-gif-raw-{}", std::process::id()));
+        let options = LoadOptions {
+            frame_mode: FrameMode::Raw,
+            ..options(aseprite::LayerMode::Merge)
+        };
+        let loaded = load_source(&walk(&dir), None, options).unwrap();
+
+        assert_eq!(keys(&loaded), ["walk_0", "walk_1"]);
+        assert_eq!(loaded.sprites[1].source_size, (1, 1));
+        let delta = loaded.sprites[1].delta.as_ref().unwrap();
+        assert_eq!((delta.x, delta.y), (1, 1));
+        assert_eq!((delta.canvas_width, delta.canvas_height), (2, 2));
+        assert_eq!(delta.dispose, FrameDispose::Background);
+        assert_eq!(delta.blend, FrameBlend::Over);
+        assert_eq!(
+            loaded.sprites[0].delta.as_ref().unwrap().dispose,
+            FrameDispose::None
+        );
+        assert_eq!(animation(&loaded, "walk").durations_ms, [100, 250]);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,146 @@
+//! Sprite pixels kept on disk for `--max-memory`. Sprites are taken in as they are
+//! loaded; once the ones held in memory reach the budget, the pixels of the others are
+//! written to a scratch file and read back one sprite at a time when they are blitted.
+
+use crate::scratch::ScratchDir;
+use crate::source::SourceSprite;
+use std::borrow::Cow;
+use std::io::{Read, Seek, Write};
+
+type SpritePixels = image::ImageBuffer<image::LumaA<u8>, Vec<u8>>;
+
+/// Where the pixels of a sprite went in the file.
+struct Spilled {
+    offset: u64,
+    width: u32,
+    height: u32,
+}
+
+struct SpillFile {
+    //
+    // removes the file with it
+    _dir: ScratchDir,
+    path: std::path::PathBuf,
+    //
+    // the pages are blitted on several threads, reads seek
+    file: std::sync::Mutex<std::fs::File>,
+    end: u64,
+}
+
+/// The sprites of a pack by id, with the pixels of the ones over the budget on disk.
+#[derive(Default)]
+pub struct Spill {
+    budget: Option<u64>,
+    scratch_dir: Option<std::path::PathBuf>,
+    resident: u64,
+    file: Option<SpillFile>,
+    sprites: Vec<Option<Spilled>>,
+}
+
+impl Spill {
+    /// Keeps up to `budget_mib` MiB of pixels in memory, the rest in a file under
+    /// `scratch_dir` (or the system temp dir). Without a budget, everything stays.
+    pub fn new(budget_mib: Option<u64>, scratch_dir: Option<&std::path::Path>) -> Spill {
+        Spill {
+            budget: budget_mib.map(|mib| mib << 20),
+            scratch_dir: scratch_dir.map(|d| d.to_path_buf()),
+            ..Default::default()
+        }
+    }
+
+    /// Takes in the sprite with the next id, moving its pixels to the file when the
+    /// ones in memory already take up the budget.
+    pub fn add(&mut self, sprite: &mut SourceSprite) -> Result<(), String> {
+        let size = sprite.pixels.as_raw().len() as u64;
+        match self.budget {
+            Some(budget) if self.resident + size > budget => {}
+            _ => {
+                self.resident += size;
+                self.sprites.push(None);
+                return Ok(());
+            }
+        }
+
+        let file = match self.file.as_mut() {
+            Some(file) => file,
+            None => {
+                let dir = ScratchDir::create(self.scratch_dir.as_deref(), false)?;
+                let path = dir.path().join("pixels.bin");
+                let file = std::fs::OpenOptions::new()
+                    .read(true)
+                    .write(true)
+                    .create_new(true)
+                    .open(&path)
+                    .map_err(|e| format!("Failed to create {}: {}", path.display(), e))?;
+                self.file.insert(SpillFile {
+                    _dir: dir,
+                    path,
+                    file: std::sync::Mutex::new(file),
+                    end: 0,
+                })
+            }
+        };
+
+        let (width, height) = sprite.pixels.dimensions();
+        let pixels = std::mem::take(&mut sprite.pixels);
+        file.file
+            .get_mut()
+            .unwrap()
+            .write_all(pixels.as_raw())
+            .map_err(|e| format!("Failed to write {}: {}", file.path.display(), e))?;
+        self.sprites.push(Some(Spilled {
+            offset: file.end,
+            width,
+            height,
+        }));
+        file.end += size;
+        Ok(())
+    }
+
+    /// Number of sprites whose pixels are on disk.
+    pub fn spilled(&self) -> usize {
+        self.sprites.iter().filter(|s| s.is_some()).count()
+    }
+
+    /// Keeps the sprites `ids` has a new id for, in their order.
+    pub fn renumber(&mut self, ids: &[Option<usize>]) {
+        let sprites = std::mem::take(&mut self.sprites);
+        self.sprites = ids
+            .iter()
+            .zip(sprites)
+            .filter_map(|(id, spilled)| id.map(|_| spilled))
+            .collect();
+    }
+
+    /// Width and height of the sprite with `id`.
+    pub fn dimensions(&self, id: usize, sprite: &SourceSprite) -> (u32, u32) {
+        match self.sprites.get(id) {
+            Some(Some(spilled)) => (spilled.width, spilled.height),
+            _ => sprite.pixels.dimensions(),
+        }
+    }
+
+    /// The pixels of the sprite with `id`, read back from the file when they are there.
+    pub fn pixels<'a>(
+        &self,
+        id: usize,
+        sprite: &'a SourceSprite,
+    ) -> Result<Cow<'a, SpritePixels>, String> {
+        let (Some(Some(spilled)), Some(file)) = (self.sprites.get(id), self.file.as_ref()) else {
+            return Ok(Cow::Borrowed(&sprite.pixels));
+        };
+
+        let failed = |e: std::io::Error| format!("Failed to read {}: {}", file.path.display(), e);
+        let mut data = vec![0; spilled.width as usize * spilled.height as usize * 2];
+        let mut reader = file.file.lock().unwrap();
+        reader
+            .seek(std::io::SeekFrom::Start(spilled.offset))
+            .map_err(failed)?;
+        reader.read_exact(&mut data).map_err(failed)?;
+
+        Ok(Cow::Owned(
+            SpritePixels::from_raw(spilled.width, spilled.height, data)
+                .expect("spilled pixels have the size of the sprite"),
+        ))
+    }
+}
@@ -0,0 +1,105 @@
+//! Atlases of their own for groups of sprites with `--split-by`: every input folder,
+//! every tag, or the opaque sprites and the masks. Each group is packed on its own into
+//! `<atlas>_<group>`, the sprites of no group (and fonts and merged atlases) into
+//! `<atlas>`.
+
+use crate::atlas::PageChannels;
+use crate::manifest::ManifestEntry;
+use crate::source::{InputFolder, LoadedSource};
+
+/// What sprites are grouped by.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum SplitBy {
+    /// The folder holding the file, by its name.
+    Folder,
+    /// The tag of the input folder or manifest entry; untagged sprites have no group.
+    Tag,
+    /// `opaque` for files with only opaque pixels, `mask` for ones with only white
+    /// pixels; the others have no group.
+    PixelFormat,
+}
+
+/// Which group a pack includes, when `--split-by` packs each on its own.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum Split {
+    #[default]
+    All,
+    /// The group of this name, the sprites of no group for `None`.
+    Group(Option<String>),
+}
+
+impl Split {
+    /// Whether the sprites loaded from a file go into the pack, with `tag` the tag of
+    /// its input folder or manifest entry.
+    pub fn includes(&self, by: Option<SplitBy>, tag: Option<&str>, loaded: &LoadedSource) -> bool {
+        match (self, by) {
+            (Split::Group(group), Some(by)) => *group == group_of(by, tag, loaded),
+            _ => true,
+        }
+    }
+
+    /// Whether the sprites of no group, fonts and merged atlases, go into the pack.
+    pub fn includes_ungrouped(&self) -> bool {
+        matches!(self, Split::All | Split::Group(None))
+    }
+}
+
+/// The group of the sprites loaded from a file.
+fn group_of(by: SplitBy, tag: Option<&str>, loaded: &LoadedSource) -> Option<String> {
+    match by {
+        SplitBy::Folder => loaded
+            .sprites
+            .first()
+            .and_then(|s| s.source.parent())
+            .and_then(folder_name),
+        SplitBy::Tag => tag.map(str::to_string),
+        //
+        // the frames of a file stay together, their animations go by all of them
+        SplitBy::PixelFormat => {
+            let mut channels = loaded.sprites.iter().map(|s| PageChannels::of(&s.pixels));
+            let first = channels.next()?;
+            let name = match first {
+                PageChannels::Luminance => "opaque",
+                PageChannels::Alpha => "mask",
+                PageChannels::LuminanceAlpha => return None,
+            };
+            channels.all(|c| c == first).then(|| name.to_string())
+        }
+    }
+}
+
+fn folder_name(dir: &std::path::Path) -> Option<String> {
+    dir.file_name().map(|n| n.to_string_lossy().into_owned())
+}
+
+/// The groups the inputs may have, the one of no group first. Groups nothing ends up in
+/// are skipped when packing.
+pub fn groups(
+    by: SplitBy,
+    folders: &[InputFolder],
+    manifest: &[ManifestEntry],
+) -> Vec<Option<String>> {
+    let mut names = match by {
+        SplitBy::Folder => folders
+            .iter()
+            .filter_map(|f| folder_name(&f.path))
+            .chain(
+                manifest
+                    .iter()
+                    .filter_map(|e| e.path.parent().and_then(folder_name)),
+            )
+            .collect::<Vec<_>>(),
+        SplitBy::Tag => folders
+            .iter()
+            .filter_map(|f| f.tag.clone())
+            .chain(manifest.iter().filter_map(|e| e.tag.clone()))
+            .collect(),
+        SplitBy::PixelFormat => vec!["opaque".to_string(), "mask".to_string()],
+    };
+    names.sort();
+    names.dedup();
+
+    std::iter::once(None)
+        .chain(names.into_iter().map(Some))
+        .collect()
+}
@@ -0,0 +1,125 @@
+//! Keeps the frame indices and layers of the previous build with `--stable-indices`, so
+//! engines baking them into level data keep working when sprites are added or removed.
+//! The previous build is read back from the descriptor it left in the output directory.
+
+use crate::atlas::{NamedTextureRegion, TextureAtlas};
+use crate::layout::OutputLayout;
+use crate::planner::Placement;
+
+/// Where every frame of the previous build was, by key.
+pub struct PreviousLayout {
+    index: fnv::FnvHashMap<String, usize>,
+    layer: fnv::FnvHashMap<String, u32>,
+}
+
+impl PreviousLayout {
+    /// The layout of the descriptor written for `atlas_name`, `None` for a first build.
+    pub fn load(
+        output_dir: &std::path::Path,
+        output_layout: OutputLayout,
+        atlas_name: &str,
+    ) -> Option<PreviousLayout> {
+        let paths = output_layout.paths(output_dir, atlas_name);
+        //
+        // an embedded descriptor when there is no file
+        let atlas = [
+            paths.meta.join(format!("{}.ron", atlas_name)),
+            paths.textures.join(format!("{}.ktx2", atlas_name)),
+        ]
+        .iter()
+        .filter(|path| path.is_file())
+        .find_map(|path| match TextureAtlas::load(path) {
+            Ok(atlas) => Some(atlas),
+            Err(e) => {
+                log::info!(
+                    "Not keeping the indices of {}, it does not load: {}",
+                    path.display(),
+                    e
+                );
+                None
+            }
+        })?;
+
+        let mut previous = PreviousLayout {
+            index: fnv::FnvHashMap::default(),
+            layer: fnv::FnvHashMap::default(),
+        };
+        for (idx, frame) in atlas.frames.iter().enumerate() {
+            previous.index.entry(frame.key.clone()).or_insert(idx);
+            previous
+                .layer
+                .entry(frame.key.clone())
+                .or_insert(frame.layer);
+        }
+        Some(previous)
+    }
+
+    /// Renumbers the pages of `placement` so pages holding the sprites of a previous
+    /// page get its number, the page keeping the most sprites first. `key` names the
+    /// placed ids. Returns the page each page went to.
+    pub fn keep_pages<Id>(
+        &self,
+        placement: &mut Placement<Id>,
+        pages: u32,
+        key: impl Fn(&Id) -> String,
+    ) -> Vec<u32> {
+        let mut kept = std::collections::BTreeMap::<(u32, u32), u32>::new();
+        for (id, placed) in placement.iter() {
+            if let Some(&layer) = self.layer.get(&key(id)).filter(|&&l| l < pages) {
+                *kept.entry((placed.page, layer)).or_default() += 1;
+            }
+        }
+
+        let mut by_count = kept.into_iter().collect::<Vec<_>>();
+        by_count.sort_by_key(|&(pair, count)| (std::cmp::Reverse(count), pair));
+
+        let mut moved_to = vec![None; pages as usize];
+        let mut taken = vec![false; pages as usize];
+        for ((page, layer), _) in by_count {
+            if moved_to[page as usize].is_none() && !taken[layer as usize] {
+                moved_to[page as usize] = Some(layer);
+                taken[layer as usize] = true;
+            }
+        }
+
+        //
+        // pages of new sprites take the numbers left, in order
+        let mut free = (0..pages).filter(|&l| !taken[l as usize]);
+        let moved_to = moved_to
+            .into_iter()
+            .map(|to| to.or_else(|| free.next()).unwrap())
+            .collect::<Vec<_>>();
+
+        for placed in placement.values_mut() {
+            placed.page = moved_to[placed.page as usize];
+        }
+        moved_to
+    }
+
+    /// Puts the frames known from the previous build back at their index. New frames
+    /// fill the places of removed ones first and go at the end after that; when there
+    /// are fewer new frames than removed ones, the frames after those move up.
+    pub fn order_frames(&self, frames: Vec<NamedTextureRegion>) -> Vec<NamedTextureRegion> {
+        let mut kept = std::collections::BTreeMap::new();
+        let mut new = Vec::new();
+        for frame in frames {
+            match self.index.get(&frame.key) {
+                Some(&idx) if !kept.contains_key(&idx) => {
+                    kept.insert(idx, frame);
+                }
+                _ => new.push(frame),
+            }
+        }
+
+        let last = kept.keys().next_back().map_or(0, |&idx| idx + 1);
+        let mut new = new.into_iter();
+        let mut ordered = Vec::new();
+        for idx in 0..last {
+            if let Some(frame) = kept.remove(&idx).or_else(|| new.next()) {
+                ordered.push(frame);
+            }
+        }
+        ordered.extend(new);
+        ordered
+    }
+}
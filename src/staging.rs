@@ -0,0 +1,43 @@
+//! Names of the hidden files outputs are written to before being renamed into place,
+//! shared by the tool's publishing and [`delta`](crate::delta).
+
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Numbers the staged files of the process, two stagings of a destination never share
+/// a file.
+static STAGED: AtomicU64 = AtomicU64::new(0);
+
+/// A new hidden file beside `path`, `.<name>.<pid>.<n>.staged`, in the same directory
+/// since renaming across file systems is not atomic.
+pub fn staged_path(path: &Path) -> PathBuf {
+    let mut file_name = std::ffi::OsString::from(".");
+    file_name.push(path.file_name().unwrap_or_default());
+    file_name.push(format!(
+        ".{}.{}.staged",
+        std::process::id(),
+        STAGED.fetch_add(1, Ordering::Relaxed)
+    ));
+    path.with_file_name(file_name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn staged_paths_are_hidden_siblings_and_never_repeat() {
+        let path = Path::new("out/meta/atlas.ron");
+        let (a, b) = (staged_path(path), staged_path(path));
+        assert_ne!(a, b);
+        for staged in [a, b] {
+            assert_eq!(staged.parent(), path.parent());
+            let name = staged.file_name().unwrap().to_string_lossy().into_owned();
+            assert!(
+                name.starts_with(".atlas.ron.") && name.ends_with(".staged"),
+                "{}",
+                name
+            );
+        }
+    }
+}
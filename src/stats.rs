@@ -0,0 +1,139 @@
+//! Statistics about a packed atlas, written as `<atlas>.stats.json`.
+//!
+//! The content statistics help catch broken bakes and exports: a lightmap page with
+//! an unexpected brightness, or sprites that came out completely black.
+
+use crate::planner::Placement;
+use crate::source::SourceSprite;
+
+pub const HISTOGRAM_BINS: usize = 64;
+
+#[derive(serde::Serialize)]
+pub struct AtlasStats {
+    pub atlas: String,
+    pub page_size: (u32, u32),
+    pub page_count: usize,
+    pub frames: usize,
+    pub mip_levels: usize,
+    pub pages: Vec<PageStats>,
+}
+
+#[derive(serde::Serialize)]
+pub struct PageStats {
+    pub page: u32,
+    pub sprites: usize,
+    /// Fraction of the page covered by sprite rects.
+    pub used: f32,
+    #[serde(flatten, skip_serializing_if = "Option::is_none")]
+    pub content: Option<ContentStats>,
+}
+
+#[derive(serde::Serialize)]
+pub struct ContentStats {
+    /// Fraction of the texels with non-zero alpha.
+    pub alpha_coverage: f32,
+    /// Average luminance of the texels with non-zero alpha, linear for HDR pages.
+    pub average_luminance: f32,
+    pub max_luminance: f32,
+    /// Texel counts (non-zero alpha) by luminance, in equal bins from 0 to
+    /// `histogram_range`.
+    pub histogram: Vec<u32>,
+    /// 1 for 8-bit pages, the maximum luminance for HDR pages.
+    pub histogram_range: f32,
+    /// Sprites with visible texels that are all black.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub black_sprites: Vec<String>,
+}
+
+/// Rec. 709 luminance of linear RGB.
+fn luminance(p: &image::Rgba<f32>) -> f32 {
+    0.2126 * p.0[0] + 0.7152 * p.0[1] + 0.0722 * p.0[2]
+}
+
+fn content_stats(
+    page: &image::ImageBuffer<image::LumaA<u8>, Vec<u8>>,
+    hdr: Option<&image::Rgba32FImage>,
+    sprites: &[&SourceSprite],
+) -> ContentStats {
+    //
+    // (luminance, alpha) of every texel, from the full precision page when there is one
+    let texels = match hdr {
+        Some(hdr) => hdr
+            .pixels()
+            .map(|p| (luminance(p), p.0[3]))
+            .collect::<Vec<_>>(),
+        None => page
+            .pixels()
+            .map(|p| (p.0[0] as f32 / 255.0, p.0[1] as f32 / 255.0))
+            .collect(),
+    };
+
+    let visible = texels.iter().filter(|(_, a)| *a > 0.0);
+    let count = visible.clone().count();
+    let max_luminance = visible.clone().map(|(l, _)| *l).fold(0f32, f32::max);
+    let histogram_range = match hdr {
+        Some(_) => max_luminance.max(f32::MIN_POSITIVE),
+        None => 1.0,
+    };
+
+    let mut histogram = vec![0u32; HISTOGRAM_BINS];
+    let mut sum = 0f64;
+    for (l, _) in visible {
+        sum += *l as f64;
+        let bin = (l / histogram_range * HISTOGRAM_BINS as f32) as usize;
+        histogram[bin.min(HISTOGRAM_BINS - 1)] += 1;
+    }
+
+    let black_sprites = sprites
+        .iter()
+        .filter(|sprite| {
+            let mut visible = sprite.pixels.pixels().filter(|p| p.0[1] > 0).peekable();
+            visible.peek().is_some() && visible.all(|p| p.0[0] == 0)
+        })
+        .map(|sprite| sprite.key.clone())
+        .collect();
+
+    ContentStats {
+        alpha_coverage: count as f32 / texels.len().max(1) as f32,
+        average_luminance: (sum / count.max(1) as f64) as f32,
+        max_luminance,
+        histogram,
+        histogram_range,
+        black_sprites,
+    }
+}
+
+/// Statistics of every page, with the content statistics if `content` is set.
+pub fn pages(
+    sprites: &[SourceSprite],
+    placement: &Placement<usize>,
+    pages: &[image::ImageBuffer<image::LumaA<u8>, Vec<u8>>],
+    hdr_pages: Option<&[image::Rgba32FImage]>,
+    content: bool,
+) -> Vec<PageStats> {
+    pages
+        .iter()
+        .enumerate()
+        .map(|(idx, page)| {
+            let on_page = placement
+                .iter()
+                .filter(|(_, placed)| placed.page == idx as u32)
+                .map(|(id, placed)| (&sprites[*id], placed))
+                .collect::<Vec<_>>();
+            let covered = on_page
+                .iter()
+                .map(|(_, p)| p.width as u64 * p.height as u64)
+                .sum::<u64>();
+
+            PageStats {
+                page: idx as u32,
+                sprites: on_page.len(),
+                used: covered as f32 / (page.width() as u64 * page.height() as u64).max(1) as f32,
+                content: content.then(|| {
+                    let sprites = on_page.iter().map(|(s, _)| *s).collect::<Vec<_>>();
+                    content_stats(page, hdr_pages.and_then(|h| h.get(idx)), &sprites)
+                }),
+            }
+        })
+        .collect()
+}
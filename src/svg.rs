@@ -0,0 +1,78 @@
+//! Rasterization of SVG inputs.
+//!
+//! Vector sources are rendered once while loading and handled like any other image
+//! from then on. Their size in pixels comes from the document's `width`/`height` at
+//! 96 DPI, scaled by the configured DPI and scale factor.
+
+/// Resolution SVG user units are defined at.
+pub const CSS_DPI: f32 = 96.0;
+
+#[derive(Copy, Clone, Debug)]
+pub struct SvgOptions {
+    /// Rendering resolution, physical units (`mm`, `in`, ...) are converted with it too.
+    pub dpi: f32,
+    pub scale: f32,
+}
+
+impl SvgOptions {
+    fn zoom(&self) -> f32 {
+        self.scale * self.dpi / CSS_DPI
+    }
+}
+
+/// Whether a file is an SVG document: markup with an `<svg` element near its start, or
+/// any markup named `.svg`.
+pub fn is_svg(path: &std::path::Path, header: &[u8]) -> bool {
+    let text = String::from_utf8_lossy(header);
+    let text = text.trim_start_matches('\u{feff}').trim_start();
+    text.starts_with('<')
+        && (text.contains("<svg")
+            || path
+                .extension()
+                .is_some_and(|e| e.eq_ignore_ascii_case("svg")))
+}
+
+pub fn rasterize(path: &std::path::Path, options: SvgOptions) -> Result<image::RgbaImage, String> {
+    let data = std::fs::read(path).map_err(|e| e.to_string())?;
+    let tree = resvg::usvg::Tree::from_data(
+        &data,
+        &resvg::usvg::Options {
+            resources_dir: path.parent().map(|p| p.to_path_buf()),
+            dpi: options.dpi,
+            ..Default::default()
+        },
+    )
+    .map_err(|e| e.to_string())?;
+
+    let zoom = options.zoom();
+    let size = tree.size();
+    let (width, height) = (
+        ((size.width() * zoom).round() as u32).max(1),
+        ((size.height() * zoom).round() as u32).max(1),
+    );
+
+    let mut pixmap = resvg::tiny_skia::Pixmap::new(width, height)
+        .ok_or_else(|| format!("invalid raster size {}x{}", width, height))?;
+    resvg::render(
+        &tree,
+        resvg::tiny_skia::Transform::from_scale(
+            width as f32 / size.width(),
+            height as f32 / size.height(),
+        ),
+        &mut pixmap.as_mut(),
+    );
+
+    //
+    // tiny-skia renders premultiplied alpha
+    let pixels = pixmap
+        .pixels()
+        .iter()
+        .flat_map(|p| {
+            let c = p.demultiply();
+            [c.red(), c.green(), c.blue(), c.alpha()]
+        })
+        .collect();
+
+    image::RgbaImage::from_raw(width, height, pixels)
+        .ok_or_else(|| "invalid SVG raster".to_string())
+}
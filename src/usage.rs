@@ -0,0 +1,159 @@
+//! Sprite co-usage from gameplay telemetry, to keep sprites drawn in the same scenes on
+//! the same pages.
+//!
+//! The usage file is JSON mapping scene names to the keys of the sprites drawn in them:
+//!
+//! ```json
+//! { "forest": ["tree", "bush", "hero"], "menu": ["button", "logo"] }
+//! ```
+//!
+//! Sprites are clustered by the number of scenes they share, strongest pairs first, as
+//! long as a cluster stays well below a page. Clusters become planner groups.
+
+use crate::planner::{Placement, PlanRect};
+use std::collections::BTreeMap;
+
+/// Clusters are kept to this fraction of a page, so they still pack together with
+/// the rest.
+const MAX_CLUSTER_FILL: f64 = 0.75;
+
+pub struct Usage {
+    scenes: BTreeMap<String, Vec<String>>,
+}
+
+impl Usage {
+    pub fn load(path: &std::path::Path) -> Result<Usage, String> {
+        let data = std::fs::read(path)
+            .map_err(|e| format!("Failed to read usage file {}: {}", path.display(), e))?;
+        let scenes = serde_json::from_slice(&data)
+            .map_err(|e| format!("Invalid usage file {}: {}", path.display(), e))?;
+        Ok(Usage { scenes })
+    }
+
+    /// Ids of the sprites in every scene, leaving out keys that are not packed.
+    /// Duplicates resolve to the sprite whose pixels they `shared`.
+    fn scene_ids(&self, keys: &[&str], shared: &[Option<usize>]) -> BTreeMap<&str, Vec<usize>> {
+        let by_key = keys
+            .iter()
+            .enumerate()
+            .map(|(id, key)| (*key, id))
+            .collect::<fnv::FnvHashMap<_, _>>();
+
+        self.scenes
+            .iter()
+            .map(|(scene, sprites)| {
+                let mut ids = sprites
+                    .iter()
+                    .filter_map(|key| by_key.get(key.as_str()))
+                    .map(|&id| shared[id].unwrap_or(id))
+                    .collect::<Vec<_>>();
+                ids.sort_unstable();
+                ids.dedup();
+                (scene.as_str(), ids)
+            })
+            .collect()
+    }
+
+    /// Puts co-used sprites into `usage:<n>` groups. `keys` holds the key of every rect
+    /// id, rects that are grouped already (`--group-tags`) are left alone. Returns the
+    /// number of clusters made.
+    pub fn group(
+        &self,
+        rects: &mut [PlanRect<usize>],
+        keys: &[&str],
+        shared: &[Option<usize>],
+        page_size: (u32, u32),
+    ) -> usize {
+        let area = |r: &PlanRect<usize>| r.width as u64 * r.height as u64;
+        let max_area = (page_size.0 as f64 * page_size.1 as f64 * MAX_CLUSTER_FILL) as u64;
+
+        //
+        // rect index of every free sprite
+        let free = rects
+            .iter()
+            .enumerate()
+            .filter(|(_, r)| r.group.is_none())
+            .map(|(idx, r)| (r.id, idx))
+            .collect::<fnv::FnvHashMap<_, _>>();
+
+        let mut together = BTreeMap::<(usize, usize), u32>::new();
+        for ids in self.scene_ids(keys, shared).values() {
+            let ids = ids
+                .iter()
+                .filter_map(|id| free.get(id).copied())
+                .collect::<Vec<_>>();
+            for (n, &a) in ids.iter().enumerate() {
+                for &b in ids[n + 1..].iter() {
+                    *together.entry((a.min(b), a.max(b))).or_default() += 1;
+                }
+            }
+        }
+
+        let mut pairs = together.into_iter().collect::<Vec<_>>();
+        pairs.sort_by_key(|&(pair, count)| (std::cmp::Reverse(count), pair));
+
+        //
+        // union-find over rect indices, the root holds the cluster area
+        let mut parent = (0..rects.len()).collect::<Vec<_>>();
+        let mut cluster_area = rects.iter().map(area).collect::<Vec<_>>();
+        fn root(parent: &mut [usize], mut idx: usize) -> usize {
+            while parent[idx] != idx {
+                parent[idx] = parent[parent[idx]];
+                idx = parent[idx];
+            }
+            idx
+        }
+
+        for ((a, b), _) in pairs {
+            let (ra, rb) = (root(&mut parent, a), root(&mut parent, b));
+            if ra != rb && cluster_area[ra] + cluster_area[rb] <= max_area {
+                parent[rb] = ra;
+                cluster_area[ra] += cluster_area[rb];
+            }
+        }
+
+        let mut sizes = fnv::FnvHashMap::<usize, usize>::default();
+        for &idx in free.values() {
+            *sizes.entry(root(&mut parent, idx)).or_default() += 1;
+        }
+
+        let mut names = BTreeMap::<usize, String>::new();
+        for (idx, rect) in rects.iter_mut().enumerate() {
+            if !free.contains_key(&rect.id) {
+                continue;
+            }
+            let r = root(&mut parent, idx);
+            if sizes[&r] > 1 {
+                let next = names.len();
+                let name = names
+                    .entry(r)
+                    .or_insert_with(|| format!("usage:{}", next))
+                    .clone();
+                rect.group = Some(name);
+            }
+        }
+
+        names.len()
+    }
+
+    /// Pages every scene draws from.
+    pub fn scene_pages(
+        &self,
+        keys: &[&str],
+        shared: &[Option<usize>],
+        placement: &Placement<usize>,
+    ) -> Vec<(&str, usize)> {
+        self.scene_ids(keys, shared)
+            .into_iter()
+            .map(|(scene, ids)| {
+                let mut pages = ids
+                    .iter()
+                    .filter_map(|id| placement.get(id).map(|p| p.page))
+                    .collect::<Vec<_>>();
+                pages.sort_unstable();
+                pages.dedup();
+                (scene, pages.len())
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,137 @@
+//! Checks that the regions of an atlas stay on their pages and apart, with the margin
+//! (padding and extrusion) they were packed with kept free around them. Every pack runs
+//! it on its layout before writing anything, `texture-packer verify` on descriptors.
+
+use crate::atlas::{NamedTextureRegion, TextureAtlas};
+use crate::diagnostic::Code;
+use std::path::PathBuf;
+
+#[derive(clap::Args, Debug)]
+pub struct VerifyOptions {
+    /// Atlas description file (.ron, or a KTX2 with the descriptor embedded).
+    atlas: PathBuf,
+    /// Pixels every region keeps free around it, the `--extrude` the atlas was packed
+    /// with.
+    #[arg(long, default_value_t = 0)]
+    margin: u32,
+}
+
+/// A region with the margin it keeps, frames sharing one (aliases, variants,
+/// duplicates) counted once.
+struct Region<'a> {
+    key: &'a str,
+    layer: u32,
+    rect: (u32, u32, u32, u32),
+    margin: u32,
+}
+
+impl Region<'_> {
+    /// The rect grown by the margin, as first and one past last pixel; signed, the
+    /// margin of a region at the edge reaches past it.
+    fn outer(&self) -> (i64, i64, i64, i64) {
+        let (x, y, w, h) = self.rect;
+        let m = self.margin as i64;
+        (
+            x as i64 - m,
+            y as i64 - m,
+            (x + w) as i64 + m,
+            (y + h) as i64 + m,
+        )
+    }
+}
+
+/// What is wrong with the layout of `frames` on `pages` pages of `page_size`, each frame
+/// keeping `margin` pixels free. Regions may touch, their margins may not overlap.
+pub fn problems(
+    frames: &[NamedTextureRegion],
+    margin: impl Fn(&NamedTextureRegion) -> u32,
+    page_size: (u32, u32),
+    pages: Option<u32>,
+) -> Vec<String> {
+    let mut regions = Vec::<Region>::new();
+    let mut seen = fnv::FnvHashMap::<(u32, (u32, u32, u32, u32)), usize>::default();
+    for frame in frames {
+        let rect = frame.pixel_rect();
+        match seen.get(&(frame.layer, rect)) {
+            Some(&idx) => regions[idx].margin = regions[idx].margin.max(margin(frame)),
+            None => {
+                seen.insert((frame.layer, rect), regions.len());
+                regions.push(Region {
+                    key: &frame.key,
+                    layer: frame.layer,
+                    rect,
+                    margin: margin(frame),
+                });
+            }
+        }
+    }
+
+    let mut problems = Vec::new();
+    for region in regions.iter() {
+        let (x0, y0, x1, y1) = region.outer();
+        if pages.is_some_and(|pages| region.layer >= pages) {
+            problems.push(format!(
+                "{} is on layer {}, the atlas has {} page(s)",
+                region.key,
+                region.layer,
+                pages.unwrap_or_default()
+            ));
+        } else if x0 < 0 || y0 < 0 || x1 > page_size.0 as i64 || y1 > page_size.1 as i64 {
+            let (x, y, w, h) = region.rect;
+            problems.push(format!(
+                "{} at ({}, {}) {}x{} with a margin of {} reaches past its {}x{} page",
+                region.key, x, y, w, h, region.margin, page_size.0, page_size.1
+            ));
+        }
+    }
+
+    //
+    // sweep every page left to right, only regions starting before one ends can
+    // overlap it
+    regions.sort_by_key(|r| (r.layer, r.outer().0));
+    for (idx, a) in regions.iter().enumerate() {
+        let (ax0, ay0, ax1, ay1) = a.outer();
+        for b in regions[idx + 1..]
+            .iter()
+            .take_while(|b| b.layer == a.layer && b.outer().0 < ax1)
+        {
+            let (_, by0, bx1, by1) = b.outer();
+            if ax0 < bx1 && ay0 < by1 && by0 < ay1 {
+                problems.push(format!(
+                    "{} and {} overlap on layer {}",
+                    a.key, b.key, a.layer
+                ));
+            }
+        }
+    }
+
+    problems
+}
+
+pub fn run(options: VerifyOptions) -> Result<(), String> {
+    let atlas = TextureAtlas::load(&options.atlas)?;
+    //
+    // the layers of a texture array are only known from the texture
+    let pages = Some(atlas.pages.len() as u32).filter(|&pages| pages > 0);
+
+    let problems = problems(&atlas.frames, |_| options.margin, atlas.size, pages);
+    for problem in problems.iter() {
+        println!("{}", problem);
+    }
+
+    match problems.is_empty() {
+        true => {
+            println!(
+                "{} frame(s) of {} are on their pages and apart",
+                atlas.frames.len(),
+                options.atlas.display()
+            );
+            Ok(())
+        }
+        false => Err(Code::InvalidLayout.error(format_args!(
+            "{} problem(s) with the layout of {}",
+            problems.len(),
+            options.atlas.display()
+        ))),
+    }
+}
@@ -0,0 +1,115 @@
+//! Pictures of where the space on the pages of an atlas went, written with
+//! `--waste-map` as `<atlas>_waste<N>.png` next to the previews:
+//!
+//! - light gray: visible pixels of sprites
+//! - dark gray: transparent pixels inside sprite rects
+//! - blue: the padding and extrusion around sprites
+//! - the free space, as the disjoint rects the planner would fill, outlined in black
+//!   and shaded by size: red for slivers too small to hold much, through yellow, to
+//!   green for the largest.
+
+use crate::atlas::NamedTextureRegion;
+use crate::planner::{self, Placed};
+
+const VISIBLE: image::Rgb<u8> = image::Rgb([200, 200, 200]);
+const TRANSPARENT: image::Rgb<u8> = image::Rgb([90, 90, 90]);
+const MARGIN: image::Rgb<u8> = image::Rgb([50, 60, 150]);
+const OUTLINE: image::Rgb<u8> = image::Rgb([0, 0, 0]);
+
+/// How much of a page is left, for the log.
+pub struct PageWaste {
+    pub free: f64,
+    pub largest: (u32, u32),
+}
+
+/// Red for `share` 0, yellow at half, green at 1.
+fn heat(share: f64) -> image::Rgb<u8> {
+    let share = share.clamp(0.0, 1.0);
+    let (r, g) = match share < 0.5 {
+        true => (1.0, share * 2.0),
+        false => (2.0 - share * 2.0, 1.0),
+    };
+    image::Rgb([(r * 220.0) as u8, (g * 200.0) as u8, 40])
+}
+
+/// The maps of every page, with `margin` the pixels kept free around every frame. The
+/// pixels of `pages` tell visible from transparent, where there are any.
+pub fn maps(
+    frames: &[NamedTextureRegion],
+    margin: impl Fn(&NamedTextureRegion) -> u32,
+    page_size: (u32, u32),
+    pages: &[image::GrayAlphaImage],
+) -> Vec<(image::RgbImage, PageWaste)> {
+    let taken = frames
+        .iter()
+        .map(|f| {
+            let m = margin(f);
+            let (x, y) = (f.x.saturating_sub(m), f.y.saturating_sub(m));
+            Placed {
+                page: f.layer,
+                x,
+                y,
+                width: (f.x + f.width + m).min(page_size.0) - x,
+                height: (f.y + f.height + m).min(page_size.1) - y,
+            }
+        })
+        .collect::<Vec<_>>();
+    let free = planner::free_space(&taken, page_size, pages.len() as u32);
+    //
+    // shaded against the largest rect of any page, a page full of slivers stands out
+    let area = |r: &Placed| r.width as u64 * r.height as u64;
+    let largest = free.iter().map(area).max().unwrap_or(1).max(1);
+
+    pages
+        .iter()
+        .enumerate()
+        .map(|(layer, page)| {
+            let layer = layer as u32;
+            let mut map = image::RgbImage::new(page_size.0, page_size.1);
+
+            for rect in free.iter().filter(|r| r.page == layer) {
+                let color = heat((area(rect) as f64 / largest as f64).sqrt());
+                for y in rect.y..rect.y + rect.height {
+                    for x in rect.x..rect.x + rect.width {
+                        let edge = x == rect.x
+                            || y == rect.y
+                            || x + 1 == rect.x + rect.width
+                            || y + 1 == rect.y + rect.height;
+                        map.put_pixel(x, y, if edge { OUTLINE } else { color });
+                    }
+                }
+            }
+
+            for (frame, outer) in frames.iter().zip(taken.iter()) {
+                if frame.layer != layer {
+                    continue;
+                }
+                for y in outer.y..outer.y + outer.height {
+                    for x in outer.x..outer.x + outer.width {
+                        let inside = x >= frame.x
+                            && y >= frame.y
+                            && x < frame.x + frame.width
+                            && y < frame.y + frame.height;
+                        let color = match inside {
+                            false => MARGIN,
+                            true if page.dimensions() != page_size => VISIBLE,
+                            true if page.get_pixel(x, y).0[1] != 0 => VISIBLE,
+                            true => TRANSPARENT,
+                        };
+                        map.put_pixel(x, y, color);
+                    }
+                }
+            }
+
+            let on_page = free.iter().filter(|r| r.page == layer);
+            let waste = PageWaste {
+                free: on_page.clone().map(area).sum::<u64>() as f64
+                    / (page_size.0 as f64 * page_size.1 as f64),
+                largest: on_page
+                    .max_by_key(|r| area(r))
+                    .map_or((0, 0), |r| (r.width, r.height)),
+            };
+            (map, waste)
+        })
+        .collect()
+}
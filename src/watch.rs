@@ -0,0 +1,427 @@
+//! `watch`: repacks whenever the inputs change, meant to run next to an editor for a
+//! long time. Decoded sources and page buffers are kept between packs so memory use
+//! stays flat, and a plain text metrics endpoint shows what the process is doing.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::diagnostic;
+use crate::encode::Encode;
+use crate::manifest;
+use crate::pack::{self, EncodeTarget, PackCache, PackOptions, PackedAtlas};
+use crate::publish::Publish;
+
+#[derive(clap::Args, Debug)]
+pub struct WatchOptions {
+    /// How often to check the inputs for changes, in milliseconds.
+    #[arg(long, default_value_t = 500)]
+    interval_ms: u64,
+    /// Serve metrics over HTTP at this address, e.g. `127.0.0.1:9184`.
+    #[arg(long)]
+    metrics_addr: Option<String>,
+    /// Write PNG pages and the descriptor right after packing and run the encode in a
+    /// background queue, superseded by newer packs. Progress is served at `/encodes`
+    /// on the metrics address.
+    #[arg(long)]
+    background_encodes: bool,
+    #[command(flatten)]
+    pack: PackOptions,
+}
+
+#[derive(Default)]
+struct Metrics {
+    packs: AtomicU64,
+    failures: AtomicU64,
+    last_pack_ms: AtomicU64,
+    cached_sources: AtomicU64,
+    pooled_pages: AtomicU64,
+    encodes_finished: AtomicU64,
+    encodes_failed: AtomicU64,
+    encodes_superseded: AtomicU64,
+}
+
+/// Resident set size from `/proc`, only available on Linux.
+fn resident_bytes() -> Option<u64> {
+    let statm = std::fs::read_to_string("/proc/self/statm").ok()?;
+    let pages = statm.split_whitespace().nth(1)?.parse::<u64>().ok()?;
+    Some(pages * 4096)
+}
+
+impl Metrics {
+    fn render(&self) -> String {
+        let mut out = String::new();
+        for (name, value) in [
+            ("texture_packer_packs_total", &self.packs),
+            ("texture_packer_pack_failures_total", &self.failures),
+            ("texture_packer_last_pack_milliseconds", &self.last_pack_ms),
+            ("texture_packer_cached_sources", &self.cached_sources),
+            ("texture_packer_pooled_pages", &self.pooled_pages),
+            (
+                "texture_packer_encodes_finished_total",
+                &self.encodes_finished,
+            ),
+            ("texture_packer_encodes_failed_total", &self.encodes_failed),
+            (
+                "texture_packer_encodes_superseded_total",
+                &self.encodes_superseded,
+            ),
+        ] {
+            out += &format!("{} {}\n", name, value.load(Ordering::Relaxed));
+        }
+        if let Some(rss) = resident_bytes() {
+            out += &format!("texture_packer_resident_bytes {}\n", rss);
+        }
+        out
+    }
+}
+
+struct EncodeJob {
+    packed: PackedAtlas,
+    targets: Vec<EncodeTarget>,
+    /// Counts the packs of the atlas, the encodes of a pack are dropped once the pages
+    /// of a later one were written.
+    generation: u64,
+}
+
+#[derive(Default)]
+struct QueueState {
+    jobs: std::collections::VecDeque<EncodeJob>,
+    /// Atlas and target being encoded.
+    running: Option<(String, String)>,
+    last_error: Option<String>,
+}
+
+/// Encodes waiting for the background worker.
+#[derive(Default)]
+struct EncodeQueue {
+    state: std::sync::Mutex<QueueState>,
+    wake: std::sync::Condvar,
+    /// Generation of the latest pack of every atlas, held while outputs of an atlas are
+    /// moved into place so the pages of a pack and the encode of another never
+    /// interleave.
+    published: std::sync::Mutex<std::collections::HashMap<String, u64>>,
+}
+
+impl EncodeQueue {
+    /// Writes the PNG pages and descriptor of a pack right away, superseding the
+    /// encodes of earlier packs of the atlas, and returns the job encoding the rest.
+    fn publish_pages(
+        &self,
+        packed: PackedAtlas,
+        targets: &[EncodeTarget],
+    ) -> Result<EncodeJob, String> {
+        let generation = {
+            let mut published = self.published.lock().unwrap();
+            let generation = published.get(packed.name()).map_or(1, |g| g + 1);
+            published.insert(packed.name().to_string(), generation);
+            generation
+        };
+
+        for target in targets {
+            let staged = packed.stage(&EncodeTarget {
+                encode: Encode::Png,
+                ..target.clone()
+            })?;
+            self.commit_latest(packed.name(), generation, staged)?;
+        }
+        Ok(EncodeJob {
+            packed,
+            targets: targets.to_vec(),
+            generation,
+        })
+    }
+
+    /// Moves the staged outputs of a pack into place unless a later pack of the atlas
+    /// was published meanwhile, whether they were.
+    fn commit_latest(&self, atlas: &str, generation: u64, staged: Publish) -> Result<bool, String> {
+        let published = self.published.lock().unwrap();
+        match published.get(atlas) {
+            Some(&latest) if latest > generation => Ok(false),
+            _ => staged.commit().map(|_| true),
+        }
+    }
+
+    /// Queues a job, dropping queued jobs for the same atlas that it supersedes.
+    fn push(&self, job: EncodeJob, metrics: &Metrics) {
+        let mut state = self.state.lock().unwrap();
+        let before = state.jobs.len();
+        state.jobs.retain(|j| j.packed.name() != job.packed.name());
+        metrics
+            .encodes_superseded
+            .fetch_add((before - state.jobs.len()) as u64, Ordering::Relaxed);
+        state.jobs.push_back(job);
+        self.wake.notify_one();
+    }
+
+    fn render(&self) -> String {
+        let state = self.state.lock().unwrap();
+        let mut out = String::new();
+        if let Some((atlas, target)) = state.running.as_ref() {
+            out += &format!("running {} {}\n", atlas, target);
+        }
+        for job in state.jobs.iter() {
+            out += &format!("queued {}\n", job.packed.name());
+        }
+        if let Some(e) = state.last_error.as_ref() {
+            out += &format!("last_error {}\n", e);
+        }
+        out
+    }
+
+    /// Encodes the targets of a job, moving them into place unless a later pack of the
+    /// atlas was published meanwhile.
+    fn run_job(&self, job: &EncodeJob, metrics: &Metrics) {
+        for target in job.targets.iter() {
+            self.state.lock().unwrap().running = Some((
+                job.packed.name().to_string(),
+                format!("{:?}", target.encode).to_lowercase(),
+            ));
+
+            let started = std::time::Instant::now();
+            let result = job
+                .packed
+                .stage(target)
+                .and_then(|staged| self.commit_latest(job.packed.name(), job.generation, staged));
+            match result {
+                Ok(true) => {
+                    metrics.encodes_finished.fetch_add(1, Ordering::Relaxed);
+                    log::info!(
+                        "Encoded {} in the background in {:.2?}",
+                        job.packed.name(),
+                        started.elapsed()
+                    );
+                }
+                Ok(false) => {
+                    metrics.encodes_superseded.fetch_add(1, Ordering::Relaxed);
+                    log::debug!(
+                        "Dropped the encode of {}, it was repacked",
+                        job.packed.name()
+                    );
+                    return;
+                }
+                Err(e) => {
+                    metrics.encodes_failed.fetch_add(1, Ordering::Relaxed);
+                    diagnostic::report_error(&e);
+                    self.state.lock().unwrap().last_error = Some(e);
+                }
+            }
+        }
+    }
+
+    fn run_worker(&self, metrics: &Metrics) {
+        loop {
+            let job = {
+                let mut state = self.state.lock().unwrap();
+                loop {
+                    match state.jobs.pop_front() {
+                        Some(job) => break job,
+                        None => state = self.wake.wait(state).unwrap(),
+                    }
+                }
+            };
+
+            self.run_job(&job, metrics);
+            self.state.lock().unwrap().running = None;
+        }
+    }
+}
+
+fn serve_metrics(
+    addr: &str,
+    metrics: std::sync::Arc<Metrics>,
+    queue: std::sync::Arc<EncodeQueue>,
+) -> Result<(), String> {
+    let listener = std::net::TcpListener::bind(addr)
+        .map_err(|e| format!("Failed to listen on {}: {}", addr, e))?;
+    log::info!("Serving metrics on http://{}/", addr);
+
+    std::thread::spawn(move || {
+        use std::io::{Read, Write};
+
+        for mut stream in listener.incoming().flatten() {
+            //
+            // `/encodes` shows the background queue, every other path the metrics
+            let mut request = [0u8; 1024];
+            let _ = stream.read(&mut request);
+
+            let body = match request.starts_with(b"GET /encodes ") {
+                true => queue.render(),
+                false => metrics.render(),
+            };
+            let _ = write!(
+                stream,
+                "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            );
+        }
+    });
+
+    Ok(())
+}
+
+/// Names, sizes and modification times of everything in the input folders, of the
+/// manifest and its entries and of the fonts.
+fn fingerprint(
+    options: &PackOptions,
+) -> Vec<(std::path::PathBuf, u64, Option<std::time::SystemTime>)> {
+    let mut entries = options
+        .input_folders()
+        .iter()
+        .filter_map(|folder| std::fs::read_dir(&folder.path).ok())
+        .flatten()
+        .filter_map(|de| de.ok().map(|de| de.path()))
+        .chain(options.manifest().map(|path| path.to_path_buf()))
+        .chain(
+            options
+                .manifest()
+                .and_then(|path| manifest::load(path).ok())
+                .into_iter()
+                .flatten()
+                .map(|entry| entry.path),
+        )
+        .chain(options.fonts().iter().cloned())
+        .filter_map(|path| {
+            let meta = std::fs::metadata(&path).ok()?;
+            Some((path, meta.len(), meta.modified().ok()))
+        })
+        .collect::<Vec<_>>();
+
+    entries.sort();
+    entries
+}
+
+pub fn run(options: WatchOptions) -> Result<(), String> {
+    let metrics = std::sync::Arc::new(Metrics::default());
+    let queue = std::sync::Arc::new(EncodeQueue::default());
+    if let Some(addr) = options.metrics_addr.as_deref() {
+        serve_metrics(addr, metrics.clone(), queue.clone())?;
+    }
+
+    let targets = [EncodeTarget {
+        name: None,
+        encode: options.pack.encode(),
+    }];
+    pack::check_targets(&options.pack, &targets)?;
+
+    //
+    // page images are the fast path, there is nothing left to do in the background
+    let background = options.background_encodes && targets.iter().any(|t| t.encode.is_ktx2());
+    if background {
+        let (queue, metrics) = (queue.clone(), metrics.clone());
+        std::thread::spawn(move || queue.run_worker(&metrics));
+    }
+
+    let mut cache = PackCache::default();
+    let mut last_fingerprint = None;
+
+    loop {
+        let current = fingerprint(&options.pack);
+
+        if last_fingerprint.as_ref() != Some(&current) {
+            let started = std::time::Instant::now();
+
+            let result = match background {
+                true => pack::run_packed(&options.pack, Some(&mut cache), &mut |packed| {
+                    let job = queue.publish_pages(packed, &targets)?;
+                    queue.push(job, &metrics);
+                    Ok(())
+                }),
+                false => pack::run_cached(&options.pack, &targets, Some(&mut cache)),
+            };
+
+            match result {
+                Ok(()) => log::info!("Packed in {:.2?}", started.elapsed()),
+                Err(e) => {
+                    metrics.failures.fetch_add(1, Ordering::Relaxed);
+                    diagnostic::report_error(&e);
+                }
+            }
+
+            metrics.packs.fetch_add(1, Ordering::Relaxed);
+            metrics
+                .last_pack_ms
+                .store(started.elapsed().as_millis() as u64, Ordering::Relaxed);
+            metrics
+                .cached_sources
+                .store(cache.cached_sources() as u64, Ordering::Relaxed);
+            metrics
+                .pooled_pages
+                .store(cache.pooled_pages() as u64, Ordering::Relaxed);
+
+            last_fingerprint = Some(current);
+        }
+
+        std::thread::sleep(std::time::Duration::from_millis(options.interval_ms));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+    use texture_packer::atlas::TextureAtlas;
+
+    #[derive(clap::Parser)]
+    struct Args {
+        #[command(flatten)]
+        pack: PackOptions,
+    }
+
+    fn pack(input: &std::path::Path, output: &std::path::Path) -> PackedAtlas {
+        let args = Args::parse_from([
+            std::ffi::OsStr::new("texture-packer"),
+            "-i".as_ref(),
+            input.as_os_str(),
+            "-a".as_ref(),
+            "atlas".as_ref(),
+            "-o".as_ref(),
+            output.as_os_str(),
+            "-s".as_ref(),
+            "64".as_ref(),
+            "--encode".as_ref(),
+            "png".as_ref(),
+        ]);
+        let mut packed = None;
+        pack::run_packed(&args.pack, None, &mut |p| {
+            packed = Some(p);
+            Ok(())
+        })
+        .unwrap();
+        packed.unwrap()
+    }
+
+    #[test]
+    fn last_pack_of_an_atlas_wins() {
+        let dir = std::env::temp_dir().join(format!("tp-watch-{}", std::process::id()));
+        let (old, new, output) = (dir.join("old"), dir.join("new"), dir.join("out"));
+        for (folder, name) in [(&old, "old.png"), (&new, "new.png")] {
+            std::fs::create_dir_all(folder).unwrap();
+            image::RgbaImage::from_pixel(8, 8, image::Rgba([255, 0, 0, 255]))
+                .save(folder.join(name))
+                .unwrap();
+        }
+
+        let (queue, metrics) = (EncodeQueue::default(), Metrics::default());
+        let targets = [EncodeTarget {
+            name: None,
+            encode: Encode::Png,
+        }];
+        let first = queue.publish_pages(pack(&old, &output), &targets).unwrap();
+        let second = queue.publish_pages(pack(&new, &output), &targets).unwrap();
+
+        let frames = || {
+            let atlas = TextureAtlas::load(&output.join("atlas.ron")).unwrap();
+            (atlas.frame("old").is_some(), atlas.frame("new").is_some())
+        };
+        //
+        // the encode of the first pack finishing late leaves the second in place
+        queue.run_job(&first, &metrics);
+        assert_eq!(frames(), (false, true));
+        queue.run_job(&second, &metrics);
+        assert_eq!(frames(), (false, true));
+
+        assert_eq!(metrics.encodes_superseded.load(Ordering::Relaxed), 1);
+        assert_eq!(metrics.encodes_finished.load(Ordering::Relaxed), 1);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}